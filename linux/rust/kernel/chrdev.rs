@@ -9,6 +9,7 @@
 //! Reference: <https://www.kernel.org/doc/html/latest/core-api/kernel-api.html#char-devices>
 
 use alloc::boxed::Box;
+use core::cell::UnsafeCell;
 use core::convert::TryInto;
 use core::marker::PhantomPinned;
 use core::pin::Pin;
@@ -75,6 +76,34 @@ impl Drop for Cdev {
     }
 }
 
+/// The context passed to [`file::Operations::open`] for character devices.
+///
+/// It gives access to the inode being opened, which tells the driver which of its minors was
+/// opened when the same [`file::Operations`] implementation is registered more than once.
+#[repr(transparent)]
+pub struct OpenContext(UnsafeCell<bindings::inode>);
+
+// SAFETY: The context only reads `i_rdev`, which never changes once the inode is set up.
+unsafe impl Sync for OpenContext {}
+
+impl OpenContext {
+    /// Returns the device number of the opened device.
+    pub fn dev(&self) -> bindings::dev_t {
+        // SAFETY: `self.0` is the inode being opened, which is valid during `open`.
+        unsafe { (*self.0.get()).i_rdev }
+    }
+
+    /// Returns the major number of the opened device, corresponds to `imajor`.
+    pub fn major(&self) -> u32 {
+        self.dev() >> bindings::MINORBITS
+    }
+
+    /// Returns the minor number of the opened device, corresponds to `iminor`.
+    pub fn minor(&self) -> u32 {
+        self.dev() & ((1 << bindings::MINORBITS) - 1)
+    }
+}
+
 struct RegistrationInner<const N: usize> {
     dev: bindings::dev_t,
     used: usize,
@@ -132,8 +161,9 @@ impl<const N: usize> Registration<{ N }> {
 
     /// Registers a character device.
     ///
-    /// You may call this once per device type, up to `N` times.
-    pub fn register<T: file::Operations<OpenData = ()>>(self: Pin<&mut Self>) -> Result {
+    /// You may call this up to `N` times, each call takes the next minor. The same type may be
+    /// registered several times; [`OpenContext::minor`] tells the instances apart in `open`.
+    pub fn register<T: file::Operations<OpenData = OpenContext>>(self: Pin<&mut Self>) -> Result {
         // SAFETY: We must ensure that we never move out of `this`.
         let this = unsafe { self.get_unchecked_mut() };
         if this.inner.is_none() {
@@ -165,7 +195,7 @@ impl<const N: usize> Registration<{ N }> {
             return Err(EINVAL);
         }
 
-        // SAFETY: The adapter doesn't retrieve any state yet, so it's compatible with any
+        // SAFETY: The adapter only wraps the inode passed to `open`, so it's compatible with any
         // registration.
         let fops = unsafe { file::OperationsVtable::<Self, T>::build() };
         let mut cdev = Cdev::alloc(fops, this.this_module)?;
@@ -174,13 +204,28 @@ impl<const N: usize> Registration<{ N }> {
         inner.used += 1;
         Ok(())
     }
+
+    /// Returns the device number of the `index`-th registered device, counting from 0.
+    ///
+    /// This is the number to pass to [`crate::device::Class::create_device`] so that a node for
+    /// the device appears in `/dev`.
+    pub fn dev(&self, index: usize) -> Option<bindings::dev_t> {
+        let inner = self.inner.as_ref()?;
+        if index >= inner.used {
+            return None;
+        }
+        Some(inner.dev + index as bindings::dev_t)
+    }
 }
 
-impl<const N: usize> file::OpenAdapter<()> for Registration<{ N }> {
-    unsafe fn convert(_inode: *mut bindings::inode, _file: *mut bindings::file) -> *const () {
-        // TODO: Update the SAFETY comment on the call to `FileOperationsVTable::build` above once
-        // this is updated to retrieve state.
-        &()
+impl<const N: usize> file::OpenAdapter<OpenContext> for Registration<{ N }> {
+    unsafe fn convert(
+        inode: *mut bindings::inode,
+        _file: *mut bindings::file,
+    ) -> *const OpenContext {
+        // `OpenContext` is a transparent wrapper around the inode, which the caller guarantees to
+        // be valid for the duration of `open`.
+        inode.cast()
     }
 }
 
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! CPU masks.
+//!
+//! C header: [`include/linux/cpumask.h`](../../../../include/linux/cpumask.h)
+
+use crate::{bindings, error::code::*, Result};
+use alloc::boxed::Box;
+use core::ops::{Deref, DerefMut};
+
+/// The number of CPU ids a single word of a mask covers.
+const BITS_PER_WORD: u32 = core::ffi::c_ulong::BITS;
+
+/// Wraps the kernel's `struct cpumask`.
+///
+/// Shared references to CPU masks owned by the kernel are returned by [`CpuMask::of`] and
+/// [`CpuMask::of_node`]; masks that can be modified are created with [`OwnedCpuMask`].
+///
+/// # Invariants
+///
+/// Only bits of possible CPU ids (below `nr_cpu_ids`) are set.
+#[repr(transparent)]
+pub struct CpuMask(bindings::cpumask);
+
+impl CpuMask {
+    /// Returns a static mask containing only `cpu`, or `None` if `cpu` is not a possible CPU id.
+    pub fn of(cpu: u32) -> Option<&'static CpuMask> {
+        if !Self::is_possible(cpu) {
+            return None;
+        }
+        // SAFETY: `cpu` was checked above, `cpumask_of` returns a pointer into a static table and
+        // `CpuMask` is transparent.
+        Some(unsafe { &*(bindings::cpumask_of(cpu) as *const CpuMask) })
+    }
+
+    /// Returns the static mask of the CPUs on NUMA node `node`, or `None` if `node` is
+    /// `NUMA_NO_NODE` or not a possible node id.
+    pub fn of_node(node: i32) -> Option<&'static CpuMask> {
+        let node = u32::try_from(node).ok()?;
+        // SAFETY: `nr_node_ids` is set up during early boot and is read-only afterwards.
+        if node >= unsafe { bindings::nr_node_ids } {
+            return None;
+        }
+        // SAFETY: `node` was checked above, `cpumask_of_node` returns a pointer to a mask that
+        // lives as long as the node and `CpuMask` is transparent.
+        Some(unsafe { &*(bindings::cpumask_of_node(node as i32) as *const CpuMask) })
+    }
+
+    /// Returns whether `cpu` is in the mask.
+    pub fn contains(&self, cpu: u32) -> bool {
+        Self::is_possible(cpu) && self.0.bits[(cpu / BITS_PER_WORD) as usize] & Self::bit(cpu) != 0
+    }
+
+    /// Adds `cpu` to the mask, corresponds to `__cpumask_set_cpu`.
+    ///
+    /// Returns `EINVAL` if `cpu` is not a possible CPU id.
+    pub fn set(&mut self, cpu: u32) -> Result {
+        if !Self::is_possible(cpu) {
+            return Err(EINVAL);
+        }
+        // INVARIANT: `cpu` was checked above.
+        self.0.bits[(cpu / BITS_PER_WORD) as usize] |= Self::bit(cpu);
+        Ok(())
+    }
+
+    /// Removes `cpu` from the mask, corresponds to `__cpumask_clear_cpu`.
+    ///
+    /// Does nothing if `cpu` is not a possible CPU id, since it can't be in the mask.
+    pub fn clear(&mut self, cpu: u32) {
+        if Self::is_possible(cpu) {
+            self.0.bits[(cpu / BITS_PER_WORD) as usize] &= !Self::bit(cpu);
+        }
+    }
+
+    /// Returns the number of CPUs in the mask, corresponds to `cpumask_weight`.
+    pub fn weight(&self) -> u32 {
+        self.0.bits.iter().map(|word| word.count_ones()).sum()
+    }
+
+    /// Returns whether the mask contains no CPU, corresponds to `cpumask_empty`.
+    pub fn is_empty(&self) -> bool {
+        self.0.bits.iter().all(|word| *word == 0)
+    }
+
+    /// Returns an iterator over the CPUs in the mask, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        // SAFETY: `nr_cpu_ids` is set up during early boot and is read-only afterwards.
+        let nr_cpu_ids = unsafe { bindings::nr_cpu_ids };
+        (0..nr_cpu_ids).filter(move |cpu| self.contains(*cpu))
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const bindings::cpumask {
+        &self.0
+    }
+
+    fn is_possible(cpu: u32) -> bool {
+        // SAFETY: `nr_cpu_ids` is set up during early boot and is read-only afterwards.
+        cpu < unsafe { bindings::nr_cpu_ids }
+    }
+
+    fn bit(cpu: u32) -> core::ffi::c_ulong {
+        1 << (cpu % BITS_PER_WORD)
+    }
+}
+
+/// A heap-allocated CPU mask that can be modified, corresponds to `cpumask_var_t`.
+///
+/// The mask is always allocated, like `cpumask_var_t` with `CONFIG_CPUMASK_OFFSTACK`, since a
+/// `struct cpumask` can be too large for the kernel stack.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut mask = OwnedCpuMask::try_new()?;
+/// mask.set(0)?;
+/// mask.set(2)?;
+/// task.set_cpus_allowed(&mask)?;
+/// ```
+pub struct OwnedCpuMask(Box<CpuMask>);
+
+impl OwnedCpuMask {
+    /// Allocates an empty mask.
+    pub fn try_new() -> Result<Self> {
+        // INVARIANT: No bit is set.
+        // SAFETY: `struct cpumask` is an array of integers, for which all zeroes is a valid value.
+        Ok(Self(Box::try_new(CpuMask(unsafe { core::mem::zeroed() }))?))
+    }
+
+    /// Allocates a copy of `mask`, corresponds to `cpumask_copy`.
+    pub fn try_from_mask(mask: &CpuMask) -> Result<Self> {
+        // INVARIANT: The bits are those of `mask`, which satisfies the invariant.
+        Ok(Self(Box::try_new(CpuMask(bindings::cpumask {
+            bits: mask.0.bits,
+        }))?))
+    }
+}
+
+impl Deref for OwnedCpuMask {
+    type Target = CpuMask;
+
+    fn deref(&self) -> &CpuMask {
+        &self.0
+    }
+}
+
+impl DerefMut for OwnedCpuMask {
+    fn deref_mut(&mut self) -> &mut CpuMask {
+        &mut self.0
+    }
+}
@@ -2,7 +2,8 @@
 
 //! Credentials management.
 //!
-//! C header: [`include/linux/cred.h`](../../../../include/linux/cred.h)
+//! C headers: [`include/linux/cred.h`](../../../../include/linux/cred.h) and
+//! [`include/linux/capability.h`](../../../../include/linux/capability.h)
 //!
 //! Reference: <https://www.kernel.org/doc/html/latest/security/credentials.html>
 
@@ -30,6 +31,66 @@ impl Credential {
         // `Credential` type being transparent makes the cast ok.
         unsafe { &*ptr.cast() }
     }
+
+    /// Returns the real user id.
+    pub fn uid(&self) -> u32 {
+        // SAFETY: The existence of a shared reference means the credential is valid, and
+        // credentials are never modified once published.
+        unsafe { (*self.0.get()).uid.val }
+    }
+
+    /// Returns the effective user id.
+    pub fn euid(&self) -> u32 {
+        // SAFETY: The existence of a shared reference means the credential is valid, and
+        // credentials are never modified once published.
+        unsafe { (*self.0.get()).euid.val }
+    }
+
+    /// Returns the user id used for filesystem accesses.
+    pub fn fsuid(&self) -> u32 {
+        // SAFETY: The existence of a shared reference means the credential is valid, and
+        // credentials are never modified once published.
+        unsafe { (*self.0.get()).fsuid.val }
+    }
+}
+
+/// Capabilities, as used with [`capable`].
+pub mod cap {
+    use crate::bindings;
+
+    /// Allows changing file ownership.
+    pub const CHOWN: u32 = bindings::CAP_CHOWN;
+
+    /// Allows bypassing file permission checks.
+    pub const DAC_OVERRIDE: u32 = bindings::CAP_DAC_OVERRIDE;
+
+    /// Allows network administration, e.g. configuring interfaces.
+    pub const NET_ADMIN: u32 = bindings::CAP_NET_ADMIN;
+
+    /// Allows raw I/O, e.g. accessing I/O ports.
+    pub const SYS_RAWIO: u32 = bindings::CAP_SYS_RAWIO;
+
+    /// Allows a broad range of system administration operations.
+    pub const SYS_ADMIN: u32 = bindings::CAP_SYS_ADMIN;
+}
+
+/// Returns whether the current task has the capability `cap` in the initial user namespace,
+/// corresponds to `capable`.
+///
+/// `cap` is one of the [`cap`] constants. As in C, a successful check marks the task as having
+/// used its privileges (`PF_SUPERPRIV`), so only call this when the privilege is needed.
+///
+/// # Examples
+///
+/// ```ignore
+/// if !cred::capable(cred::cap::SYS_ADMIN) {
+///     return Err(EPERM);
+/// }
+/// ```
+pub fn capable(cap: u32) -> bool {
+    // SAFETY: FFI call, there are no requirements on `cap`; invalid values are rejected by the C
+    // side.
+    unsafe { bindings::capable(cap as _) }
 }
 
 // SAFETY: The type invariants guarantee that `Credential` is always ref-counted.
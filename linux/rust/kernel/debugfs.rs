@@ -0,0 +1,190 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Debugfs directories and read-only files backed by `seq_file`.
+//!
+//! C headers: [`include/linux/debugfs.h`](../../../../include/linux/debugfs.h) and
+//! [`include/linux/seq_file.h`](../../../../include/linux/seq_file.h)
+
+use crate::{
+    bindings,
+    error::{from_kernel_err_ptr, from_kernel_result},
+    str::CStr,
+    sync::Arc,
+    Result,
+};
+use core::ptr;
+
+pub use crate::seq_file::SeqFile;
+
+/// A directory in debugfs.
+///
+/// The directory is removed, together with everything still in it, when the object is dropped.
+///
+/// # Invariants
+///
+/// `dentry` is a valid debugfs dentry created by `debugfs_create_dir`.
+pub struct Dir {
+    dentry: *mut bindings::dentry,
+}
+
+// SAFETY: The dentry is only used by debugfs functions, which can be called from any thread.
+unsafe impl Send for Dir {}
+
+// SAFETY: `Dir` has no interior mutability, shared references only give access to the dentry.
+unsafe impl Sync for Dir {}
+
+impl Dir {
+    /// Creates a new directory called `name`, in the debugfs root if `parent` is `None`.
+    ///
+    /// Callers usually ignore the error: debugfs is a debugging aid and may be disabled.
+    pub fn try_new(name: &CStr, parent: Option<&Dir>) -> Result<Arc<Self>> {
+        let parent = parent.map_or(ptr::null_mut(), |p| p.dentry);
+        // SAFETY: `name` is a valid null-terminated string and `parent` is either null or a valid
+        // dentry by the type invariants.
+        let dentry = from_kernel_err_ptr(unsafe {
+            bindings::debugfs_create_dir(name.as_char_ptr(), parent)
+        })?;
+        // INVARIANTS: `debugfs_create_dir` succeeded.
+        Arc::try_new(Self { dentry })
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        // SAFETY: `dentry` is valid by the type invariants. Files in the directory hold a
+        // reference to it, so none of them is left over.
+        unsafe { bindings::debugfs_remove(self.dentry) };
+    }
+}
+
+/// Generates the contents of a read-only debugfs [`File`].
+pub trait Show: Send + Sync {
+    /// Writes the whole contents of the file to `m`.
+    ///
+    /// Called each time the file is opened and may be called again if the output is larger than
+    /// the buffer.
+    fn show(&self, m: &mut SeqFile) -> Result;
+}
+
+/// A read-only debugfs file whose contents are generated by `T` when it is opened.
+///
+/// The file is removed when the object is dropped.
+///
+/// # Invariants
+///
+/// `dentry` is a valid debugfs dentry created by `debugfs_create_file`, with `data` as its
+/// private data.
+pub struct File<T: Show> {
+    dentry: *mut bindings::dentry,
+    data: Arc<T>,
+    _parent: Arc<Dir>,
+}
+
+// SAFETY: The dentry is only used by debugfs functions, which can be called from any thread, and
+// `T` is `Send + Sync`.
+unsafe impl<T: Show> Send for File<T> {}
+
+// SAFETY: `File` has no interior mutability and `T` is `Sync`.
+unsafe impl<T: Show> Sync for File<T> {}
+
+impl<T: Show> File<T> {
+    /// Creates a file called `name` in `parent`.
+    ///
+    /// The file keeps `parent` alive, so the directory is never removed before the file.
+    pub fn try_new(name: &CStr, parent: &Arc<Dir>, data: Arc<T>) -> Result<Self> {
+        // SAFETY: `name` is a valid null-terminated string and `parent.dentry` is valid by the
+        // type invariants. `data` is kept alive until the file is removed in `drop`, after which
+        // debugfs no longer calls `FileAdapter::open_callback`.
+        let dentry = from_kernel_err_ptr(unsafe {
+            bindings::debugfs_create_file(
+                name.as_char_ptr(),
+                0o444,
+                parent.dentry,
+                &*data as *const T as *mut core::ffi::c_void,
+                &FileAdapter::<T>::VTABLE,
+            )
+        })?;
+        // INVARIANTS: `debugfs_create_file` succeeded.
+        Ok(Self {
+            dentry,
+            data,
+            _parent: parent.clone(),
+        })
+    }
+
+    /// Returns the data the file is generated from.
+    pub fn data(&self) -> &Arc<T> {
+        &self.data
+    }
+}
+
+impl<T: Show> Drop for File<T> {
+    fn drop(&mut self) {
+        // SAFETY: `dentry` is valid by the type invariants. `debugfs_remove` waits for callbacks
+        // already running, so `data` is no longer used once it returns.
+        unsafe { bindings::debugfs_remove(self.dentry) };
+    }
+}
+
+struct FileAdapter<T: Show>(core::marker::PhantomData<T>);
+
+impl<T: Show> FileAdapter<T> {
+    unsafe extern "C" fn open_callback(
+        inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> core::ffi::c_int {
+        // SAFETY: `inode` is valid during the callback. Its private data is the pointer to `T`
+        // passed to `debugfs_create_file`, which `single_open` stores in the `seq_file`.
+        unsafe { bindings::single_open(file, Some(Self::show_callback), (*inode).i_private) }
+    }
+
+    unsafe extern "C" fn show_callback(
+        m: *mut bindings::seq_file,
+        _v: *mut core::ffi::c_void,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: `m` is valid during the callback and its private data was set by
+            // `open_callback` to a `T` that outlives the file, see `File::drop`.
+            let data = unsafe { &*((*m).private as *const T) };
+            data.show(&mut SeqFile(m))?;
+            Ok(0)
+        }
+    }
+
+    const VTABLE: bindings::file_operations = bindings::file_operations {
+        open: Some(Self::open_callback),
+        release: Some(bindings::single_release),
+        read: Some(bindings::seq_read),
+        write: None,
+        llseek: Some(bindings::seq_lseek),
+        check_flags: None,
+        compat_ioctl: None,
+        copy_file_range: None,
+        fallocate: None,
+        fadvise: None,
+        fasync: None,
+        flock: None,
+        flush: None,
+        fsync: None,
+        get_unmapped_area: None,
+        iterate: None,
+        iterate_shared: None,
+        iopoll: None,
+        lock: None,
+        mmap: None,
+        mmap_supported_flags: 0,
+        owner: ptr::null_mut(),
+        poll: None,
+        read_iter: None,
+        remap_file_range: None,
+        sendpage: None,
+        setlease: None,
+        show_fdinfo: None,
+        splice_read: None,
+        splice_write: None,
+        unlocked_ioctl: None,
+        uring_cmd: None,
+        uring_cmd_iopoll: None,
+        write_iter: None,
+    };
+}
@@ -5,22 +5,23 @@
 //! C header: [`include/linux/device.h`](../../../../include/linux/device.h)
 
 #[cfg(CONFIG_COMMON_CLK)]
-use crate::{clk::Clk, error::from_kernel_err_ptr};
+use crate::clk::Clk;
 
 use crate::{
     bindings,
+    error::from_kernel_err_ptr,
     revocable::{Revocable, RevocableGuard},
     str::CStr,
     sync::{LockClassKey, NeedsLockClass, RevocableMutex, RevocableMutexGuard, UniqueArc},
-    Result,
+    to_result, Result, ThisModule,
 };
+use alloc::{boxed::Box, vec::Vec};
 use core::{
     fmt,
     ops::{Deref, DerefMut},
     pin::Pin,
 };
 
-#[cfg(CONFIG_PRINTK)]
 use crate::c_str;
 
 /// A raw device.
@@ -52,6 +53,15 @@ pub unsafe trait RawDevice {
         unsafe { CStr::from_char_ptr(name) }
     }
 
+    /// Returns the NUMA node the device is attached to, or `NUMA_NO_NODE` if it is unknown.
+    ///
+    /// `dma_alloc_coherent` already allocates from this node; other per-device memory (page
+    /// pools, interrupt affinity) can use it to stay close to the device.
+    fn numa_node(&self) -> i32 {
+        // SAFETY: `self.raw_device()` is valid because `self` keeps it alive.
+        unsafe { bindings::dev_to_node(self.raw_device()) }
+    }
+
     /// Lookups a clock producer consumed by this device.
     ///
     /// Returns a managed reference to the clock producer.
@@ -213,6 +223,123 @@ impl Drop for Device {
     }
 }
 
+/// Hands `data` over to the driver core, which drops it when the driver is unbound from `dev`.
+///
+/// This corresponds to `devm_add_action_or_reset`. Resources registered this way during probe are
+/// released after the driver's `remove` returns, in the reverse order of their registration, so
+/// `remove` doesn't need to release them by hand. If the registration fails, `data` is dropped
+/// right away.
+pub fn devm_add_action<T: Send + 'static>(dev: &dyn RawDevice, data: T) -> Result {
+    unsafe extern "C" fn release<T>(ptr: *mut core::ffi::c_void) {
+        // SAFETY: `ptr` was created by `Box::into_raw` below and the driver core calls the action
+        // exactly once.
+        drop(unsafe { Box::from_raw(ptr as *mut T) });
+    }
+
+    let ptr = Box::into_raw(Box::try_new(data)?);
+    // SAFETY: `dev.raw_device()` is valid by the safety requirements of `RawDevice`. On failure
+    // `devm_add_action_or_reset` calls `release`, which frees `ptr`.
+    to_result(unsafe {
+        bindings::devm_add_action_or_reset(dev.raw_device(), Some(release::<T>), ptr as _)
+    })
+}
+
+/// A device class, corresponds to `struct class`.
+///
+/// Devices created in a class with [`Class::create_device`] are announced to userspace, so udev
+/// or devtmpfs create the matching node in `/dev`. The devices are destroyed, in the reverse order
+/// of their creation, and the class is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `ptr` is a class returned by `class_create`, and every entry of `devices` is the number of a
+/// device created in it.
+///
+/// # Examples
+///
+/// ```ignore
+/// static KEY: LockClassKey = LockClassKey::new();
+///
+/// let mut class = Class::try_new(c_str!("rust_chrdev"), &KEY, module)?;
+/// class.create_device(None, devt, fmt!("rust_chrdev{}", 0))?;
+/// ```
+pub struct Class {
+    ptr: *mut bindings::class,
+    devices: Vec<bindings::dev_t>,
+}
+
+// SAFETY: `Class` only holds a pointer to a C class, which is safe to be used from any thread.
+unsafe impl Send for Class {}
+
+// SAFETY: The only method taking `&self` just reads the pointer, the driver core has its own
+// locking.
+unsafe impl Sync for Class {}
+
+impl Class {
+    /// Registers a class named `name`, which shows up in `/sys/class`.
+    ///
+    /// `key` is the lockdep class of the class' mutex.
+    pub fn try_new(
+        name: &'static CStr,
+        key: &'static LockClassKey,
+        module: &'static ThisModule,
+    ) -> Result<Self> {
+        // SAFETY: `name` and `key` are static and `module.0` is the owning module.
+        let ptr = from_kernel_err_ptr(unsafe {
+            bindings::__class_create(module.0, name.as_char_ptr(), key.get())
+        })?;
+        // INVARIANT: `ptr` was just created and no devices exist yet.
+        Ok(Self {
+            ptr,
+            devices: Vec::new(),
+        })
+    }
+
+    /// Creates a device with number `devt` in the class, corresponds to `device_create`.
+    ///
+    /// The device, and its node in `/dev`, is named after `name`. It is destroyed when the class
+    /// is dropped.
+    pub fn create_device(
+        &mut self,
+        parent: Option<&dyn RawDevice>,
+        devt: bindings::dev_t,
+        name: fmt::Arguments<'_>,
+    ) -> Result {
+        self.devices.try_reserve(1)?;
+        let parent = parent.map_or(core::ptr::null_mut(), |p| p.raw_device());
+        // SAFETY: `self.ptr` is valid by the type invariants and `parent` is either null or kept
+        // alive by the reference. The "%pA" format string expects a pointer to `fmt::Arguments`,
+        // which is what we're passing as the last argument.
+        from_kernel_err_ptr(unsafe {
+            bindings::device_create(
+                self.ptr,
+                parent,
+                devt,
+                core::ptr::null_mut(),
+                c_str!("%pA").as_char_ptr(),
+                &name as *const _ as *const core::ffi::c_void,
+            )
+        })?;
+        // INVARIANT: The device was created above. The push doesn't fail since space was
+        // reserved.
+        self.devices.try_push(devt)?;
+        Ok(())
+    }
+}
+
+impl Drop for Class {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `self.ptr` is a registered class and the devices in
+        // `self.devices` were created in it.
+        unsafe {
+            while let Some(devt) = self.devices.pop() {
+                bindings::device_destroy(self.ptr, devt);
+            }
+            bindings::class_destroy(self.ptr);
+        }
+    }
+}
+
 /// Device data.
 ///
 /// When a device is removed (for whatever reason, for example, because the device was unplugged or
@@ -525,3 +652,21 @@ macro_rules! dev_info {
 macro_rules! dev_dbg {
     ($($f:tt)*) => { $crate::dev_printk!(pr_dbg, $($f)*); }
 }
+
+/// Like [`dev_err`], but prints at most a few messages every five seconds per call site.
+///
+/// Use this in paths that may run for every interrupt or request.
+#[macro_export]
+macro_rules! dev_err_ratelimited {
+    ($($f:tt)*) => { $crate::print_ratelimited!(dev_err, $($f)*); }
+}
+
+/// Like [`dev_dbg`], but prints at most a few messages every five seconds per call site.
+#[macro_export]
+macro_rules! dev_dbg_ratelimited {
+    ($($f:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::print_ratelimited!(dev_dbg, $($f)*);
+        }
+    }
+}
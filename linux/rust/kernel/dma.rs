@@ -4,22 +4,69 @@
 //!
 //! C header: [`include/linux/dma-mapping.h`](../../../../include/linux/dma-mapping.h)
 
-use crate::{bindings, device, device::RawDevice, error, to_result, Result};
+use crate::{
+    bindings, device, device::RawDevice, error, io_buffer::ReadableFromBytes, str::CStr, sync::Arc,
+    to_result, Result,
+};
+use alloc::vec::Vec;
+use core::{marker::PhantomData, ptr::NonNull};
+
+/// Returns a mask with the low `bits` bits set, corresponds to `DMA_BIT_MASK`.
+pub const fn bit_mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
 
 /// Set the DMA mask to inform the kernel about DMA addressing capabilities.
+///
+/// This only constrains streaming mappings such as [`MapSingle`], see [`set_mask_and_coherent`]
+/// for setting both masks.
 pub fn set_mask(dev: &dyn device::RawDevice, mask: u64) -> Result {
     to_result(unsafe { bindings::dma_set_mask(dev.raw_device(), mask) })
 }
 
 /// Set the DMA coherent mask to inform the kernel about DMA addressing capabilities.
+///
+/// This only constrains coherent allocations such as [`Allocation`].
 pub fn set_coherent_mask(dev: &dyn device::RawDevice, mask: u64) -> Result {
     to_result(unsafe { bindings::dma_set_coherent_mask(dev.raw_device(), mask) })
 }
 
+/// Sets both the streaming and the coherent DMA mask, corresponds to
+/// `dma_set_mask_and_coherent`.
+///
+/// Fails with `EIO` if the platform can't address memory through `mask`, in which case drivers
+/// usually retry with a smaller mask such as `bit_mask(32)`.
+pub fn set_mask_and_coherent(dev: &dyn device::RawDevice, mask: u64) -> Result {
+    // SAFETY: `dev.raw_device()` is guaranteed to be valid.
+    to_result(unsafe { bindings::dma_set_mask_and_coherent(dev.raw_device(), mask) })
+}
+
+/// Attributes of DMA-coherent allocations, see [`Allocation::try_new_attrs`].
+///
+/// The values can be combined with `|`.
+pub mod attrs {
+    use crate::bindings;
+
+    /// Map the memory write-combined on the CPU side, e.g. for buffers the CPU only fills.
+    pub const WRITE_COMBINE: usize = bindings::DMA_ATTR_WRITE_COMBINE as _;
+
+    /// Don't warn when the allocation fails, e.g. when a smaller fallback is tried next.
+    pub const NO_WARN: usize = bindings::DMA_ATTR_NO_WARN as _;
+
+    /// Require physically contiguous memory, even when an IOMMU could map scattered pages to a
+    /// contiguous DMA range, e.g. for devices that bypass the IOMMU for some accesses.
+    pub const FORCE_CONTIGUOUS: usize = bindings::DMA_ATTR_FORCE_CONTIGUOUS as _;
+}
+
 /// Information about allocated DMA-coherent memory.
 pub struct Allocation<T> {
     dev: device::Device,
     count: usize,
+    attrs: usize,
     /// DMA address
     pub dma_handle: bindings::dma_addr_t,
     /// processor memory
@@ -33,23 +80,82 @@ impl<T> Allocation<T> {
         count: usize,
         flag: bindings::gfp_t,
     ) -> Result<Allocation<T>> {
+        Self::try_new_attrs(dev, count, flag, 0)
+    }
+
+    /// Allocates DMA-coherent memory for `count` objects and fills it with zeroes.
+    ///
+    /// Useful for descriptor rings, where the device must not see stale data in fields the
+    /// driver doesn't set explicitly.
+    pub fn try_new_zeroed(
+        dev: &dyn device::RawDevice,
+        count: usize,
+        flag: bindings::gfp_t,
+    ) -> Result<Allocation<T>> {
+        let alloc = Self::try_new(dev, count, flag)?;
+        // SAFETY: `cpu_addr` points to `count` objects of type `T` that we just allocated and
+        // that the device doesn't know about yet.
+        unsafe { core::ptr::write_bytes(alloc.cpu_addr, 0, count) };
+        Ok(alloc)
+    }
+
+    /// Allocates DMA-coherent memory with the given [`attrs`], corresponds to `dma_alloc_attrs`.
+    ///
+    /// The memory is freed with the same attributes when the allocation is dropped.
+    pub fn try_new_attrs(
+        dev: &dyn device::RawDevice,
+        count: usize,
+        flag: bindings::gfp_t,
+        attrs: usize,
+    ) -> Result<Allocation<T>> {
+        let size = core::mem::size_of::<T>()
+            .checked_mul(count)
+            .ok_or(error::code::EINVAL)?;
         let mut dma_handle = 0;
-        let size = core::mem::size_of::<T>() * count;
         // SAFETY: dev.raw_device() is guaranteed to be valid.
-        let ptr =
-            unsafe { bindings::dma_alloc_coherent(dev.raw_device(), size, &mut dma_handle, flag) };
+        let ptr = unsafe {
+            bindings::dma_alloc_attrs(dev.raw_device(), size, &mut dma_handle, flag, attrs as _)
+        };
         if ptr.is_null() {
             Err(error::code::ENOMEM)
         } else {
             Ok(Allocation {
                 dev: device::Device::from_dev(dev),
                 count,
+                attrs,
                 dma_handle,
                 cpu_addr: ptr as _,
             })
         }
     }
 
+    /// Returns the number of objects in the allocation.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the allocated objects.
+    ///
+    /// The device may write to the memory at any time, so `T` must be valid for any bit pattern.
+    pub fn as_slice(&self) -> &[T]
+    where
+        T: ReadableFromBytes,
+    {
+        // SAFETY: `cpu_addr` points to `count` objects owned by the allocation, and `T` is valid
+        // for any bit pattern.
+        unsafe { core::slice::from_raw_parts(self.cpu_addr, self.count) }
+    }
+
+    /// Returns the allocated objects for modification.
+    pub fn as_mut_slice(&mut self) -> &mut [T]
+    where
+        T: ReadableFromBytes,
+    {
+        // SAFETY: As in `as_slice`, and we have exclusive access to the allocation.
+        unsafe { core::slice::from_raw_parts_mut(self.cpu_addr, self.count) }
+    }
+
     /// Performs a volatile read of the object by index.
     pub fn read_volatile(&self, index: usize) -> Option<T> {
         if index >= self.count {
@@ -80,18 +186,208 @@ impl<T> Allocation<T> {
 impl<T> Drop for Allocation<T> {
     fn drop(&mut self) {
         let size = core::mem::size_of::<T>() * self.count;
-        // SAFETY: Allocation holds a reference to the device so self.dev.raw_device() is valid.
+        // SAFETY: Allocation holds a reference to the device so self.dev.raw_device() is valid,
+        // and the memory was allocated with the same size and attributes.
         unsafe {
-            bindings::dma_free_coherent(
+            bindings::dma_free_attrs(
                 self.dev.raw_device(),
                 size,
                 self.cpu_addr as _,
                 self.dma_handle,
+                self.attrs as _,
             )
         }
     }
 }
 
+/// Returns the index following `idx` in a ring of `len` entries.
+pub const fn ring_next(idx: usize, len: usize) -> usize {
+    if idx + 1 == len {
+        0
+    } else {
+        idx + 1
+    }
+}
+
+/// Returns the number of entries between `next_to_clean` and `next_to_use` in a ring of `len`
+/// entries, i.e. the ones handed to the device and not yet reclaimed.
+pub const fn ring_used(next_to_clean: usize, next_to_use: usize, len: usize) -> usize {
+    (next_to_use + len - next_to_clean) % len
+}
+
+/// Returns the number of entries that may still be handed to the device in a ring of `len`
+/// entries.
+///
+/// One entry is always kept free so that a full ring can be told apart from an empty one.
+pub const fn ring_unused(next_to_clean: usize, next_to_use: usize, len: usize) -> usize {
+    len - 1 - ring_used(next_to_clean, next_to_use, len)
+}
+
+/// A ring of hardware descriptors in DMA-coherent memory, as used by most NICs.
+///
+/// The ring tracks two indices: `next_to_use` is where the driver places the next descriptor
+/// (usually mirrored into the device's tail register), `next_to_clean` is the oldest descriptor
+/// the driver has not reclaimed yet. Descriptors may be written by the device at any time, so
+/// `T` must be valid for any bit pattern.
+pub struct DescRing<T: ReadableFromBytes> {
+    alloc: Allocation<T>,
+    next_to_use: usize,
+    next_to_clean: usize,
+}
+
+// SAFETY: The ring exclusively owns its descriptors.
+unsafe impl<T: ReadableFromBytes + Send> Send for DescRing<T> {}
+
+impl<T: ReadableFromBytes> DescRing<T> {
+    /// Allocates a ring of `count` zeroed descriptors for `dev`, with both indices at zero.
+    pub fn try_new(
+        dev: &dyn device::RawDevice,
+        count: usize,
+        flag: bindings::gfp_t,
+    ) -> Result<Self> {
+        if count == 0 {
+            return Err(error::code::EINVAL);
+        }
+        Ok(Self {
+            alloc: Allocation::try_new_zeroed(dev, count, flag)?,
+            next_to_use: 0,
+            next_to_clean: 0,
+        })
+    }
+
+    /// Returns the number of descriptors in the ring.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.alloc.len()
+    }
+
+    /// Returns the DMA address of the first descriptor, to be programmed into the device.
+    pub fn dma_handle(&self) -> bindings::dma_addr_t {
+        self.alloc.dma_handle
+    }
+
+    /// Returns the descriptors.
+    pub fn as_slice(&self) -> &[T] {
+        self.alloc.as_slice()
+    }
+
+    /// Returns the descriptors for modification.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.alloc.as_mut_slice()
+    }
+
+    /// Returns the index following `idx`, wrapping around at the end of the ring.
+    pub fn next(&self, idx: usize) -> usize {
+        ring_next(idx, self.len())
+    }
+
+    /// Returns the index of the next descriptor to hand to the device.
+    pub fn next_to_use(&self) -> usize {
+        self.next_to_use
+    }
+
+    /// Returns the index of the oldest descriptor not reclaimed yet.
+    pub fn next_to_clean(&self) -> usize {
+        self.next_to_clean
+    }
+
+    /// Sets the index of the next descriptor to hand to the device, e.g. after a reset.
+    pub fn set_next_to_use(&mut self, idx: usize) {
+        self.next_to_use = idx % self.len();
+    }
+
+    /// Sets the index of the oldest descriptor not reclaimed yet.
+    pub fn set_next_to_clean(&mut self, idx: usize) {
+        self.next_to_clean = idx % self.len();
+    }
+
+    /// Advances `next_to_use` by one and returns its previous value.
+    pub fn advance_use(&mut self) -> usize {
+        let idx = self.next_to_use;
+        self.next_to_use = self.next(idx);
+        idx
+    }
+
+    /// Advances `next_to_clean` by one and returns its previous value.
+    pub fn advance_clean(&mut self) -> usize {
+        let idx = self.next_to_clean;
+        self.next_to_clean = self.next(idx);
+        idx
+    }
+
+    /// Returns the number of descriptors handed to the device and not reclaimed yet.
+    pub fn used(&self) -> usize {
+        ring_used(self.next_to_clean, self.next_to_use, self.len())
+    }
+
+    /// Returns the number of descriptors that may still be handed to the device.
+    pub fn unused(&self) -> usize {
+        ring_unused(self.next_to_clean, self.next_to_use, self.len())
+    }
+
+    /// Returns the indices from `next_to_clean` up to, but excluding, `next_to_use`.
+    ///
+    /// The iterator doesn't borrow the ring, so descriptors may be modified while iterating.
+    pub fn pending(&self) -> RingIndices {
+        RingIndices::new(self.next_to_clean, self.used(), self.len())
+    }
+}
+
+/// Iterator over `count` consecutive indices of a ring of `len` entries, starting at `start`
+/// and wrapping around at the end.
+#[derive(Clone, Debug)]
+pub struct RingIndices {
+    idx: usize,
+    remaining: usize,
+    len: usize,
+}
+
+impl RingIndices {
+    /// Creates an iterator over `count` indices starting at `start`.
+    pub fn new(start: usize, count: usize, len: usize) -> Self {
+        Self {
+            idx: start,
+            remaining: count,
+            len,
+        }
+    }
+}
+
+impl Iterator for RingIndices {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let idx = self.idx;
+        self.idx = ring_next(idx, self.len);
+        self.remaining -= 1;
+        Some(idx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for RingIndices {}
+
+/// Checks whether `dma_handle`, returned by a streaming mapping function of `dev`, denotes a
+/// failed mapping, corresponds to `dma_mapping_error`.
+///
+/// A failed mapping doesn't necessarily return 0 (e.g. `DMA_MAPPING_ERROR` is all ones), so the
+/// handle must be checked with this before it is handed to the device.
+pub fn mapping_error(dev: &dyn device::RawDevice, dma_handle: bindings::dma_addr_t) -> Result {
+    // SAFETY: `dev.raw_device()` is guaranteed to be valid.
+    let ret = unsafe { bindings::dma_mapping_error(dev.raw_device(), dma_handle) };
+    if ret != 0 {
+        Err(error::Error::from_kernel_errno(ret))
+    } else {
+        Ok(())
+    }
+}
+
 /// Information about mapped single processor memory.
 pub struct MapSingle<T> {
     dev: device::Device,
@@ -104,6 +400,10 @@ pub struct MapSingle<T> {
 
 impl<T> MapSingle<T> {
     /// Map single processor memory.
+    ///
+    /// Fails with the error reported by [`mapping_error`] if no valid DMA address could be
+    /// obtained, e.g. because the IOMMU or swiotlb space is exhausted. Nothing needs to be
+    /// unmapped in that case.
     pub fn try_new(
         dev: &dyn device::RawDevice,
         ptr: *mut T,
@@ -111,20 +411,43 @@ impl<T> MapSingle<T> {
         dir: bindings::dma_data_direction,
     ) -> Result<MapSingle<T>> {
         // SAFETY: dev.raw_device() is guaranteed to be valid.
+        let dma_handle =
+            unsafe { bindings::dma_map_single_attrs(dev.raw_device(), ptr as _, size, dir, 0) };
+        mapping_error(dev, dma_handle)?;
+        Ok(MapSingle {
+            dev: device::Device::from_dev(dev),
+            size,
+            dma_handle,
+            cpu_addr: ptr as _,
+            dir,
+        })
+    }
+
+    /// Hands the memory back to the CPU after the device wrote to it, corresponds to
+    /// `dma_sync_single_for_cpu`.
+    pub fn sync_for_cpu(&self) {
+        // SAFETY: `self.dev` is valid and `dma_handle` was mapped with `size` and `dir`.
+        unsafe {
+            bindings::dma_sync_single_for_cpu(
+                self.dev.raw_device(),
+                self.dma_handle,
+                self.size,
+                self.dir,
+            )
+        }
+    }
+
+    /// Hands the memory to the device after the CPU wrote to it, corresponds to
+    /// `dma_sync_single_for_device`.
+    pub fn sync_for_device(&self) {
+        // SAFETY: `self.dev` is valid and `dma_handle` was mapped with `size` and `dir`.
         unsafe {
-            let raw_dev = dev.raw_device();
-            let dma_handle = bindings::dma_map_single_attrs(raw_dev, ptr as _, size, dir, 0);
-            if bindings::dma_mapping_error(raw_dev, dma_handle) != 0 {
-                Err(error::code::ENOMEM)
-            } else {
-                Ok(MapSingle {
-                    dev: device::Device::from_dev(dev),
-                    size,
-                    dma_handle,
-                    cpu_addr: ptr as _,
-                    dir,
-                })
-            }
+            bindings::dma_sync_single_for_device(
+                self.dev.raw_device(),
+                self.dma_handle,
+                self.size,
+                self.dir,
+            )
         }
     }
 }
@@ -143,3 +466,497 @@ impl<T> Drop for MapSingle<T> {
         }
     }
 }
+
+/// A streaming DMA mapping of part of a page, e.g. an skb fragment or a page that isn't mapped
+/// into the kernel's address space.
+///
+/// The page is unmapped when this is dropped.
+pub struct MapPage {
+    dev: device::Device,
+    size: usize,
+    /// DMA address
+    pub dma_handle: bindings::dma_addr_t,
+    dir: bindings::dma_data_direction,
+}
+
+impl MapPage {
+    /// Maps `size` bytes starting at `offset` in `page`, corresponds to `dma_map_page`.
+    ///
+    /// # Safety
+    ///
+    /// `page` must be a valid page, the range must lie within it (or within the compound page it
+    /// heads), and the page must stay allocated until the returned [`MapPage`] is dropped.
+    pub unsafe fn try_new(
+        dev: &dyn device::RawDevice,
+        page: *mut bindings::page,
+        offset: usize,
+        size: usize,
+        dir: bindings::dma_data_direction,
+    ) -> Result<Self> {
+        // SAFETY: `dev.raw_device()` is guaranteed to be valid and the caller guarantees the
+        // validity of the page range.
+        let dma_handle = unsafe {
+            bindings::dma_map_page_attrs(dev.raw_device(), page, offset as _, size, dir, 0)
+        };
+        mapping_error(dev, dma_handle)?;
+        Ok(Self {
+            dev: device::Device::from_dev(dev),
+            size,
+            dma_handle,
+            dir,
+        })
+    }
+
+    /// Maps the data of a paged skb fragment, e.g. for scatter-gather transmission.
+    ///
+    /// # Safety
+    ///
+    /// The skb holding `frag` must be kept alive until the returned [`MapPage`] is dropped.
+    #[cfg(CONFIG_NET)]
+    pub unsafe fn try_from_frag(
+        dev: &dyn device::RawDevice,
+        frag: &crate::net::SkbFrag,
+        dir: bindings::dma_data_direction,
+    ) -> Result<Self> {
+        // SAFETY: The fragment lies within its page, which the skb holds a reference to as long
+        // as the caller keeps it alive.
+        unsafe {
+            Self::try_new(
+                dev,
+                frag.raw_page(),
+                frag.page_offset() as usize,
+                frag.len() as usize,
+                dir,
+            )
+        }
+    }
+
+    /// Returns the length of the mapping in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Hands the page back to the CPU after the device wrote to it, corresponds to
+    /// `dma_sync_single_for_cpu`.
+    pub fn sync_for_cpu(&self) {
+        // SAFETY: `self.dev` is valid and `dma_handle` was mapped with `size` and `dir`.
+        unsafe {
+            bindings::dma_sync_single_for_cpu(
+                self.dev.raw_device(),
+                self.dma_handle,
+                self.size,
+                self.dir,
+            )
+        }
+    }
+
+    /// Hands the page to the device after the CPU wrote to it, corresponds to
+    /// `dma_sync_single_for_device`.
+    pub fn sync_for_device(&self) {
+        // SAFETY: `self.dev` is valid and `dma_handle` was mapped with `size` and `dir`.
+        unsafe {
+            bindings::dma_sync_single_for_device(
+                self.dev.raw_device(),
+                self.dma_handle,
+                self.size,
+                self.dir,
+            )
+        }
+    }
+}
+
+impl Drop for MapPage {
+    fn drop(&mut self) {
+        // SAFETY: `MapPage` holds a reference to the device so `self.dev.raw_device()` is valid,
+        // and the page was mapped with the same size and direction.
+        unsafe {
+            bindings::dma_unmap_page_attrs(
+                self.dev.raw_device(),
+                self.dma_handle,
+                self.size,
+                self.dir,
+                0,
+            )
+        }
+    }
+}
+
+/// Maps all entries of `sgl` for DMA, see [`ScatterList::map_sg`].
+pub fn map_sg(
+    sgl: ScatterList,
+    dev: &dyn device::RawDevice,
+    dir: bindings::dma_data_direction,
+) -> Result<MapSg> {
+    sgl.map_sg(dev, dir)
+}
+
+/// A pool of fixed-size DMA-coherent blocks, each large enough to hold `count` objects of type
+/// `T`.
+///
+/// This is useful for drivers that need many small coherent buffers (e.g. per-packet bounce
+/// buffers or context descriptors) and would otherwise waste memory with [`Allocation`], which
+/// always hands out at least a page.
+///
+/// # Invariants
+///
+/// `ptr` is a valid pool returned by `dma_pool_create` for `dev`.
+pub struct Pool<T> {
+    // The pool keeps a pointer to the device and uses it when blocks are freed and when it is
+    // destroyed, so a reference is held for as long as the pool exists.
+    dev: device::Device,
+    ptr: NonNull<bindings::dma_pool>,
+    count: usize,
+    _p: PhantomData<T>,
+}
+
+// SAFETY: `dma_pool_alloc` and `dma_pool_free` can be called concurrently from any thread.
+unsafe impl<T> Send for Pool<T> {}
+
+// SAFETY: `dma_pool_alloc` and `dma_pool_free` can be called concurrently from any thread.
+unsafe impl<T> Sync for Pool<T> {}
+
+impl<T> Pool<T> {
+    /// Creates a new pool of blocks holding `count` objects of type `T`.
+    ///
+    /// `align` is the alignment of each block (zero means the natural alignment of `T`) and
+    /// `boundary`, if nonzero, is a power of two that returned blocks will not cross.
+    pub fn try_new(
+        name: &'static CStr,
+        dev: &dyn device::RawDevice,
+        count: usize,
+        align: usize,
+        boundary: usize,
+    ) -> Result<Arc<Self>> {
+        let size = core::mem::size_of::<T>()
+            .checked_mul(count)
+            .ok_or(error::code::EINVAL)?;
+        let align = if align == 0 {
+            core::mem::align_of::<T>()
+        } else {
+            align
+        };
+        // SAFETY: `dev.raw_device()` is guaranteed to be valid and `name` is a static
+        // NUL-terminated string.
+        let ptr = unsafe {
+            bindings::dma_pool_create(name.as_char_ptr(), dev.raw_device(), size, align, boundary)
+        };
+        let ptr = NonNull::new(ptr).ok_or(error::code::ENOMEM)?;
+        // INVARIANT: `ptr` was just returned by a successful call to `dma_pool_create`.
+        Arc::try_new(Self {
+            dev: device::Device::from_dev(dev),
+            ptr,
+            count,
+            _p: PhantomData,
+        })
+    }
+
+    /// Allocates a block from the pool.
+    ///
+    /// The returned [`PoolAllocation`] holds a reference to the pool, so the pool is only
+    /// destroyed once all blocks have been returned to it.
+    pub fn alloc(self: &Arc<Self>, flag: bindings::gfp_t) -> Result<PoolAllocation<T>> {
+        let mut dma_handle = 0;
+        // SAFETY: By the type invariants, `self.ptr` is a valid pool.
+        let ptr = unsafe { bindings::dma_pool_alloc(self.ptr.as_ptr(), flag, &mut dma_handle) };
+        if ptr.is_null() {
+            return Err(error::code::ENOMEM);
+        }
+        Ok(PoolAllocation {
+            pool: self.clone(),
+            dma_handle,
+            cpu_addr: ptr as _,
+        })
+    }
+}
+
+impl<T> Drop for Pool<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `self.ptr` is a valid pool, and `self.dev` keeps its
+        // device alive. All blocks hold a reference to the pool, so none are outstanding at this
+        // point.
+        unsafe { bindings::dma_pool_destroy(self.ptr.as_ptr()) };
+    }
+}
+
+/// A block allocated from a DMA [`Pool`].
+pub struct PoolAllocation<T> {
+    pool: Arc<Pool<T>>,
+    /// DMA address
+    pub dma_handle: bindings::dma_addr_t,
+    /// processor memory
+    pub cpu_addr: *mut T,
+}
+
+impl<T> PoolAllocation<T> {
+    /// Performs a volatile read of the object by index.
+    pub fn read_volatile(&self, index: usize) -> Option<T> {
+        if index >= self.pool.count {
+            return None;
+        }
+
+        let ptr = self.cpu_addr.wrapping_add(index);
+        // SAFETY: We just checked that the index is within bounds.
+        Some(unsafe { ptr.read_volatile() })
+    }
+
+    /// Performs a write of the object by index.
+    pub fn write(&self, index: usize, value: &T) -> Result
+    where
+        T: Copy,
+    {
+        if index >= self.pool.count {
+            return Err(error::code::EINVAL);
+        }
+
+        let ptr = self.cpu_addr.wrapping_add(index);
+        // SAFETY: We just checked that the index is within bounds.
+        unsafe { ptr.write(*value) };
+        Ok(())
+    }
+}
+
+impl<T> Drop for PoolAllocation<T> {
+    fn drop(&mut self) {
+        // SAFETY: The block was allocated from `self.pool`, which is still alive because we hold
+        // a reference to it.
+        unsafe {
+            bindings::dma_pool_free(self.pool.ptr.as_ptr(), self.cpu_addr as _, self.dma_handle)
+        }
+    }
+}
+
+/// A scatter/gather list, wraps an array of `struct scatterlist`.
+///
+/// Entries are filled with [`ScatterList::set_buf`] or [`ScatterList::try_from_skb`] and then
+/// mapped as a whole with [`ScatterList::map_sg`].
+pub struct ScatterList {
+    entries: Vec<bindings::scatterlist>,
+}
+
+impl ScatterList {
+    /// Creates a new scatter/gather list with `nents` empty entries.
+    pub fn try_new(nents: usize) -> Result<Self> {
+        if nents == 0 {
+            return Err(error::code::EINVAL);
+        }
+        let mut entries = Vec::try_with_capacity(nents)?;
+        // SAFETY: An all-zero `struct scatterlist` is valid, `sg_init_table` is called below.
+        entries.try_resize(nents, unsafe { core::mem::zeroed() })?;
+        // SAFETY: `entries` holds `nents` elements.
+        unsafe { bindings::sg_init_table(entries.as_mut_ptr(), nents as _) };
+        Ok(Self { entries })
+    }
+
+    /// Creates a scatter/gather list describing the whole data of `skb`, its linear part and
+    /// all of its paged fragments, corresponds to `skb_to_sgvec`.
+    #[cfg(CONFIG_NET)]
+    pub fn try_from_skb(skb: &crate::net::SkBuff) -> Result<Self> {
+        let mut sgl = Self::try_new(skb.nr_frags() as usize + 1)?;
+        // SAFETY: `skb` is valid by the shared reference and `sgl` has room for the linear part
+        // plus one entry per fragment.
+        let nents = unsafe {
+            bindings::skb_to_sgvec(skb.raw_skb(), sgl.entries.as_mut_ptr(), 0, skb.len() as _)
+        };
+        to_result(nents)?;
+        sgl.entries.truncate(nents as usize);
+        Ok(sgl)
+    }
+
+    /// Returns the number of entries in the list.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Points entry `index` at `buf`.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must be directly mapped kernel memory (not vmalloc or stack memory) and must remain
+    /// valid until the list is no longer used (including while mapped).
+    pub unsafe fn set_buf(&mut self, index: usize, buf: &[u8]) -> Result {
+        let sg = self.entries.get_mut(index).ok_or(error::code::EINVAL)?;
+        // SAFETY: `sg` is a valid entry and the caller guarantees the validity of `buf`.
+        unsafe { bindings::sg_set_buf(sg, buf.as_ptr() as _, buf.len() as _) };
+        Ok(())
+    }
+
+    /// Maps the whole list for DMA, corresponds to `dma_map_sg`.
+    ///
+    /// The entries are unmapped when the returned [`MapSg`] is dropped.
+    pub fn map_sg(
+        self,
+        dev: &dyn device::RawDevice,
+        dir: bindings::dma_data_direction,
+    ) -> Result<MapSg> {
+        let mut sgl = self;
+        // SAFETY: `dev.raw_device()` is guaranteed to be valid and `sgl.entries` is an initialised
+        // scatter/gather table.
+        let mapped = unsafe {
+            bindings::dma_map_sg_attrs(
+                dev.raw_device(),
+                sgl.entries.as_mut_ptr(),
+                sgl.entries.len() as _,
+                dir,
+                0,
+            )
+        };
+        if mapped == 0 {
+            return Err(error::code::ENOMEM);
+        }
+        Ok(MapSg {
+            dev: device::Device::from_dev(dev),
+            sgl,
+            mapped: mapped as usize,
+            dir,
+        })
+    }
+}
+
+/// A [`ScatterList`] mapped for DMA.
+///
+/// The IOMMU may merge entries, so the number of mapped segments returned by
+/// [`MapSg::segments`] may be smaller than the number of entries in the list.
+pub struct MapSg {
+    dev: device::Device,
+    sgl: ScatterList,
+    mapped: usize,
+    dir: bindings::dma_data_direction,
+}
+
+impl MapSg {
+    /// Returns the number of mapped segments.
+    pub fn mapped(&self) -> usize {
+        self.mapped
+    }
+
+    /// Returns an iterator over the DMA address and length of every mapped segment.
+    pub fn segments(&self) -> impl Iterator<Item = (bindings::dma_addr_t, u32)> + '_ {
+        self.sgl.entries[..self.mapped].iter().map(|sg| {
+            #[cfg(CONFIG_NEED_SG_DMA_LENGTH)]
+            let len = sg.dma_length;
+            #[cfg(not(CONFIG_NEED_SG_DMA_LENGTH))]
+            let len = sg.length;
+            (sg.dma_address, len)
+        })
+    }
+
+    /// Hands the buffers back to the CPU after the device wrote to them, corresponds to
+    /// `dma_sync_sg_for_cpu`.
+    pub fn sync_for_cpu(&mut self) {
+        // SAFETY: `self.dev` is valid and the list was mapped with the same number of entries
+        // and direction.
+        unsafe {
+            bindings::dma_sync_sg_for_cpu(
+                self.dev.raw_device(),
+                self.sgl.entries.as_mut_ptr(),
+                self.sgl.entries.len() as _,
+                self.dir,
+            )
+        }
+    }
+
+    /// Hands the buffers to the device after the CPU wrote to them, corresponds to
+    /// `dma_sync_sg_for_device`.
+    pub fn sync_for_device(&mut self) {
+        // SAFETY: `self.dev` is valid and the list was mapped with the same number of entries
+        // and direction.
+        unsafe {
+            bindings::dma_sync_sg_for_device(
+                self.dev.raw_device(),
+                self.sgl.entries.as_mut_ptr(),
+                self.sgl.entries.len() as _,
+                self.dir,
+            )
+        }
+    }
+}
+
+impl Drop for MapSg {
+    fn drop(&mut self) {
+        // SAFETY: MapSg holds a reference to the device so self.dev.raw_device() is valid, and
+        // the list was mapped with the same number of entries and direction.
+        unsafe {
+            bindings::dma_unmap_sg_attrs(
+                self.dev.raw_device(),
+                self.sgl.entries.as_mut_ptr(),
+                self.sgl.entries.len() as _,
+                self.dir,
+                0,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_next() {
+        assert_eq!(ring_next(0, 4), 1);
+        assert_eq!(ring_next(3, 4), 0);
+        assert_eq!(ring_next(0, 1), 0);
+    }
+
+    #[test]
+    fn test_ring_occupancy() {
+        // Empty ring.
+        assert_eq!(ring_used(2, 2, 8), 0);
+        assert_eq!(ring_unused(2, 2, 8), 7);
+        // Wrapped around.
+        assert_eq!(ring_used(6, 1, 8), 3);
+        assert_eq!(ring_unused(6, 1, 8), 4);
+        // Full ring, one slot is kept free.
+        assert_eq!(ring_used(3, 2, 8), 7);
+        assert_eq!(ring_unused(3, 2, 8), 0);
+    }
+
+    #[test]
+    fn test_ring_fill_and_drain_wraps() {
+        let len = 4;
+        let (mut ntc, mut ntu) = (2, 2);
+
+        // Fill the ring; the producer wraps past the end.
+        while ring_unused(ntc, ntu, len) > 0 {
+            ntu = ring_next(ntu, len);
+        }
+        assert_eq!(ntu, 1);
+        assert_eq!(ring_used(ntc, ntu, len), len - 1);
+
+        // Drain it again; the consumer follows across the wrap and catches up.
+        let pending = RingIndices::new(ntc, ring_used(ntc, ntu, len), len);
+        assert!(pending.clone().eq([2, 3, 0]));
+        for idx in pending {
+            assert_eq!(idx, ntc);
+            ntc = ring_next(ntc, len);
+        }
+        assert_eq!(ntc, ntu);
+        assert_eq!(ring_used(ntc, ntu, len), 0);
+        assert_eq!(ring_unused(ntc, ntu, len), len - 1);
+    }
+
+    #[test]
+    fn test_ring_rx_tail() {
+        // RX rings are handed over with the tail on the last descriptor, so every descriptor but
+        // one belongs to the device and cleaning starts right after the tail.
+        let len = 8;
+        let tail = len - 1;
+        assert_eq!(ring_next(tail, len), 0);
+        assert_eq!(ring_used(0, tail, len), len - 1);
+        assert_eq!(ring_unused(0, tail, len), 0);
+    }
+
+    #[test]
+    fn test_ring_indices() {
+        let mut it = RingIndices::new(6, 4, 8);
+        assert_eq!(it.len(), 4);
+        assert_eq!(it.next(), Some(6));
+        assert_eq!(it.next(), Some(7));
+        assert_eq!(it.next(), Some(0));
+        assert_eq!(it.next(), Some(1));
+        assert_eq!(it.next(), None);
+        assert_eq!(RingIndices::new(0, 0, 8).next(), None);
+    }
+}
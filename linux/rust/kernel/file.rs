@@ -10,9 +10,11 @@ use crate::{
     cred::Credential,
     error::{code::*, from_kernel_result, Error, Result},
     io_buffer::{IoBufferReader, IoBufferWriter},
+    ioctl,
     iov_iter::IovIter,
     mm,
     sync::CondVar,
+    task::Signal,
     types::PointerWrapper,
     user_ptr::{UserSlicePtr, UserSlicePtrReader, UserSlicePtrWriter},
     ARef, AlwaysRefCounted,
@@ -274,6 +276,59 @@ impl PollTable {
     }
 }
 
+/// The files that asked to be notified with a signal when something happens, e.g. with
+/// `fcntl(F_SETFL, O_ASYNC)`. Wraps a `struct fasync_struct` list.
+///
+/// Files are added and removed from [`Operations::fasync`] with [`FasyncQueue::helper`]. Since the
+/// list keeps pointers to the files, implementations must also remove the file on
+/// [`Operations::release`], by calling [`FasyncQueue::helper`] with `fd` set to -1 and `on` set to
+/// `false`.
+pub struct FasyncQueue {
+    head: UnsafeCell<*mut bindings::fasync_struct>,
+}
+
+// SAFETY: The list is only modified by `fasync_helper`, which takes `fasync_lock`, and only read
+// by `kill_fasync` under RCU.
+unsafe impl Sync for FasyncQueue {}
+
+// SAFETY: The list holds no thread-local state.
+unsafe impl Send for FasyncQueue {}
+
+impl FasyncQueue {
+    /// Creates a new, empty queue.
+    pub const fn new() -> Self {
+        Self {
+            head: UnsafeCell::new(ptr::null_mut()),
+        }
+    }
+
+    /// Adds `file` to the queue if `on` is `true`, or removes it otherwise, corresponds to
+    /// `fasync_helper`.
+    pub fn helper(&self, fd: i32, file: &File, on: bool) -> Result {
+        // SAFETY: `file` is valid by the shared reference and `head` is a valid list head.
+        let ret = unsafe { bindings::fasync_helper(fd, file.0.get(), on as _, self.head.get()) };
+        if ret < 0 {
+            return Err(Error::from_kernel_errno(ret));
+        }
+        Ok(())
+    }
+
+    /// Sends `sig` to the owners of all files in the queue, corresponds to `kill_fasync`.
+    ///
+    /// `sig` is usually [`Signal::Io`]. `band` is one of the `POLL_*` values (e.g. `POLL_IN` when
+    /// new data is available), which the receiver sees in `si_band`.
+    pub fn kill(&self, sig: Signal, band: i32) {
+        // SAFETY: `head` is a valid list head, `kill_fasync` walks it under RCU.
+        unsafe { bindings::kill_fasync(self.head.get(), sig.as_raw(), band) };
+    }
+}
+
+impl Default for FasyncQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Equivalent to [`std::io::SeekFrom`].
 ///
 /// [`std::io::SeekFrom`]: https://doc.rust-lang.org/std/io/enum.SeekFrom.html
@@ -459,8 +514,13 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
-            let off = T::seek(f, unsafe { File::from_ptr(file) }, off)?;
-            Ok(off as bindings::loff_t)
+            let off: bindings::loff_t = T::seek(f, unsafe { File::from_ptr(file) }, off)?
+                .try_into()
+                .map_err(|_| EINVAL)?;
+            // Like `generic_file_llseek`, store the new position so that subsequent reads and
+            // writes start there. The VFS serialises this with other users of `f_pos`.
+            unsafe { (*file).f_pos = off };
+            Ok(off)
         }
     }
 
@@ -523,6 +583,22 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
         }
     }
 
+    unsafe extern "C" fn flush_callback(
+        file: *mut bindings::file,
+        _id: bindings::fl_owner_t,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_pointer`. `T::Data::from_pointer` is only called by the
+            // `release` callback, which the C API guarantees that will be called only when all
+            // references to `file` have been released, so we know it can't be called while this
+            // function is running.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            T::flush(f, unsafe { File::from_ptr(file) })?;
+            Ok(0)
+        }
+    }
+
     unsafe extern "C" fn fsync_callback(
         file: *mut bindings::file,
         start: bindings::loff_t,
@@ -561,6 +637,23 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
         }
     }
 
+    unsafe extern "C" fn fasync_callback(
+        fd: core::ffi::c_int,
+        file: *mut bindings::file,
+        on: core::ffi::c_int,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_pointer`. `T::Data::from_pointer` is only called by the
+            // `release` callback, which the C API guarantees that will be called only when all
+            // references to `file` have been released, so we know it can't be called while this
+            // function is running.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            T::fasync(f, unsafe { File::from_ptr(file) }, fd, on != 0)?;
+            Ok(0)
+        }
+    }
+
     const VTABLE: bindings::file_operations = bindings::file_operations {
         open: Some(Self::open_callback),
         release: Some(Self::release_callback),
@@ -589,9 +682,17 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
         copy_file_range: None,
         fallocate: None,
         fadvise: None,
-        fasync: None,
+        fasync: if T::HAS_FASYNC {
+            Some(Self::fasync_callback)
+        } else {
+            None
+        },
         flock: None,
-        flush: None,
+        flush: if T::HAS_FLUSH {
+            Some(Self::flush_callback)
+        } else {
+            None
+        },
         fsync: if T::HAS_FSYNC {
             Some(Self::fsync_callback)
         } else {
@@ -708,7 +809,7 @@ pub struct IoctlCommand {
 impl IoctlCommand {
     /// Constructs a new [`IoctlCommand`].
     fn new(cmd: u32, arg: usize) -> Self {
-        let size = (cmd >> bindings::_IOC_SIZESHIFT) & bindings::_IOC_SIZEMASK;
+        let size = ioctl::_IOC_SIZE(cmd);
 
         // SAFETY: We only create one instance of the user slice per ioctl call, so TOCTOU issues
         // are not possible.
@@ -731,7 +832,7 @@ impl IoctlCommand {
         handler: T::Target<'_>,
         file: &File,
     ) -> Result<i32> {
-        let dir = (self.cmd >> bindings::_IOC_DIRSHIFT) & bindings::_IOC_DIRMASK;
+        let dir = ioctl::_IOC_DIR(self.cmd);
         if dir == bindings::_IOC_NONE {
             return T::pure(handler, file, self.cmd, self.arg);
         }
@@ -824,6 +925,9 @@ pub trait Operations {
 
     /// Changes the position of the file.
     ///
+    /// Returns the new position, which is stored in the file and passed as the offset to
+    /// subsequent reads and writes. Positions beyond `i64::MAX` are rejected with `EINVAL`.
+    ///
     /// Corresponds to the `llseek` function pointer in `struct file_operations`.
     fn seek(
         _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
@@ -855,6 +959,16 @@ pub trait Operations {
         Err(ENOTTY)
     }
 
+    /// Called whenever a file descriptor referring to this file is closed, before the last one
+    /// leads to [`Operations::release`].
+    ///
+    /// Errors are returned by `close`, although the descriptor is closed regardless.
+    ///
+    /// Corresponds to the `flush` function pointer in `struct file_operations`.
+    fn flush(_data: <Self::Data as PointerWrapper>::Borrowed<'_>, _file: &File) -> Result {
+        Ok(())
+    }
+
     /// Syncs pending changes to this file.
     ///
     /// Corresponds to the `fsync` function pointer in `struct file_operations`.
@@ -890,4 +1004,17 @@ pub trait Operations {
     ) -> Result<u32> {
         Ok(bindings::POLLIN | bindings::POLLOUT | bindings::POLLRDNORM | bindings::POLLWRNORM)
     }
+
+    /// Enables (`on` is `true`) or disables asynchronous notification of the file's owner,
+    /// usually by calling [`FasyncQueue::helper`].
+    ///
+    /// Corresponds to the `fasync` function pointer in `struct file_operations`.
+    fn fasync(
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _file: &File,
+        _fd: i32,
+        _on: bool,
+    ) -> Result {
+        Err(EINVAL)
+    }
 }
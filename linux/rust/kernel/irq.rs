@@ -19,6 +19,8 @@ use crate::{
 use core::{fmt, marker::PhantomData, ops::Deref};
 use macros::vtable;
 
+pub use crate::cpumask::CpuMask;
+
 /// The type of irq hardware numbers.
 pub type HwNumber = bindings::irq_hw_number_t;
 
@@ -352,10 +354,27 @@ impl<T: PointerWrapper> InternalRegistration<T> {
             _p: PhantomData,
         })
     }
+
+    fn set_affinity_hint(&self, mask: Option<&'static CpuMask>) -> Result {
+        let mask = mask.map_or(core::ptr::null(), CpuMask::as_ptr);
+        // SAFETY: The irq was successfully requested and `mask` is either null or static.
+        to_result(unsafe { bindings::irq_set_affinity_hint(self.irq, mask) })
+    }
+
+    fn set_affinity(&self, mask: &CpuMask) -> Result {
+        // SAFETY: The irq was successfully requested and `mask` is valid for the duration of the
+        // call, its contents are copied.
+        to_result(unsafe { bindings::irq_set_affinity(self.irq, mask.as_ptr()) })
+    }
 }
 
 impl<T: PointerWrapper> Drop for InternalRegistration<T> {
     fn drop(&mut self) {
+        // Clear the affinity hint, `free_irq` warns if one is still set.
+        //
+        // SAFETY: The irq was successfully requested in `try_new`.
+        unsafe { bindings::irq_set_affinity_hint(self.irq, core::ptr::null()) };
+
         // Unregister irq handler.
         //
         // SAFETY: When `try_new` succeeds, the irq was successfully requested, so it is ok to free
@@ -420,6 +439,19 @@ impl<H: Handler> Registration<H> {
         }))
     }
 
+    /// Sets the affinity hint shown to user space (e.g. to `irqbalance`) in
+    /// `/proc/irq/<irq>/affinity_hint`, and applies it as the current affinity.
+    ///
+    /// `None` clears the hint.
+    pub fn set_affinity_hint(&self, mask: Option<&'static CpuMask>) -> Result {
+        self.0.set_affinity_hint(mask)
+    }
+
+    /// Moves the interrupt to the CPUs in `mask`.
+    pub fn set_affinity(&self, mask: &CpuMask) -> Result {
+        self.0.set_affinity(mask)
+    }
+
     unsafe extern "C" fn handler(
         _irq: core::ffi::c_int,
         raw_data: *mut core::ffi::c_void,
@@ -498,6 +530,16 @@ impl<H: ThreadedHandler> ThreadedRegistration<H> {
         }))
     }
 
+    /// Sets the affinity hint of the interrupt, see [`Registration::set_affinity_hint`].
+    pub fn set_affinity_hint(&self, mask: Option<&'static CpuMask>) -> Result {
+        self.0.set_affinity_hint(mask)
+    }
+
+    /// Moves the interrupt to the CPUs in `mask`.
+    pub fn set_affinity(&self, mask: &CpuMask) -> Result {
+        self.0.set_affinity(mask)
+    }
+
     unsafe extern "C" fn primary_handler(
         _irq: core::ffi::c_int,
         raw_data: *mut core::ffi::c_void,
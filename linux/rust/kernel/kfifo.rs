@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Byte FIFOs.
+//!
+//! C header: [`include/linux/kfifo.h`](../../../../include/linux/kfifo.h)
+
+use crate::{
+    bindings,
+    error::{code::*, to_result},
+    io_buffer::{IoBufferReader, IoBufferWriter},
+    Result,
+};
+use core::cmp::min;
+
+/// A byte FIFO backed by a `struct kfifo`.
+///
+/// Bytes are written at the producer index (`in`) and read at the consumer index (`out`). Both
+/// indices only ever grow and wrap around at `u32::MAX`; their difference is the number of bytes
+/// in the FIFO, and each is masked with the size to find its position in the buffer.
+///
+/// Unlike the C API, which allows one lockless producer and consumer, all modifications take
+/// `&mut self`, so a FIFO shared between threads has to be behind a lock.
+///
+/// # Invariants
+///
+/// `fifo` was set up by `__kfifo_alloc` with an element size of 1, so its size is a power of two
+/// and `data` points to that many bytes. `in - out` never exceeds the size.
+pub struct KFifo {
+    fifo: bindings::__kfifo,
+}
+
+// SAFETY: The FIFO owns its buffer, which can be used and freed from any thread.
+unsafe impl Send for KFifo {}
+
+// SAFETY: Methods taking `&self` only read the indices.
+unsafe impl Sync for KFifo {}
+
+impl KFifo {
+    /// Allocates a FIFO that can hold `size` bytes, rounded up to a power of two.
+    pub fn try_new(size: usize) -> Result<Self> {
+        if size < 2 || size > u32::MAX as usize / 2 {
+            return Err(EINVAL);
+        }
+        // SAFETY: An all-zero `struct __kfifo` is a valid, empty FIFO without a buffer.
+        let mut fifo: bindings::__kfifo = unsafe { core::mem::zeroed() };
+        // SAFETY: `fifo` is valid for writes, the buffer is freed in `drop`.
+        to_result(unsafe {
+            bindings::__kfifo_alloc(&mut fifo, size as _, 1, bindings::GFP_KERNEL)
+        })?;
+        // INVARIANT: The FIFO was just allocated with an element size of 1 and is empty.
+        Ok(Self { fifo })
+    }
+
+    /// Returns the number of bytes the FIFO can hold, corresponds to `kfifo_size`.
+    pub fn size(&self) -> usize {
+        self.fifo.mask as usize + 1
+    }
+
+    /// Returns the number of bytes in the FIFO, corresponds to `kfifo_len`.
+    pub fn len(&self) -> usize {
+        self.fifo.in_.wrapping_sub(self.fifo.out) as usize
+    }
+
+    /// Returns `true` if the FIFO holds no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if no more bytes fit in the FIFO.
+    pub fn is_full(&self) -> bool {
+        self.len() == self.size()
+    }
+
+    /// Returns the number of bytes that can still be written, corresponds to `kfifo_avail`.
+    pub fn avail(&self) -> usize {
+        self.size() - self.len()
+    }
+
+    /// Returns the producer index, which counts the bytes ever written.
+    pub fn producer_index(&self) -> u32 {
+        self.fifo.in_
+    }
+
+    /// Returns the consumer index, which counts the bytes ever read or skipped.
+    pub fn consumer_index(&self) -> u32 {
+        self.fifo.out
+    }
+
+    /// Discards all bytes and sets both indices back to 0, corresponds to `kfifo_reset`.
+    pub fn reset(&mut self) {
+        self.fifo.in_ = 0;
+        self.fifo.out = 0;
+    }
+
+    /// Discards up to `count` of the oldest bytes and returns how many were discarded.
+    pub fn skip(&mut self, count: usize) -> usize {
+        let count = min(count, self.len());
+        self.fifo.out = self.fifo.out.wrapping_add(count as u32);
+        count
+    }
+
+    /// Appends as much of `data` as fits and returns the number of bytes written, corresponds to
+    /// `kfifo_in`.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        // SAFETY: By the type invariants the FIFO is set up, and `data` is valid for reads of
+        // `data.len()` bytes. `__kfifo_in` copies at most the available space.
+        unsafe { bindings::__kfifo_in(&mut self.fifo, data.as_ptr().cast(), data.len() as _) as _ }
+    }
+
+    /// Removes up to `buf.len()` of the oldest bytes into `buf` and returns the number of bytes
+    /// read, corresponds to `kfifo_out`.
+    pub fn pop(&mut self, buf: &mut [u8]) -> usize {
+        // SAFETY: By the type invariants the FIFO is set up, and `buf` is valid for writes of
+        // `buf.len()` bytes.
+        unsafe {
+            bindings::__kfifo_out(&mut self.fifo, buf.as_mut_ptr().cast(), buf.len() as _) as _
+        }
+    }
+
+    /// Appends up to `len` bytes read from `reader`, as many as fit, and returns the number of
+    /// bytes written.
+    ///
+    /// Nothing is added to the FIFO if reading from `reader` fails.
+    pub fn push_from(&mut self, reader: &mut impl IoBufferReader, len: usize) -> Result<usize> {
+        let count = min(len, self.avail());
+        let (first, second) = self.split(self.fifo.in_, count);
+        // SAFETY: `split` returns two ranges that lie inside the buffer.
+        unsafe {
+            reader.read_raw(first.0, first.1)?;
+            reader.read_raw(second.0, second.1)?;
+        }
+        self.fifo.in_ = self.fifo.in_.wrapping_add(count as u32);
+        Ok(count)
+    }
+
+    /// Removes up to `len` of the oldest bytes into `writer` and returns the number of bytes
+    /// read.
+    ///
+    /// Nothing is removed from the FIFO if writing to `writer` fails.
+    pub fn pop_into(&mut self, writer: &mut impl IoBufferWriter, len: usize) -> Result<usize> {
+        let count = min(len, self.len());
+        let (first, second) = self.split(self.fifo.out, count);
+        // SAFETY: `split` returns two ranges that lie inside the buffer.
+        unsafe {
+            writer.write_raw(first.0, first.1)?;
+            writer.write_raw(second.0, second.1)?;
+        }
+        self.fifo.out = self.fifo.out.wrapping_add(count as u32);
+        Ok(count)
+    }
+
+    /// Splits the `count` bytes starting at index `index` into the part up to the end of the
+    /// buffer and the part that wraps around to its start.
+    fn split(&self, index: u32, count: usize) -> ((*mut u8, usize), (*mut u8, usize)) {
+        let data = self.fifo.data as *mut u8;
+        let offset = (index & self.fifo.mask) as usize;
+        let first = min(count, self.size() - offset);
+        // SAFETY: By the type invariants `data` points to `size()` bytes and `offset` is masked.
+        ((unsafe { data.add(offset) }, first), (data, count - first))
+    }
+}
+
+impl Drop for KFifo {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants the buffer was allocated by `__kfifo_alloc`.
+        unsafe { bindings::__kfifo_free(&mut self.fifo) };
+    }
+}
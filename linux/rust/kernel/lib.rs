@@ -48,7 +48,10 @@ pub mod amba;
 pub mod chrdev;
 #[cfg(CONFIG_COMMON_CLK)]
 pub mod clk;
+pub mod cpumask;
 pub mod cred;
+#[cfg(CONFIG_DEBUG_FS)]
+pub mod debugfs;
 pub mod delay;
 pub mod device;
 pub mod dma;
@@ -59,18 +62,24 @@ pub mod file;
 pub mod fs;
 pub mod gpio;
 pub mod hwrng;
+pub mod ioctl;
 pub mod irq;
 pub mod kasync;
+pub mod kfifo;
 pub mod miscdev;
 pub mod mm;
 #[cfg(CONFIG_NET)]
 pub mod net;
 pub mod pages;
+pub mod percpu;
 #[cfg(CONFIG_PCI)]
 pub mod pci;
 pub mod power;
+#[cfg(CONFIG_PROC_FS)]
+pub mod proc_fs;
 pub mod revocable;
 pub mod security;
+pub mod seq_file;
 pub mod str;
 pub mod task;
 pub mod workqueue;
@@ -191,6 +191,18 @@ impl<T: file::Operations> Registration<T> {
 
         Ok(())
     }
+
+    /// Returns the minor number of the device, or `None` if it isn't registered.
+    ///
+    /// When no minor was requested through [`Options::minor`], this is the one `misc_register`
+    /// picked dynamically.
+    pub fn minor(&self) -> Option<i32> {
+        if self.registered {
+            Some(self.mdev.minor)
+        } else {
+            None
+        }
+    }
 }
 
 impl<T: file::Operations> Default for Registration<T> {
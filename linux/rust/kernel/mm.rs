@@ -61,6 +61,12 @@ pub mod virt {
             unsafe { (*self.vma).vm_end as _ }
         }
 
+        /// Returns the offset of the area within the mapped file, in pages.
+        pub fn pgoff(&self) -> usize {
+            // SAFETY: `self.vma` is valid by the type invariants.
+            unsafe { (*self.vma).vm_pgoff as _ }
+        }
+
         /// Maps a single page at the given address within the virtual memory area.
         pub fn insert_page(&mut self, address: usize, page: &pages::Pages<0>) -> Result {
             // SAFETY: The page is guaranteed to be order 0 by the type system. The range of
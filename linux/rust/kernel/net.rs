@@ -8,23 +8,43 @@
 
 use crate::{
     bindings, device,
-    error::{code::ENOMEM, from_kernel_result},
+    percpu::PerCpu,
+    error::{
+        code::{EBUSY, EINVAL, ENOMEM, EOPNOTSUPP},
+        from_kernel_result,
+    },
     str::CStr,
     sync::UniqueArc,
     to_result,
     types::PointerWrapper,
     ARef, AlwaysRefCounted, Error, Result,
 };
+use alloc::vec::Vec;
 use core::{
     cell::UnsafeCell,
+    fmt,
     marker::PhantomData,
     pin::Pin,
     ptr::{addr_of, addr_of_mut, NonNull},
 };
 use macros::vtable;
 
+#[cfg(CONFIG_PRINTK)]
+use crate::c_str;
+
 #[cfg(CONFIG_NETFILTER)]
 pub mod filter;
+pub mod genetlink;
+pub mod mii;
+mod notifier;
+mod page_pool;
+#[cfg(CONFIG_PHYLIB)]
+pub mod phy;
+mod xdp;
+
+pub use notifier::{NetdevEvent, NetdevNotifier, NetdevNotify};
+pub use page_pool::{PagePool, PoolPage};
+pub use xdp::{BpfProg, NetdevBpf, XdpAction, XdpBuff, XdpFrame, XdpFrames, XdpRxqInfo};
 
 /// Wraps the kernel's `struct net_device`.
 #[repr(transparent)]
@@ -59,6 +79,37 @@ impl Device {
         self.0.get()
     }
 
+    /// Returns the interface name of the device, e.g. `eth0`.
+    ///
+    /// Before the device is registered this is still the name pattern, e.g. `eth%d`.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount,
+        // and `name` is always NUL-terminated.
+        unsafe { CStr::from_char_ptr(addr_of!((*self.0.get()).name).cast()) }
+    }
+
+    /// Returns the interface index of the device, which is 0 until the device is registered.
+    pub fn ifindex(&self) -> i32 {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { addr_of!((*self.0.get()).ifindex).read() }
+    }
+
+    /// Marks the device as removed from the system and stops its TX queues, corresponds to
+    /// `netif_device_detach`.
+    ///
+    /// Afterwards the stack no longer calls [`DeviceOperations::start_xmit`].
+    pub fn netif_device_detach(&self) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_device_detach(self.0.get()) }
+    }
+
+    /// Marks the device as present again and wakes its TX queues if it is running, corresponds
+    /// to `netif_device_attach`.
+    pub fn netif_device_attach(&self) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_device_attach(self.0.get()) }
+    }
+
     /// Sets carrier.
     pub fn netif_carrier_on(&self) {
         // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
@@ -71,6 +122,12 @@ impl Device {
         unsafe { bindings::netif_carrier_off(self.0.get()) }
     }
 
+    /// Tests if carrier is present.
+    pub fn netif_carrier_ok(&self) -> bool {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_carrier_ok(self.0.get()) }
+    }
+
     /// Assigns Ethernet address to a net_device.
     pub fn eth_hw_addr_set(&self, addr: &[u8; 6]) {
         // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
@@ -95,6 +152,25 @@ impl Device {
         unsafe { addr_of_mut!((*self.0.get()).min_mtu).write(min_mtu) };
     }
 
+    /// Sets the time after which the core reports a stuck TX queue through `ndo_tx_timeout`.
+    ///
+    /// The core's TX watchdog only runs for drivers that provide `ndo_tx_timeout`. Should be set
+    /// before the device is registered.
+    pub fn watchdog_timeo_set(&self, timeout: core::time::Duration) {
+        let millis = timeout.as_millis().try_into().unwrap_or(core::ffi::c_uint::MAX);
+        // SAFETY: `__msecs_to_jiffies` has no safety requirements.
+        let jiffies = unsafe { bindings::__msecs_to_jiffies(millis) };
+        let jiffies = jiffies.try_into().unwrap_or(i32::MAX);
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { addr_of_mut!((*self.0.get()).watchdog_timeo).write(jiffies) };
+    }
+
+    /// Sets the length of the queueing discipline's TX queue, in packets.
+    pub fn tx_queue_len_set(&self, len: u32) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { addr_of_mut!((*self.0.get()).tx_queue_len).write(len) };
+    }
+
     ///　Returns the flags of the device.
     pub fn flags_get(&self) -> u32 {
         // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
@@ -113,12 +189,60 @@ impl Device {
         unsafe { addr_of_mut!((*self.0.get()).priv_flags).write(flags) }
     }
 
+    /// Returns the currently active features of the device.
+    pub fn features(&self) -> Features {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        Features(unsafe { addr_of!((*self.0.get()).features).read() })
+    }
+
+    /// Sets the currently active features of the device.
+    ///
+    /// Only meant to be used before the device is registered, afterwards the core changes them
+    /// through [`DeviceOperations::set_features`].
+    pub fn features_set(&self, features: Features) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { addr_of_mut!((*self.0.get()).features).write(features.0) }
+    }
+
+    /// Returns the features the user may toggle with `ethtool -K`.
+    pub fn hw_features(&self) -> Features {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        Features(unsafe { addr_of!((*self.0.get()).hw_features).read() })
+    }
+
+    /// Sets the features the user may toggle with `ethtool -K`.
+    pub fn hw_features_set(&self, features: Features) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { addr_of_mut!((*self.0.get()).hw_features).write(features.0) }
+    }
+
+    /// Sets the features that VLAN devices stacked on top of this one inherit.
+    pub fn vlan_features_set(&self, features: Features) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { addr_of_mut!((*self.0.get()).vlan_features).write(features.0) }
+    }
+
+    /// Advertises the device's offloads in one go, see [`FeatureSet`].
+    ///
+    /// Only meant to be used before the device is registered.
+    pub fn set_feature_set(&self, set: FeatureSet) {
+        self.hw_features_set(set.hw);
+        self.features_set(set.active);
+        self.vlan_features_set(set.vlan);
+    }
+
     /// Reports the number of bytes queued to hardware.
     pub fn sent_queue(&self, bytes: u32) {
         // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
         unsafe { bindings::netdev_sent_queue(self.0.get(), bytes) }
     }
 
+    /// Tests if the device is running (i.e. it has been brought up).
+    pub fn netif_running(&self) -> bool {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_running(self.0.get()) }
+    }
+
     /// Allows the upper layers to transmit.
     pub fn netif_start_queue(&self) {
         // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
@@ -131,11 +255,128 @@ impl Device {
         unsafe { bindings::netif_stop_queue(self.0.get()) }
     }
 
+    /// Allows the upper layers to transmit again and reschedules the queue, e.g. once the driver
+    /// has reclaimed enough TX descriptors after [`Device::netif_stop_queue`].
+    pub fn netif_wake_queue(&self) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_wake_queue(self.0.get()) }
+    }
+
+    /// Tests if the upper layers are stopped from transmitting.
+    pub fn netif_queue_stopped(&self) -> bool {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_queue_stopped(self.0.get()) }
+    }
+
+    /// Allows the upper layers to transmit on all TX queues.
+    pub fn netif_tx_start_all_queues(&self) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_tx_start_all_queues(self.0.get()) }
+    }
+
+    /// Stops the upper layers to transmit on all TX queues.
+    pub fn netif_tx_stop_all_queues(&self) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_tx_stop_all_queues(self.0.get()) }
+    }
+
+    /// Allows the upper layers to transmit on all TX queues again and reschedules them.
+    pub fn netif_tx_wake_all_queues(&self) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_tx_wake_all_queues(self.0.get()) }
+    }
+
     /// Reports bytes and packets completed by device.
     pub fn completed_queue(&self, pkts: u32, bytes: u32) {
         unsafe { bindings::netdev_completed_queue(self.0.get(), pkts, bytes) }
     }
 
+    /// Sets the number of TX queues in use, must not exceed the number the device was allocated
+    /// with.
+    pub fn netif_set_real_num_tx_queues(&self, txq: u32) -> Result {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        to_result(unsafe { bindings::netif_set_real_num_tx_queues(self.0.get(), txq) })
+    }
+
+    /// Sets the number of RX queues in use, must not exceed the number the device was allocated
+    /// with.
+    pub fn netif_set_real_num_rx_queues(&self, rxq: u32) -> Result {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        to_result(unsafe { bindings::netif_set_real_num_rx_queues(self.0.get(), rxq) })
+    }
+
+    /// Returns the number of TX queues in use.
+    pub fn real_num_tx_queues(&self) -> u32 {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { addr_of!((*self.0.get()).real_num_tx_queues).read() }
+    }
+
+    /// Picks a TX queue for `skb` with the default policy (XPS, then flow hash).
+    pub fn pick_tx(&self, skb: &SkBuff) -> u16 {
+        // SAFETY: The netdev and the skb are valid because of the shared references.
+        unsafe { bindings::netdev_pick_tx(self.0.get(), skb.0.get(), core::ptr::null_mut()) }
+    }
+
+    /// Stops the upper layers to transmit on TX queue `queue_index`.
+    pub fn netif_stop_subqueue(&self, queue_index: u16) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_stop_subqueue(self.0.get(), queue_index) }
+    }
+
+    /// Allows the upper layers to transmit on TX queue `queue_index` again and reschedules it.
+    pub fn netif_wake_subqueue(&self, queue_index: u16) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_wake_subqueue(self.0.get(), queue_index) }
+    }
+
+    /// Allows the upper layers to transmit on TX queue `queue_index`.
+    pub fn netif_start_subqueue(&self, queue_index: u16) {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::netif_start_subqueue(self.0.get(), queue_index) }
+    }
+
+    /// Tests if the upper layers are stopped from transmitting on TX queue `queue_index`,
+    /// corresponds to `__netif_subqueue_stopped`.
+    pub fn netif_subqueue_stopped(&self, queue_index: u16) -> bool {
+        // SAFETY: The netdev is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::__netif_subqueue_stopped(self.0.get(), queue_index) }
+    }
+
+    /// Reports the number of bytes queued to hardware on TX queue `queue_index`.
+    pub fn tx_sent_queue(&self, queue_index: u16, bytes: u32) {
+        // SAFETY: The netdev is valid and `netdev_get_tx_queue` returns a valid queue for any
+        // index below `num_tx_queues`.
+        unsafe {
+            let txq = bindings::netdev_get_tx_queue(self.0.get(), queue_index as _);
+            bindings::netdev_tx_sent_queue(txq, bytes)
+        }
+    }
+
+    /// Reports bytes and packets completed by device on TX queue `queue_index`.
+    pub fn tx_completed_queue(&self, queue_index: u16, pkts: u32, bytes: u32) {
+        // SAFETY: The netdev is valid and `netdev_get_tx_queue` returns a valid queue for any
+        // index below `num_tx_queues`.
+        unsafe {
+            let txq = bindings::netdev_get_tx_queue(self.0.get(), queue_index as _);
+            bindings::netdev_tx_completed_queue(txq, pkts, bytes)
+        }
+    }
+
+    /// Resets the byte queue limits state of TX queue `queue_index`, corresponds to
+    /// `netdev_tx_reset_queue`.
+    ///
+    /// Must be called whenever the packets reported with [`Device::tx_sent_queue`] are dropped
+    /// without being completed, e.g. when the TX ring is freed, otherwise the queue stays
+    /// throttled forever.
+    pub fn tx_reset_queue(&self, queue_index: u16) {
+        // SAFETY: The netdev is valid and `netdev_get_tx_queue` returns a valid queue for any
+        // index below `num_tx_queues`.
+        unsafe {
+            let txq = bindings::netdev_get_tx_queue(self.0.get(), queue_index as _);
+            bindings::netdev_tx_reset_queue(txq)
+        }
+    }
+
     /// Allocate an skbuff for rx on the device.
     /// with IP header placed at an aligned offset.
     pub fn alloc_skb_ip_align(&self, length: u32) -> Result<ARef<SkBuff>> {
@@ -147,6 +388,207 @@ impl Device {
             Ok(unsafe { &*(skb as *const SkBuff) }.into())
         }
     }
+
+    /// Returns the driver data of a device registered through a [`Registration<T>`].
+    ///
+    /// Useful in contexts that only hold a reference to the device, such as work items, where the
+    /// driver data isn't passed in by the callback.
+    ///
+    /// # Safety
+    ///
+    /// The device must have been registered by a `Registration<T>`, and the registration must not
+    /// be dropped while the returned value is in use.
+    pub unsafe fn drvdata<T: DeviceOperations>(&self) -> <T::Data as PointerWrapper>::Borrowed<'_> {
+        // SAFETY: The safety requirements guarantee that the driver data was set by `register`
+        // from `T::Data::into_pointer` and is still alive.
+        unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*self.0.get()).dev)) }
+    }
+}
+
+impl Device {
+    /// Prints an error-level message (level 3) prefixed with the driver, bus and interface names.
+    ///
+    /// More details are available from [`netdev_err`].
+    pub fn pr_err(&self, args: fmt::Arguments<'_>) {
+        // SAFETY: `klevel` is null-terminated, uses one of the kernel constants.
+        unsafe { self.printk(bindings::KERN_ERR, args) };
+    }
+
+    /// Prints a warning-level message (level 4) prefixed with the driver, bus and interface names.
+    ///
+    /// More details are available from [`netdev_warn`].
+    pub fn pr_warn(&self, args: fmt::Arguments<'_>) {
+        // SAFETY: `klevel` is null-terminated, uses one of the kernel constants.
+        unsafe { self.printk(bindings::KERN_WARNING, args) };
+    }
+
+    /// Prints an info-level message (level 6) prefixed with the driver, bus and interface names.
+    ///
+    /// More details are available from [`netdev_info`].
+    pub fn pr_info(&self, args: fmt::Arguments<'_>) {
+        // SAFETY: `klevel` is null-terminated, uses one of the kernel constants.
+        unsafe { self.printk(bindings::KERN_INFO, args) };
+    }
+
+    /// Prints a debug-level message (level 7) prefixed with the driver, bus and interface names.
+    ///
+    /// More details are available from [`netdev_dbg`].
+    pub fn pr_dbg(&self, args: fmt::Arguments<'_>) {
+        if cfg!(debug_assertions) {
+            // SAFETY: `klevel` is null-terminated, uses one of the kernel constants.
+            unsafe { self.printk(bindings::KERN_DEBUG, args) };
+        }
+    }
+
+    /// Prints the provided message to the console, corresponds to `netdev_printk`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `klevel` is null-terminated; in particular, one of the
+    /// `KERN_*`constants, for example, `KERN_CRIT`, `KERN_ALERT`, etc.
+    #[cfg_attr(not(CONFIG_PRINTK), allow(unused_variables))]
+    unsafe fn printk(&self, klevel: &[u8], msg: fmt::Arguments<'_>) {
+        // SAFETY: `klevel` is null-terminated and one of the kernel constants. `self.0` is valid
+        // because `self` is valid. The "%pA" format string expects a pointer to `fmt::Arguments`,
+        // which is what we're passing as the last argument.
+        #[cfg(CONFIG_PRINTK)]
+        unsafe {
+            bindings::netdev_printk(
+                klevel as *const _ as *const core::ffi::c_char,
+                self.0.get(),
+                c_str!("%pA").as_char_ptr(),
+                &msg as *const _ as *const core::ffi::c_void,
+            )
+        };
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! netdev_printk {
+    ($method:ident, $dev:expr, $($f:tt)*) => {
+        {
+            ($dev).$method(core::format_args!($($f)*));
+        }
+    }
+}
+
+/// Prints an error-level message (level 3) prefixed with the driver, bus and interface names,
+/// e.g. `e1000 0000:00:03.0 eth0: `.
+///
+/// Equivalent to the kernel's `netdev_err` macro. Before the device is registered, the interface
+/// name is still a template such as `eth%d`; use [`dev_err`](crate::dev_err) on the parent device
+/// instead.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::net::Device;
+///
+/// fn example(dev: &Device) {
+///     netdev_err!(dev, "hello {}\n", "there");
+/// }
+/// ```
+#[macro_export]
+macro_rules! netdev_err {
+    ($($f:tt)*) => { $crate::netdev_printk!(pr_err, $($f)*); }
+}
+
+/// Prints a warning-level message (level 4) prefixed with the driver, bus and interface names.
+///
+/// Equivalent to the kernel's `netdev_warn` macro.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::net::Device;
+///
+/// fn example(dev: &Device) {
+///     netdev_warn!(dev, "hello {}\n", "there");
+/// }
+/// ```
+#[macro_export]
+macro_rules! netdev_warn {
+    ($($f:tt)*) => { $crate::netdev_printk!(pr_warn, $($f)*); }
+}
+
+/// Prints an info-level message (level 6) prefixed with the driver, bus and interface names.
+///
+/// Equivalent to the kernel's `netdev_info` macro.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::net::Device;
+///
+/// fn example(dev: &Device) {
+///     netdev_info!(dev, "hello {}\n", "there");
+/// }
+/// ```
+#[macro_export]
+macro_rules! netdev_info {
+    ($($f:tt)*) => { $crate::netdev_printk!(pr_info, $($f)*); }
+}
+
+/// Prints a debug-level message (level 7) prefixed with the driver, bus and interface names.
+///
+/// Equivalent to the kernel's `netdev_dbg` macro, except that it doesn't support dynamic debug
+/// yet.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::net::Device;
+///
+/// fn example(dev: &Device) {
+///     netdev_dbg!(dev, "hello {}\n", "there");
+/// }
+/// ```
+#[macro_export]
+macro_rules! netdev_dbg {
+    ($($f:tt)*) => { $crate::netdev_printk!(pr_dbg, $($f)*); }
+}
+
+/// Like [`netdev_err`], but prints at most a few messages every five seconds per call site.
+///
+/// Use this in the data path, where an error may repeat for every packet.
+#[macro_export]
+macro_rules! netdev_err_ratelimited {
+    ($($f:tt)*) => { $crate::print_ratelimited!(netdev_err, $($f)*); }
+}
+
+/// Like [`netdev_dbg`], but prints at most a few messages every five seconds per call site.
+#[macro_export]
+macro_rules! netdev_dbg_ratelimited {
+    ($($f:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::print_ratelimited!(netdev_dbg, $($f)*);
+        }
+    }
+}
+
+/// Holds the RTNL lock until dropped, corresponds to `rtnl_lock` and `rtnl_unlock`.
+///
+/// The lock serialises configuration changes of all network devices. The callbacks of
+/// [`DeviceOperations`] such as `open` and `stop` already run with it held, so this is only needed
+/// when reconfiguring a device from other contexts, e.g. a work item.
+pub struct RtnlGuard(PhantomData<*mut ()>);
+
+impl RtnlGuard {
+    /// Acquires the RTNL lock, sleeping until it is available.
+    pub fn lock() -> Self {
+        // SAFETY: FFI call with no preconditions other than being in process context.
+        unsafe { bindings::rtnl_lock() };
+        Self(PhantomData)
+    }
+}
+
+impl Drop for RtnlGuard {
+    fn drop(&mut self) {
+        // SAFETY: The lock was acquired in `lock`, and the guard isn't `Send`, so it is released
+        // by the same task.
+        unsafe { bindings::rtnl_unlock() };
+    }
 }
 
 /// Registration structure for a network device.
@@ -156,22 +598,119 @@ pub struct Registration<T: DeviceOperations> {
     _p: PhantomData<T>,
 }
 
+/// Options for allocating a network device with [`Registration::try_new_with_options`].
+///
+/// # Examples
+///
+/// ```ignore
+/// let reg = net::Registration::<MyDevice>::try_new_with_options(
+///     net::Options::new()
+///         .name(c_str!("r4l%d"))
+///         .parent(pdev)
+///         .queues(4, 4),
+/// )?;
+/// ```
+pub struct Options<'a> {
+    name: Option<&'a CStr>,
+    parent: Option<&'a dyn device::RawDevice>,
+    txqs: u32,
+    rxqs: u32,
+}
+
+impl<'a> Options<'a> {
+    /// Creates new [`Options`] for an `eth%d` device with one TX and one RX queue.
+    pub const fn new() -> Self {
+        Self {
+            name: None,
+            parent: None,
+            txqs: 1,
+            rxqs: 1,
+        }
+    }
+
+    /// Sets the name of the device, which may contain one `%d` to be replaced by the first free
+    /// number when the device is registered (e.g. `r4l%d`).
+    ///
+    /// Must be shorter than `IFNAMSIZ` bytes.
+    pub const fn name(&mut self, name: &'a CStr) -> &mut Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Sets the parent device, shown as `/sys/class/net/<name>/device`.
+    pub const fn parent(&mut self, parent: &'a dyn device::RawDevice) -> &mut Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    /// Sets the number of TX and RX queues to allocate.
+    ///
+    /// These are the maximum numbers of queues, see [`Registration::try_new_mq`].
+    pub const fn queues(&mut self, txqs: u32, rxqs: u32) -> &mut Self {
+        self.txqs = txqs;
+        self.rxqs = rxqs;
+        self
+    }
+}
+
+impl Default for Options<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: DeviceOperations> Registration<T> {
     /// Creates new instance of registration.
     pub fn try_new(parent: &dyn device::RawDevice) -> Result<Self> {
+        Self::try_new_mq(parent, 1, 1)
+    }
+
+    /// Creates new instance of registration for a device with `txqs` TX queues and `rxqs` RX
+    /// queues.
+    ///
+    /// These are the maximum numbers of queues; the numbers actually in use can be lowered with
+    /// [`Device::netif_set_real_num_tx_queues`] and [`Device::netif_set_real_num_rx_queues`].
+    pub fn try_new_mq(parent: &dyn device::RawDevice, txqs: u32, rxqs: u32) -> Result<Self> {
+        Self::try_new_with_options(Options::new().parent(parent).queues(txqs, rxqs))
+    }
+
+    /// Creates new instance of registration with the configured [`Options`].
+    pub fn try_new_with_options(opts: &Options<'_>) -> Result<Self> {
+        if opts.txqs == 0 || opts.rxqs == 0 {
+            return Err(EINVAL);
+        }
+        let name = match opts.name {
+            Some(name) if name.len() >= bindings::IFNAMSIZ as usize => return Err(EINVAL),
+            name => name,
+        };
+
         // SAFETY: FFI call.
-        let dev = unsafe { bindings::alloc_etherdev_mqs(0, 1, 1) };
+        let dev = unsafe { bindings::alloc_etherdev_mqs(0, opts.txqs, opts.rxqs) };
         if dev.is_null() {
-            Err(ENOMEM)
-        } else {
+            return Err(ENOMEM);
+        }
+        if let Some(parent) = opts.parent {
             // SAFETY: `dev` was allocated during initialization and is guaranteed to be valid.
             unsafe { (*dev).dev.parent = parent.raw_device() }
-            Ok(Registration {
-                dev,
-                registered: false,
-                _p: PhantomData,
-            })
         }
+        if let Some(name) = name {
+            let bytes = name.as_bytes_with_nul();
+            // SAFETY: `dev` is valid and `name` (checked above) fits into the `IFNAMSIZ` bytes of
+            // `dev->name` together with its terminating NUL. `register_netdev` expands a `%d`
+            // in it to the first free number.
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    bytes.as_ptr(),
+                    addr_of_mut!((*dev).name).cast::<u8>(),
+                    bytes.len(),
+                )
+            }
+        }
+        Ok(Registration {
+            dev,
+            registered: false,
+            _p: PhantomData,
+        })
     }
 
     /// Returns a network device.
@@ -180,6 +719,47 @@ impl<T: DeviceOperations> Registration<T> {
         unsafe { &*(self.dev as *const Device) }.into()
     }
 
+    /// Returns the driver data passed to [`Registration::register`], or `None` if the device
+    /// isn't registered.
+    pub fn drvdata(&self) -> Option<<T::Data as PointerWrapper>::Borrowed<'_>> {
+        if !self.registered {
+            return None;
+        }
+        // SAFETY: `dev` is valid, and since the device is registered its driver data was set by
+        // `register` from `T::Data::into_pointer`. It is only reclaimed in `drop` (or by
+        // `register` when registration fails), which can't happen while the returned value
+        // borrows `self`.
+        Some(unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*self.dev).dev)) })
+    }
+
+    /// Unregisters the network device, closing it first if it is up.
+    ///
+    /// The device itself and the driver data passed to [`Registration::register`] are only
+    /// freed when `self` is dropped, so resources tied to them (such as NAPI instances and work
+    /// items using the driver data) can be released in between. Does nothing if the device isn't
+    /// registered.
+    pub fn unregister(&mut self) {
+        if self.registered {
+            // SAFETY: `dev` was allocated during initialization and is registered.
+            unsafe { bindings::unregister_netdev(self.dev) };
+            self.registered = false;
+        }
+    }
+
+    /// Installs the ethtool operations of `E`, which must be done before the device is
+    /// registered.
+    ///
+    /// Fails with `EBUSY` if the device is already registered.
+    pub fn set_ethtool_ops<E: EthtoolOps<Data = T::Data>>(&mut self) -> Result {
+        if self.registered {
+            return Err(EBUSY);
+        }
+        // SAFETY: `dev` was allocated during initialization and is guaranteed to be valid, and
+        // the core doesn't look at `ethtool_ops` before the device is registered.
+        unsafe { (*self.dev).ethtool_ops = &EthtoolVtable::<E>::OPS };
+        Ok(())
+    }
+
     /// Register a network device.
     pub fn register(&mut self, data: T::Data) -> Result {
         // SAFETY: `dev` was allocated during initialization and is guaranteed to be valid.
@@ -195,7 +775,13 @@ impl<T: DeviceOperations> Registration<T> {
         };
         if ret != 0 {
             // SAFETY: `dev` was allocated during initialization and is guaranteed to be valid.
-            unsafe { bindings::dev_set_drvdata(&mut (*self.dev).dev, core::ptr::null_mut()) }
+            // The registration failed, so no callback can use the driver data anymore and it can
+            // be reclaimed; it came from `into_pointer` above.
+            unsafe {
+                let ptr = bindings::dev_get_drvdata(&mut (*self.dev).dev);
+                bindings::dev_set_drvdata(&mut (*self.dev).dev, core::ptr::null_mut());
+                T::Data::from_pointer(ptr);
+            }
             Err(Error::from_kernel_errno(ret))
         } else {
             self.registered = true;
@@ -206,13 +792,19 @@ impl<T: DeviceOperations> Registration<T> {
 
 impl<T: DeviceOperations> Drop for Registration<T> {
     fn drop(&mut self) {
+        self.unregister();
+
         // SAFETY: `dev` was allocated during initialization and guaranteed to be valid.
-        unsafe {
-            if self.registered {
-                bindings::unregister_netdev(self.dev);
-            }
-            bindings::free_netdev(self.dev);
+        let data = unsafe { bindings::dev_get_drvdata(&mut (*self.dev).dev) };
+        if !data.is_null() {
+            // SAFETY: The driver data is only non-null after a successful `register`, which set
+            // it from `into_pointer`. The device is no longer registered, so the callbacks that
+            // borrow it can't run anymore.
+            unsafe { T::Data::from_pointer(data) };
         }
+
+        // SAFETY: `dev` was allocated during initialization and guaranteed to be valid.
+        unsafe { bindings::free_netdev(self.dev) };
     }
 }
 
@@ -236,13 +828,21 @@ impl<T: DeviceOperations> Registration<T> {
             None
         },
         ndo_features_check: None,
-        ndo_select_queue: None,
+        ndo_select_queue: if <T>::HAS_SELECT_QUEUE {
+            Some(Self::select_queue_callback)
+        } else {
+            None
+        },
         ndo_change_rx_flags: None,
         ndo_set_rx_mode: None,
         ndo_set_mac_address: None,
         ndo_validate_addr: None,
         ndo_do_ioctl: None,
-        ndo_eth_ioctl: None,
+        ndo_eth_ioctl: if <T>::HAS_ETH_IOCTL {
+            Some(Self::eth_ioctl_callback)
+        } else {
+            None
+        },
         ndo_siocbond: None,
         ndo_siocwandev: None,
         ndo_siocdevprivate: None,
@@ -300,8 +900,16 @@ impl<T: DeviceOperations> Registration<T> {
         ndo_del_slave: None,
         ndo_get_xmit_slave: None,
         ndo_sk_get_lower_dev: None,
-        ndo_fix_features: None,
-        ndo_set_features: None,
+        ndo_fix_features: if <T>::HAS_FIX_FEATURES {
+            Some(Self::fix_features_callback)
+        } else {
+            None
+        },
+        ndo_set_features: if <T>::HAS_SET_FEATURES {
+            Some(Self::set_features_callback)
+        } else {
+            None
+        },
         ndo_neigh_construct: None,
         ndo_neigh_destroy: None,
         ndo_fdb_add: None,
@@ -322,8 +930,16 @@ impl<T: DeviceOperations> Registration<T> {
         ndo_get_iflink: None,
         ndo_fill_metadata_dst: None,
         ndo_set_rx_headroom: None,
-        ndo_bpf: None,
-        ndo_xdp_xmit: None,
+        ndo_bpf: if <T>::HAS_BPF {
+            Some(Self::bpf_callback)
+        } else {
+            None
+        },
+        ndo_xdp_xmit: if <T>::HAS_XDP_XMIT {
+            Some(Self::xdp_xmit_callback)
+        } else {
+            None
+        },
         ndo_xdp_get_xmit_slave: None,
         ndo_xsk_wakeup: None,
         ndo_get_devlink_port: None,
@@ -337,127 +953,1132 @@ impl<T: DeviceOperations> Registration<T> {
         &Self::DEVICE_OPS
     }
 
-    unsafe extern "C" fn open_callback(netdev: *mut bindings::net_device) -> core::ffi::c_int {
+    unsafe extern "C" fn open_callback(netdev: *mut bindings::net_device) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+            let dev = unsafe { Device::from_ptr(netdev) };
+            // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+            let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+            T::open(dev, data)?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn stop_callback(netdev: *mut bindings::net_device) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+            let dev = unsafe { Device::from_ptr(netdev) };
+            // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+            let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+            T::stop(dev, data)?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn start_xmit_callback(
+        skb: *mut bindings::sk_buff,
+        netdev: *mut bindings::net_device,
+    ) -> bindings::netdev_tx_t {
+        // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+        let dev = unsafe { Device::from_ptr(netdev) };
+        // SAFETY: The C API guarantees that `sk_buff` isn't released while this function is running.
+        let skb = unsafe { SkBuff::from_ptr(skb) };
+        // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+        let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+        T::start_xmit(skb, dev, data) as bindings::netdev_tx_t
+    }
+
+    unsafe extern "C" fn select_queue_callback(
+        netdev: *mut bindings::net_device,
+        skb: *mut bindings::sk_buff,
+        _sb_dev: *mut bindings::net_device,
+    ) -> u16 {
+        // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+        let dev = unsafe { Device::from_ptr(netdev) };
+        // SAFETY: The C API guarantees that `sk_buff` isn't released while this function is running.
+        let skb = unsafe { SkBuff::from_ptr(skb) };
+        // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+        let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+        T::select_queue(dev, data, skb)
+    }
+
+    unsafe extern "C" fn bpf_callback(
+        netdev: *mut bindings::net_device,
+        bpf: *mut bindings::netdev_bpf,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+            let dev = unsafe { Device::from_ptr(netdev) };
+            // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+            let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+            let mut bpf = NetdevBpf::new(bpf);
+            T::bpf(dev, data, &mut bpf)?;
+            // On success the core hands its reference on the installed program over to the
+            // driver. Drivers take their own reference (`ARef`) instead, so drop this one.
+            if let Ok(Some(prog)) = bpf.prog() {
+                // SAFETY: We own the reference handed over by the core.
+                unsafe { BpfProg::dec_ref(NonNull::from(prog)) };
+            }
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn xdp_xmit_callback(
+        netdev: *mut bindings::net_device,
+        n: core::ffi::c_int,
+        frames: *mut *mut bindings::xdp_frame,
+        flags: u32,
+    ) -> core::ffi::c_int {
+        // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+        let dev = unsafe { Device::from_ptr(netdev) };
+        // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+        let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+        // SAFETY: The C API guarantees that `frames` holds `n` valid frames.
+        let frames = unsafe { core::slice::from_raw_parts(frames, n as usize) };
+        let mut frames = XdpFrames::new(frames);
+        T::xdp_xmit(dev, data, &mut frames, flags);
+        frames.taken() as _
+    }
+
+    unsafe extern "C" fn eth_ioctl_callback(
+        netdev: *mut bindings::net_device,
+        ifr: *mut bindings::ifreq,
+        cmd: core::ffi::c_int,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+            let dev = unsafe { Device::from_ptr(netdev) };
+            // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+            let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+            // SAFETY: The C API guarantees that `ifr` is valid while this function is running.
+            let mut mii_data = unsafe { mii::MiiIoctlData::from_ifreq(ifr) };
+            T::eth_ioctl(dev, data, &mut mii_data, cmd as u32)?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn fix_features_callback(
+        netdev: *mut bindings::net_device,
+        features: bindings::netdev_features_t,
+    ) -> bindings::netdev_features_t {
+        // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+        let dev = unsafe { Device::from_ptr(netdev) };
+        // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+        let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+        T::fix_features(dev, data, Features(features)).0
+    }
+
+    unsafe extern "C" fn set_features_callback(
+        netdev: *mut bindings::net_device,
+        features: bindings::netdev_features_t,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+            let dev = unsafe { Device::from_ptr(netdev) };
+            // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+            let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+            T::set_features(dev, data, Features(features))?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn get_stats64_callback(
+        netdev: *mut bindings::net_device,
+        storage: *mut bindings::rtnl_link_stats64,
+    ) {
+        // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+        let dev = unsafe { Device::from_ptr(netdev) };
+        // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+        let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+
+        T::get_stats64(dev, data, &mut RtnlLinkStats64 { ptr: storage });
+    }
+
+}
+
+/// Length of an Ethernet address.
+pub const ETH_ALEN: usize = 6;
+
+/// Returns whether `addr` can be used as the address of a device, i.e. it is neither a multicast
+/// address nor all zeros. Corresponds to `is_valid_ether_addr`.
+pub fn is_valid_ether_addr(addr: &[u8; ETH_ALEN]) -> bool {
+    addr[0] & 1 == 0 && addr.iter().any(|&b| b != 0)
+}
+
+/// Parses an Ethernet address in the `xx:xx:xx:xx:xx:xx` or `xx-xx-xx-xx-xx-xx` form, e.g. from a
+/// module parameter. Corresponds to `mac_pton`.
+///
+/// Returns `None` if `s` isn't a well-formed address. The address isn't otherwise validated; see
+/// [`is_valid_ether_addr`].
+pub fn mac_pton(s: &[u8]) -> Option<[u8; ETH_ALEN]> {
+    if s.len() != 3 * ETH_ALEN - 1 {
+        return None;
+    }
+
+    let hex = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    let mut addr = [0; ETH_ALEN];
+    for (i, byte) in addr.iter_mut().enumerate() {
+        let part = &s[3 * i..];
+        if i != ETH_ALEN - 1 && part[2] != b':' && part[2] != b'-' {
+            return None;
+        }
+        *byte = hex(part[0])? << 4 | hex(part[1])?;
+    }
+    Some(addr)
+}
+
+/// Net device features (`NETIF_F_*`), as used by [`Device::features`] and
+/// [`DeviceOperations::set_features`].
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Features(u64);
+
+impl Features {
+    /// Scatter/gather IO.
+    pub const SG: Self = Self(1 << bindings::NETIF_F_SG_BIT);
+
+    /// Checksum offload of TCP/UDP over IPv4.
+    pub const IP_CSUM: Self = Self(1 << bindings::NETIF_F_IP_CSUM_BIT);
+
+    /// Checksum offload of any protocol with the generic start/offset scheme.
+    pub const HW_CSUM: Self = Self(1 << bindings::NETIF_F_HW_CSUM_BIT);
+
+    /// Checksum offload of TCP/UDP over IPv6.
+    pub const IPV6_CSUM: Self = Self(1 << bindings::NETIF_F_IPV6_CSUM_BIT);
+
+    /// The device can DMA to and from high memory.
+    pub const HIGHDMA: Self = Self(1 << bindings::NETIF_F_HIGHDMA_BIT);
+
+    /// TCP segmentation offload over IPv4.
+    pub const TSO: Self = Self(1 << bindings::NETIF_F_TSO_BIT);
+
+    /// TCP segmentation offload over IPv6.
+    pub const TSO6: Self = Self(1 << bindings::NETIF_F_TSO6_BIT);
+
+    /// Receive checksumming offload.
+    pub const RXCSUM: Self = Self(1 << bindings::NETIF_F_RXCSUM_BIT);
+
+    /// Append the FCS to received frames.
+    pub const RXFCS: Self = Self(1 << bindings::NETIF_F_RXFCS_BIT);
+
+    /// Receive errored frames too.
+    pub const RXALL: Self = Self(1 << bindings::NETIF_F_RXALL_BIT);
+
+    /// Receive VLAN CTAG acceleration.
+    pub const HW_VLAN_CTAG_RX: Self = Self(1 << bindings::NETIF_F_HW_VLAN_CTAG_RX_BIT);
+
+    /// Transmit VLAN CTAG acceleration.
+    pub const HW_VLAN_CTAG_TX: Self = Self(1 << bindings::NETIF_F_HW_VLAN_CTAG_TX_BIT);
+
+    /// Receive filtering on VLAN CTAGs.
+    pub const HW_VLAN_CTAG_FILTER: Self = Self(1 << bindings::NETIF_F_HW_VLAN_CTAG_FILTER_BIT);
+
+    /// Returns the empty set.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Creates a set from raw `netdev_features_t` bits.
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the raw `netdev_features_t` bits.
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Returns `true` if no feature is set.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all features in `other` are set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if any feature in `other` is set.
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl core::ops::BitOr for Features {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for Features {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl core::ops::BitAnd for Features {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl core::ops::BitAndAssign for Features {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl core::ops::BitXor for Features {
+    type Output = Self;
+    fn bitxor(self, rhs: Self) -> Self {
+        Self(self.0 ^ rhs.0)
+    }
+}
+
+impl core::ops::Not for Features {
+    type Output = Self;
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+/// The offloads a driver advertises before registering its device, applied with
+/// [`Device::set_feature_set`].
+///
+/// # Examples
+///
+/// ```ignore
+/// netdev.set_feature_set(
+///     FeatureSet::new()
+///         .toggleable(Features::RXCSUM | Features::RXFCS)
+///         .enabled(Features::RXCSUM)
+///         .fixed(Features::HIGHDMA),
+/// );
+/// ```
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FeatureSet {
+    hw: Features,
+    active: Features,
+    vlan: Features,
+}
+
+impl FeatureSet {
+    /// Creates an empty set of offloads.
+    pub const fn new() -> Self {
+        Self {
+            hw: Features::empty(),
+            active: Features::empty(),
+            vlan: Features::empty(),
+        }
+    }
+
+    /// Adds features the user may toggle with `ethtool -K`, initially off.
+    pub const fn toggleable(mut self, features: Features) -> Self {
+        self.hw.0 |= features.0;
+        self
+    }
+
+    /// Turns on toggleable features from the start.
+    ///
+    /// Only features already added with [`FeatureSet::toggleable`] are turned on, use
+    /// [`FeatureSet::fixed`] for features that can't be toggled.
+    pub const fn enabled(mut self, features: Features) -> Self {
+        self.active.0 |= features.0 & self.hw.0;
+        self
+    }
+
+    /// Adds features that are always on and can't be toggled.
+    pub const fn fixed(mut self, features: Features) -> Self {
+        self.active.0 |= features.0;
+        self
+    }
+
+    /// Adds features that VLAN devices stacked on top of this one inherit.
+    pub const fn vlan(mut self, features: Features) -> Self {
+        self.vlan.0 |= features.0;
+        self
+    }
+}
+
+/// Corresponds to the kernel's `struct rtnl_link_stats64`.
+pub struct RtnlLinkStats64 {
+    ptr: *mut bindings::rtnl_link_stats64,
+}
+
+impl RtnlLinkStats64 {
+    /// Set rx_bytes.
+    pub fn set_rx_bytes(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_bytes = value }
+    }
+
+    /// Set rx_packets.
+    pub fn set_rx_packets(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_packets = value }
+    }
+
+    /// Set tx_bytes.
+    pub fn set_tx_bytes(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_bytes = value }
+    }
+
+    /// Set tx_packets.
+    pub fn set_tx_packets(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_packets = value }
+    }
+
+    /// Set rx_errors.
+    pub fn set_rx_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_errors = value }
+    }
+
+    /// Set rx_crc_errors.
+    pub fn set_rx_crc_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_crc_errors = value }
+    }
+
+    /// Set rx_dropped.
+    pub fn set_rx_dropped(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_dropped = value }
+    }
+
+    /// Set tx_dropped.
+    pub fn set_tx_dropped(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_dropped = value }
+    }
+
+    /// Set tx_errors.
+    pub fn set_tx_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_errors = value }
+    }
+
+    /// Set multicast.
+    pub fn set_multicast(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).multicast = value }
+    }
+
+    /// Set collisions.
+    pub fn set_collisions(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).collisions = value }
+    }
+
+    /// Set rx_length_errors.
+    pub fn set_rx_length_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_length_errors = value }
+    }
+
+    /// Set rx_over_errors.
+    pub fn set_rx_over_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_over_errors = value }
+    }
+
+    /// Set rx_frame_errors.
+    pub fn set_rx_frame_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_frame_errors = value }
+    }
+
+    /// Set rx_fifo_errors.
+    pub fn set_rx_fifo_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_fifo_errors = value }
+    }
+
+    /// Set rx_missed_errors.
+    pub fn set_rx_missed_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_missed_errors = value }
+    }
+
+    /// Set tx_aborted_errors.
+    pub fn set_tx_aborted_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_aborted_errors = value }
+    }
+
+    /// Set tx_carrier_errors.
+    pub fn set_tx_carrier_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_carrier_errors = value }
+    }
+
+    /// Set tx_fifo_errors.
+    pub fn set_tx_fifo_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_fifo_errors = value }
+    }
+
+    /// Set tx_heartbeat_errors.
+    pub fn set_tx_heartbeat_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_heartbeat_errors = value }
+    }
+
+    /// Set tx_window_errors.
+    pub fn set_tx_window_errors(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_window_errors = value }
+    }
+
+    /// Set rx_compressed.
+    pub fn set_rx_compressed(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_compressed = value }
+    }
+
+    /// Set tx_compressed.
+    pub fn set_tx_compressed(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_compressed = value }
+    }
+
+    /// Set rx_nohandler.
+    pub fn set_rx_nohandler(&mut self, value: u64) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_nohandler = value }
+    }
+}
+
+pub use crate::percpu::U64StatsSync;
+
+/// Software packet and byte counters, corresponds to `struct pcpu_sw_netstats`.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SwNetStats {
+    /// Number of received packets.
+    pub rx_packets: u64,
+    /// Number of received bytes.
+    pub rx_bytes: u64,
+    /// Number of transmitted packets.
+    pub tx_packets: u64,
+    /// Number of transmitted bytes.
+    pub tx_bytes: u64,
+}
+
+/// Per-CPU software packet and byte counters.
+///
+/// Drivers update them lockless from the datapath and sum them up in `ndo_get_stats64`, see
+/// [`PerCpu`].
+pub type PerCpuStats = PerCpu<SwNetStats>;
+
+impl PerCpuStats {
+    /// Accounts `packets` received packets totalling `bytes` bytes on the current CPU,
+    /// corresponds to `dev_sw_netstats_rx_add`.
+    ///
+    /// Must not be called from hard interrupt context or with interrupts disabled.
+    pub fn rx_add(&self, packets: u64, bytes: u64) {
+        self.update(|s| {
+            s.rx_packets += packets;
+            s.rx_bytes += bytes;
+        });
+    }
+
+    /// Accounts `packets` transmitted packets totalling `bytes` bytes on the current CPU,
+    /// corresponds to `dev_sw_netstats_tx_add`.
+    ///
+    /// Must not be called from hard interrupt context or with interrupts disabled.
+    pub fn tx_add(&self, packets: u64, bytes: u64) {
+        self.update(|s| {
+            s.tx_packets += packets;
+            s.tx_bytes += bytes;
+        });
+    }
+
+    /// Returns the sum of the counters of all CPUs, corresponds to `dev_fetch_sw_netstats`.
+    pub fn fetch(&self) -> SwNetStats {
+        self.fold(SwNetStats::default(), |mut total, s| {
+            total.rx_packets += s.rx_packets;
+            total.rx_bytes += s.rx_bytes;
+            total.tx_packets += s.tx_packets;
+            total.tx_bytes += s.tx_bytes;
+            total
+        })
+    }
+}
+
+/// Corresponds to the kernel's `struct ethtool_ringparam`.
+pub struct EthtoolRingParam {
+    ptr: *mut bindings::ethtool_ringparam,
+}
+
+impl EthtoolRingParam {
+    /// Returns the number of RX descriptors requested.
+    pub fn rx_pending(&self) -> u32 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_pending }
+    }
+
+    /// Returns the number of RX mini descriptors requested.
+    pub fn rx_mini_pending(&self) -> u32 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_mini_pending }
+    }
+
+    /// Returns the number of RX jumbo descriptors requested.
+    pub fn rx_jumbo_pending(&self) -> u32 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_jumbo_pending }
+    }
+
+    /// Returns the number of TX descriptors requested.
+    pub fn tx_pending(&self) -> u32 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_pending }
+    }
+
+    /// Set rx_max_pending.
+    pub fn set_rx_max_pending(&mut self, value: u32) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_max_pending = value }
+    }
+
+    /// Set tx_max_pending.
+    pub fn set_tx_max_pending(&mut self, value: u32) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_max_pending = value }
+    }
+
+    /// Set rx_pending.
+    pub fn set_rx_pending(&mut self, value: u32) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_pending = value }
+    }
+
+    /// Set tx_pending.
+    pub fn set_tx_pending(&mut self, value: u32) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).tx_pending = value }
+    }
+}
+
+/// Corresponds to the kernel's `struct ethtool_coalesce`.
+pub struct EthtoolCoalesce {
+    ptr: *mut bindings::ethtool_coalesce,
+}
+
+impl EthtoolCoalesce {
+    /// Returns the RX interrupt delay in microseconds.
+    pub fn rx_coalesce_usecs(&self) -> u32 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_coalesce_usecs }
+    }
+
+    /// Set rx_coalesce_usecs.
+    pub fn set_rx_coalesce_usecs(&mut self, value: u32) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).rx_coalesce_usecs = value }
+    }
+
+    /// Returns whether adaptive RX interrupt moderation is requested.
+    pub fn use_adaptive_rx_coalesce(&self) -> bool {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).use_adaptive_rx_coalesce != 0 }
+    }
+
+    /// Set use_adaptive_rx_coalesce.
+    pub fn set_use_adaptive_rx_coalesce(&mut self, value: bool) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).use_adaptive_rx_coalesce = value as u32 }
+    }
+}
+
+/// Coalescing parameters (`ETHTOOL_COALESCE_*`) a driver accepts in
+/// [`EthtoolOps::set_coalesce`], see [`EthtoolOps::SUPPORTED_COALESCE_PARAMS`].
+///
+/// The C definitions use `BIT()`, which bindgen cannot evaluate.
+pub mod coalesce {
+    /// `rx_coalesce_usecs`.
+    pub const RX_USECS: u32 = 1 << 0;
+
+    /// `use_adaptive_rx_coalesce`.
+    pub const USE_ADAPTIVE_RX: u32 = 1 << 10;
+}
+
+/// Length of each string reported through [`EthtoolOps::get_strings`], including the
+/// terminating NUL.
+pub const ETH_GSTRING_LEN: usize = bindings::ETH_GSTRING_LEN as usize;
+
+/// String set of the driver statistics reported by `ethtool -S`.
+pub const ETH_SS_STATS: u32 = bindings::ethtool_stringset_ETH_SS_STATS;
+
+/// The string table filled in by [`EthtoolOps::get_strings`].
+pub struct EthtoolStrings<'a> {
+    buf: &'a mut [[u8; ETH_GSTRING_LEN]],
+    pos: usize,
+}
+
+impl EthtoolStrings<'_> {
+    /// Appends the next string, truncated to `ETH_GSTRING_LEN - 1` bytes.
+    ///
+    /// Strings beyond the count returned by [`EthtoolOps::get_sset_count`] are ignored.
+    pub fn add(&mut self, args: fmt::Arguments<'_>) {
+        let slot = match self.buf.get_mut(self.pos) {
+            Some(slot) => slot,
+            None => return,
+        };
+        self.pos += 1;
+
+        *slot = [0; ETH_GSTRING_LEN];
+        let mut writer = GstringWriter { slot, len: 0 };
+        // Truncation is not an error, see `GstringWriter`.
+        let _ = fmt::write(&mut writer, args);
+    }
+}
+
+/// Writes into a string slot, silently truncating and leaving room for the NUL.
+struct GstringWriter<'a> {
+    slot: &'a mut [u8; ETH_GSTRING_LEN],
+    len: usize,
+}
+
+impl fmt::Write for GstringWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = ETH_GSTRING_LEN - 1 - self.len;
+        let n = s.len().min(room);
+        self.slot[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// The values filled in by [`EthtoolOps::get_ethtool_stats`], in the same order as the
+/// strings of [`ETH_SS_STATS`].
+pub struct EthtoolStats<'a> {
+    buf: &'a mut [u64],
+    pos: usize,
+}
+
+impl EthtoolStats<'_> {
+    /// Appends the next value. Values beyond the number of statistics are ignored.
+    pub fn add(&mut self, value: u64) {
+        if let Some(slot) = self.buf.get_mut(self.pos) {
+            *slot = value;
+            self.pos += 1;
+        }
+    }
+}
+
+/// Driver transmit return codes.
+#[repr(i32)]
+pub enum NetdevTx {
+    /// Driver took care of packet.
+    Ok = bindings::netdev_tx_NETDEV_TX_OK,
+    /// Driver tx path was busy.
+    Busy = bindings::netdev_tx_NETDEV_TX_BUSY,
+}
+
+// SAFETY: `Registration` does not expose any of its state across threads.
+unsafe impl<T: DeviceOperations> Sync for Registration<T> {}
+
+// SAFETY: `Registration` is not restricted to a single thread,
+// its `T::Data` is also `Send` so it may be moved to different threads.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<T: DeviceOperations> Send for Registration<T> {}
+
+/// Corresponds to the kernel's `struct net_device_ops`.
+#[vtable]
+pub trait DeviceOperations {
+    /// The pointer type that will be used to hold driver-defined data type.
+    type Data: PointerWrapper + Send + Sync = ();
+
+    /// Corresponds to `ndo_open` in `struct net_device_ops`.
+    fn open(dev: &Device, data: <Self::Data as PointerWrapper>::Borrowed<'_>) -> Result;
+
+    /// Corresponds to `ndo_stop` in `struct net_device_ops`.
+    fn stop(dev: &Device, data: <Self::Data as PointerWrapper>::Borrowed<'_>) -> Result;
+
+    /// Corresponds to `ndo_start_xmit` in `struct net_device_ops`.
+    fn start_xmit(
+        skb: &SkBuff,
+        dev: &Device,
+        data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+    ) -> NetdevTx;
+
+    /// Corresponds to `ndo_select_queue` in `struct net_device_ops`.
+    ///
+    /// Returns the index of the TX queue `skb` is transmitted on. Drivers may fall back to the
+    /// default policy with [`Device::pick_tx`].
+    fn select_queue(
+        dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        skb: &SkBuff,
+    ) -> u16 {
+        dev.pick_tx(skb)
+    }
+
+    /// Corresponds to `ndo_get_stats64` in `struct net_device_ops`.
+    fn get_stats64(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _storage: &mut RtnlLinkStats64,
+    ) {
+    }
+
+    /// Corresponds to `ndo_eth_ioctl` in `struct net_device_ops`.
+    ///
+    /// Called for the MII ioctls (`SIOCGMIIPHY`, `SIOCGMIIREG` and `SIOCSMIIREG`), which can be
+    /// handled with [`mii::MiiIf::generic_ioctl`].
+    fn eth_ioctl(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _mii_data: &mut mii::MiiIoctlData,
+        _cmd: u32,
+    ) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Corresponds to `ndo_fix_features` in `struct net_device_ops`.
+    ///
+    /// Adjusts the requested [`Features`] to what the device can currently do, for example
+    /// dropping a feature that depends on another one being disabled.
+    fn fix_features(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        features: Features,
+    ) -> Features {
+        features
+    }
+
+    /// Corresponds to `ndo_set_features` in `struct net_device_ops`.
+    ///
+    /// Called with the new [`Features`] before they become active; [`Device::features`] still
+    /// returns the old ones, so drivers can compare both to reconfigure only what changed.
+    fn set_features(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _features: Features,
+    ) -> Result {
+        Ok(())
+    }
+
+    /// Corresponds to `ndo_bpf` in `struct net_device_ops`.
+    ///
+    /// For `XDP_SETUP_PROG`, drivers that want to keep the program take their own reference
+    /// with [`ARef::from`].
+    fn bpf(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _bpf: &mut NetdevBpf,
+    ) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Corresponds to `ndo_xdp_xmit` in `struct net_device_ops`.
+    ///
+    /// Frames taken from `frames` are owned by the driver and must be dropped once transmitted;
+    /// the remaining ones are freed by the caller. Every frame taken is reported as transmitted,
+    /// so a driver should only take a frame it is able to queue, see [`XdpFrames::peek_data`].
+    /// `flags` may contain `XDP_XMIT_FLUSH`.
+    fn xdp_xmit(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _frames: &mut XdpFrames<'_>,
+        _flags: u32,
+    ) {
+    }
+}
+
+/// Corresponds to the kernel's `struct ethtool_ops`.
+///
+/// Installed with [`Registration::set_ethtool_ops`]; devices without it keep the kernel's default
+/// ethtool support. `Data` must be the same type as [`DeviceOperations::Data`] of the device,
+/// since both are borrowed from the same driver data.
+#[vtable]
+pub trait EthtoolOps {
+    /// The pointer type that will be used to hold driver-defined data type.
+    type Data: PointerWrapper + Send + Sync = ();
+
+    /// Corresponds to `get_ringparam` in `struct ethtool_ops`.
+    fn get_ringparam(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _ring: &mut EthtoolRingParam,
+    ) {
+    }
+
+    /// Corresponds to `set_ringparam` in `struct ethtool_ops`.
+    fn set_ringparam(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _ring: &EthtoolRingParam,
+    ) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Coalescing parameters accepted by [`EthtoolOps::set_coalesce`], a combination of the
+    /// constants in [`coalesce`]. The ethtool core rejects requests that change any other parameter.
+    const SUPPORTED_COALESCE_PARAMS: u32 = 0;
+
+    /// Corresponds to `get_coalesce` in `struct ethtool_ops`.
+    fn get_coalesce(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _coal: &mut EthtoolCoalesce,
+    ) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Corresponds to `set_coalesce` in `struct ethtool_ops`.
+    fn set_coalesce(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _coal: &EthtoolCoalesce,
+    ) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Corresponds to `get_sset_count` in `struct ethtool_ops`.
+    ///
+    /// Returns the number of strings in string set `sset`, such as [`ETH_SS_STATS`].
+    fn get_sset_count(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _sset: u32,
+    ) -> Result<u32> {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Corresponds to `get_strings` in `struct ethtool_ops`.
+    fn get_strings(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _sset: u32,
+        _strings: &mut EthtoolStrings<'_>,
+    ) {
+    }
+
+    /// Corresponds to `get_ethtool_stats` in `struct ethtool_ops`.
+    fn get_ethtool_stats(
+        _dev: &Device,
+        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        _stats: &mut EthtoolStats<'_>,
+    ) {
+    }
+}
+
+struct EthtoolVtable<E: EthtoolOps>(PhantomData<E>);
+
+impl<E: EthtoolOps> EthtoolVtable<E> {
+    const OPS: bindings::ethtool_ops = bindings::ethtool_ops {
+        // `cap_link_lanes_supported` is the only bitfield and stays clear.
+        _bitfield_align_1: [],
+        _bitfield_1: bindings::__BindgenBitfieldUnit::new([0; 1]),
+        supported_coalesce_params: <E>::SUPPORTED_COALESCE_PARAMS,
+        supported_ring_params: 0,
+        get_drvinfo: None,
+        get_regs_len: None,
+        get_regs: None,
+        get_wol: None,
+        set_wol: None,
+        get_msglevel: None,
+        set_msglevel: None,
+        nway_reset: None,
+        get_link: None,
+        get_link_ext_state: None,
+        get_eeprom_len: None,
+        get_eeprom: None,
+        set_eeprom: None,
+        get_coalesce: if <E>::HAS_GET_COALESCE {
+            Some(Self::get_coalesce_callback)
+        } else {
+            None
+        },
+        set_coalesce: if <E>::HAS_SET_COALESCE {
+            Some(Self::set_coalesce_callback)
+        } else {
+            None
+        },
+        get_ringparam: if <E>::HAS_GET_RINGPARAM {
+            Some(Self::get_ringparam_callback)
+        } else {
+            None
+        },
+        set_ringparam: if <E>::HAS_SET_RINGPARAM {
+            Some(Self::set_ringparam_callback)
+        } else {
+            None
+        },
+        get_pause_stats: None,
+        get_pauseparam: None,
+        set_pauseparam: None,
+        self_test: None,
+        get_strings: if <E>::HAS_GET_STRINGS {
+            Some(Self::get_strings_callback)
+        } else {
+            None
+        },
+        set_phys_id: None,
+        get_ethtool_stats: if <E>::HAS_GET_ETHTOOL_STATS {
+            Some(Self::get_ethtool_stats_callback)
+        } else {
+            None
+        },
+        begin: None,
+        complete: None,
+        get_priv_flags: None,
+        set_priv_flags: None,
+        get_sset_count: if <E>::HAS_GET_SSET_COUNT {
+            Some(Self::get_sset_count_callback)
+        } else {
+            None
+        },
+        get_rxnfc: None,
+        set_rxnfc: None,
+        flash_device: None,
+        reset: None,
+        get_rxfh_key_size: None,
+        get_rxfh_indir_size: None,
+        get_rxfh: None,
+        set_rxfh: None,
+        get_rxfh_context: None,
+        set_rxfh_context: None,
+        get_channels: None,
+        set_channels: None,
+        get_dump_flag: None,
+        get_dump_data: None,
+        set_dump: None,
+        get_ts_info: None,
+        get_module_info: None,
+        get_module_eeprom: None,
+        get_eee: None,
+        set_eee: None,
+        get_tunable: None,
+        set_tunable: None,
+        get_per_queue_coalesce: None,
+        set_per_queue_coalesce: None,
+        get_link_ksettings: None,
+        set_link_ksettings: None,
+        get_fec_stats: None,
+        get_fecparam: None,
+        set_fecparam: None,
+        get_ethtool_phy_stats: None,
+        get_phy_tunable: None,
+        set_phy_tunable: None,
+        get_module_eeprom_by_page: None,
+        get_eth_phy_stats: None,
+        get_eth_mac_stats: None,
+        get_eth_ctrl_stats: None,
+        get_rmon_stats: None,
+        get_module_power_mode: None,
+        set_module_power_mode: None,
+    };
+
+    unsafe extern "C" fn get_ringparam_callback(
+        netdev: *mut bindings::net_device,
+        ring: *mut bindings::ethtool_ringparam,
+        _kernel_ring: *mut bindings::kernel_ethtool_ringparam,
+        _extack: *mut bindings::netlink_ext_ack,
+    ) {
+        // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+        let dev = unsafe { Device::from_ptr(netdev) };
+        // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+        let data = unsafe { E::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+
+        E::get_ringparam(dev, data, &mut EthtoolRingParam { ptr: ring });
+    }
+
+    unsafe extern "C" fn set_ringparam_callback(
+        netdev: *mut bindings::net_device,
+        ring: *mut bindings::ethtool_ringparam,
+        _kernel_ring: *mut bindings::kernel_ethtool_ringparam,
+        _extack: *mut bindings::netlink_ext_ack,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+            let dev = unsafe { Device::from_ptr(netdev) };
+            // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+            let data = unsafe { E::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+            E::set_ringparam(dev, data, &EthtoolRingParam { ptr: ring })?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn get_coalesce_callback(
+        netdev: *mut bindings::net_device,
+        coal: *mut bindings::ethtool_coalesce,
+        _kernel_coal: *mut bindings::kernel_ethtool_coalesce,
+        _extack: *mut bindings::netlink_ext_ack,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
+            let dev = unsafe { Device::from_ptr(netdev) };
+            // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+            let data = unsafe { E::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+            E::get_coalesce(dev, data, &mut EthtoolCoalesce { ptr: coal })?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn set_coalesce_callback(
+        netdev: *mut bindings::net_device,
+        coal: *mut bindings::ethtool_coalesce,
+        _kernel_coal: *mut bindings::kernel_ethtool_coalesce,
+        _extack: *mut bindings::netlink_ext_ack,
+    ) -> core::ffi::c_int {
         from_kernel_result! {
             // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
             let dev = unsafe { Device::from_ptr(netdev) };
             // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
-            let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
-            T::open(dev, data)?;
+            let data = unsafe { E::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+            E::set_coalesce(dev, data, &EthtoolCoalesce { ptr: coal })?;
             Ok(0)
         }
     }
 
-    unsafe extern "C" fn stop_callback(netdev: *mut bindings::net_device) -> core::ffi::c_int {
+    unsafe extern "C" fn get_sset_count_callback(
+        netdev: *mut bindings::net_device,
+        sset: core::ffi::c_int,
+    ) -> core::ffi::c_int {
         from_kernel_result! {
             // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
             let dev = unsafe { Device::from_ptr(netdev) };
             // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
-            let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
-            T::stop(dev, data)?;
-            Ok(0)
+            let data = unsafe { E::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+            let count = E::get_sset_count(dev, data, sset as u32)?;
+            Ok(count.try_into()?)
         }
     }
 
-    unsafe extern "C" fn start_xmit_callback(
-        skb: *mut bindings::sk_buff,
+    unsafe extern "C" fn get_strings_callback(
         netdev: *mut bindings::net_device,
-    ) -> bindings::netdev_tx_t {
+        sset: u32,
+        buf: *mut u8,
+    ) {
         // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
         let dev = unsafe { Device::from_ptr(netdev) };
-        // SAFETY: The C API guarantees that `sk_buff` isn't released while this function is running.
-        let skb = unsafe { SkBuff::from_ptr(skb) };
         // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
-        let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
-        T::start_xmit(skb, dev, data) as bindings::netdev_tx_t
+        let data = unsafe { E::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+        // The core sized `buf` with the count returned by `get_sset_count` just before.
+        let count = E::get_sset_count(dev, data, sset).unwrap_or(0) as usize;
+        // SAFETY: `buf` holds `count` strings of `ETH_GSTRING_LEN` bytes each.
+        let buf = unsafe { core::slice::from_raw_parts_mut(buf.cast(), count) };
+        E::get_strings(dev, data, sset, &mut EthtoolStrings { buf, pos: 0 });
     }
 
-    unsafe extern "C" fn get_stats64_callback(
+    unsafe extern "C" fn get_ethtool_stats_callback(
         netdev: *mut bindings::net_device,
-        storage: *mut bindings::rtnl_link_stats64,
+        stats: *mut bindings::ethtool_stats,
+        buf: *mut u64,
     ) {
         // SAFETY: The C API guarantees that `net_device` isn't released while this function is running.
         let dev = unsafe { Device::from_ptr(netdev) };
         // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
-        let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
-
-        T::get_stats64(dev, data, &mut RtnlLinkStats64 { ptr: storage });
-    }
-}
-
-/// Corresponds to the kernel's `struct rtnl_link_stats64`.
-pub struct RtnlLinkStats64 {
-    ptr: *mut bindings::rtnl_link_stats64,
-}
-
-impl RtnlLinkStats64 {
-    /// Set rx_bytes.
-    pub fn set_rx_bytes(&mut self, value: u64) {
-        // SAFETY: By the type invariants, `self.ptr` is valid.
-        unsafe { (*self.ptr).rx_bytes = value }
-    }
-
-    /// Set rx_packets.
-    pub fn set_rx_packets(&mut self, value: u64) {
-        // SAFETY: By the type invariants, `self.ptr` is valid.
-        unsafe { (*self.ptr).rx_packets = value }
-    }
-
-    /// Set tx_bytes.
-    pub fn set_tx_bytes(&mut self, value: u64) {
-        // SAFETY: By the type invariants, `self.ptr` is valid.
-        unsafe { (*self.ptr).tx_bytes = value }
-    }
-
-    /// Set tx_packets.
-    pub fn set_tx_packets(&mut self, value: u64) {
-        // SAFETY: By the type invariants, `self.ptr` is valid.
-        unsafe { (*self.ptr).tx_packets = value }
-    }
-}
-
-/// Driver transmit return codes.
-#[repr(i32)]
-pub enum NetdevTx {
-    /// Driver took care of packet.
-    Ok = bindings::netdev_tx_NETDEV_TX_OK,
-    /// Driver tx path was busy.
-    Busy = bindings::netdev_tx_NETDEV_TX_BUSY,
-}
-
-// SAFETY: `Registration` does not expose any of its state across threads.
-unsafe impl<T: DeviceOperations> Sync for Registration<T> {}
-
-// SAFETY: `Registration` is not restricted to a single thread,
-// its `T::Data` is also `Send` so it may be moved to different threads.
-#[allow(clippy::non_send_fields_in_send_ty)]
-unsafe impl<T: DeviceOperations> Send for Registration<T> {}
-
-/// Corresponds to the kernel's `struct net_device_ops`.
-#[vtable]
-pub trait DeviceOperations {
-    /// The pointer type that will be used to hold driver-defined data type.
-    type Data: PointerWrapper + Send + Sync = ();
-
-    /// Corresponds to `ndo_open` in `struct net_device_ops`.
-    fn open(dev: &Device, data: <Self::Data as PointerWrapper>::Borrowed<'_>) -> Result;
-
-    /// Corresponds to `ndo_stop` in `struct net_device_ops`.
-    fn stop(dev: &Device, data: <Self::Data as PointerWrapper>::Borrowed<'_>) -> Result;
-
-    /// Corresponds to `ndo_start_xmit` in `struct net_device_ops`.
-    fn start_xmit(
-        skb: &SkBuff,
-        dev: &Device,
-        data: <Self::Data as PointerWrapper>::Borrowed<'_>,
-    ) -> NetdevTx;
-
-    /// Corresponds to `ndo_get_stats64` in `struct net_device_ops`.
-    fn get_stats64(
-        _dev: &Device,
-        _data: <Self::Data as PointerWrapper>::Borrowed<'_>,
-        _storage: &mut RtnlLinkStats64,
-    ) {
+        let data = unsafe { E::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+        // SAFETY: The core allocated `buf` with room for `n_stats` values.
+        let buf = unsafe { core::slice::from_raw_parts_mut(buf, (*stats).n_stats as usize) };
+        E::get_ethtool_stats(dev, data, &mut EthtoolStats { buf, pos: 0 });
     }
 }
 
@@ -485,6 +2106,9 @@ impl Napi {
     }
 
     /// Disable NAPI scheduling.
+    ///
+    /// Waits for a poll that is already running to finish, so that afterwards the rings can be
+    /// torn down until [`Napi::enable`] is called again. Might sleep.
     pub fn disable(&self) {
         // SAFETY: The existence of a shared reference means `self.0` is valid.
         unsafe {
@@ -492,6 +2116,28 @@ impl Napi {
         }
     }
 
+    /// Waits until a poll that is currently running has finished, corresponds to
+    /// `napi_synchronize`.
+    ///
+    /// Unlike [`Napi::disable`], the instance may be scheduled again right afterwards, so this is
+    /// meant for making a change made outside of the poll routine (e.g. masking the interrupt)
+    /// visible to it. Might sleep.
+    pub fn synchronize(&self) {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::napi_synchronize(self.0.get()) }
+    }
+
+    /// Removes the NAPI instance from its network device, corresponds to `netif_napi_del`.
+    ///
+    /// The instance must already be disabled with [`Napi::disable`] and must not be enabled or
+    /// scheduled again afterwards. It has to be called before the network device is freed.
+    pub fn del(&self) {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe {
+            bindings::netif_napi_del(self.0.get());
+        }
+    }
+
     /// Schedule NAPI poll routine to be called if it is not already running.
     pub fn schedule(&self) {
         // SAFETY: The existence of a shared reference means `self.0` is valid.
@@ -503,11 +2149,25 @@ impl Napi {
     }
 
     /// Marks NAPI processing as complete.
-    pub fn complete_done(&self, work_done: i32) {
+    ///
+    /// Returns `false` if the NAPI instance was rescheduled in the meantime, in which case the
+    /// driver must not re-enable its interrupts.
+    pub fn complete_done(&self, work_done: i32) -> bool {
         // SAFETY: The existence of a shared reference means `self.0` is valid.
-        unsafe {
-            bindings::napi_complete_done(self.0.get(), work_done);
-        }
+        unsafe { bindings::napi_complete_done(self.0.get(), work_done) }
+    }
+
+    /// Allocates an skbuff for rx in NAPI context, corresponds to `napi_alloc_skb`.
+    ///
+    /// Small buffers are carved out of the per-CPU NAPI page fragment cache, which is cheaper than
+    /// [`Device::alloc_skb_ip_align`]. The headroom is already reserved with the IP header placed
+    /// at an aligned offset. Must only be called from the NAPI poll routine.
+    pub fn alloc_skb(&self, length: u32) -> Result<ARef<SkBuff>> {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        let skb = unsafe { bindings::napi_alloc_skb(self.0.get(), length) };
+        let skb = NonNull::new(skb as *mut SkBuff).ok_or(ENOMEM)?;
+        // SAFETY: `napi_alloc_skb` returns a new skb with a refcount of one, which we now own.
+        Ok(unsafe { ARef::from_raw(skb) })
     }
 
     /// Sends the skb up the stack.
@@ -603,6 +2263,101 @@ pub fn init_ns() -> &'static Namespace {
     unsafe { &*core::ptr::addr_of!(bindings::init_net).cast() }
 }
 
+/// The checksum state of an skb (`skb->ip_summed`).
+///
+/// See the comment at the top of `include/linux/skbuff.h` for what each state means on receive
+/// and transmit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum ChecksumState {
+    /// The checksum hasn't been verified (receive) or was already filled in (transmit).
+    None = bindings::CHECKSUM_NONE as u8,
+
+    /// The device verified the checksum of the packet (receive only).
+    Unnecessary = bindings::CHECKSUM_UNNECESSARY as u8,
+
+    /// The device computed the checksum of the whole packet and stored it in
+    /// [`SkBuff::csum`] (receive only).
+    Complete = bindings::CHECKSUM_COMPLETE as u8,
+
+    /// The device must compute the checksum from [`SkBuff::csum_start`] to the end of the packet
+    /// and store it at [`SkBuff::csum_offset`] (transmit only).
+    Partial = bindings::CHECKSUM_PARTIAL as u8,
+}
+
+impl ChecksumState {
+    fn from_raw(raw: u8) -> Self {
+        match raw as u32 {
+            bindings::CHECKSUM_UNNECESSARY => Self::Unnecessary,
+            bindings::CHECKSUM_COMPLETE => Self::Complete,
+            bindings::CHECKSUM_PARTIAL => Self::Partial,
+            _ => Self::None,
+        }
+    }
+}
+
+/// The protocol of a VLAN tag, see [`SkBuff::vlan_hwaccel_put_tag`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VlanProto {
+    /// 802.1Q customer tag (`ETH_P_8021Q`).
+    Ctag,
+
+    /// 802.1ad service tag (`ETH_P_8021AD`).
+    Stag,
+}
+
+impl VlanProto {
+    /// Returns the ethertype in network byte order, as stored in `skb->vlan_proto`.
+    fn to_be(self) -> u16 {
+        let proto = match self {
+            Self::Ctag => bindings::ETH_P_8021Q,
+            Self::Stag => bindings::ETH_P_8021AD,
+        };
+        (proto as u16).to_be()
+    }
+}
+
+/// A paged fragment of an skb, wraps `skb_frag_t`.
+///
+/// Obtained with [`SkBuff::frag`] or [`SkBuff::frags`], and only valid while the skb is.
+#[repr(transparent)]
+pub struct SkbFrag(UnsafeCell<bindings::skb_frag_t>);
+
+impl SkbFrag {
+    /// Returns the raw pointer to the page holding the fragment, corresponds to
+    /// `skb_frag_page`.
+    pub(crate) fn raw_page(&self) -> *mut bindings::page {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_frag_page(self.0.get()) }
+    }
+
+    /// Returns the offset of the fragment within its page, corresponds to `skb_frag_off`.
+    pub fn page_offset(&self) -> u32 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_frag_off(self.0.get()) }
+    }
+
+    /// Returns the length of the fragment in bytes, corresponds to `skb_frag_size`.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_frag_size(self.0.get()) }
+    }
+
+    /// Returns the data of the fragment, or `None` if its page isn't permanently mapped into the
+    /// kernel's address space (highmem).
+    pub fn data(&self) -> Option<&[u8]> {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        let addr = unsafe { bindings::skb_frag_address_safe(self.0.get()) };
+        if addr.is_null() {
+            return None;
+        }
+        // SAFETY: A non-null address covers the `len` bytes of the fragment, which stay valid as
+        // long as the skb holds its page reference.
+        Some(unsafe { core::slice::from_raw_parts(addr as *const u8, self.len() as usize) })
+    }
+}
+
 /// Wraps the kernel's `struct sk_buff`.
 #[repr(transparent)]
 pub struct SkBuff(UnsafeCell<bindings::sk_buff>);
@@ -620,6 +2375,11 @@ impl SkBuff {
         unsafe { &*ptr.cast() }
     }
 
+    /// Returns the raw `struct sk_buff` pointer.
+    pub(crate) fn raw_skb(&self) -> *mut bindings::sk_buff {
+        self.0.get()
+    }
+
     /// Returns the remaining data in the buffer's first segment.
     pub fn head_data(&self) -> &[u8] {
         // SAFETY: The existence of a shared reference means that the refcount is nonzero.
@@ -645,6 +2405,59 @@ impl SkBuff {
         unsafe { core::ptr::addr_of!((*self.0.get()).data_len).read() }
     }
 
+    /// Returns the TX queue the skb was assigned to by `ndo_select_queue`.
+    pub fn queue_mapping(&self) -> u16 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_get_queue_mapping(self.0.get()) }
+    }
+
+    /// Returns the shared info of the skb, corresponds to `skb_shinfo`.
+    fn shinfo(&self) -> *const bindings::skb_shared_info {
+        // SAFETY: The existence of a shared reference means `self.0` is valid, and the
+        // `struct skb_shared_info` of an skb always lives at `skb_end_pointer(skb)`.
+        unsafe { bindings::skb_end_pointer(self.0.get()) as *const bindings::skb_shared_info }
+    }
+
+    /// Returns the number of paged fragments (`skb_shinfo(skb)->nr_frags`).
+    pub fn nr_frags(&self) -> u8 {
+        // SAFETY: `shinfo` is valid for as long as the skb is.
+        unsafe { addr_of!((*self.shinfo()).nr_frags).read() }
+    }
+
+    /// Returns paged fragment `index`, or `None` if the skb has fewer fragments.
+    pub fn frag(&self, index: usize) -> Option<&SkbFrag> {
+        if index >= self.nr_frags() as usize {
+            return None;
+        }
+        // SAFETY: `shinfo` is valid for as long as the skb is, and the first `nr_frags` entries
+        // of `frags` are initialised. `SkbFrag` is transparent over `skb_frag_t`.
+        Some(unsafe { &*addr_of!((*self.shinfo()).frags[index]).cast() })
+    }
+
+    /// Returns an iterator over the paged fragments, in packet order after
+    /// [`SkBuff::head_data`].
+    pub fn frags(&self) -> impl Iterator<Item = &SkbFrag> + '_ {
+        (0..self.nr_frags() as usize).filter_map(move |i| self.frag(i))
+    }
+
+    /// Returns the size of the segments a GSO packet must be cut into (`gso_size`), or 0 if
+    /// the skb is not a GSO packet.
+    pub fn gso_size(&self) -> u16 {
+        // SAFETY: `shinfo` is valid for as long as the skb is.
+        unsafe { addr_of!((*self.shinfo()).gso_size).read() }
+    }
+
+    /// Returns the number of segments of a GSO packet (`gso_segs`).
+    pub fn gso_segs(&self) -> u16 {
+        // SAFETY: `shinfo` is valid for as long as the skb is.
+        unsafe { addr_of!((*self.shinfo()).gso_segs).read() }
+    }
+
+    /// Returns whether the skb is a GSO packet, corresponds to `skb_is_gso`.
+    pub fn is_gso(&self) -> bool {
+        self.gso_size() != 0
+    }
+
     /// Returns the packet's protocol ID.
     pub fn eth_type_trans(&self, dev: &Device) -> u16 {
         // SAFETY: The existence of a shared reference means `self.0` is valid.
@@ -663,12 +2476,216 @@ impl SkBuff {
         unsafe { bindings::napi_consume_skb(self.0.get(), budget) }
     }
 
+    /// Drops the skb in any context, corresponds to `dev_kfree_skb_any`.
+    ///
+    /// Used by `start_xmit` to drop a packet it can't transmit while still returning
+    /// [`NetdevTx::Ok`], which hands the core's reference over to the driver.
+    pub fn free_any(&self) {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::dev_kfree_skb_any(self.0.get()) }
+    }
+
+    /// Frees the skb in any context after it has been sent, corresponds to
+    /// `dev_consume_skb_any`.
+    ///
+    /// Unlike [`SkBuff::free_any`], the packet is not reported as dropped.
+    pub fn consume_any(&self) {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::dev_consume_skb_any(self.0.get()) }
+    }
+
     /// Extends the used data area of the buffer.
-    pub fn put(&self, len: u32) {
+    ///
+    /// Returns the newly added area at the tail of the buffer so that it can be filled in.
+    #[allow(clippy::mut_from_ref)]
+    pub fn put(&self, len: u32) -> &mut [u8] {
+        // SAFETY: The existence of a shared reference means `self.0` is valid. `skb_put` panics
+        // if there isn't enough tailroom, so `len` bytes are valid from the returned pointer and
+        // nobody else refers to this freshly added area.
+        unsafe {
+            let tail = bindings::skb_put(self.0.get(), len);
+            core::slice::from_raw_parts_mut(tail as *mut u8, len as usize)
+        }
+    }
+
+    /// Adds data to the start of the buffer, corresponds to `skb_push`.
+    ///
+    /// Returns the newly added area at the head of the buffer so that it can be filled in.
+    #[allow(clippy::mut_from_ref)]
+    pub fn push(&self, len: u32) -> &mut [u8] {
+        // SAFETY: The existence of a shared reference means `self.0` is valid. `skb_push` panics
+        // if there isn't enough headroom, so `len` bytes are valid from the returned pointer.
+        unsafe {
+            let data = bindings::skb_push(self.0.get(), len);
+            core::slice::from_raw_parts_mut(data as *mut u8, len as usize)
+        }
+    }
+
+    /// Removes `len` bytes from the start of the buffer, corresponds to `skb_pull`.
+    ///
+    /// Fails with `EINVAL` if the buffer holds less than `len` bytes.
+    pub fn pull(&self, len: u32) -> Result {
         // SAFETY: The existence of a shared reference means `self.0` is valid.
+        let data = unsafe { bindings::skb_pull(self.0.get(), len) };
+        if data.is_null() {
+            return Err(EINVAL);
+        }
+        Ok(())
+    }
+
+    /// Reserves `len` bytes of headroom in an empty buffer, corresponds to `skb_reserve`.
+    pub fn reserve(&self, len: i32) {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_reserve(self.0.get(), len) }
+    }
+
+    /// Returns the number of bytes of free space at the head of the buffer.
+    pub fn headroom(&self) -> u32 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_headroom(self.0.get()) }
+    }
+
+    /// Returns the number of bytes of free space at the tail of the buffer.
+    pub fn tailroom(&self) -> i32 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_tailroom(self.0.get()) }
+    }
+
+    /// Returns whether part of the data lives in paged fragments.
+    pub fn is_nonlinear(&self) -> bool {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_is_nonlinear(self.0.get()) }
+    }
+
+    /// Copies all paged fragments into the linear data area, corresponds to `skb_linearize`.
+    ///
+    /// Afterwards [`SkBuff::head_data`] covers the whole packet.
+    pub fn linearize(&self) -> Result {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        to_result(unsafe { bindings::skb_linearize(self.0.get()) })
+    }
+
+    /// Tells the stack the device already verified the checksum, corresponds to setting
+    /// `ip_summed` to `CHECKSUM_UNNECESSARY`.
+    pub fn checksum_unnecessary(&self) {
+        self.set_ip_summed(ChecksumState::Unnecessary)
+    }
+
+    /// Returns the checksum state of the skb (`ip_summed`).
+    pub fn ip_summed(&self) -> ChecksumState {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        let raw = unsafe { (*self.0.get()).__bindgen_anon_5.headers.as_ref().ip_summed() };
+        ChecksumState::from_raw(raw)
+    }
+
+    /// Sets the checksum state of the skb (`ip_summed`).
+    ///
+    /// On receive, [`ChecksumState::Complete`] must be paired with [`SkBuff::csum_set`].
+    pub fn set_ip_summed(&self, state: ChecksumState) {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe {
+            (*self.0.get())
+                .__bindgen_anon_5
+                .headers
+                .as_mut()
+                .set_ip_summed(state as u8)
+        }
+    }
+
+    /// Returns the checksum of the packet data (`csum`), only meaningful on receive with
+    /// [`ChecksumState::Complete`].
+    pub fn csum(&self) -> u32 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid, and all members of
+        // the `csum` union are plain integers.
+        unsafe {
+            addr_of!((*self.0.get()).__bindgen_anon_5.headers.as_ref().__bindgen_anon_1.csum)
+                .read()
+        }
+    }
+
+    /// Sets the checksum of the packet data computed by the device, see
+    /// [`ChecksumState::Complete`].
+    pub fn csum_set(&self, csum: u32) {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe {
+            addr_of_mut!((*self.0.get()).__bindgen_anon_5.headers.as_mut().__bindgen_anon_1.csum)
+                .write(csum)
+        }
+    }
+
+    /// Returns the offset from the start of the buffer's head at which checksumming starts
+    /// (`csum_start`), only meaningful on transmit with [`ChecksumState::Partial`].
+    pub fn csum_start(&self) -> u16 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid, and all members of
+        // the `csum` union are plain integers.
+        unsafe {
+            addr_of!(
+                (*self.0.get())
+                    .__bindgen_anon_5
+                    .headers
+                    .as_ref()
+                    .__bindgen_anon_1
+                    .__bindgen_anon_1
+                    .csum_start
+            )
+            .read()
+        }
+    }
+
+    /// Returns the offset from [`SkBuff::csum_start`] at which the checksum is stored
+    /// (`csum_offset`), only meaningful on transmit with [`ChecksumState::Partial`].
+    pub fn csum_offset(&self) -> u16 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid, and all members of
+        // the `csum` union are plain integers.
         unsafe {
-            bindings::skb_put(self.0.get(), len);
+            addr_of!(
+                (*self.0.get())
+                    .__bindgen_anon_5
+                    .headers
+                    .as_ref()
+                    .__bindgen_anon_1
+                    .__bindgen_anon_1
+                    .csum_offset
+            )
+            .read()
+        }
+    }
+
+    /// Returns the offset of [`SkBuff::csum_start`] from the start of the packet data,
+    /// corresponds to `skb_checksum_start_offset`.
+    ///
+    /// This is what the `CSS` field of a transmit context descriptor wants.
+    pub fn checksum_start_offset(&self) -> i32 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_checksum_start_offset(self.0.get()) }
+    }
+
+    /// Records a VLAN tag stripped by the device, corresponds to `__vlan_hwaccel_put_tag`.
+    ///
+    /// Used on receive with [`Features::HW_VLAN_CTAG_RX`], so that the stack sees the tag without
+    /// it being in the packet data.
+    pub fn vlan_hwaccel_put_tag(&self, proto: VlanProto, tci: u16) {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::__vlan_hwaccel_put_tag(self.0.get(), proto.to_be(), tci) }
+    }
+
+    /// Returns whether the skb carries an out-of-band VLAN tag, corresponds to
+    /// `skb_vlan_tag_present`.
+    ///
+    /// On transmit with [`Features::HW_VLAN_CTAG_TX`], the device must insert this tag.
+    pub fn vlan_tag_present(&self) -> bool {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::skb_vlan_tag_present(self.0.get()) }
+    }
+
+    /// Returns the out-of-band VLAN tag control information, or `None` if
+    /// [`SkBuff::vlan_tag_present`] is false.
+    pub fn vlan_tci(&self) -> Option<u16> {
+        if !self.vlan_tag_present() {
+            return None;
         }
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        Some(unsafe { bindings::skb_vlan_tag_get(self.0.get()) })
     }
 
     /// Set the protocol ID in the skb.
@@ -972,3 +2989,29 @@ impl Drop for TcpStream {
         unsafe { bindings::sock_release(self.sock) };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mac_pton() {
+        assert_eq!(
+            mac_pton(b"52:54:00:aa:BB:cc"),
+            Some([0x52, 0x54, 0x00, 0xaa, 0xbb, 0xcc])
+        );
+        assert_eq!(mac_pton(b"02-00-00-00-00-01"), Some([2, 0, 0, 0, 0, 1]));
+        assert_eq!(mac_pton(b"52:54:00:aa:bb"), None);
+        assert_eq!(mac_pton(b"52:54:00:aa:bb:cc:"), None);
+        assert_eq!(mac_pton(b"52.54.00.aa.bb.cc"), None);
+        assert_eq!(mac_pton(b"52:54:00:aa:bb:cg"), None);
+    }
+
+    #[test]
+    fn test_is_valid_ether_addr() {
+        assert!(is_valid_ether_addr(&[0x52, 0x54, 0, 0x12, 0x34, 0x56]));
+        assert!(!is_valid_ether_addr(&[0; ETH_ALEN]));
+        assert!(!is_valid_ether_addr(&[0xff; ETH_ALEN]));
+        assert!(!is_valid_ether_addr(&[0x01, 0, 0x5e, 0, 0, 1]));
+    }
+}
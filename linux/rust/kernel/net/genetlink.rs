@@ -0,0 +1,396 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Generic netlink families.
+//!
+//! C header: [`include/net/genetlink.h`](../../../../../include/net/genetlink.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/userspace-api/netlink/intro.html>
+
+use crate::{
+    bindings,
+    error::{code::*, from_kernel_result, to_result},
+    str::CStr,
+    types::PointerWrapper,
+    Result, ThisModule,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{cell::UnsafeCell, ffi::c_void, marker::PhantomPinned, pin::Pin};
+
+/// The type of an attribute, checked by the kernel before the command is handed to
+/// [`Family::doit`]. Corresponds to the `NLA_*` values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttrType {
+    /// Any length, not validated. Used for attribute 0, which is reserved.
+    Unspec,
+    /// An 8-bit unsigned integer.
+    U8,
+    /// A 16-bit unsigned integer.
+    U16,
+    /// A 32-bit unsigned integer.
+    U32,
+    /// A 64-bit unsigned integer.
+    U64,
+    /// A null-terminated string.
+    String,
+    /// A flag, i.e. an attribute without payload.
+    Flag,
+    /// A nested set of attributes.
+    Nested,
+}
+
+impl AttrType {
+    fn to_raw(self) -> u8 {
+        (match self {
+            Self::Unspec => bindings::NLA_UNSPEC,
+            Self::U8 => bindings::NLA_U8,
+            Self::U16 => bindings::NLA_U16,
+            Self::U32 => bindings::NLA_U32,
+            Self::U64 => bindings::NLA_U64,
+            Self::String => bindings::NLA_NUL_STRING,
+            Self::Flag => bindings::NLA_FLAG,
+            Self::Nested => bindings::NLA_NESTED,
+        }) as u8
+    }
+}
+
+/// A command accepted by a [`Family`].
+#[derive(Clone, Copy)]
+pub struct Command {
+    cmd: u8,
+    flags: u8,
+}
+
+impl Command {
+    /// Creates a command with number `cmd` that anyone may send.
+    ///
+    /// Command 0 is conventionally reserved as `*_CMD_UNSPEC`.
+    pub const fn new(cmd: u8) -> Self {
+        Self { cmd, flags: 0 }
+    }
+
+    /// Requires `CAP_NET_ADMIN` to send the command, corresponds to `GENL_ADMIN_PERM`.
+    pub const fn admin(self) -> Self {
+        Self {
+            cmd: self.cmd,
+            flags: self.flags | bindings::GENL_ADMIN_PERM as u8,
+        }
+    }
+}
+
+/// A generic netlink family, i.e. a set of commands that userspace can send by family name.
+///
+/// Commands are serialised by the generic netlink core and handled in process context, so
+/// [`Family::doit`] may sleep.
+pub trait Family {
+    /// The data passed to [`Registration::try_new`] and handed back for every command.
+    type Data: PointerWrapper + Send + Sync;
+
+    /// The name userspace resolves to the family id, at most `GENL_NAMSIZ - 1` bytes.
+    const NAME: &'static CStr;
+
+    /// The version of the family, reported to userspace.
+    const VERSION: u32 = 1;
+
+    /// The type of each attribute, indexed by attribute number. Entry 0 is reserved and should
+    /// be [`AttrType::Unspec`]. Requests carrying attributes of a different type or beyond the end
+    /// of this table are rejected before [`Family::doit`] is called.
+    const ATTRS: &'static [AttrType];
+
+    /// The commands of the family.
+    const COMMANDS: &'static [Command];
+
+    /// Handles a request; `info` tells which command it is and gives access to its attributes.
+    fn doit(data: <Self::Data as PointerWrapper>::Borrowed<'_>, info: &Info) -> Result;
+}
+
+/// A registered generic netlink family, corresponds to `genl_register_family`.
+///
+/// The family is unregistered when dropped.
+///
+/// # Examples
+///
+/// ```ignore
+/// const ECHO_CMD_ECHO: u8 = 1;
+/// const ECHO_ATTR_VALUE: u16 = 1;
+///
+/// struct Echo;
+///
+/// impl Family for Echo {
+///     type Data = ();
+///     const NAME: &'static CStr = c_str!("rust_echo");
+///     const ATTRS: &'static [AttrType] = &[AttrType::Unspec, AttrType::U32];
+///     const COMMANDS: &'static [Command] = &[Command::new(ECHO_CMD_ECHO)];
+///
+///     fn doit(_data: (), info: &Info) -> Result {
+///         let value = info.attr(ECHO_ATTR_VALUE).ok_or(EINVAL)?.as_u32()?;
+///         info.reply(ECHO_CMD_ECHO, |msg| msg.put_u32(ECHO_ATTR_VALUE, value))
+///     }
+/// }
+///
+/// let _family = Registration::<Echo>::try_new(module, ())?;
+/// ```
+pub struct Registration<T: Family> {
+    family: bindings::genl_family,
+    ops: Vec<bindings::genl_small_ops>,
+    policy: Vec<bindings::nla_policy>,
+    data: *const c_void,
+    registered: bool,
+    _p: core::marker::PhantomData<T>,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: `data` comes from `T::Data`, which is `Send + Sync`, and the family is only touched by
+// the generic netlink core, which has its own locking.
+unsafe impl<T: Family> Send for Registration<T> {}
+
+// SAFETY: See the `Send` implementation above.
+unsafe impl<T: Family> Sync for Registration<T> {}
+
+impl<T: Family> Registration<T> {
+    /// Registers the family described by `T`, whose commands are handled with `data`.
+    pub fn try_new(module: &'static ThisModule, data: T::Data) -> Result<Pin<Box<Self>>> {
+        let mut this = Pin::from(Box::try_new(Self {
+            // SAFETY: An all-zero `struct genl_family` is valid, the fields are set below.
+            family: unsafe { core::mem::zeroed() },
+            ops: Vec::new(),
+            policy: Vec::new(),
+            data: data.into_pointer(),
+            registered: false,
+            _p: core::marker::PhantomData,
+            _pin: PhantomPinned,
+        })?);
+
+        // SAFETY: The family is not moved out of the pinned box.
+        let this_mut = unsafe { this.as_mut().get_unchecked_mut() };
+
+        let name = T::NAME.as_bytes();
+        if name.len() >= this_mut.family.name.len() {
+            return Err(EINVAL);
+        }
+        for (dst, src) in this_mut.family.name.iter_mut().zip(name) {
+            *dst = *src as _;
+        }
+
+        for command in T::COMMANDS {
+            // SAFETY: An all-zero `struct genl_small_ops` is valid, it has no handlers.
+            let mut op: bindings::genl_small_ops = unsafe { core::mem::zeroed() };
+            op.doit = Some(Self::doit_callback);
+            op.cmd = command.cmd;
+            op.flags = command.flags;
+            this_mut.ops.try_push(op)?;
+        }
+        for attr in T::ATTRS {
+            // SAFETY: An all-zero `struct nla_policy` is valid, it accepts any attribute.
+            let mut policy: bindings::nla_policy = unsafe { core::mem::zeroed() };
+            policy.type_ = attr.to_raw();
+            this_mut.policy.try_push(policy)?;
+        }
+
+        let family = &mut this_mut.family;
+        family.version = T::VERSION;
+        family.module = module.0;
+        family.small_ops = this_mut.ops.as_ptr();
+        family.n_small_ops = this_mut.ops.len().try_into()?;
+        if !this_mut.policy.is_empty() {
+            family.policy = this_mut.policy.as_ptr();
+            family.maxattr = (this_mut.policy.len() - 1) as _;
+        }
+
+        // SAFETY: The family is pinned and stays registered until `self` is dropped. The ops and
+        // the policy it points to live as long as the family and are not modified afterwards.
+        to_result(unsafe { bindings::genl_register_family(family) })?;
+        this_mut.registered = true;
+        Ok(this)
+    }
+
+    unsafe extern "C" fn doit_callback(
+        _skb: *mut bindings::sk_buff,
+        info: *mut bindings::genl_info,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The generic netlink core passes a valid `info` whose family is the `family`
+            // field of a registered `Registration<T>`.
+            let this = unsafe { &*crate::container_of!((*info).family, Self, family) };
+            // SAFETY: `data` was returned by `into_pointer` and is only reclaimed on drop, after
+            // the family has been unregistered.
+            let data = unsafe { T::Data::borrow(this.data) };
+            // SAFETY: `info` is valid for the duration of the call.
+            T::doit(data, unsafe { Info::from_ptr(info) })?;
+            Ok(0)
+        }
+    }
+}
+
+impl<T: Family> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: The family was registered in `try_new`. Unregistering takes the generic
+            // netlink lock, so commands already running have completed when it returns.
+            unsafe { bindings::genl_unregister_family(&self.family) };
+        }
+        // SAFETY: `data` was returned by `into_pointer` in `try_new`, and the callback can no
+        // longer run.
+        unsafe { T::Data::from_pointer(self.data) };
+    }
+}
+
+/// A request being handled, wraps `struct genl_info`.
+#[repr(transparent)]
+pub struct Info(UnsafeCell<bindings::genl_info>);
+
+impl Info {
+    /// Creates a reference to an [`Info`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is valid and remains valid for the lifetime of the
+    /// returned [`Info`] instance.
+    unsafe fn from_ptr<'a>(ptr: *const bindings::genl_info) -> &'a Info {
+        // SAFETY: The safety requirements guarantee the validity of the dereference, while the
+        // `Info` type being transparent makes the cast ok.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the command number of the request.
+    pub fn cmd(&self) -> u8 {
+        // SAFETY: The generic netlink header has been validated before the command is handled.
+        unsafe { (*(*self.0.get()).genlhdr).cmd }
+    }
+
+    /// Returns attribute `ty` of the request, or `None` if the request doesn't carry it.
+    ///
+    /// The attribute has already been checked against [`Family::ATTRS`].
+    pub fn attr(&self, ty: u16) -> Option<Attr<'_>> {
+        let info = self.0.get();
+        // SAFETY: `info` is valid. `attrs` is either null (the family has no attributes) or has
+        // `maxattr + 1` entries, each null or pointing into the request.
+        unsafe {
+            let attrs = (*info).attrs;
+            if attrs.is_null() || ty as u32 > (*(*info).family).maxattr {
+                return None;
+            }
+            let attr = *attrs.add(ty as usize);
+            (!attr.is_null()).then(|| Attr(&*attr))
+        }
+    }
+
+    /// Sends a reply to the sender of the request. `f` adds the attributes of the reply, `cmd`
+    /// is the command number put in its header.
+    ///
+    /// The reply has to fit in a single page (`NLMSG_GOODSIZE`), otherwise `EMSGSIZE` is
+    /// returned and nothing is sent.
+    pub fn reply(&self, cmd: u8, f: impl FnOnce(&mut Message) -> Result) -> Result {
+        // SAFETY: FFI call, there are no requirements.
+        let skb =
+            unsafe { bindings::genlmsg_new(bindings::NLMSG_GOODSIZE as _, bindings::GFP_KERNEL) };
+        if skb.is_null() {
+            return Err(ENOMEM);
+        }
+        let mut msg = Message { skb };
+
+        let info = self.0.get();
+        // SAFETY: `skb` was just allocated and `info` is valid.
+        let hdr = unsafe { bindings::genlmsg_put_reply(skb, info, (*info).family, 0, cmd) };
+        if hdr.is_null() {
+            return Err(EMSGSIZE);
+        }
+        f(&mut msg)?;
+
+        // The message is consumed by `genlmsg_reply`, even on failure.
+        core::mem::forget(msg);
+        // SAFETY: `hdr` was returned by `genlmsg_put_reply` for `skb`, and `info` is valid.
+        unsafe {
+            bindings::genlmsg_end(skb, hdr);
+            to_result(bindings::genlmsg_reply(skb, info))
+        }
+    }
+}
+
+/// An attribute of a request.
+pub struct Attr<'a>(&'a bindings::nlattr);
+
+impl<'a> Attr<'a> {
+    /// Returns the payload of the attribute.
+    pub fn data(&self) -> &'a [u8] {
+        let attr: *const bindings::nlattr = self.0;
+        let len = (self.0.nla_len as usize).saturating_sub(bindings::NLA_HDRLEN as usize);
+        // SAFETY: The netlink core checked that the attribute, whose length includes the header,
+        // lies within the request. The payload starts after the aligned header.
+        unsafe {
+            core::slice::from_raw_parts(attr.cast::<u8>().add(bindings::NLA_HDRLEN as usize), len)
+        }
+    }
+
+    /// Returns the payload of a [`AttrType::U32`] attribute.
+    pub fn as_u32(&self) -> Result<u32> {
+        let bytes = self.data().try_into().map_err(|_| EINVAL)?;
+        Ok(u32::from_ne_bytes(bytes))
+    }
+}
+
+/// A reply being built by [`Info::reply`].
+pub struct Message {
+    skb: *mut bindings::sk_buff,
+}
+
+impl Message {
+    /// Adds an attribute with an arbitrary payload, corresponds to `nla_put`.
+    pub fn put(&mut self, ty: u16, data: &[u8]) -> Result {
+        // SAFETY: `skb` is valid and `data` is valid for reads of `data.len()` bytes.
+        to_result(unsafe {
+            bindings::nla_put(self.skb, ty as _, data.len() as _, data.as_ptr().cast())
+        })
+    }
+
+    /// Adds a [`AttrType::U32`] attribute.
+    pub fn put_u32(&mut self, ty: u16, value: u32) -> Result {
+        self.put(ty, &value.to_ne_bytes())
+    }
+
+    /// Adds a [`AttrType::U64`] attribute, corresponds to `nla_put_u64_64bit`.
+    ///
+    /// Attribute `pad` is inserted before it if needed to align the payload to 8 bytes.
+    pub fn put_u64(&mut self, ty: u16, value: u64, pad: u16) -> Result {
+        let data = value.to_ne_bytes();
+        // SAFETY: `skb` is valid and `data` is valid for reads of 8 bytes.
+        to_result(unsafe {
+            bindings::nla_put_64bit(self.skb, ty as _, 8, data.as_ptr().cast(), pad as _)
+        })
+    }
+
+    /// Adds a [`AttrType::String`] attribute, including the null terminator.
+    pub fn put_string(&mut self, ty: u16, value: &CStr) -> Result {
+        self.put(ty, value.as_bytes_with_nul())
+    }
+
+    /// Adds a [`AttrType::Nested`] attribute whose contents are added by `f`.
+    ///
+    /// Nothing is added if `f` fails.
+    pub fn put_nested(&mut self, ty: u16, f: impl FnOnce(&mut Message) -> Result) -> Result {
+        // SAFETY: `skb` is valid.
+        let start = unsafe { bindings::nla_nest_start(self.skb, ty as _) };
+        if start.is_null() {
+            return Err(EMSGSIZE);
+        }
+        match f(self) {
+            Ok(()) => {
+                // SAFETY: `start` was returned by `nla_nest_start` for `skb`.
+                unsafe { bindings::nla_nest_end(self.skb, start) };
+                Ok(())
+            }
+            Err(e) => {
+                // SAFETY: `start` was returned by `nla_nest_start` for `skb`, and everything
+                // after it was added by `f`.
+                unsafe { bindings::nla_nest_cancel(self.skb, start) };
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for Message {
+    fn drop(&mut self) {
+        // SAFETY: The message was allocated by `genlmsg_new` and not sent.
+        unsafe { bindings::nlmsg_free(self.skb) };
+    }
+}
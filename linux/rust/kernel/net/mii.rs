@@ -0,0 +1,295 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Generic MII (Media Independent Interface) helpers.
+//!
+//! These mirror the helpers in `drivers/net/mii.c` but are layered on top of the [`Mdio`] trait
+//! instead of raw `mdio_read`/`mdio_write` function pointers, so that any driver able to access
+//! its PHY registers can reuse them.
+//!
+//! C header: [`include/linux/mii.h`](../../../../../include/linux/mii.h)
+
+use super::Device;
+use crate::{
+    bindings,
+    error::code::{EINVAL, EOPNOTSUPP},
+    Result,
+};
+
+/// Accessors for the registers of a PHY attached to an MDIO bus.
+pub trait Mdio {
+    /// Reads the PHY register `reg` of the PHY at address `phy_id`.
+    fn mdio_read(&self, phy_id: u8, reg: u8) -> Result<u16>;
+
+    /// Writes `val` to the PHY register `reg` of the PHY at address `phy_id`.
+    fn mdio_write(&self, phy_id: u8, reg: u8, val: u16) -> Result;
+}
+
+/// Duplex mode of a link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Duplex {
+    /// Half duplex.
+    Half,
+    /// Full duplex.
+    Full,
+}
+
+/// Link settings, corresponds to the subset of `struct ethtool_link_ksettings` handled by
+/// `mii_ethtool_get_link_ksettings` and `mii_ethtool_set_link_ksettings`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkSettings {
+    /// Link speed in Mb/s (one of `SPEED_10`, `SPEED_100` or `SPEED_1000`).
+    pub speed: u32,
+    /// Duplex mode.
+    pub duplex: Duplex,
+    /// Whether autonegotiation is enabled.
+    pub autoneg: bool,
+    /// Local advertisement, as a combination of `ADVERTISE_*` bits.
+    pub advertising: u32,
+    /// Link partner advertisement, as a combination of `LPA_*` bits.
+    pub lp_advertising: u32,
+}
+
+/// The argument of the MII ioctls, corresponds to `struct mii_ioctl_data`.
+///
+/// # Invariants
+///
+/// `ptr` is valid for the duration of the ioctl.
+pub struct MiiIoctlData {
+    ptr: *mut bindings::mii_ioctl_data,
+}
+
+impl MiiIoctlData {
+    /// Creates the MII ioctl argument from an `ifreq`, corresponds to `if_mii`.
+    ///
+    /// # Safety
+    ///
+    /// `ifr` must be valid for the duration of the ioctl.
+    pub(crate) unsafe fn from_ifreq(ifr: *mut bindings::ifreq) -> Self {
+        // SAFETY: The caller guarantees `ifr` is valid, `ifr_ifru` is large enough to hold a
+        // `struct mii_ioctl_data`.
+        Self {
+            ptr: unsafe { core::ptr::addr_of_mut!((*ifr).ifr_ifru) }.cast(),
+        }
+    }
+
+    /// Returns the PHY address the request is for.
+    pub fn phy_id(&self) -> u16 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).phy_id }
+    }
+
+    /// Sets the PHY address, reported by `SIOCGMIIPHY`.
+    pub fn set_phy_id(&mut self, phy_id: u16) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).phy_id = phy_id }
+    }
+
+    /// Returns the register the request is for.
+    pub fn reg_num(&self) -> u16 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).reg_num }
+    }
+
+    /// Returns the value to write for `SIOCSMIIREG`.
+    pub fn val_in(&self) -> u16 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).val_in }
+    }
+
+    /// Sets the value read by `SIOCGMIIREG`.
+    pub fn set_val_out(&mut self, val: u16) {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).val_out = val }
+    }
+}
+
+/// A PHY reachable through an [`Mdio`] implementation, corresponds to `struct mii_if_info`.
+pub struct MiiIf<'a, M: Mdio + ?Sized> {
+    mdio: &'a M,
+    phy_id: u8,
+    supports_gmii: bool,
+}
+
+impl<'a, M: Mdio + ?Sized> MiiIf<'a, M> {
+    /// Creates a new MII interface for the PHY at address `phy_id`.
+    pub fn new(mdio: &'a M, phy_id: u8, supports_gmii: bool) -> Self {
+        Self {
+            mdio,
+            phy_id,
+            supports_gmii,
+        }
+    }
+
+    fn read(&self, reg: u32) -> Result<u32> {
+        Ok(self.mdio.mdio_read(self.phy_id, reg as u8)?.into())
+    }
+
+    fn write(&self, reg: u32, val: u32) -> Result {
+        self.mdio.mdio_write(self.phy_id, reg as u8, val as u16)
+    }
+
+    /// Returns whether the link is up, corresponds to `mii_link_ok`.
+    pub fn link_ok(&self) -> Result<bool> {
+        // The link status bit is latched low, the first read clears a stale value.
+        self.read(bindings::MII_BMSR)?;
+        Ok(self.read(bindings::MII_BMSR)? & bindings::BMSR_LSTATUS != 0)
+    }
+
+    /// Restarts autonegotiation if it is enabled, corresponds to `mii_nway_restart`.
+    pub fn nway_restart(&self) -> Result {
+        let bmcr = self.read(bindings::MII_BMCR)?;
+        if bmcr & bindings::BMCR_ANENABLE == 0 {
+            return Err(EINVAL);
+        }
+        self.write(bindings::MII_BMCR, bmcr | bindings::BMCR_ANRESTART)
+    }
+
+    /// Updates the carrier state of `dev` from the PHY link state, corresponds to
+    /// `mii_check_link`.
+    ///
+    /// Returns whether the link is up.
+    pub fn check_link(&self, dev: &Device) -> Result<bool> {
+        let link = self.link_ok()?;
+        if link && !dev.netif_carrier_ok() {
+            dev.netif_carrier_on();
+        } else if !link && dev.netif_carrier_ok() {
+            dev.netif_carrier_off();
+        }
+        Ok(link)
+    }
+
+    /// Reads the current link settings, corresponds to `mii_ethtool_get_link_ksettings`.
+    pub fn ethtool_get(&self) -> Result<LinkSettings> {
+        let bmcr = self.read(bindings::MII_BMCR)?;
+        let advertising = self.read(bindings::MII_ADVERTISE)?;
+        let (ctrl1000, stat1000) = if self.supports_gmii {
+            (
+                self.read(bindings::MII_CTRL1000)?,
+                self.read(bindings::MII_STAT1000)?,
+            )
+        } else {
+            (0, 0)
+        };
+
+        if bmcr & bindings::BMCR_ANENABLE == 0 {
+            let speed = if bmcr & bindings::BMCR_SPEED1000 != 0 && self.supports_gmii {
+                bindings::SPEED_1000
+            } else if bmcr & bindings::BMCR_SPEED100 != 0 {
+                bindings::SPEED_100
+            } else {
+                bindings::SPEED_10
+            };
+            let duplex = if bmcr & bindings::BMCR_FULLDPLX != 0 {
+                Duplex::Full
+            } else {
+                Duplex::Half
+            };
+            return Ok(LinkSettings {
+                speed,
+                duplex,
+                autoneg: false,
+                advertising,
+                lp_advertising: 0,
+            });
+        }
+
+        let lp_advertising = self.read(bindings::MII_LPA)?;
+        let common = advertising & lp_advertising;
+        let (speed, duplex) = if ctrl1000 & bindings::ADVERTISE_1000FULL != 0
+            && stat1000 & bindings::LPA_1000FULL != 0
+        {
+            (bindings::SPEED_1000, Duplex::Full)
+        } else if ctrl1000 & bindings::ADVERTISE_1000HALF != 0
+            && stat1000 & bindings::LPA_1000HALF != 0
+        {
+            (bindings::SPEED_1000, Duplex::Half)
+        } else if common & bindings::LPA_100FULL != 0 {
+            (bindings::SPEED_100, Duplex::Full)
+        } else if common & bindings::LPA_100HALF != 0 {
+            (bindings::SPEED_100, Duplex::Half)
+        } else if common & bindings::LPA_10FULL != 0 {
+            (bindings::SPEED_10, Duplex::Full)
+        } else {
+            (bindings::SPEED_10, Duplex::Half)
+        };
+
+        Ok(LinkSettings {
+            speed,
+            duplex,
+            autoneg: true,
+            advertising,
+            lp_advertising,
+        })
+    }
+
+    /// Applies new link settings, corresponds to `mii_ethtool_set_link_ksettings`.
+    ///
+    /// With autonegotiation enabled, `settings.advertising` is programmed and negotiation is
+    /// restarted; otherwise the PHY is forced to `settings.speed` and `settings.duplex`.
+    pub fn ethtool_set(&self, settings: &LinkSettings) -> Result {
+        let mask = bindings::ADVERTISE_ALL | bindings::ADVERTISE_100BASE4;
+        if settings.autoneg {
+            let advert = self.read(bindings::MII_ADVERTISE)?;
+            self.write(
+                bindings::MII_ADVERTISE,
+                (advert & !mask) | (settings.advertising & mask),
+            )?;
+
+            if self.supports_gmii {
+                let gmii_mask = bindings::ADVERTISE_1000FULL | bindings::ADVERTISE_1000HALF;
+                let ctrl1000 = self.read(bindings::MII_CTRL1000)?;
+                let ctrl1000 = (ctrl1000 & !gmii_mask) | (settings.advertising & gmii_mask);
+                self.write(bindings::MII_CTRL1000, ctrl1000)?;
+            }
+
+            let bmcr = self.read(bindings::MII_BMCR)?;
+            return self.write(
+                bindings::MII_BMCR,
+                bmcr | bindings::BMCR_ANENABLE | bindings::BMCR_ANRESTART,
+            );
+        }
+
+        let mut bmcr = self.read(bindings::MII_BMCR)?;
+        bmcr &= !(bindings::BMCR_ANENABLE
+            | bindings::BMCR_SPEED100
+            | bindings::BMCR_SPEED1000
+            | bindings::BMCR_FULLDPLX);
+        match settings.speed {
+            bindings::SPEED_1000 if self.supports_gmii => bmcr |= bindings::BMCR_SPEED1000,
+            bindings::SPEED_100 => bmcr |= bindings::BMCR_SPEED100,
+            bindings::SPEED_10 => {}
+            _ => return Err(EINVAL),
+        }
+        if settings.duplex == Duplex::Full {
+            bmcr |= bindings::BMCR_FULLDPLX;
+        }
+        self.write(bindings::MII_BMCR, bmcr)
+    }
+
+    /// Handles the standard MII ioctls, corresponds to `generic_mii_ioctl`.
+    ///
+    /// `SIOCGMIIPHY` reports the address of this PHY and reads the requested register like
+    /// `SIOCGMIIREG`; `SIOCSMIIREG` writes it. The core already checked `CAP_NET_ADMIN` for
+    /// `SIOCSMIIREG`. Other commands fail with `EOPNOTSUPP`.
+    pub fn generic_ioctl(&self, mii_data: &mut MiiIoctlData, cmd: u32) -> Result {
+        // Same masks as `mii_if_info.phy_id_mask` and `reg_num_mask` for plain MII.
+        let phy_id = |d: &MiiIoctlData| (d.phy_id() & 0x1f) as u8;
+        let reg_num = |d: &MiiIoctlData| (d.reg_num() & 0x1f) as u8;
+
+        match cmd {
+            bindings::SIOCGMIIPHY | bindings::SIOCGMIIREG => {
+                if cmd == bindings::SIOCGMIIPHY {
+                    mii_data.set_phy_id(self.phy_id.into());
+                }
+                let val = self.mdio.mdio_read(phy_id(mii_data), reg_num(mii_data))?;
+                mii_data.set_val_out(val);
+                Ok(())
+            }
+            bindings::SIOCSMIIREG => {
+                self.mdio
+                    .mdio_write(phy_id(mii_data), reg_num(mii_data), mii_data.val_in())
+            }
+            _ => Err(EOPNOTSUPP),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Network device notifiers.
+//!
+//! C header: [`include/linux/netdevice.h`](../../../../../include/linux/netdevice.h)
+
+use super::Device;
+use crate::{bindings, error::to_result, types::PointerWrapper, Result};
+use alloc::boxed::Box;
+use core::{ffi::c_void, marker::PhantomPinned, pin::Pin};
+
+/// An event a [`NetdevNotifier`] is told about, corresponds to the `NETDEV_*` values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetdevEvent {
+    /// A device was registered.
+    ///
+    /// Also sent for every existing device when the notifier is registered.
+    Register,
+    /// A device is being unregistered; references to it must be dropped.
+    Unregister,
+    /// A device was brought up.
+    ///
+    /// Also sent for every running device when the notifier is registered.
+    Up,
+    /// A device is about to be brought down.
+    GoingDown,
+    /// A device was brought down.
+    Down,
+    /// The hardware address of a device changed.
+    ChangeAddr,
+    /// A device was renamed.
+    ChangeName,
+    /// The MTU of a device changed.
+    ChangeMtu,
+    /// The carrier state of a device changed.
+    Change,
+    /// Any other event, with its raw `NETDEV_*` value.
+    Other(u64),
+}
+
+impl NetdevEvent {
+    fn from_raw(event: u64) -> Self {
+        match event as u32 {
+            bindings::netdev_cmd_NETDEV_REGISTER => Self::Register,
+            bindings::netdev_cmd_NETDEV_UNREGISTER => Self::Unregister,
+            bindings::netdev_cmd_NETDEV_UP => Self::Up,
+            bindings::netdev_cmd_NETDEV_GOING_DOWN => Self::GoingDown,
+            bindings::netdev_cmd_NETDEV_DOWN => Self::Down,
+            bindings::netdev_cmd_NETDEV_CHANGEADDR => Self::ChangeAddr,
+            bindings::netdev_cmd_NETDEV_CHANGENAME => Self::ChangeName,
+            bindings::netdev_cmd_NETDEV_CHANGEMTU => Self::ChangeMtu,
+            bindings::netdev_cmd_NETDEV_CHANGE => Self::Change,
+            _ => Self::Other(event),
+        }
+    }
+}
+
+/// Receives the events of all network devices in all namespaces.
+pub trait NetdevNotify {
+    /// The data passed to [`NetdevNotifier::try_new`] and handed back on every event.
+    type Data: PointerWrapper + Send + Sync;
+
+    /// Called with the RTNL lock held for every `event` concerning `dev`.
+    fn notify(data: <Self::Data as PointerWrapper>::Borrowed<'_>, event: NetdevEvent, dev: &Device);
+}
+
+/// A registered network device notifier, corresponds to `register_netdevice_notifier`.
+///
+/// The notifier is unregistered when dropped.
+///
+/// # Examples
+///
+/// ```ignore
+/// struct LinkLogger;
+///
+/// impl NetdevNotify for LinkLogger {
+///     type Data = ();
+///
+///     fn notify(_data: (), event: NetdevEvent, dev: &Device) {
+///         if event == NetdevEvent::Up {
+///             pr_info!("{} is up\n", dev.name());
+///         }
+///     }
+/// }
+///
+/// let _notifier = NetdevNotifier::<LinkLogger>::try_new(())?;
+/// ```
+pub struct NetdevNotifier<T: NetdevNotify> {
+    nb: bindings::notifier_block,
+    data: *const c_void,
+    registered: bool,
+    _p: core::marker::PhantomData<T>,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: `data` comes from `T::Data`, which is `Send + Sync`, and the notifier block is only
+// touched by the notifier chain, which has its own locking.
+unsafe impl<T: NetdevNotify> Send for NetdevNotifier<T> {}
+
+// SAFETY: See the `Send` implementation above.
+unsafe impl<T: NetdevNotify> Sync for NetdevNotifier<T> {}
+
+impl<T: NetdevNotify> NetdevNotifier<T> {
+    /// Registers a notifier that calls [`NetdevNotify::notify`] with `data` on every event.
+    ///
+    /// [`NetdevEvent::Register`] and [`NetdevEvent::Up`] are replayed for the devices that
+    /// already exist before this returns.
+    pub fn try_new(data: T::Data) -> Result<Pin<Box<Self>>> {
+        let mut this = Pin::from(Box::try_new(Self {
+            // SAFETY: An all-zero `struct notifier_block` is valid, the callback is set below.
+            nb: unsafe { core::mem::zeroed() },
+            data: data.into_pointer(),
+            registered: false,
+            _p: core::marker::PhantomData,
+            _pin: PhantomPinned,
+        })?);
+
+        // SAFETY: The notifier block is not moved out of the pinned box.
+        let this_mut = unsafe { this.as_mut().get_unchecked_mut() };
+        this_mut.nb.notifier_call = Some(Self::notifier_callback);
+        // SAFETY: `nb` is pinned and stays registered until `self` is dropped.
+        let ret = unsafe { bindings::register_netdevice_notifier(&mut this_mut.nb) };
+        to_result(ret)?;
+        this_mut.registered = true;
+        Ok(this)
+    }
+
+    unsafe extern "C" fn notifier_callback(
+        nb: *mut bindings::notifier_block,
+        event: core::ffi::c_ulong,
+        ptr: *mut c_void,
+    ) -> core::ffi::c_int {
+        // SAFETY: `nb` is the `nb` field of a registered `NetdevNotifier<T>`.
+        let this = unsafe { &*crate::container_of!(nb, Self, nb) };
+        // SAFETY: `data` was returned by `into_pointer` and is only reclaimed on drop, after the
+        // notifier has been unregistered.
+        let data = unsafe { T::Data::borrow(this.data) };
+        // SAFETY: Netdevice notifiers are always passed a `struct netdev_notifier_info`, whose
+        // device is valid for the duration of the call.
+        let dev =
+            unsafe { Device::from_ptr(bindings::netdev_notifier_info_to_dev(ptr as *const _)) };
+        T::notify(data, NetdevEvent::from_raw(event as u64), dev);
+        bindings::NOTIFY_DONE as _
+    }
+}
+
+impl<T: NetdevNotify> Drop for NetdevNotifier<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: The notifier was registered in `try_new`. Unregistering waits for running
+            // callbacks.
+            unsafe { bindings::unregister_netdevice_notifier(&mut self.nb) };
+        }
+        // SAFETY: `data` was returned by `into_pointer` in `try_new`, and the callback can no
+        // longer run.
+        unsafe { T::Data::from_pointer(self.data) };
+    }
+}
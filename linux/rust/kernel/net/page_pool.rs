@@ -0,0 +1,251 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Page pool for RX buffers.
+//!
+//! A page pool hands out pages that stay DMA-mapped for their whole lifetime. Once the stack is
+//! done with an skb built around such a page, the page goes back into the pool instead of being
+//! unmapped and freed.
+//!
+//! C header: [`include/net/page_pool.h`](../../../../../include/net/page_pool.h)
+
+use super::SkBuff;
+use crate::{
+    bindings, device,
+    error::code::{EMSGSIZE, ENOMEM},
+    error::from_kernel_err_ptr,
+    sync::Arc,
+    ARef, Result,
+};
+use core::ptr::NonNull;
+
+/// Wraps the kernel's `struct page_pool`.
+///
+/// Pages are mapped with `PP_FLAG_DMA_MAP` and synced for the device on recycle with
+/// `PP_FLAG_DMA_SYNC_DEV`, so drivers only need to sync the received area for the CPU.
+///
+/// # Invariants
+///
+/// `ptr` is a valid pool returned by `page_pool_create`.
+pub struct PagePool {
+    ptr: NonNull<bindings::page_pool>,
+    dev: device::Device,
+    dir: bindings::dma_data_direction,
+    order: u32,
+}
+
+// SAFETY: The page pool API may be used from any thread.
+unsafe impl Send for PagePool {}
+
+// SAFETY: Allocation and recycling are internally synchronised by the page pool.
+unsafe impl Sync for PagePool {}
+
+impl PagePool {
+    /// Creates a new pool of pages of `order` mapped for `dev`.
+    ///
+    /// `pool_size` is the number of pages kept in the recycling ring, usually the size of the RX
+    /// ring. The device may write `max_len` bytes starting at `offset` in each page. Drivers with
+    /// receive buffers larger than a page use a non-zero `order`, which must leave room for the
+    /// `skb_shared_info` after the buffer.
+    ///
+    /// Pages are allocated on the NUMA node of `dev`.
+    pub fn try_new(
+        dev: &dyn device::RawDevice,
+        pool_size: u32,
+        order: u32,
+        dir: bindings::dma_data_direction,
+        offset: u32,
+        max_len: u32,
+    ) -> Result<Arc<Self>> {
+        let mut params = bindings::page_pool_params::default();
+        params.flags = bindings::PP_FLAG_DMA_MAP | bindings::PP_FLAG_DMA_SYNC_DEV;
+        params.order = order;
+        params.pool_size = pool_size;
+        // Allocate the pages next to the device rather than next to the CPU that refills the ring.
+        params.nid = dev.numa_node();
+        params.dev = dev.raw_device();
+        params.dma_dir = dir;
+        params.offset = offset;
+        params.max_len = max_len;
+
+        // SAFETY: `params` is fully initialised and `dev.raw_device()` is valid.
+        let ptr = from_kernel_err_ptr(unsafe { bindings::page_pool_create(&params) })?;
+        let ptr = NonNull::new(ptr).ok_or(ENOMEM)?;
+        // INVARIANT: `ptr` was just returned by a successful call to `page_pool_create`.
+        Arc::try_new(Self {
+            ptr,
+            dev: device::Device::from_dev(dev),
+            dir,
+            order,
+        })
+    }
+
+    /// Returns the raw `struct page_pool` pointer.
+    pub(crate) fn as_ptr(&self) -> *mut bindings::page_pool {
+        self.ptr.as_ptr()
+    }
+
+    /// Allocates a DMA-mapped page from the pool.
+    ///
+    /// The page returns to the pool when the returned [`PoolPage`] is dropped, or when the skb
+    /// built around it with [`PoolPage::build_skb`] is freed.
+    pub fn alloc(self: &Arc<Self>) -> Result<PoolPage> {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        let page = unsafe { bindings::page_pool_dev_alloc_pages(self.ptr.as_ptr()) };
+        let page = NonNull::new(page).ok_or(ENOMEM)?;
+        Ok(PoolPage {
+            pool: self.clone(),
+            page,
+        })
+    }
+}
+
+impl Drop for PagePool {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `self.ptr` is valid. Pages still held by skbs in flight
+        // are handled by the deferred release in `page_pool_destroy`.
+        unsafe { bindings::page_pool_destroy(self.ptr.as_ptr()) };
+    }
+}
+
+/// A DMA-mapped page allocated from a [`PagePool`].
+pub struct PoolPage {
+    pool: Arc<PagePool>,
+    page: NonNull<bindings::page>,
+}
+
+// SAFETY: The page is exclusively owned and can be recycled from any thread.
+unsafe impl Send for PoolPage {}
+
+impl PoolPage {
+    /// Returns the raw `struct page` pointer.
+    pub(crate) fn raw_page(&self) -> *mut bindings::page {
+        self.page.as_ptr()
+    }
+
+    /// Returns the size of the page in bytes, `PAGE_SIZE << order` of the pool.
+    pub fn size(&self) -> usize {
+        (bindings::PAGE_SIZE as usize) << self.pool.order
+    }
+
+    /// Returns the DMA address of the start of the page.
+    pub fn dma_addr(&self) -> bindings::dma_addr_t {
+        // SAFETY: The page was allocated from a pool with `PP_FLAG_DMA_MAP`.
+        unsafe { bindings::page_pool_get_dma_addr(self.page.as_ptr()) }
+    }
+
+    /// Makes `len` bytes at `offset` written by the device visible to the CPU.
+    pub fn sync_for_cpu(&self, offset: u32, len: u32) {
+        // SAFETY: The page is mapped for `self.pool.dev` in direction `self.pool.dir`.
+        unsafe {
+            bindings::dma_sync_single_range_for_cpu(
+                self.pool.dev.raw_device(),
+                self.dma_addr(),
+                offset as _,
+                len as _,
+                self.pool.dir,
+            )
+        }
+    }
+
+    /// Makes `len` bytes at `offset` written by the CPU visible to the device, e.g. before
+    /// transmitting a received page back out with `XDP_TX`.
+    ///
+    /// The pool must have been created with `DMA_BIDIRECTIONAL`.
+    pub fn sync_for_device(&self, offset: u32, len: u32) {
+        // SAFETY: The page is mapped for `self.pool.dev` in direction `self.pool.dir`.
+        unsafe {
+            bindings::dma_sync_single_range_for_device(
+                self.pool.dev.raw_device(),
+                self.dma_addr(),
+                offset as _,
+                len as _,
+                self.pool.dir,
+            )
+        }
+    }
+
+    /// Returns the contents of the page.
+    pub fn data(&self) -> &[u8] {
+        // SAFETY: The page is a valid lowmem page owned by `self`.
+        unsafe {
+            let addr = bindings::page_address(self.page.as_ptr());
+            core::slice::from_raw_parts(addr as *const u8, self.size())
+        }
+    }
+
+    /// Returns the contents of the page for modification, e.g. to build a frame to transmit.
+    ///
+    /// The device must not be accessing the page; call [`PoolPage::sync_for_device`] afterwards.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        // SAFETY: The page is a valid lowmem page exclusively owned by `self`.
+        unsafe {
+            let addr = bindings::page_address(self.page.as_ptr());
+            core::slice::from_raw_parts_mut(addr as *mut u8, self.size())
+        }
+    }
+
+    /// Builds an skb around the page, with `len` bytes of data starting at `headroom`.
+    ///
+    /// The skb is marked for recycling, so the page returns to the pool when the skb is freed.
+    pub fn build_skb(self, headroom: u32, len: u32) -> Result<ARef<SkBuff>> {
+        // SAFETY: The page is a valid lowmem page owned by `self`.
+        let addr = unsafe { bindings::page_address(self.page.as_ptr()) };
+        // SAFETY: `addr` points to a whole (possibly compound) page, enough for the data and the
+        // shared info.
+        let skb = unsafe { bindings::build_skb(addr, self.size() as _) };
+        // On failure `self` is dropped and the page goes back to the pool.
+        let skb = NonNull::new(skb as *mut SkBuff).ok_or(ENOMEM)?;
+        // SAFETY: `build_skb` returns a new skb with a refcount of one, which we now own.
+        let skb = unsafe { ARef::<SkBuff>::from_raw(skb) };
+        // The skb now owns the page, it is recycled through `skb_mark_for_recycle`. Only release
+        // our reference on the pool.
+        let this = core::mem::ManuallyDrop::new(self);
+        // SAFETY: `this` is never used again and its destructor doesn't run.
+        drop(unsafe { core::ptr::read(&this.pool) });
+        skb.reserve(headroom as _);
+        skb.put(len);
+        // SAFETY: The skb was just built around a page pool page.
+        unsafe { bindings::skb_mark_for_recycle(skb.0.get()) };
+        Ok(skb)
+    }
+
+    /// Appends `len` bytes at `offset` of the page to `skb` as a paged fragment, for frames that
+    /// span several receive buffers. Corresponds to `skb_add_rx_frag`.
+    ///
+    /// `skb` must have been built with [`PoolPage::build_skb`] so that the page is recycled when
+    /// the skb is freed. Fails with `EMSGSIZE` if `skb` has no fragment slot left, in which case
+    /// the page goes back to the pool.
+    pub fn add_to_skb(self, skb: &SkBuff, offset: u32, len: u32) -> Result {
+        let nr_frags = skb.nr_frags() as u32;
+        if nr_frags >= bindings::MAX_SKB_FRAGS {
+            return Err(EMSGSIZE);
+        }
+        let truesize = self.size();
+        let this = core::mem::ManuallyDrop::new(self);
+        // SAFETY: The existence of a shared reference means the skb is valid, and there is a free
+        // fragment slot. The skb takes over the page, which is recycled through the
+        // `skb_mark_for_recycle` done in `build_skb`.
+        unsafe {
+            bindings::skb_add_rx_frag(
+                skb.0.get(),
+                nr_frags as _,
+                this.page.as_ptr(),
+                offset as _,
+                len as _,
+                truesize as _,
+            )
+        };
+        // SAFETY: `this` is never used again and its destructor doesn't run.
+        drop(unsafe { core::ptr::read(&this.pool) });
+        Ok(())
+    }
+}
+
+impl Drop for PoolPage {
+    fn drop(&mut self) {
+        // SAFETY: The page was allocated from `self.pool`, which is still alive.
+        unsafe {
+            bindings::page_pool_put_full_page(self.pool.ptr.as_ptr(), self.page.as_ptr(), false)
+        }
+    }
+}
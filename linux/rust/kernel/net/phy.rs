@@ -0,0 +1,301 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Network PHY device support (phylib).
+//!
+//! A driver whose MAC can access its PHY through the [`Mdio`] trait registers an [`MdioBus`] on
+//! top of it, connects the PHY found on the bus to its network device and lets the generic PHY
+//! state machine handle autonegotiation and link polling. Link changes are reported to the
+//! driver through [`AdjustLink::adjust_link`].
+//!
+//! C headers: [`include/linux/phy.h`](../../../../../include/linux/phy.h) and
+//! [`include/linux/mdio.h`](../../../../../include/linux/mdio.h)
+
+use super::{mii::Mdio, Device, DeviceOperations};
+use crate::{
+    bindings, device,
+    error::{code::*, from_kernel_result, to_result},
+    str::CStr,
+    sync::Arc,
+    types::PointerWrapper,
+    Result, ThisModule,
+};
+use core::{cell::UnsafeCell, ffi::c_int, marker::PhantomData};
+
+/// Called by the PHY state machine whenever the link state, speed or duplex changes.
+///
+/// The callback runs in process context with the PHY lock held, so it must not call back into
+/// phylib functions that take the lock.
+pub trait AdjustLink: DeviceOperations {
+    /// Reconfigures the MAC for the new link state of `phydev`, e.g. the speed and duplex.
+    fn adjust_link(
+        dev: &Device,
+        data: <Self::Data as PointerWrapper>::Borrowed<'_>,
+        phydev: &PhyDevice,
+    );
+}
+
+/// The interface between the MAC and the PHY, corresponds to `phy_interface_t`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interface {
+    /// The PHY is built into the same chip as the MAC.
+    Internal,
+    /// Media Independent Interface (10/100 Mb/s).
+    Mii,
+    /// Gigabit Media Independent Interface.
+    Gmii,
+    /// Reduced Gigabit Media Independent Interface.
+    Rgmii,
+}
+
+impl Interface {
+    fn to_raw(self) -> bindings::phy_interface_t {
+        match self {
+            Self::Internal => bindings::phy_interface_t_PHY_INTERFACE_MODE_INTERNAL,
+            Self::Mii => bindings::phy_interface_t_PHY_INTERFACE_MODE_MII,
+            Self::Gmii => bindings::phy_interface_t_PHY_INTERFACE_MODE_GMII,
+            Self::Rgmii => bindings::phy_interface_t_PHY_INTERFACE_MODE_RGMII,
+        }
+    }
+}
+
+/// An MDIO bus registered with the kernel, wraps `struct mii_bus`.
+///
+/// Register reads and writes from the PHY layer are forwarded to the [`Mdio`] implementation
+/// passed to [`MdioBus::try_new`]. The bus is unregistered and freed when dropped.
+///
+/// # Invariants
+///
+/// `bus` is a registered bus whose `priv` field holds a pointer returned by
+/// `Arc::<T>::into_raw`.
+pub struct MdioBus<T: Mdio + Send + Sync> {
+    bus: *mut bindings::mii_bus,
+    _p: PhantomData<Arc<T>>,
+}
+
+// SAFETY: The bus is only used through the thread-safe phylib API, and `T` is `Send + Sync`.
+unsafe impl<T: Mdio + Send + Sync> Send for MdioBus<T> {}
+
+// SAFETY: See the `Send` implementation above.
+unsafe impl<T: Mdio + Send + Sync> Sync for MdioBus<T> {}
+
+impl<T: Mdio + Send + Sync> MdioBus<T> {
+    /// Allocates and registers an MDIO bus whose accesses go to `mdio`.
+    ///
+    /// The bus is named `name` and identified by the name of `parent` in sysfs. Only the
+    /// addresses set in `phy_addrs` (a bit per address) are scanned for PHYs, which avoids
+    /// probing 32 addresses on a MAC that only has one PHY.
+    pub fn try_new(
+        parent: &dyn device::RawDevice,
+        name: &'static CStr,
+        mdio: Arc<T>,
+        phy_addrs: u32,
+        module: &'static ThisModule,
+    ) -> Result<Self> {
+        // SAFETY: FFI call.
+        let bus = unsafe { bindings::mdiobus_alloc_size(0) };
+        if bus.is_null() {
+            return Err(ENOMEM);
+        }
+
+        let parent = parent.raw_device();
+        // SAFETY: `bus` was just allocated, `parent` is valid and `name` is a static string.
+        // `MII_BUS_ID_SIZE` is large enough for the truncated device name.
+        unsafe {
+            (*bus).name = name.as_char_ptr();
+            (*bus).read = Some(Self::read_callback);
+            (*bus).write = Some(Self::write_callback);
+            (*bus).parent = parent;
+            (*bus).phy_mask = !phy_addrs;
+            (*bus).priv_ = Arc::into_raw(mdio) as *mut core::ffi::c_void;
+            bindings::snprintf(
+                (*bus).id.as_mut_ptr(),
+                bindings::MII_BUS_ID_SIZE as _,
+                b"%s\0".as_ptr() as _,
+                bindings::dev_name(parent),
+            );
+        }
+
+        // SAFETY: `bus` is fully set up above and `module.0` is the owning module.
+        let ret = unsafe { bindings::__mdiobus_register(bus, module.0) };
+        if let Err(e) = to_result(ret) {
+            // SAFETY: `priv_` was set from `Arc::into_raw` above and the unregistered bus no
+            // longer uses it.
+            unsafe {
+                drop(Arc::from_raw((*bus).priv_ as *const T));
+                bindings::mdiobus_free(bus);
+            }
+            return Err(e);
+        }
+
+        // INVARIANT: `bus` was registered above with `priv` set from `Arc::into_raw`.
+        Ok(Self {
+            bus,
+            _p: PhantomData,
+        })
+    }
+
+    /// Returns the PHY found at address `addr` when the bus was registered, if any.
+    pub fn phy(&self, addr: u8) -> Option<&PhyDevice> {
+        if addr as u32 >= bindings::PHY_MAX_ADDR {
+            return None;
+        }
+        // SAFETY: By the type invariants, `self.bus` is valid and registered.
+        let phydev = unsafe { bindings::mdiobus_get_phy(self.bus, addr as c_int) };
+        if phydev.is_null() {
+            return None;
+        }
+        // SAFETY: PHYs found during registration stay alive until the bus is unregistered, which
+        // only happens when `self` is dropped.
+        Some(unsafe { PhyDevice::from_ptr(phydev) })
+    }
+
+    unsafe extern "C" fn read_callback(
+        bus: *mut bindings::mii_bus,
+        addr: c_int,
+        regnum: c_int,
+    ) -> c_int {
+        from_kernel_result! {
+            // SAFETY: By the type invariants, `priv` points to a live `T`.
+            let mdio = unsafe { &*((*bus).priv_ as *const T) };
+            Ok(mdio.mdio_read(addr as u8, regnum as u8)? as c_int)
+        }
+    }
+
+    unsafe extern "C" fn write_callback(
+        bus: *mut bindings::mii_bus,
+        addr: c_int,
+        regnum: c_int,
+        val: u16,
+    ) -> c_int {
+        from_kernel_result! {
+            // SAFETY: By the type invariants, `priv` points to a live `T`.
+            let mdio = unsafe { &*((*bus).priv_ as *const T) };
+            mdio.mdio_write(addr as u8, regnum as u8, val)?;
+            Ok(0)
+        }
+    }
+}
+
+impl<T: Mdio + Send + Sync> Drop for MdioBus<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `self.bus` is registered and `priv` was set from
+        // `Arc::into_raw`. Once unregistered, the callbacks are no longer called.
+        unsafe {
+            bindings::mdiobus_unregister(self.bus);
+            drop(Arc::from_raw((*self.bus).priv_ as *const T));
+            bindings::mdiobus_free(self.bus);
+        }
+    }
+}
+
+/// A PHY device on an [`MdioBus`], wraps `struct phy_device`.
+#[repr(transparent)]
+pub struct PhyDevice(UnsafeCell<bindings::phy_device>);
+
+impl PhyDevice {
+    /// Creates a reference to a [`PhyDevice`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is valid and remains valid for the lifetime of the
+    /// returned [`PhyDevice`] instance.
+    unsafe fn from_ptr<'a>(ptr: *const bindings::phy_device) -> &'a PhyDevice {
+        // SAFETY: The safety requirements guarantee the validity of the dereference, while the
+        // `PhyDevice` type being transparent makes the cast ok.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Attaches the PHY to `dev` and arranges for [`AdjustLink::adjust_link`] to be called on
+    /// link changes, corresponds to `phy_connect_direct`.
+    ///
+    /// The PHY state machine only runs after [`PhyConnection::start`]. The PHY is detached again
+    /// when the returned [`PhyConnection`] is dropped, which must happen before the bus is.
+    pub fn connect<T: AdjustLink>(
+        &self,
+        dev: &Device,
+        interface: Interface,
+    ) -> Result<PhyConnection<'_>> {
+        // SAFETY: `self.0` and `dev.0` are valid by the shared references.
+        to_result(unsafe {
+            bindings::phy_connect_direct(
+                dev.0.get(),
+                self.0.get(),
+                Some(adjust_link_callback::<T>),
+                interface.to_raw(),
+            )
+        })?;
+        Ok(PhyConnection { phydev: self })
+    }
+
+    /// Returns whether the link is up.
+    pub fn link(&self) -> bool {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { (*self.0.get()).link() != 0 }
+    }
+
+    /// Returns the negotiated speed in Mb/s (one of the `SPEED_*` values).
+    pub fn speed(&self) -> i32 {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { (*self.0.get()).speed }
+    }
+
+    /// Returns whether the link is full duplex.
+    pub fn full_duplex(&self) -> bool {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { (*self.0.get()).duplex == bindings::DUPLEX_FULL as c_int }
+    }
+
+    /// Logs the link state, speed and duplex, corresponds to `phy_print_status`.
+    pub fn print_status(&self) {
+        // SAFETY: The existence of a shared reference means `self.0` is valid.
+        unsafe { bindings::phy_print_status(self.0.get()) }
+    }
+}
+
+/// A PHY attached to a network device, returned by [`PhyDevice::connect`].
+///
+/// The PHY is detached from the network device when this is dropped.
+pub struct PhyConnection<'a> {
+    phydev: &'a PhyDevice,
+}
+
+impl PhyConnection<'_> {
+    /// Returns the connected PHY.
+    pub fn phydev(&self) -> &PhyDevice {
+        self.phydev
+    }
+
+    /// Starts the PHY state machine, corresponds to `phy_start`. Called from `ndo_open`.
+    pub fn start(&self) {
+        // SAFETY: The PHY is valid and attached.
+        unsafe { bindings::phy_start(self.phydev.0.get()) }
+    }
+
+    /// Stops the PHY state machine and reports the link as down, corresponds to `phy_stop`.
+    /// Called from `ndo_stop`.
+    pub fn stop(&self) {
+        // SAFETY: The PHY is valid, attached and was started.
+        unsafe { bindings::phy_stop(self.phydev.0.get()) }
+    }
+}
+
+impl Drop for PhyConnection<'_> {
+    fn drop(&mut self) {
+        // SAFETY: The PHY is valid and was attached by `connect`.
+        unsafe { bindings::phy_disconnect(self.phydev.0.get()) }
+    }
+}
+
+unsafe extern "C" fn adjust_link_callback<T: AdjustLink>(netdev: *mut bindings::net_device) {
+    // SAFETY: The C API guarantees that `net_device` isn't released while this function is
+    // running, and `phydev` is set while a PHY is attached.
+    let (dev, phydev) = unsafe {
+        (
+            Device::from_ptr(netdev),
+            PhyDevice::from_ptr((*netdev).phydev),
+        )
+    };
+    // SAFETY: The value stored as driver data was returned by `into_pointer` during registration.
+    let data = unsafe { T::Data::borrow(bindings::dev_get_drvdata(&mut (*netdev).dev)) };
+    T::adjust_link(dev, data, phydev);
+}
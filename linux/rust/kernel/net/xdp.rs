@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! eXpress Data Path (XDP).
+//!
+//! C headers: [`include/net/xdp.h`](../../../../../include/net/xdp.h),
+//! [`include/linux/filter.h`](../../../../../include/linux/filter.h).
+
+use super::{Device, PagePool, PoolPage};
+use crate::{bindings, error::code::EINVAL, to_result, AlwaysRefCounted, Result};
+use core::{cell::UnsafeCell, ptr::NonNull};
+
+/// The verdict of an XDP program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XdpAction {
+    /// The program failed, the packet is dropped.
+    Aborted,
+    /// Drop the packet.
+    Drop,
+    /// Pass the packet to the network stack.
+    Pass,
+    /// Transmit the packet back out of the interface it was received on.
+    Tx,
+    /// Redirect the packet to another interface, CPU or socket.
+    Redirect,
+}
+
+impl XdpAction {
+    fn from_raw(act: u32) -> Self {
+        match act {
+            bindings::xdp_action_XDP_DROP => Self::Drop,
+            bindings::xdp_action_XDP_PASS => Self::Pass,
+            bindings::xdp_action_XDP_TX => Self::Tx,
+            bindings::xdp_action_XDP_REDIRECT => Self::Redirect,
+            _ => Self::Aborted,
+        }
+    }
+}
+
+/// Wraps the kernel's `struct bpf_prog`.
+#[repr(transparent)]
+pub struct BpfProg(UnsafeCell<bindings::bpf_prog>);
+
+// SAFETY: Instances of `BpfProg` are created on the C side. They are always refcounted.
+unsafe impl AlwaysRefCounted for BpfProg {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference means that the refcount is nonzero.
+        unsafe { bindings::bpf_prog_inc(self.0.get()) };
+    }
+
+    unsafe fn dec_ref(obj: NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is nonzero.
+        unsafe { bindings::bpf_prog_put(obj.cast().as_ptr()) };
+    }
+}
+
+// SAFETY: Programs may be run and released from any thread.
+unsafe impl Send for BpfProg {}
+
+// SAFETY: Programs are immutable once loaded and may be run concurrently.
+unsafe impl Sync for BpfProg {}
+
+impl BpfProg {
+    /// Runs the XDP program on `xdp`, corresponds to `bpf_prog_run_xdp`.
+    ///
+    /// Must be called from the NAPI poll routine.
+    pub fn run_xdp(&self, xdp: &mut XdpBuff) -> XdpAction {
+        // SAFETY: The existence of a shared reference means `self.0` is valid, and `xdp` was
+        // initialised by `XdpBuff::new`.
+        let act = unsafe { bindings::bpf_prog_run_xdp(self.0.get(), &mut xdp.0) };
+        XdpAction::from_raw(act)
+    }
+}
+
+/// Wraps the kernel's `struct xdp_rxq_info`, the per-RX-queue information XDP programs see.
+///
+/// The queue is unregistered when the object is dropped.
+pub struct XdpRxqInfo(UnsafeCell<bindings::xdp_rxq_info>);
+
+// SAFETY: The registration may be dropped from any thread.
+unsafe impl Send for XdpRxqInfo {}
+
+// SAFETY: The registration is only read once set up.
+unsafe impl Sync for XdpRxqInfo {}
+
+impl XdpRxqInfo {
+    /// Registers RX queue `queue_index` of `dev`, whose buffers come from `pool`.
+    pub fn try_new(dev: &Device, queue_index: u32, pool: &PagePool) -> Result<Self> {
+        let rxq = Self(UnsafeCell::new(bindings::xdp_rxq_info::default()));
+        // SAFETY: `rxq.0` is valid and `dev` is valid because of the shared reference.
+        to_result(unsafe { bindings::xdp_rxq_info_reg(rxq.0.get(), dev.0.get(), queue_index, 0) })?;
+        // SAFETY: `rxq.0` was just registered and `pool` outlives the memory model because
+        // pages in flight hold a reference to it.
+        let ret = unsafe {
+            bindings::xdp_rxq_info_reg_mem_model(
+                rxq.0.get(),
+                bindings::xdp_mem_type_MEM_TYPE_PAGE_POOL,
+                pool.as_ptr() as _,
+            )
+        };
+        // On failure `rxq` is dropped, which unregisters it.
+        to_result(ret)?;
+        Ok(rxq)
+    }
+}
+
+impl Drop for XdpRxqInfo {
+    fn drop(&mut self) {
+        // SAFETY: `self.0` was registered in `try_new`.
+        unsafe { bindings::xdp_rxq_info_unreg(self.0.get()) };
+    }
+}
+
+/// Wraps the kernel's `struct xdp_buff`, a received packet handed to an XDP program.
+pub struct XdpBuff(bindings::xdp_buff);
+
+impl XdpBuff {
+    /// Describes `len` bytes received at `headroom` in `page`.
+    pub fn new(rxq: &XdpRxqInfo, page: &PoolPage, headroom: u32, len: u32) -> Self {
+        let mut xdp = bindings::xdp_buff::default();
+        // SAFETY: `xdp` is a valid buffer, `rxq` is registered and the page is a valid lowmem
+        // allocation of `page.size()` bytes.
+        unsafe {
+            bindings::xdp_init_buff(&mut xdp, page.size() as _, rxq.0.get());
+            bindings::xdp_prepare_buff(
+                &mut xdp,
+                bindings::page_address(page.raw_page()) as _,
+                headroom as _,
+                len as _,
+                false,
+            );
+        }
+        Self(xdp)
+    }
+
+    /// Returns the headroom in front of the packet, which the program may have changed.
+    pub fn headroom(&self) -> u32 {
+        (self.0.data as usize - self.0.data_hard_start as usize) as u32
+    }
+
+    /// Returns the length of the packet, which the program may have changed.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u32 {
+        (self.0.data_end as usize - self.0.data as usize) as u32
+    }
+}
+
+/// Wraps the kernel's `struct xdp_frame`, a packet redirected to this device for transmission.
+///
+/// The frame is returned to its memory allocator when dropped.
+pub struct XdpFrame(NonNull<bindings::xdp_frame>);
+
+// SAFETY: Frames may be returned from any thread.
+unsafe impl Send for XdpFrame {}
+
+impl XdpFrame {
+    /// Returns the packet data.
+    pub fn data(&self) -> &[u8] {
+        // SAFETY: By the type invariants, `self.0` is a valid frame holding `len` bytes at `data`.
+        unsafe {
+            let frame = self.0.as_ptr();
+            core::slice::from_raw_parts((*frame).data as *const u8, (*frame).len as usize)
+        }
+    }
+}
+
+impl Drop for XdpFrame {
+    fn drop(&mut self) {
+        // SAFETY: We own the frame.
+        unsafe { bindings::xdp_return_frame(self.0.as_ptr()) };
+    }
+}
+
+/// The frames passed to `ndo_xdp_xmit`.
+///
+/// Frames taken from the iterator become owned by the driver; the ones left behind are freed by
+/// the caller.
+pub struct XdpFrames<'a> {
+    frames: &'a [*mut bindings::xdp_frame],
+    taken: usize,
+}
+
+impl<'a> XdpFrames<'a> {
+    pub(crate) fn new(frames: &'a [*mut bindings::xdp_frame]) -> Self {
+        Self { frames, taken: 0 }
+    }
+
+    /// Returns the number of frames taken so far.
+    pub fn taken(&self) -> usize {
+        self.taken
+    }
+
+    /// Returns the packet data of the next frame without taking it.
+    ///
+    /// This lets a driver prepare the transmission (e.g. map the data for DMA) and only take the
+    /// frame once it is sure to send it, since every frame taken counts as transmitted.
+    pub fn peek_data(&self) -> Option<&[u8]> {
+        let frame = NonNull::new(*self.frames.get(self.taken)?)?;
+        // SAFETY: Frames that haven't been taken are valid and still owned by the caller of
+        // `ndo_xdp_xmit`, which keeps them alive for the duration of the call.
+        unsafe {
+            let frame = frame.as_ptr();
+            Some(core::slice::from_raw_parts(
+                (*frame).data as *const u8,
+                (*frame).len as usize,
+            ))
+        }
+    }
+}
+
+impl Iterator for XdpFrames<'_> {
+    type Item = XdpFrame;
+
+    fn next(&mut self) -> Option<XdpFrame> {
+        let frame = NonNull::new(*self.frames.get(self.taken)?)?;
+        self.taken += 1;
+        Some(XdpFrame(frame))
+    }
+}
+
+/// Wraps the kernel's `struct netdev_bpf`, the argument of `ndo_bpf`.
+pub struct NetdevBpf {
+    ptr: *mut bindings::netdev_bpf,
+}
+
+impl NetdevBpf {
+    pub(crate) fn new(ptr: *mut bindings::netdev_bpf) -> Self {
+        Self { ptr }
+    }
+
+    /// Returns the command, one of `bindings::bpf_netdev_command_*`.
+    pub fn command(&self) -> bindings::bpf_netdev_command {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).command }
+    }
+
+    /// Returns the program to install for `XDP_SETUP_PROG`, `None` to remove the current one.
+    pub fn prog(&self) -> Result<Option<&BpfProg>> {
+        if self.command() != bindings::bpf_netdev_command_XDP_SETUP_PROG {
+            return Err(EINVAL);
+        }
+        // SAFETY: By the type invariants, `self.ptr` is valid and `prog` is the active member
+        // of the union for `XDP_SETUP_PROG`.
+        let prog = unsafe { (*self.ptr).__bindgen_anon_1.__bindgen_anon_1.prog };
+        // SAFETY: The program is kept alive by the caller for the duration of `ndo_bpf`.
+        Ok(unsafe { (prog as *const BpfProg).as_ref() })
+    }
+}
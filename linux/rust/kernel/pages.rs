@@ -5,8 +5,10 @@
 //! TODO: This module is a work in progress.
 
 use crate::{
-    bindings, error::code::*, io_buffer::IoBufferReader, user_ptr::UserSlicePtrReader, Result,
-    PAGE_SIZE,
+    bindings,
+    error::code::*,
+    io_buffer::{IoBufferReader, IoBufferWriter},
+    Result, PAGE_SIZE,
 };
 use core::{marker::PhantomData, ptr};
 
@@ -22,6 +24,14 @@ pub struct Pages<const ORDER: u32> {
     pub(crate) pages: *mut bindings::page,
 }
 
+// SAFETY: `Pages` only owns the pages, which are not tied to the thread that allocated them.
+unsafe impl<const ORDER: u32> Send for Pages<ORDER> {}
+
+// SAFETY: The contents are plain bytes that may be mapped into userspace anyway, so concurrent
+// copies through shared references are no worse than concurrent accesses from userspace. Accessors
+// that work on raw pointers are `unsafe` and leave the synchronisation to the caller.
+unsafe impl<const ORDER: u32> Sync for Pages<ORDER> {}
+
 impl<const ORDER: u32> Pages<ORDER> {
     /// Allocates a new set of contiguous pages.
     pub fn new() -> Result<Self> {
@@ -40,10 +50,11 @@ impl<const ORDER: u32> Pages<ORDER> {
         Ok(Self { pages })
     }
 
-    /// Copies data from the given [`UserSlicePtrReader`] into the pages.
+    /// Copies data from the given reader, e.g. a [`crate::user_ptr::UserSlicePtrReader`], into
+    /// the pages.
     pub fn copy_into_page(
         &self,
-        reader: &mut UserSlicePtrReader,
+        reader: &mut impl IoBufferReader,
         offset: usize,
         len: usize,
     ) -> Result {
@@ -60,6 +71,27 @@ impl<const ORDER: u32> Pages<ORDER> {
         Ok(())
     }
 
+    /// Copies data from the pages into the given writer, e.g. a
+    /// [`crate::user_ptr::UserSlicePtrWriter`].
+    pub fn copy_from_page(
+        &self,
+        writer: &mut impl IoBufferWriter,
+        offset: usize,
+        len: usize,
+    ) -> Result {
+        // TODO: For now this only works on the first page.
+        let end = offset.checked_add(len).ok_or(EINVAL)?;
+        if end > PAGE_SIZE {
+            return Err(EINVAL);
+        }
+
+        let mapping = self.kmap(0).ok_or(EINVAL)?;
+
+        // SAFETY: We ensured that the buffer was valid with the check above.
+        unsafe { writer.write_raw((mapping.ptr as usize + offset) as _, len) }?;
+        Ok(())
+    }
+
     /// Maps the pages and reads from them into the given buffer.
     ///
     /// # Safety
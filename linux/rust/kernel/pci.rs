@@ -34,6 +34,7 @@ impl<T: Driver> driver::DriverOps for Adapter<T> {
         pdrv.name = name.as_char_ptr();
         pdrv.probe = Some(Self::probe_callback);
         pdrv.remove = Some(Self::remove_callback);
+        pdrv.shutdown = Some(Self::shutdown_callback);
         pdrv.id_table = T::ID_TABLE.as_ref();
         // SAFETY:
         //   - `pdrv` lives at least until the call to `pci_unregister_driver()` returns.
@@ -88,9 +89,27 @@ impl<T: Driver> Adapter<T> {
         //     `remove` is the canonical kernel location to free driver data. so OK
         //     to convert the pointer back to a Rust structure here.
         let data = unsafe { T::Data::from_pointer(ptr) };
-        T::remove(&data);
+        // SAFETY: `pdev` is valid by the contract with the C code. `dev` is alive only for the
+        // duration of this call, so it is guaranteed to remain alive for the lifetime of `pdev`.
+        let mut dev = unsafe { Device::from_ptr(pdev) };
+        T::remove(&mut dev, &data);
         <T::Data as driver::DeviceRemoval>::device_remove(&data);
     }
+
+    extern "C" fn shutdown_callback(pdev: *mut bindings::pci_dev) {
+        // SAFETY: `pdev` is guaranteed to be a valid, non-null pointer.
+        let ptr = unsafe { bindings::pci_get_drvdata(pdev) };
+        if ptr.is_null() {
+            return;
+        }
+        // SAFETY: The pointer was returned by `T::Data::into_pointer` in `probe` and is only
+        // reclaimed in `remove`, which doesn't run concurrently with `shutdown`.
+        let data = unsafe { T::Data::borrow(ptr) };
+        // SAFETY: `pdev` is valid by the contract with the C code. `dev` is alive only for the
+        // duration of this call, so it is guaranteed to remain alive for the lifetime of `pdev`.
+        let mut dev = unsafe { Device::from_ptr(pdev) };
+        T::shutdown(&mut dev, data);
+    }
 }
 
 /// Abstraction for bindings::pci_device_id.
@@ -224,7 +243,29 @@ pub trait Driver {
     ///
     /// Called when a platform device is removed.
     /// Implementers should prepare the device for complete removal here.
-    fn remove(_data: &Self::Data);
+    fn remove(_dev: &mut Device, _data: &Self::Data);
+
+    /// PCI driver shutdown.
+    ///
+    /// Called on reboot, power off and before `kexec` jumps to a new kernel. `remove` is not
+    /// called afterwards, so implementers should stop all DMA and interrupts here, otherwise the
+    /// device may keep writing into memory that now belongs to someone else.
+    fn shutdown(_dev: &mut Device, _data: <Self::Data as PointerWrapper>::Borrowed<'_>) {}
+}
+
+/// Interrupt types that can be requested with [`Device::alloc_irq_vectors`].
+pub mod irq_types {
+    /// Legacy INTx interrupt.
+    pub const LEGACY: u32 = bindings::PCI_IRQ_LEGACY;
+
+    /// Message signaled interrupts.
+    pub const MSI: u32 = bindings::PCI_IRQ_MSI;
+
+    /// Extended message signaled interrupts.
+    pub const MSIX: u32 = bindings::PCI_IRQ_MSIX;
+
+    /// Any of the types above, MSI-X is preferred, then MSI, then INTx.
+    pub const ALL_TYPES: u32 = LEGACY | MSI | MSIX;
 }
 
 /// PCI resource
@@ -249,6 +290,26 @@ impl Resource {
     pub fn check_flags(&self, bits: u32) -> bool {
         self.flags & (bits as u64) > 0
     }
+
+    /// Returns whether the resource is memory-mapped I/O.
+    pub fn is_mem(&self) -> bool {
+        self.check_flags(bindings::IORESOURCE_MEM)
+    }
+
+    /// Returns whether the resource is in the I/O port space.
+    pub fn is_io(&self) -> bool {
+        self.check_flags(bindings::IORESOURCE_IO)
+    }
+
+    /// Returns whether the resource is a 64-bit memory BAR, which takes up two BAR slots.
+    pub fn is_mem_64(&self) -> bool {
+        self.is_mem() && self.check_flags(bindings::IORESOURCE_MEM_64)
+    }
+
+    /// Returns whether the resource is prefetchable memory.
+    pub fn is_prefetchable(&self) -> bool {
+        self.check_flags(bindings::IORESOURCE_PREFETCH)
+    }
 }
 
 /// A PCI device.
@@ -282,6 +343,12 @@ impl Device {
         unsafe { bindings::pci_set_master(self.ptr) };
     }
 
+    /// disables bus-mastering for device, after which it can no longer start DMA
+    pub fn clear_master(&self) {
+        // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
+        unsafe { bindings::pci_clear_master(self.ptr) };
+    }
+
     /// get legacy irq number
     pub fn irq(&self) -> u32 {
         // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
@@ -310,28 +377,207 @@ impl Device {
         })
     }
 
+    /// Returns the resource of BAR number `bar`, or `None` if `bar` is not a standard BAR
+    /// (0 to 5) or the BAR is not implemented by the device.
+    pub fn resource(&self, bar: u8) -> Option<Resource> {
+        if bar as u32 >= bindings::PCI_STD_NUM_BARS {
+            return None;
+        }
+        // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
+        let res = unsafe { &(*self.ptr).resource[bar as usize] };
+        let res = Resource {
+            start: res.start,
+            end: res.end,
+            flags: res.flags,
+        };
+        if res.len() == 0 {
+            None
+        } else {
+            Some(res)
+        }
+    }
+
+    /// Returns the length of BAR number `bar` in bytes, or 0 if it is not implemented,
+    /// corresponds to `pci_resource_len`.
+    pub fn resource_len(&self, bar: u8) -> usize {
+        self.resource(bar).map_or(0, |res| res.len())
+    }
+
     /// Return BAR mask from the type of resource
-    pub fn select_bars(&self, flags: u64) -> i32 {
+    pub fn select_bars(&self, flags: u64) -> Bars {
         // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
-        unsafe { bindings::pci_select_bars(self.ptr, flags) }
+        Bars(unsafe { bindings::pci_select_bars(self.ptr, flags) })
     }
 
     /// Reserve selected PCI I/O and memory resources
-    pub fn request_selected_regions(&mut self, bars: i32, name: &'static CStr) -> Result {
+    ///
+    /// The regions stay reserved until the returned [`SelectedRegions`] is dropped or
+    /// [`SelectedRegions::release`] is called, which must happen before the driver is unbound.
+    pub fn request_selected_regions(
+        &mut self,
+        bars: Bars,
+        name: &'static CStr,
+    ) -> Result<SelectedRegions> {
         // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
         let ret =
-            unsafe { bindings::pci_request_selected_regions(self.ptr, bars, name.as_char_ptr()) };
+            unsafe { bindings::pci_request_selected_regions(self.ptr, bars.0, name.as_char_ptr()) };
         if ret != 0 {
+            return Err(Error::from_kernel_errno(ret));
+        }
+        // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid. The
+        // reference is dropped together with the regions.
+        unsafe { bindings::pci_dev_get(self.ptr) };
+        Ok(SelectedRegions {
+            ptr: self.ptr,
+            bars: bars.0,
+        })
+    }
+
+    /// Reserve selected PCI I/O and memory resources until the driver is unbound
+    ///
+    /// The regions are released by the driver core after `remove` returns, see
+    /// [`device::devm_add_action`].
+    pub fn devm_request_selected_regions(&mut self, bars: Bars, name: &'static CStr) -> Result {
+        let regions = self.request_selected_regions(bars, name)?;
+        device::devm_add_action(&*self, regions)
+    }
+
+    /// Allocates between `min_vecs` and `max_vecs` interrupt vectors of the given
+    /// [`irq_types`], returning the number of vectors allocated.
+    ///
+    /// The vectors are freed by the driver core after `remove` returns, see
+    /// [`device::devm_add_action`]. Use [`Device::irq_vector`] to get the Linux irq number of
+    /// each vector.
+    pub fn alloc_irq_vectors(&mut self, min_vecs: u32, max_vecs: u32, flags: u32) -> Result<u32> {
+        // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
+        let ret = unsafe { bindings::pci_alloc_irq_vectors(self.ptr, min_vecs, max_vecs, flags) };
+        if ret < 0 {
+            return Err(Error::from_kernel_errno(ret));
+        }
+        device::devm_add_action(&*self, IrqVectors { ptr: self.ptr })?;
+        Ok(ret as u32)
+    }
+
+    /// Returns the Linux irq number of vector `nr` allocated with
+    /// [`Device::alloc_irq_vectors`]
+    pub fn irq_vector(&self, nr: u32) -> Result<u32> {
+        // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
+        let ret = unsafe { bindings::pci_irq_vector(self.ptr, nr) };
+        if ret < 0 {
             Err(Error::from_kernel_errno(ret))
         } else {
-            Ok(())
+            Ok(ret as u32)
         }
     }
 
+    /// Returns whether the device uses MSI-X interrupts
+    pub fn msix_enabled(&self) -> bool {
+        // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
+        unsafe { (*self.ptr).msix_enabled() != 0 }
+    }
+
     /// Get address for accessing the device
     pub fn map_resource(&self, resource: &Resource, len: usize) -> Result<MappedResource> {
         MappedResource::try_new(resource.start, len)
     }
+
+    /// Initialize device until the driver is unbound, corresponds to `pcim_enable_device`
+    ///
+    /// The device is disabled again by the driver core after `remove` returns, or when `probe`
+    /// fails after this call.
+    pub fn pcim_enable_device(&mut self) -> Result {
+        // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
+        to_result(unsafe { bindings::pcim_enable_device(self.ptr) })
+    }
+
+    /// Maps the first `len` bytes of BAR number `bar` until the driver is unbound, corresponds
+    /// to `pcim_iomap`
+    ///
+    /// The mapping is undone by the driver core after `remove` returns, so the returned object
+    /// must not be used after that; dropping it earlier doesn't unmap anything. Fails with
+    /// `EINVAL` if the BAR is not implemented or shorter than `len`.
+    pub fn pcim_iomap(&self, bar: u8, len: usize) -> Result<MappedResource> {
+        if len == 0 || self.resource_len(bar) < len {
+            return Err(EINVAL);
+        }
+        // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid, and
+        // `bar` is a standard BAR as checked by `resource_len`.
+        let addr = unsafe { bindings::pcim_iomap(self.ptr, bar as _, len as _) };
+        if addr.is_null() {
+            return Err(ENOMEM);
+        }
+        Ok(MappedResource {
+            ptr: addr as usize,
+            len,
+            managed: true,
+        })
+    }
+}
+
+/// A set of BARs, one bit per BAR number, as returned by [`Device::select_bars`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bars(i32);
+
+impl Bars {
+    /// Returns whether BAR number `bar` is in the set.
+    pub fn contains(self, bar: u8) -> bool {
+        bar < 32 && self.0 & (1 << bar) != 0
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Regions of a PCI device reserved with [`Device::request_selected_regions`], released when
+/// dropped.
+///
+/// # Invariants
+///
+/// `bars` are reserved, and `ptr` is valid because a reference to the device is held.
+pub struct SelectedRegions {
+    ptr: *mut bindings::pci_dev,
+    bars: i32,
+}
+
+// SAFETY: The regions may be released from any thread.
+unsafe impl Send for SelectedRegions {}
+
+impl SelectedRegions {
+    /// Releases the regions, corresponds to `pci_release_selected_regions`.
+    ///
+    /// Consuming `self` makes releasing the same regions twice impossible.
+    pub fn release(self) {
+        drop(self);
+    }
+}
+
+impl Drop for SelectedRegions {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `ptr` is valid and `bars` are reserved. The reference
+        // taken in `request_selected_regions` is dropped afterwards.
+        unsafe {
+            bindings::pci_release_selected_regions(self.ptr, self.bars);
+            bindings::pci_dev_put(self.ptr);
+        }
+    }
+}
+
+/// Interrupt vectors of a PCI device, freed when dropped.
+struct IrqVectors {
+    ptr: *mut bindings::pci_dev,
+}
+
+// SAFETY: The vectors may be freed from any thread.
+unsafe impl Send for IrqVectors {}
+
+impl Drop for IrqVectors {
+    fn drop(&mut self) {
+        // SAFETY: The vectors are dropped by the driver core while unbinding the driver, when
+        // `ptr` is still valid and all irq handlers have been freed in `remove`.
+        unsafe { bindings::pci_free_irq_vectors(self.ptr) };
+    }
 }
 
 unsafe impl device::RawDevice for Device {
@@ -348,6 +594,17 @@ pub struct MappedResource {
     /// address
     pub ptr: usize,
     len: usize,
+    // Mapped with `pcim_iomap`, unmapped by devres instead of on drop.
+    managed: bool,
+}
+
+// Fails with `EINVAL` unless an access of type `T` at `offset` lies entirely within a region of
+// `len` bytes. A huge `offset` must not wrap around and pass the check.
+fn check_io_range<T>(offset: usize, len: usize) -> Result {
+    match offset.checked_add(core::mem::size_of::<T>()) {
+        Some(end) if end <= len => Ok(()),
+        _ => Err(EINVAL),
+    }
 }
 
 macro_rules! define_read {
@@ -356,12 +613,10 @@ macro_rules! define_read {
         $(#[$attr])*
         #[inline]
         pub fn $name(&self, offset: usize) -> Result<$type_name> {
-            if offset + core::mem::size_of::<$type_name>() > self.len {
-                return Err(EINVAL);
-            }
+            check_io_range::<$type_name>(offset, self.len)?;
             // SAFETY: The type invariants guarantee that `ptr` is a valid pointer. The check above
-            // guarantees that the code won't link if `offset` makes the write go out of bounds
-            // (including the type size).
+            // guarantees that `offset` doesn't make the read go out of bounds (including the type
+            // size).
             let ptr = self.ptr.wrapping_add(offset);
             Ok(unsafe { bindings::$name(ptr as _) })
         }
@@ -374,12 +629,10 @@ macro_rules! define_write {
         $(#[$attr])*
         #[inline]
         pub fn $name(&self, value: $type_name, offset: usize) -> Result {
-            if offset + core::mem::size_of::<$type_name>() > self.len {
-                return Err(EINVAL);
-            }
+            check_io_range::<$type_name>(offset, self.len)?;
             // SAFETY: The type invariants guarantee that `ptr` is a valid pointer. The check above
-            // guarantees that the code won't link if `offset` makes the write go out of bounds
-            // (including the type size).
+            // guarantees that `offset` doesn't make the write go out of bounds (including the type
+            // size).
             let ptr = self.ptr.wrapping_add(offset);
             unsafe { bindings::$name(value, ptr as _) };
             Ok(())
@@ -396,10 +649,13 @@ impl MappedResource {
             Ok(Self {
                 ptr: addr as usize,
                 len,
+                managed: false,
             })
         }
     }
 
+    // The byte and word accessors are needed for registers narrower than 32 bits, e.g. the flash
+    // and EEPROM windows of some NICs, which must not be accessed with `readl`/`writel`.
     define_read!(readb, u8);
     define_read!(readb_relaxed, u8);
     define_read!(readw, u16);
@@ -437,6 +693,9 @@ impl MappedResource {
 
 impl Drop for MappedResource {
     fn drop(&mut self) {
+        if self.managed {
+            return;
+        }
         unsafe {
             // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
             bindings::iounmap(self.ptr as _);
@@ -444,21 +703,49 @@ impl Drop for MappedResource {
     }
 }
 
+/// A range of I/O ports taken from a PCI I/O resource.
 ///
+/// Every access is checked against the size of the range, so a bad offset fails with `EINVAL`
+/// instead of touching ports that belong to another device.
+///
+/// # Invariants
+///
+/// `ptr..ptr + len` lies within the I/O port resource the object was created from.
 pub struct IoPort {
     ptr: usize,
     len: usize,
 }
 
 impl IoPort {
+    /// Creates an accessor for all the ports of the I/O resource `re`.
     ///
+    /// Fails with `EINVAL` if `re` isn't an I/O port resource or is empty.
     pub fn try_new(re: &Resource) -> Result<Self> {
+        Self::try_new_len(re, re.len())
+    }
+
+    /// Creates an accessor for the first `len` ports of the I/O resource `re`, for devices that
+    /// only use part of their I/O BAR.
+    ///
+    /// Fails with `EINVAL` if `re` isn't an I/O port resource, or if `len` is zero or larger than
+    /// the resource.
+    pub fn try_new_len(re: &Resource, len: usize) -> Result<Self> {
+        if !re.check_flags(bindings::IORESOURCE_IO) || len == 0 || len > re.len() {
+            return Err(EINVAL);
+        }
+        // INVARIANTS: `len` was checked against the size of the resource above.
         Ok(Self {
             ptr: re.start as usize,
-            len: re.len(),
+            len,
         })
     }
 
+    /// Returns the number of ports in the range.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
     define_read!(inb, u8);
     define_read!(inw, u16);
     define_read!(inl, u32);
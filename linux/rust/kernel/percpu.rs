@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Per-CPU counters.
+//!
+//! C headers: [`include/linux/percpu.h`](../../../../include/linux/percpu.h) and
+//! [`include/linux/u64_stats_sync.h`](../../../../include/linux/u64_stats_sync.h)
+
+use crate::{bindings, Result};
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+
+/// Wraps the kernel's `struct u64_stats_sync`.
+///
+/// On 64-bit systems this is empty and all operations compile to nothing; on 32-bit systems it is
+/// a seqcount that lets readers detect torn reads of 64-bit counters.
+#[repr(transparent)]
+pub struct U64StatsSync(UnsafeCell<bindings::u64_stats_sync>);
+
+impl U64StatsSync {
+    /// Creates a new, initialised [`U64StatsSync`].
+    pub fn new() -> Self {
+        // SAFETY: An all-zero `struct u64_stats_sync` is a valid, initialised seqcount.
+        Self(UnsafeCell::new(unsafe { core::mem::zeroed() }))
+    }
+
+    /// Runs `f` as a writer, corresponds to `u64_stats_update_begin`/`u64_stats_update_end`.
+    ///
+    /// Writers must be serialised by the caller, e.g. by only ever updating from one CPU with
+    /// bottom halves disabled.
+    pub fn update<R>(&self, f: impl FnOnce() -> R) -> R {
+        // SAFETY: `self.0` is valid and initialised.
+        unsafe { bindings::u64_stats_update_begin(self.0.get()) };
+        let ret = f();
+        // SAFETY: `self.0` is valid and initialised, and `u64_stats_update_begin` was called above.
+        unsafe { bindings::u64_stats_update_end(self.0.get()) };
+        ret
+    }
+
+    /// Runs `f` as a reader until it observes a consistent snapshot, corresponds to
+    /// `u64_stats_fetch_begin`/`u64_stats_fetch_retry`.
+    pub fn fetch<R>(&self, mut f: impl FnMut() -> R) -> R {
+        loop {
+            // SAFETY: `self.0` is valid and initialised.
+            let start = unsafe { bindings::u64_stats_fetch_begin(self.0.get()) };
+            let ret = f();
+            // SAFETY: `self.0` is valid and initialised.
+            if !unsafe { bindings::u64_stats_fetch_retry(self.0.get(), start) } {
+                return ret;
+            }
+        }
+    }
+}
+
+impl Default for U64StatsSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The copy of a single CPU, aligned to a cache line to avoid false sharing.
+#[repr(align(64))]
+#[derive(Default)]
+struct CpuSlot<T> {
+    syncp: U64StatsSync,
+    data: UnsafeCell<T>,
+}
+
+/// A copy of `T` for every possible CPU, for counters updated on hot paths.
+///
+/// Each CPU only ever updates its own copy with bottom halves disabled, so updates need neither
+/// locks nor atomic instructions and never bounce cache lines between CPUs. Readers fold the
+/// copies of all CPUs together, using [`U64StatsSync`] to get consistent 64-bit values on 32-bit
+/// systems too.
+pub struct PerCpu<T> {
+    cpus: Vec<CpuSlot<T>>,
+}
+
+// SAFETY: A CPU slot is only written by its own CPU with bottom halves disabled, and readers use
+// the slot's `U64StatsSync` to detect concurrent writes.
+unsafe impl<T: Send> Sync for PerCpu<T> {}
+
+// SAFETY: `PerCpu` holds no thread-local state.
+unsafe impl<T: Send> Send for PerCpu<T> {}
+
+impl<T: Default + Copy> PerCpu<T> {
+    /// Allocates a default-initialised copy of `T` for all possible CPUs.
+    pub fn try_new() -> Result<Self> {
+        // SAFETY: `nr_cpu_ids` is set up during boot and never changes afterwards.
+        let nr_cpus = unsafe { bindings::nr_cpu_ids } as usize;
+        let mut cpus = Vec::try_with_capacity(nr_cpus)?;
+        for _ in 0..nr_cpus {
+            cpus.try_push(CpuSlot::default())?;
+        }
+        Ok(Self { cpus })
+    }
+
+    /// Updates the copy of the current CPU.
+    ///
+    /// Must not be called from hard interrupt context or with interrupts disabled, e.g. while
+    /// holding a spinlock taken with `lock_irqdisable`, because re-enabling bottom halves may run
+    /// pending softirqs.
+    pub fn update(&self, f: impl FnOnce(&mut T)) {
+        // SAFETY: Disabling bottom halves also disables preemption, so the current CPU can't
+        // change and no softirq on this CPU can race with us on the same slot.
+        unsafe { bindings::local_bh_disable() };
+        // SAFETY: Preemption is disabled.
+        let cpu = unsafe { bindings::raw_smp_processor_id() } as usize;
+        let slot = &self.cpus[cpu];
+        // SAFETY: Only the current CPU writes to its own slot, and it can't be preempted here.
+        slot.syncp.update(|| f(unsafe { &mut *slot.data.get() }));
+        // SAFETY: Bottom halves were disabled above.
+        unsafe { bindings::local_bh_enable() };
+    }
+
+    /// Folds the copies of all CPUs into `init` with `f`, e.g. to sum up counters.
+    pub fn fold<A>(&self, init: A, mut f: impl FnMut(A, &T) -> A) -> A {
+        self.cpus.iter().fold(init, |acc, slot| {
+            // SAFETY: The slot is valid, torn reads are detected and retried by `fetch`.
+            let data = slot
+                .syncp
+                .fetch(|| unsafe { core::ptr::read_volatile(slot.data.get()) });
+            f(acc, &data)
+        })
+    }
+}
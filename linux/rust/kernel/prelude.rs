@@ -20,8 +20,11 @@ pub use macros::{module, vtable};
 pub use super::build_assert;
 
 pub use super::{
-    dbg, dev_alert, dev_crit, dev_dbg, dev_emerg, dev_err, dev_info, dev_notice, dev_warn, fmt,
-    pr_alert, pr_crit, pr_debug, pr_emerg, pr_err, pr_info, pr_notice, pr_warn,
+    dbg, dev_alert, dev_crit, dev_dbg, dev_dbg_ratelimited, dev_emerg, dev_err,
+    dev_err_ratelimited, dev_info, dev_notice, dev_warn, fmt, netdev_dbg, netdev_dbg_ratelimited,
+    netdev_err, netdev_err_ratelimited, netdev_info, netdev_warn, pr_alert, pr_crit, pr_debug,
+    pr_debug_ratelimited, pr_emerg, pr_err, pr_err_ratelimited, pr_info, pr_info_ratelimited,
+    pr_notice, pr_warn, pr_warn_ratelimited,
 };
 
 pub use super::{module_fs, module_misc_device};
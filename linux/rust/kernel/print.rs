@@ -9,6 +9,7 @@
 use core::{
     ffi::{c_char, c_void},
     fmt,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
 };
 
 use crate::str::RawFormatter;
@@ -132,6 +133,76 @@ pub fn call_printk_cont(args: fmt::Arguments<'_>) {
     }
 }
 
+/// Number of messages a call site may print in each [`Ratelimit`] interval, corresponds to
+/// `DEFAULT_RATELIMIT_BURST`.
+const RATELIMIT_BURST: u32 = 10;
+
+/// Length of a [`Ratelimit`] interval in milliseconds, corresponds to
+/// `DEFAULT_RATELIMIT_INTERVAL`.
+const RATELIMIT_INTERVAL_MS: u32 = 5000;
+
+/// Per call site state of the rate-limited printing macros, corresponds to
+/// `struct ratelimit_state`.
+///
+/// Unlike the C version it doesn't take a lock, so a few more messages than the burst may get
+/// through when several CPUs start a new interval at the same time. Suppressed messages are not
+/// reported.
+///
+/// Public but hidden since it should only be used from public macros.
+#[doc(hidden)]
+pub struct Ratelimit {
+    begin: AtomicUsize,
+    printed: AtomicU32,
+}
+
+impl Ratelimit {
+    /// Creates a new state, the first interval starts with the first message.
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self {
+            begin: AtomicUsize::new(0),
+            printed: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns whether a message may be printed now, corresponds to `__ratelimit`.
+    pub fn allow(&self) -> bool {
+        // SAFETY: `jiffies` is always valid to read; it's updated concurrently, hence the
+        // volatile read.
+        let now = unsafe { core::ptr::read_volatile(core::ptr::addr_of!(crate::bindings::jiffies)) }
+            as usize;
+        // SAFETY: FFI call without safety requirements.
+        let interval =
+            unsafe { crate::bindings::__msecs_to_jiffies(RATELIMIT_INTERVAL_MS) } as usize;
+
+        let begin = self.begin.load(Ordering::Relaxed);
+        if (begin == 0 || now.wrapping_sub(begin) >= interval)
+            && self
+                .begin
+                .compare_exchange(begin, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.printed.store(0, Ordering::Relaxed);
+        }
+
+        self.printed.fetch_add(1, Ordering::Relaxed) < RATELIMIT_BURST
+    }
+}
+
+/// Forwards to the given printing macro if the call site's [`Ratelimit`] allows it.
+///
+/// Public but hidden since it should only be used from public macros.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! print_ratelimited (
+    ($print:ident, $($arg:tt)+) => ({
+        static RATELIMIT: $crate::print::Ratelimit = $crate::print::Ratelimit::new();
+        if RATELIMIT.allow() {
+            $crate::$print!($($arg)+);
+        }
+    })
+);
+
 /// Performs formatting and forwards the string to [`call_printk`].
 ///
 /// Public but hidden since it should only be used from public macros.
@@ -404,3 +475,80 @@ macro_rules! pr_cont (
         $crate::print_macro!($crate::print::format_strings::CONT, true, $($arg)*)
     )
 );
+
+/// Prints an error-level message (level 3), at most a few times every five seconds per call site.
+///
+/// Use this in paths that may run for every packet or interrupt, where an unlimited message
+/// would flood the log and slow the system down.
+///
+/// Equivalent to the kernel's `pr_err_ratelimited` macro.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::pr_err_ratelimited;
+/// pr_err_ratelimited!("hello {}\n", "there");
+/// ```
+#[macro_export]
+macro_rules! pr_err_ratelimited (
+    ($($arg:tt)*) => (
+        $crate::print_ratelimited!(pr_err, $($arg)*)
+    )
+);
+
+/// Prints a warning-level message (level 4), at most a few times every five seconds per call
+/// site.
+///
+/// Equivalent to the kernel's `pr_warn_ratelimited` macro.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::pr_warn_ratelimited;
+/// pr_warn_ratelimited!("hello {}\n", "there");
+/// ```
+#[macro_export]
+macro_rules! pr_warn_ratelimited (
+    ($($arg:tt)*) => (
+        $crate::print_ratelimited!(pr_warn, $($arg)*)
+    )
+);
+
+/// Prints an info-level message (level 6), at most a few times every five seconds per call site.
+///
+/// Equivalent to the kernel's `pr_info_ratelimited` macro.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::pr_info_ratelimited;
+/// pr_info_ratelimited!("hello {}\n", "there");
+/// ```
+#[macro_export]
+macro_rules! pr_info_ratelimited (
+    ($($arg:tt)*) => (
+        $crate::print_ratelimited!(pr_info, $($arg)*)
+    )
+);
+
+/// Prints a debug-level message (level 7), at most a few times every five seconds per call site.
+///
+/// Like [`pr_debug!`], it is compiled out without debug assertions.
+///
+/// Equivalent to the kernel's `pr_debug_ratelimited` macro, except that it doesn't support
+/// dynamic debug yet.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::pr_debug_ratelimited;
+/// pr_debug_ratelimited!("hello {}\n", "there");
+/// ```
+#[macro_export]
+macro_rules! pr_debug_ratelimited (
+    ($($arg:tt)*) => (
+        if cfg!(debug_assertions) {
+            $crate::print_ratelimited!(pr_debug, $($arg)*)
+        }
+    )
+);
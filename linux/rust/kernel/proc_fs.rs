@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Files in `/proc`.
+//!
+//! C header: [`include/linux/proc_fs.h`](../../../../include/linux/proc_fs.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_kernel_result, to_result},
+    seq_file::{SeqOperations, SeqOperationsVtable},
+    str::CStr,
+    sync::Arc,
+    Result,
+};
+use core::{marker::PhantomData, ptr};
+
+/// A read-only file in `/proc` whose contents are generated record by record by `T`.
+///
+/// The file is removed when the object is dropped.
+///
+/// # Invariants
+///
+/// `entry` is a valid entry created by `proc_create_data`, with `data` as its private data.
+///
+/// # Examples
+///
+/// ```ignore
+/// let _entry = SeqEntry::try_new(c_str!("rust_stats"), 0o444, Arc::try_new(Stats)?)?;
+/// ```
+pub struct SeqEntry<T: SeqOperations> {
+    entry: *mut bindings::proc_dir_entry,
+    data: Arc<T>,
+}
+
+// SAFETY: The entry is only used by procfs functions, which can be called from any thread, and
+// `T` is `Send + Sync`.
+unsafe impl<T: SeqOperations> Send for SeqEntry<T> {}
+
+// SAFETY: `SeqEntry` has no interior mutability and `T` is `Sync`.
+unsafe impl<T: SeqOperations> Sync for SeqEntry<T> {}
+
+impl<T: SeqOperations> SeqEntry<T> {
+    /// Creates a file called `name` in the root of `/proc`, with permissions `mode`.
+    pub fn try_new(name: &CStr, mode: u16, data: Arc<T>) -> Result<Self> {
+        // SAFETY: `name` is a valid null-terminated string. `data` is kept alive until the entry
+        // is removed in `drop`, after which procfs no longer calls `open_callback`.
+        let entry = unsafe {
+            bindings::proc_create_data(
+                name.as_char_ptr(),
+                mode,
+                ptr::null_mut(),
+                &SeqEntryAdapter::<T>::PROC_OPS,
+                &*data as *const T as *mut core::ffi::c_void,
+            )
+        };
+        if entry.is_null() {
+            return Err(ENOMEM);
+        }
+        // INVARIANTS: `proc_create_data` succeeded.
+        Ok(Self { entry, data })
+    }
+
+    /// Returns the data the file is generated from.
+    pub fn data(&self) -> &Arc<T> {
+        &self.data
+    }
+}
+
+impl<T: SeqOperations> Drop for SeqEntry<T> {
+    fn drop(&mut self) {
+        // SAFETY: `entry` is valid by the type invariants. `proc_remove` waits for callbacks
+        // already running, so `data` is no longer used once it returns.
+        unsafe { bindings::proc_remove(self.entry) };
+    }
+}
+
+struct SeqEntryAdapter<T: SeqOperations>(PhantomData<T>);
+
+impl<T: SeqOperations> SeqEntryAdapter<T> {
+    unsafe extern "C" fn open_callback(
+        inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: `file` is valid during the callback and the vtable has static lifetime.
+            to_result(unsafe { bindings::seq_open(file, &SeqOperationsVtable::<T>::VTABLE) })?;
+            // SAFETY: `seq_open` stored the new `seq_file` in the file's private data. The
+            // inode's private data is the pointer to `T` passed to `proc_create_data` (this is
+            // what `pde_data` returns), which outlives the file, see `SeqEntry::drop`.
+            unsafe {
+                let m = (*file).private_data as *mut bindings::seq_file;
+                (*m).private = (*inode).i_private;
+            }
+            Ok(0)
+        }
+    }
+
+    const PROC_OPS: bindings::proc_ops = bindings::proc_ops {
+        proc_flags: 0,
+        proc_open: Some(Self::open_callback),
+        proc_read: None,
+        proc_read_iter: Some(bindings::seq_read_iter),
+        proc_write: None,
+        proc_lseek: Some(bindings::seq_lseek),
+        proc_release: Some(bindings::seq_release),
+        proc_poll: None,
+        proc_ioctl: None,
+        #[cfg(CONFIG_COMPAT)]
+        proc_compat_ioctl: None,
+        proc_mmap: None,
+        proc_get_unmapped_area: None,
+    };
+}
@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Sequential files, i.e. read-only files whose contents are generated when they are read.
+//!
+//! C header: [`include/linux/seq_file.h`](../../../../include/linux/seq_file.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/filesystems/seq_file.html>
+
+use crate::{bindings, error::code::*, error::from_kernel_result, Result};
+use alloc::boxed::Box;
+use core::{fmt, marker::PhantomData};
+
+/// A sequential file being generated, wraps `struct seq_file`.
+pub struct SeqFile(pub(crate) *mut bindings::seq_file);
+
+impl SeqFile {
+    /// Appends formatted output to the file.
+    ///
+    /// Output that doesn't fit in the buffer is discarded and the record is generated again with
+    /// a larger one, so there is no error to report here.
+    pub fn call_printf(&mut self, args: fmt::Arguments<'_>) {
+        // SAFETY: `self.0` is valid while the show callback runs. The "%pA" format string expects
+        // a pointer to `fmt::Arguments`, which is what we're passing as the last argument.
+        unsafe {
+            bindings::seq_printf(
+                self.0,
+                crate::c_str!("%pA").as_char_ptr(),
+                &args as *const _ as *const core::ffi::c_void,
+            )
+        };
+    }
+}
+
+/// Prints to a [`SeqFile`], with the same arguments as [`core::format_args!`].
+#[macro_export]
+macro_rules! seq_print {
+    ($m:expr, $($arg:tt)*) => (
+        $m.call_printf(core::format_args!($($arg)*))
+    )
+}
+
+/// Generates a sequential file one record at a time, corresponds to `struct seq_operations`.
+///
+/// Reading the file walks [`SeqOperations::iter`] and calls [`SeqOperations::show`] for each
+/// record. Unlike a single `show` for the whole file, the output doesn't have to fit in one
+/// buffer: when the buffer is full, the iterator is dropped and recreated later, skipping the
+/// records that were already read.
+///
+/// # Examples
+///
+/// ```ignore
+/// struct Squares;
+///
+/// impl SeqOperations for Squares {
+///     type Item = u64;
+///     type Iter<'a> = core::iter::Map<core::ops::Range<u64>, fn(u64) -> u64>;
+///
+///     fn iter(&self) -> Self::Iter<'_> {
+///         (0..10).map(|i| i * i)
+///     }
+///
+///     fn show(&self, m: &mut SeqFile, item: &u64) -> Result {
+///         seq_print!(m, "{}\n", item);
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait SeqOperations: Send + Sync + 'static {
+    /// A record of the file.
+    type Item;
+
+    /// The iterator over the records.
+    type Iter<'a>: Iterator<Item = Self::Item>
+    where
+        Self: 'a;
+
+    /// Returns an iterator over the records, from the first one.
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /// Writes `item` to `m`.
+    fn show(&self, m: &mut SeqFile, item: &Self::Item) -> Result;
+}
+
+/// The iterator and the current record, passed between the `seq_operations` callbacks.
+struct SeqState<T: SeqOperations> {
+    iter: T::Iter<'static>,
+    item: T::Item,
+}
+
+/// Builds the `struct seq_operations` for a [`SeqOperations`] implementation.
+///
+/// The `private` field of the `seq_file` must point to the `T` the file is generated from, which
+/// must outlive the file.
+pub(crate) struct SeqOperationsVtable<T: SeqOperations>(PhantomData<T>);
+
+impl<T: SeqOperations> SeqOperationsVtable<T> {
+    /// Returns the data the file is generated from.
+    ///
+    /// # Safety
+    ///
+    /// `m` must be valid and its private data must point to a `T` that outlives the file.
+    unsafe fn data<'a>(m: *mut bindings::seq_file) -> &'a T {
+        // SAFETY: Guaranteed by the safety requirements.
+        unsafe { &*((*m).private as *const T) }
+    }
+
+    unsafe extern "C" fn start_callback(
+        m: *mut bindings::seq_file,
+        pos: *mut bindings::loff_t,
+    ) -> *mut core::ffi::c_void {
+        // SAFETY: The C API guarantees that `m` and `pos` are valid, and the private data was set
+        // up as required by the vtable. `data` isn't really `'static`, but it outlives the file and
+        // the iterator borrowing it is dropped in `next_callback` or `stop_callback`, while the
+        // file is still open.
+        let (data, pos): (&'static T, _) = unsafe { (Self::data(m), *pos) };
+        let mut iter = data.iter();
+        let item = match iter.nth(pos as usize) {
+            Some(item) => item,
+            None => return core::ptr::null_mut(),
+        };
+        match Box::try_new(SeqState::<T> { iter, item }) {
+            Ok(state) => Box::into_raw(state).cast(),
+            // SAFETY: FFI call, `ERR_PTR` has no requirements.
+            Err(_) => unsafe { bindings::ERR_PTR(ENOMEM.to_kernel_errno() as _) },
+        }
+    }
+
+    unsafe extern "C" fn next_callback(
+        _m: *mut bindings::seq_file,
+        v: *mut core::ffi::c_void,
+        pos: *mut bindings::loff_t,
+    ) -> *mut core::ffi::c_void {
+        // SAFETY: The C API guarantees that `pos` is valid, and `v` was returned by
+        // `start_callback` or a previous `next_callback`, so it is a live `SeqState<T>`.
+        let state = unsafe {
+            *pos += 1;
+            &mut *(v as *mut SeqState<T>)
+        };
+        match state.iter.next() {
+            Some(item) => {
+                state.item = item;
+                v
+            }
+            None => {
+                // SAFETY: `v` came from `Box::into_raw` and seq_file doesn't use it after `next`
+                // returned null.
+                drop(unsafe { Box::from_raw(v as *mut SeqState<T>) });
+                core::ptr::null_mut()
+            }
+        }
+    }
+
+    unsafe extern "C" fn stop_callback(_m: *mut bindings::seq_file, v: *mut core::ffi::c_void) {
+        // SAFETY: FFI call, `IS_ERR` only looks at the value of the pointer.
+        if v.is_null() || unsafe { bindings::IS_ERR(v) } {
+            return;
+        }
+        // SAFETY: `v` is neither null nor an error, so it was returned by `start_callback` or
+        // `next_callback` and is a live `SeqState<T>` created by `Box::into_raw`.
+        drop(unsafe { Box::from_raw(v as *mut SeqState<T>) });
+    }
+
+    unsafe extern "C" fn show_callback(
+        m: *mut bindings::seq_file,
+        v: *mut core::ffi::c_void,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: `m` is valid during the callback, its private data was set up as required
+            // by the vtable, and `v` is a live `SeqState<T>` returned by `start` or `next`.
+            let (data, state) = unsafe { (Self::data(m), &*(v as *const SeqState<T>)) };
+            data.show(&mut SeqFile(m), &state.item)?;
+            Ok(0)
+        }
+    }
+
+    pub(crate) const VTABLE: bindings::seq_operations = bindings::seq_operations {
+        start: Some(Self::start_callback),
+        stop: Some(Self::stop_callback),
+        next: Some(Self::next_callback),
+        show: Some(Self::show_callback),
+    };
+}
@@ -5,11 +5,26 @@
 //! C header: [`include/linux/sched.h`](../../../../include/linux/sched.h).
 
 use crate::{
-    bindings, c_str, error::from_kernel_err_ptr, types::PointerWrapper, ARef, AlwaysRefCounted,
-    Result, ScopeGuard,
+    bindings, c_str,
+    cpumask::CpuMask,
+    cred::Credential,
+    error::{code::*, from_kernel_err_ptr, to_result},
+    str::{CStr, CString},
+    sync::rcu,
+    types::PointerWrapper,
+    ARef, AlwaysRefCounted, Result, ScopeGuard,
 };
 use alloc::boxed::Box;
-use core::{cell::UnsafeCell, fmt, marker::PhantomData, ops::Deref, ptr};
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::Deref,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 /// Wraps the kernel's `struct task_struct`.
 ///
@@ -94,12 +109,178 @@ impl Task {
         unsafe { &*ptr.cast() }
     }
 
+    /// Returns the current parent of the given task, i.e. the task that receives `SIGCHLD` when
+    /// it exits, which is the tracer while the task is being ptraced.
+    ///
+    /// The parent changes when it exits and the task is reparented, so the returned reference is
+    /// only valid while the RCU read lock is held. For the init task, this is the task itself.
+    pub fn parent<'a>(&'a self, _guard: &'a rcu::Guard) -> &'a Task {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        let ptr = unsafe { core::ptr::addr_of!((*self.0.get()).parent).read_volatile() };
+
+        // SAFETY: `parent` is never null, and task structs are freed after an RCU grace period,
+        // so it remains valid while the RCU read lock is held.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the task that created the given task, or the task it was reparented to when that
+    /// one exited. Unlike [`Task::parent`], it is not changed by ptrace.
+    ///
+    /// See [`Task::parent`] for the lifetime of the returned reference.
+    pub fn real_parent<'a>(&'a self, _guard: &'a rcu::Guard) -> &'a Task {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        let ptr = unsafe { core::ptr::addr_of!((*self.0.get()).real_parent).read_volatile() };
+
+        // SAFETY: As in `parent`, `real_parent` is never null and is valid under RCU.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns an iterator over the threads of the given task's process, starting with the
+    /// group leader, corresponds to `for_each_thread`.
+    ///
+    /// Threads can exit (and new ones be added) while iterating, so an exiting thread may or may
+    /// not be returned; the returned references are valid while the RCU read lock is held.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let guard = rcu::read_lock();
+    /// for thread in Task::current().threads(&guard) {
+    ///     pr_info!("{} {}\n", thread.pid(), thread.comm());
+    /// }
+    /// ```
+    pub fn threads<'a>(&'a self, _guard: &'a rcu::Guard) -> TaskIter<'a> {
+        // SAFETY: By the type invariant, we know that `self.0` is valid. `signal` is set when the
+        // task is created and freed with the task struct.
+        let head = unsafe { ptr::addr_of!((*(*self.0.get()).signal).thread_head) };
+
+        // SAFETY: `thread_head` is the head of the RCU list of `task_struct::thread_node`
+        // entries, and the list is only walked while the RCU read lock is held.
+        unsafe { TaskIter::new(head, crate::offset_of!(bindings::task_struct, thread_node)) }
+    }
+
+    /// Returns an iterator over the children of the given task, excluding those being ptraced
+    /// by it.
+    ///
+    /// The list of children is not RCU-safe, so it is walked while holding `tasklist_lock` for
+    /// reading, which keeps the returned references valid.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let tasklist = TasklistGuard::lock();
+    /// let count = Task::current().children(&tasklist).count();
+    /// ```
+    pub fn children<'a>(&'a self, _guard: &'a TasklistGuard) -> TaskIter<'a> {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        let head = unsafe { ptr::addr_of!((*self.0.get()).children) };
+
+        // SAFETY: `children` is the head of the list of `task_struct::sibling` entries, which is
+        // only modified with `tasklist_lock` held for writing.
+        unsafe { TaskIter::new(head, crate::offset_of!(bindings::task_struct, sibling)) }
+    }
+
     /// Returns the PID of the given task.
     pub fn pid(&self) -> Pid {
         // SAFETY: By the type invariant, we know that `self.0` is valid.
         unsafe { core::ptr::addr_of!((*self.0.get()).pid).read() }
     }
 
+    /// Returns the thread group id of the given task, i.e. the PID of its process as seen from
+    /// userspace.
+    pub fn tgid(&self) -> Pid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        unsafe { core::ptr::addr_of!((*self.0.get()).tgid).read() }
+    }
+
+    /// Returns a copy of the name of the given task, corresponds to `get_task_comm`.
+    ///
+    /// The name can be changed at any time (e.g. by `prctl(PR_SET_NAME)`), so it is copied under
+    /// the task lock rather than borrowed.
+    pub fn comm(&self) -> Comm {
+        let mut comm = Comm([0; bindings::TASK_COMM_LEN as usize]);
+        // SAFETY: By the type invariant, we know that `self.0` is valid. The buffer is
+        // `TASK_COMM_LEN` bytes long, as `__get_task_comm` requires.
+        unsafe {
+            bindings::__get_task_comm(comm.0.as_mut_ptr().cast(), comm.0.len(), self.0.get())
+        };
+        // INVARIANT: `__get_task_comm` always null-terminates the copy.
+        comm
+    }
+
+    /// Returns the objective credentials of the given task, corresponds to `get_task_cred`.
+    ///
+    /// These are the credentials used when the task is acted upon, e.g. by a signal; they can be
+    /// replaced at any time, so the returned reference is a snapshot.
+    pub fn cred(&self) -> ARef<Credential> {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        let ptr = unsafe { bindings::get_task_cred(self.0.get()) };
+        // SAFETY: `get_task_cred` never returns null, and it took a reference that we now own.
+        unsafe { ARef::from_raw(ptr::NonNull::new_unchecked(ptr as *mut Credential)) }
+    }
+
+    /// Returns the real user id of the given task.
+    pub fn uid(&self) -> u32 {
+        self.cred().uid()
+    }
+
+    /// Returns the effective user id of the given task.
+    pub fn euid(&self) -> u32 {
+        self.cred().euid()
+    }
+
+    /// Returns the CPU time the given task has spent in user mode, corresponds to
+    /// `task_cputime`.
+    ///
+    /// This only covers the task itself; other threads of its process aren't included.
+    pub fn utime(&self) -> Duration {
+        self.cputime().0
+    }
+
+    /// Returns the CPU time the given task has spent in kernel mode, see [`Task::utime`].
+    pub fn stime(&self) -> Duration {
+        self.cputime().1
+    }
+
+    fn cputime(&self) -> (Duration, Duration) {
+        let (mut utime, mut stime) = (0, 0);
+        // SAFETY: By the type invariant, we know that `self.0.get()` is valid. `task_cputime`
+        // takes care of the synchronisation with the accounting code.
+        unsafe { bindings::task_cputime(self.0.get(), &mut utime, &mut stime) };
+        (Duration::from_nanos(utime), Duration::from_nanos(stime))
+    }
+
+    /// Returns the time at which the given task was created, on the monotonic clock (i.e. since
+    /// boot, excluding suspend).
+    pub fn start_time(&self) -> Duration {
+        // SAFETY: By the type invariant, we know that `self.0` is valid. `start_time` is set
+        // when the task is created and never changes afterwards.
+        Duration::from_nanos(unsafe { core::ptr::addr_of!((*self.0.get()).start_time).read() })
+    }
+
+    /// Returns the number of voluntary context switches of the given task, i.e. times it
+    /// blocked.
+    pub fn nvcsw(&self) -> u64 {
+        // SAFETY: By the type invariant, we know that `self.0` is valid. The counter is only
+        // updated by the scheduler, so the value may be stale but is never torn.
+        unsafe { core::ptr::addr_of!((*self.0.get()).nvcsw).read_volatile() as u64 }
+    }
+
+    /// Returns the number of involuntary context switches of the given task, i.e. times it was
+    /// preempted.
+    pub fn nivcsw(&self) -> u64 {
+        // SAFETY: As in `nvcsw`.
+        unsafe { core::ptr::addr_of!((*self.0.get()).nivcsw).read_volatile() as u64 }
+    }
+
+    /// Returns whether the given task is a kernel thread (`PF_KTHREAD`).
+    pub fn is_kthread(&self) -> bool {
+        // SAFETY: By the type invariant, we know that `self.0` is valid. `PF_KTHREAD` is set when
+        // the task is created and never changes afterwards.
+        let flags = unsafe { core::ptr::addr_of!((*self.0.get()).flags).read() };
+        flags & bindings::PF_KTHREAD != 0
+    }
+
     /// Determines whether the given task has pending signals.
     pub fn signal_pending(&self) -> bool {
         // SAFETY: By the type invariant, we know that `self.0` is valid.
@@ -146,6 +327,49 @@ impl Task {
     pub fn spawn<T: FnOnce() + Send + 'static>(
         name: fmt::Arguments<'_>,
         func: T,
+    ) -> Result<ARef<Task>> {
+        Self::spawn_with(name, None, func)
+    }
+
+    /// Returns a builder for a kernel thread named `name`, which can also set its CPU affinity
+    /// and scheduling priority before it runs.
+    ///
+    /// The thread is started by [`TaskBuilder::spawn`], which returns a [`KThread`] handle to
+    /// stop it.
+    pub fn builder(name: fmt::Arguments<'_>) -> TaskBuilder<'_> {
+        TaskBuilder {
+            name,
+            cpu: None,
+            cpus: None,
+            nice: None,
+            fifo_priority: None,
+        }
+    }
+
+    /// Starts a new kernel thread bound to `cpu` and runs it, corresponds to
+    /// `kthread_create_on_cpu`.
+    ///
+    /// The thread only ever runs on `cpu`, which must be online, and its affinity can't be
+    /// changed afterwards with [`Task::set_cpus_allowed`]. Its memory is allocated on the NUMA
+    /// node of `cpu`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// Task::spawn_on_cpu(fmt!("poll/{}", 2), 2, || poll_loop())?;
+    /// ```
+    pub fn spawn_on_cpu<T: FnOnce() + Send + 'static>(
+        name: fmt::Arguments<'_>,
+        cpu: u32,
+        func: T,
+    ) -> Result<ARef<Task>> {
+        Self::spawn_with(name, Some(cpu), func)
+    }
+
+    fn spawn_with<T: FnOnce() + Send + 'static>(
+        name: fmt::Arguments<'_>,
+        cpu: Option<u32>,
+        func: T,
     ) -> Result<ARef<Task>> {
         unsafe extern "C" fn threadfn<T: FnOnce() + Send + 'static>(
             arg: *mut core::ffi::c_void,
@@ -164,31 +388,38 @@ impl Task {
             Box::<T>::from_pointer(arg);
         });
 
-        // SAFETY: The function pointer is always valid (as long as the module remains loaded).
-        // Ownership of `raw` is transferred to the new thread (if one is actually created), so it
-        // remains valid. Lastly, the C format string is a constant that require formatting as the
-        // one and only extra argument.
-        let ktask = from_kernel_err_ptr(unsafe {
-            bindings::kthread_create_on_node(
-                Some(threadfn::<T>),
-                arg as _,
-                bindings::NUMA_NO_NODE,
-                c_str!("%pA").as_char_ptr(),
-                &name as *const _ as *const core::ffi::c_void,
-            )
-        })?;
+        // SAFETY: Ownership of `arg` is transferred to the new thread (if one is actually
+        // created), so it remains valid for `threadfn`, which expects a `Box<T>`.
+        let task = unsafe { create_kthread(name, cpu, threadfn::<T>, arg as _) }?;
 
-        // SAFETY: Since the kthread creation succeeded and we haven't run it yet, we know the task
-        // is valid.
-        let task: ARef<_> = unsafe { &*(ktask as *const Task) }.into();
+        guard.dismiss();
 
         // Wakes up the thread, otherwise it won't run.
         task.wake_up();
-
-        guard.dismiss();
         Ok(task)
     }
 
+    /// Sends `sig` to the given task, corresponds to `send_sig`.
+    ///
+    /// The signal is sent on behalf of the kernel, so no permission checks are made and the
+    /// receiver sees `SI_KERNEL` in `si_code`. Kernel threads ignore signals unless they
+    /// explicitly allow them.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// task.send_sig(Signal::Usr1)?;
+    /// ```
+    pub fn send_sig(&self, sig: Signal) -> Result {
+        // SAFETY: By the type invariant, we know that `self.0.get()` is non-null and valid.
+        to_result(unsafe { bindings::send_sig(sig.as_raw(), self.0.get(), 1) })
+    }
+
+    /// Sends `SIGKILL` to the given task, see [`Task::send_sig`].
+    pub fn kill(&self) -> Result {
+        self.send_sig(Signal::Kill)
+    }
+
     /// Wakes up the task.
     pub fn wake_up(&self) {
         // SAFETY: By the type invariant, we know that `self.0.get()` is non-null and valid.
@@ -197,6 +428,73 @@ impl Task {
         unsafe { bindings::wake_up_process(self.0.get()) };
     }
 
+    /// Sets the nice value of the task, corresponds to `set_user_nice`.
+    ///
+    /// `nice` ranges from -20 (highest priority) to 19 (lowest priority), otherwise `EINVAL` is
+    /// returned. It only has an effect while the task runs under a fair scheduling policy such as
+    /// `SCHED_NORMAL`, but is kept across policy changes.
+    ///
+    /// Unlike `setpriority(2)`, no permission checks are made, so this is meant for kernel
+    /// threads the caller created.
+    pub fn set_user_nice(&self, nice: i32) -> Result {
+        if !(MIN_NICE..=MAX_NICE).contains(&nice) {
+            return Err(EINVAL);
+        }
+        // SAFETY: By the type invariant, we know that `self.0.get()` is non-null and valid.
+        unsafe { bindings::set_user_nice(self.0.get(), nice.into()) };
+        Ok(())
+    }
+
+    /// Switches the task to the `SCHED_FIFO` real-time policy with the given priority.
+    ///
+    /// `priority` ranges from 1 (lowest) to 99 (highest), otherwise `EINVAL` is returned. A
+    /// `SCHED_FIFO` task preempts every fair task and runs until it blocks or yields, so this
+    /// is for short, latency-sensitive work; a busy loop at real-time priority starves the CPU.
+    ///
+    /// Like [`Task::set_user_nice`], no permission checks are made.
+    pub fn set_fifo_priority(&self, priority: u32) -> Result {
+        if !(1..bindings::MAX_RT_PRIO).contains(&priority) {
+            return Err(EINVAL);
+        }
+        self.setscheduler(bindings::SCHED_FIFO, priority)
+    }
+
+    /// Switches the task back to the default `SCHED_NORMAL` policy.
+    ///
+    /// The nice value of the task is left unchanged, see [`Task::set_user_nice`].
+    pub fn set_normal(&self) -> Result {
+        self.setscheduler(bindings::SCHED_NORMAL, 0)
+    }
+
+    /// Restricts the task to the CPUs in `mask`, corresponds to `set_cpus_allowed_ptr`.
+    ///
+    /// If the task is running on a CPU outside `mask`, it is migrated before this returns. Fails
+    /// with `EINVAL` if no CPU in `mask` is online, or if the affinity of the task can't be
+    /// changed, as is the case for threads started with [`Task::spawn_on_cpu`] and per-CPU
+    /// kernel threads.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let thread = Task::spawn(fmt!("rust_stats"), stats_loop)?;
+    /// thread.set_cpus_allowed(CpuMask::of_node(numa_node).ok_or(EINVAL)?)?;
+    /// ```
+    pub fn set_cpus_allowed(&self, mask: &CpuMask) -> Result {
+        // SAFETY: By the type invariant, we know that `self.0.get()` is non-null and valid.
+        // `mask` is valid for the duration of the call, its contents are copied.
+        to_result(unsafe { bindings::set_cpus_allowed_ptr(self.0.get(), mask.as_ptr()) })
+    }
+
+    fn setscheduler(&self, policy: u32, priority: u32) -> Result {
+        let param = bindings::sched_param {
+            sched_priority: priority as _,
+        };
+        // SAFETY: By the type invariant, we know that `self.0.get()` is non-null and valid.
+        // `param` is valid for the duration of the call, which only reads it.
+        to_result(unsafe {
+            bindings::sched_setscheduler_nocheck(self.0.get(), policy as _, &param)
+        })
+    }
 
     /// 等待任务变得不活跃或达到指定的状态。
     ///
@@ -253,6 +551,581 @@ unsafe impl AlwaysRefCounted for Task {
     }
 }
 
+/// The highest priority nice value, corresponds to `MIN_NICE`.
+const MIN_NICE: i32 = -20;
+
+/// The lowest priority nice value, corresponds to `MAX_NICE`.
+const MAX_NICE: i32 = 19;
+
+/// Creates a kernel thread named `name` that runs `threadfn(arg)`, bound to `cpu` if given.
+///
+/// The thread doesn't run until it is woken up.
+///
+/// # Safety
+///
+/// `threadfn` must be safe to call with `arg` on the new thread, at any time until it exits.
+unsafe fn create_kthread(
+    name: fmt::Arguments<'_>,
+    cpu: Option<u32>,
+    threadfn: unsafe extern "C" fn(*mut core::ffi::c_void) -> core::ffi::c_int,
+    arg: *mut core::ffi::c_void,
+) -> Result<ARef<Task>> {
+    let ktask = match cpu {
+        // SAFETY: The function pointer is always valid (as long as the module remains loaded),
+        // and `arg` is valid for it by the safety requirements. The C format string is a constant
+        // that requires formatting as the one and only extra argument.
+        None => from_kernel_err_ptr(unsafe {
+            bindings::kthread_create_on_node(
+                Some(threadfn),
+                arg,
+                bindings::NUMA_NO_NODE,
+                c_str!("%pA").as_char_ptr(),
+                &name as *const _ as *const core::ffi::c_void,
+            )
+        })?,
+        Some(cpu) => {
+            // SAFETY: `nr_cpu_ids` is set up during early boot and is read-only afterwards, and
+            // `cpu_online` only reads the online mask for a valid CPU id.
+            if cpu >= unsafe { bindings::nr_cpu_ids } || !unsafe { bindings::cpu_online(cpu) } {
+                return Err(EINVAL);
+            }
+
+            // `kthread_create_on_cpu` only takes a format string (with the CPU id as its
+            // argument), so the name is formatted here and any `%` in it escaped.
+            let namefmt = CString::try_from_fmt(format_args!("{}", EscapePercent(name)))?;
+            // SAFETY: As above. `namefmt` is a valid null-terminated string without conversion
+            // specifications.
+            from_kernel_err_ptr(unsafe {
+                bindings::kthread_create_on_cpu(Some(threadfn), arg, cpu, namefmt.as_char_ptr())
+            })?
+        }
+    };
+
+    // SAFETY: Since the kthread creation succeeded and we haven't run it yet, we know the task is
+    // valid.
+    Ok(unsafe { &*(ktask as *const Task) }.into())
+}
+
+/// Formats its arguments with every `%` doubled, so that the result can be used as a C format
+/// string.
+struct EscapePercent<'a>(fmt::Arguments<'a>);
+
+impl fmt::Display for EscapePercent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Writer<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+        impl fmt::Write for Writer<'_, '_> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                for (i, part) in s.split('%').enumerate() {
+                    if i > 0 {
+                        self.0.write_str("%%")?;
+                    }
+                    self.0.write_str(part)?;
+                }
+                Ok(())
+            }
+        }
+
+        fmt::write(&mut Writer(f), self.0)
+    }
+}
+
+/// A kernel thread that can be asked to stop, and whose exit code is returned when it is stopped.
+///
+/// The thread function runs until it returns or until [`KThread::should_stop`] tells it to
+/// return. [`KThread::stop`] sets the flag checked by `should_stop`, wakes the thread up and waits
+/// for it to exit, corresponding to `kthread_stop`. Dropping the handle stops the thread as well,
+/// so a thread can't outlive the module that owns its handle.
+///
+/// Threads that need a CPU affinity or a scheduling priority are started with [`Task::builder`].
+///
+/// # Invariants
+///
+/// `start` points to the `ThreadStart<T>` passed to the thread, and `free_start` is
+/// `ThreadStart::<T>::free`, until the thread is stopped; `start` is null afterwards.
+///
+/// # Examples
+///
+/// ```ignore
+/// let thread = KThread::spawn(fmt!("rust_worker"), || {
+///     while !KThread::should_stop() {
+///         do_work()?;
+///         // Returns early when `stop` wakes the thread up.
+///         Task::current().sleep_interruptible(Duration::from_millis(100))?;
+///     }
+///     Ok(())
+/// })?;
+///
+/// // Later, e.g. when the module is unloaded.
+/// thread.stop()?;
+/// ```
+pub struct KThread {
+    task: ARef<Task>,
+    start: *mut core::ffi::c_void,
+    free_start: unsafe fn(*mut core::ffi::c_void),
+}
+
+// SAFETY: The thread function is `Send`, and `start` is only freed by the handle once the thread
+// has exited.
+unsafe impl Send for KThread {}
+
+// SAFETY: Methods taking `&self` only give access to the task, which is `Sync`.
+unsafe impl Sync for KThread {}
+
+impl KThread {
+    /// Starts a new kernel thread running `func` and returns a handle to stop it.
+    pub fn spawn<T: FnOnce() -> Result + Send + 'static>(
+        name: fmt::Arguments<'_>,
+        func: T,
+    ) -> Result<Self> {
+        Self::spawn_with(name, None, func)
+    }
+
+    /// Starts a new kernel thread bound to `cpu`, like [`Task::spawn_on_cpu`], and returns a
+    /// handle to stop it.
+    pub fn spawn_on_cpu<T: FnOnce() -> Result + Send + 'static>(
+        name: fmt::Arguments<'_>,
+        cpu: u32,
+        func: T,
+    ) -> Result<Self> {
+        Self::spawn_with(name, Some(cpu), func)
+    }
+
+    fn spawn_with<T: FnOnce() -> Result + Send + 'static>(
+        name: fmt::Arguments<'_>,
+        cpu: Option<u32>,
+        func: T,
+    ) -> Result<Self> {
+        let thread = Self::create(name, cpu, func)?;
+        thread.task.wake_up();
+        Ok(thread)
+    }
+
+    /// Creates the thread without waking it up, so that it can be set up before it runs.
+    ///
+    /// Dropping the handle before the thread is woken up stops it without running `func`.
+    fn create<T: FnOnce() -> Result + Send + 'static>(
+        name: fmt::Arguments<'_>,
+        cpu: Option<u32>,
+        func: T,
+    ) -> Result<Self> {
+        let start = Box::into_raw(Box::try_new(ThreadStart {
+            started: AtomicBool::new(false),
+            func: UnsafeCell::new(ManuallyDrop::new(func)),
+        })?)
+        .cast();
+
+        // SAFETY: `start` is a `ThreadStart<T>`, as `ThreadStart::<T>::threadfn` expects, and is
+        // only freed after the thread has exited (see `stop_raw`) or if it couldn't be created.
+        match unsafe { create_kthread(name, cpu, ThreadStart::<T>::threadfn, start) } {
+            // INVARIANT: `start` is the `ThreadStart<T>` passed to the thread.
+            Ok(task) => Ok(Self {
+                task,
+                start,
+                free_start: ThreadStart::<T>::free,
+            }),
+            Err(e) => {
+                // SAFETY: No thread was created, so nothing else uses `start`.
+                unsafe { ThreadStart::<T>::free(start) };
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the task running the thread.
+    pub fn task(&self) -> &Task {
+        &self.task
+    }
+
+    /// Returns whether the current kernel thread has been asked to stop, corresponds to
+    /// `kthread_should_stop`.
+    ///
+    /// Called from the thread function of a [`KThread`], which should return as soon as possible
+    /// once this returns `true`. Waits in the thread should be interruptible by a wake-up, since
+    /// [`KThread::stop`] wakes the thread up after setting the flag. Always returns `false` when
+    /// not called from a kernel thread.
+    pub fn should_stop() -> bool {
+        // SAFETY: FFI call, the current task was checked to be a kernel thread.
+        Task::current().is_kthread() && unsafe { bindings::kthread_should_stop() }
+    }
+
+    /// Asks the thread to stop, waits for it to exit and returns its exit code, corresponds to
+    /// `kthread_stop`.
+    ///
+    /// Returns the result of the thread function, or `EINTR` if the thread was stopped before
+    /// the function started to run.
+    pub fn stop(mut self) -> Result {
+        to_result(self.stop_raw())
+    }
+
+    fn stop_raw(&mut self) -> core::ffi::c_int {
+        // SAFETY: The task is a kernel thread created by `create_kthread` and we hold a
+        // reference to it, so it is still valid even if the thread has already exited.
+        let ret = unsafe { bindings::kthread_stop(self.task.0.get()) };
+        // SAFETY: The thread has exited, so it no longer uses `start`, which is valid by the type
+        // invariants.
+        unsafe { (self.free_start)(self.start) };
+        // INVARIANT: The thread has been stopped.
+        self.start = ptr::null_mut();
+        ret
+    }
+}
+
+impl Drop for KThread {
+    fn drop(&mut self) {
+        if !self.start.is_null() {
+            self.stop_raw();
+        }
+    }
+}
+
+/// The argument of a thread started by [`KThread`], owned by the handle.
+///
+/// The thread moves `func` out when it starts running; if it is stopped before that, the handle
+/// drops `func` instead.
+struct ThreadStart<T> {
+    started: AtomicBool,
+    func: UnsafeCell<ManuallyDrop<T>>,
+}
+
+impl<T: FnOnce() -> Result + Send + 'static> ThreadStart<T> {
+    unsafe extern "C" fn threadfn(arg: *mut core::ffi::c_void) -> core::ffi::c_int {
+        // SAFETY: The thread argument is always a `ThreadStart<T>` that outlives the thread, see
+        // `KThread::spawn_with`.
+        let start = unsafe { &*(arg as *const Self) };
+        // The handle only reads `started` after the thread has exited, so relaxed ordering is
+        // enough.
+        start.started.store(true, Ordering::Relaxed);
+        // SAFETY: The thread function is only called once, and the handle doesn't touch `func`
+        // once `started` is set.
+        let func = unsafe { ManuallyDrop::take(&mut *start.func.get()) };
+        match func() {
+            Ok(()) => 0,
+            Err(e) => e.to_kernel_errno(),
+        }
+    }
+
+    /// Frees a `ThreadStart<T>`, dropping the thread function if the thread never ran it.
+    ///
+    /// # Safety
+    ///
+    /// `arg` must be a `ThreadStart<T>` created by `KThread::spawn_with` that the thread no
+    /// longer uses.
+    unsafe fn free(arg: *mut core::ffi::c_void) {
+        // SAFETY: By the safety requirements, `arg` came from `Box::into_raw` and nothing else
+        // uses it.
+        let mut start = unsafe { Box::from_raw(arg as *mut Self) };
+        if !start.started.load(Ordering::Relaxed) {
+            // SAFETY: The thread never took `func`, so it is still initialised.
+            unsafe { ManuallyDrop::drop(start.func.get_mut()) };
+        }
+    }
+}
+
+/// An iterator over a list of tasks, returned by [`Task::threads`] and [`Task::children`].
+///
+/// # Invariants
+///
+/// `head` is the head of a list of `task_struct` entries whose links are `offset` bytes into the
+/// task struct, and `pos` is `head` or an entry of the list. The list remains valid for `'a`.
+pub struct TaskIter<'a> {
+    head: *const bindings::list_head,
+    pos: *const bindings::list_head,
+    offset: isize,
+    _p: PhantomData<&'a Task>,
+}
+
+impl<'a> TaskIter<'a> {
+    /// Creates an iterator over the list at `head`.
+    ///
+    /// # Safety
+    ///
+    /// `head` must be the head of a list of `task_struct` entries linked through the field at
+    /// `offset`, and the list must be protected for `'a` against entries being freed and against
+    /// concurrent modifications that aren't RCU-safe.
+    unsafe fn new(head: *const bindings::list_head, offset: isize) -> Self {
+        // INVARIANT: Guaranteed by the safety requirements.
+        Self {
+            head,
+            pos: head,
+            offset,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for TaskIter<'a> {
+    type Item = &'a Task;
+
+    fn next(&mut self) -> Option<&'a Task> {
+        // SAFETY: `pos` is the head or an entry of the list, which is valid by the type
+        // invariants. The list may be modified concurrently under RCU, hence the volatile read
+        // (`READ_ONCE` in `list_for_each_entry_rcu`).
+        let next = unsafe { ptr::addr_of!((*self.pos).next).read_volatile() };
+        if ptr::eq(next, self.head) {
+            return None;
+        }
+        // INVARIANT: `next` is an entry of the list.
+        self.pos = next;
+        // SAFETY: Entries of the list are embedded `offset` bytes into a task struct, which
+        // remains valid for `'a` by the type invariants. `Task` is transparent.
+        Some(unsafe { &*(next.cast::<u8>().offset(-self.offset) as *const Task) })
+    }
+}
+
+/// Holds `tasklist_lock` for reading, which protects the process tree.
+///
+/// This is required to walk the children of a task with [`Task::children`]. It's a spinning lock,
+/// so the holder must not sleep.
+pub struct TasklistGuard {
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl TasklistGuard {
+    /// Acquires `tasklist_lock` for reading.
+    pub fn lock() -> Self {
+        // SAFETY: `tasklist_lock` is a static lock; readers don't need to disable interrupts.
+        unsafe { bindings::read_lock(ptr::addr_of_mut!(bindings::tasklist_lock)) };
+        Self {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for TasklistGuard {
+    fn drop(&mut self) {
+        // SAFETY: The lock was acquired for reading in `lock`, on the same thread since the guard
+        // isn't `Send`.
+        unsafe { bindings::read_unlock(ptr::addr_of_mut!(bindings::tasklist_lock)) };
+    }
+}
+
+/// Queries about the context the caller runs in.
+///
+/// These are mostly useful in debug assertions, e.g. that a function which frees DMA memory isn't
+/// called from an interrupt handler or under a spinlock.
+///
+/// # Examples
+///
+/// ```ignore
+/// use kernel::task::context;
+///
+/// fn free_rings(&self) {
+///     debug_assert!(context::may_sleep());
+///     // ...
+/// }
+/// ```
+pub mod context {
+    use crate::bindings;
+
+    /// Returns whether the caller is in hard interrupt or NMI context, corresponds to
+    /// `in_hardirq() || in_nmi()`.
+    pub fn in_hardirq() -> bool {
+        // SAFETY: FFI calls, they only read the preemption count of the current CPU.
+        unsafe { bindings::in_hardirq() || bindings::in_nmi() }
+    }
+
+    /// Returns whether the caller is running a softirq (e.g. a NAPI poll or a timer), corresponds
+    /// to `in_serving_softirq`.
+    ///
+    /// Unlike the C `in_softirq`, this is false in process context with bottom halves disabled.
+    pub fn in_softirq() -> bool {
+        // SAFETY: FFI call, it only reads the preemption count of the current CPU.
+        unsafe { bindings::in_serving_softirq() }
+    }
+
+    /// Returns whether the caller is in hard interrupt, NMI or softirq context, or has bottom
+    /// halves disabled, corresponds to `in_interrupt`.
+    pub fn in_interrupt() -> bool {
+        // SAFETY: FFI call, it only reads the preemption count of the current CPU.
+        unsafe { bindings::in_interrupt() }
+    }
+
+    /// Returns whether the caller can be preempted, corresponds to `preemptible`.
+    ///
+    /// This is never the case with interrupts disabled or inside a spinlock. Without
+    /// `CONFIG_PREEMPT_COUNT` the kernel doesn't track it and this always returns `false`, see
+    /// [`may_sleep`] for a check that is usable in assertions.
+    pub fn preemptible() -> bool {
+        // SAFETY: FFI call, it only reads the preemption count and interrupt state of the current
+        // CPU.
+        unsafe { bindings::preemptible() }
+    }
+
+    /// Returns whether the caller is in process context and may sleep, as far as the kernel can
+    /// tell.
+    ///
+    /// Atomic sections (e.g. spinlocks) are only detected with `CONFIG_PREEMPT_COUNT`; without it
+    /// only interrupt context and disabled interrupts are, so a `true` result is not a guarantee.
+    pub fn may_sleep() -> bool {
+        // SAFETY: FFI calls, they only read the state of the current CPU.
+        let (irqs_disabled, preempt_count) =
+            unsafe { (bindings::irqs_disabled(), bindings::preempt_count()) };
+        if cfg!(CONFIG_PREEMPT_COUNT) {
+            preempt_count == 0 && !irqs_disabled
+        } else {
+            !in_interrupt() && !irqs_disabled
+        }
+    }
+}
+
+/// A signal number, as used with [`Task::send_sig`].
+///
+/// The numbering depends on the architecture, so the values come from the C definitions.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    /// Hangup (`SIGHUP`).
+    Hup = bindings::SIGHUP,
+    /// Interrupt from the keyboard (`SIGINT`).
+    Int = bindings::SIGINT,
+    /// Quit from the keyboard (`SIGQUIT`).
+    Quit = bindings::SIGQUIT,
+    /// Abort (`SIGABRT`).
+    Abrt = bindings::SIGABRT,
+    /// Kill, can't be caught or ignored (`SIGKILL`).
+    Kill = bindings::SIGKILL,
+    /// User-defined signal 1 (`SIGUSR1`).
+    Usr1 = bindings::SIGUSR1,
+    /// User-defined signal 2 (`SIGUSR2`).
+    Usr2 = bindings::SIGUSR2,
+    /// Broken pipe (`SIGPIPE`).
+    Pipe = bindings::SIGPIPE,
+    /// Timer expired (`SIGALRM`).
+    Alrm = bindings::SIGALRM,
+    /// Termination (`SIGTERM`).
+    Term = bindings::SIGTERM,
+    /// Child stopped or terminated (`SIGCHLD`).
+    Chld = bindings::SIGCHLD,
+    /// Continue if stopped (`SIGCONT`).
+    Cont = bindings::SIGCONT,
+    /// Stop, can't be caught or ignored (`SIGSTOP`).
+    Stop = bindings::SIGSTOP,
+    /// Stop from the terminal (`SIGTSTP`).
+    Tstp = bindings::SIGTSTP,
+    /// Urgent condition on a socket (`SIGURG`).
+    Urg = bindings::SIGURG,
+    /// Window resize (`SIGWINCH`).
+    Winch = bindings::SIGWINCH,
+    /// I/O is possible, e.g. for files with `O_ASYNC` (`SIGIO`).
+    Io = bindings::SIGIO,
+}
+
+impl Signal {
+    /// Returns the C signal number.
+    pub fn as_raw(self) -> core::ffi::c_int {
+        self as u32 as _
+    }
+}
+
+/// A copy of the name of a task, returned by [`Task::comm`].
+///
+/// # Invariants
+///
+/// The buffer contains a null byte.
+#[derive(Clone, Copy)]
+pub struct Comm([u8; bindings::TASK_COMM_LEN as usize]);
+
+impl Comm {
+    /// Returns the name as a C string.
+    pub fn as_cstr(&self) -> &CStr {
+        // The invariant guarantees there is a null byte.
+        let len = self
+            .0
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(self.0.len() - 1);
+        // SAFETY: `self.0[len]` is the first null byte.
+        unsafe { CStr::from_bytes_with_nul_unchecked(&self.0[..=len]) }
+    }
+}
+
+impl fmt::Display for Comm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_cstr(), f)
+    }
+}
+
+impl fmt::Debug for Comm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_cstr(), f)
+    }
+}
+
+/// Configures and starts a [`KThread`], returned by [`Task::builder`].
+///
+/// The settings are applied before the thread first runs, so the thread function always runs
+/// with them. If one can't be applied, the thread is stopped without running its function.
+///
+/// # Examples
+///
+/// ```ignore
+/// let thread = Task::builder(fmt!("rx-poll/{}", cpu))
+///     .on_cpu(cpu)
+///     .nice(-5)
+///     .spawn(move || rx_poll_loop(cpu))?;
+/// ```
+pub struct TaskBuilder<'a> {
+    name: fmt::Arguments<'a>,
+    cpu: Option<u32>,
+    cpus: Option<&'a CpuMask>,
+    nice: Option<i32>,
+    fifo_priority: Option<u32>,
+}
+
+impl<'a> TaskBuilder<'a> {
+    /// Binds the thread to `cpu`, like [`Task::spawn_on_cpu`].
+    ///
+    /// Can't be combined with [`TaskBuilder::cpus`].
+    pub fn on_cpu(mut self, cpu: u32) -> Self {
+        self.cpu = Some(cpu);
+        self
+    }
+
+    /// Restricts the thread to the CPUs in `mask`, see [`Task::set_cpus_allowed`].
+    ///
+    /// Unlike with [`TaskBuilder::on_cpu`], the affinity can be changed later.
+    pub fn cpus(mut self, mask: &'a CpuMask) -> Self {
+        self.cpus = Some(mask);
+        self
+    }
+
+    /// Sets the nice value of the thread, see [`Task::set_user_nice`].
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.nice = Some(nice);
+        self
+    }
+
+    /// Runs the thread under the `SCHED_FIFO` policy with the given priority, see
+    /// [`Task::set_fifo_priority`].
+    pub fn fifo_priority(mut self, priority: u32) -> Self {
+        self.fifo_priority = Some(priority);
+        self
+    }
+
+    /// Starts the thread running `func` and returns a handle to stop it.
+    pub fn spawn<T: FnOnce() -> Result + Send + 'static>(self, func: T) -> Result<KThread> {
+        if self.cpu.is_some() && self.cpus.is_some() {
+            return Err(EINVAL);
+        }
+
+        // Errors below drop `thread`, which stops it before `func` runs.
+        let thread = KThread::create(self.name, self.cpu, func)?;
+        let task = thread.task();
+        if let Some(mask) = self.cpus {
+            task.set_cpus_allowed(mask)?;
+        }
+        if let Some(nice) = self.nice {
+            task.set_user_nice(nice)?;
+        }
+        if let Some(priority) = self.fifo_priority {
+            task.set_fifo_priority(priority)?;
+        }
+        task.wake_up();
+        Ok(thread)
+    }
+}
+
 /// A wrapper for a shared reference to [`Task`] that isn't [`Send`].
 ///
 /// We make this explicitly not [`Send`] so that we can use it to represent the current thread
@@ -274,6 +1147,64 @@ impl Deref for TaskRef<'_> {
     }
 }
 
+impl TaskRef<'_> {
+    /// Sleeps for `duration`, or until the current task is woken up or a signal is pending,
+    /// corresponds to `schedule_timeout_interruptible`.
+    ///
+    /// Returns `EINTR` if a signal is pending, and otherwise the time left, which is zero unless
+    /// the task was woken up early (e.g. by [`KThread::stop`]). `duration` is rounded up to
+    /// jiffies.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// while !KThread::should_stop() {
+    ///     poll_once()?;
+    ///     Task::current().sleep_interruptible(Duration::from_millis(100))?;
+    /// }
+    /// ```
+    pub fn sleep_interruptible(&self, duration: Duration) -> Result<Duration> {
+        // SAFETY: FFI call, `schedule_timeout_interruptible` sets the state of the current task
+        // before sleeping, and `self` is the current task.
+        let left =
+            unsafe { bindings::schedule_timeout_interruptible(duration_to_jiffies(duration)) };
+        if self.signal_pending() {
+            return Err(EINTR);
+        }
+        Ok(jiffies_to_duration(left))
+    }
+
+    /// Sleeps for `duration`, or until the current task is woken up, ignoring signals,
+    /// corresponds to `schedule_timeout_uninterruptible`.
+    ///
+    /// Returns the time left, see [`TaskRef::sleep_interruptible`]. The sleep counts towards the
+    /// load average, and a long one can trigger the hung task detector, so prefer
+    /// [`TaskRef::sleep_interruptible`] in kernel threads, which don't receive signals anyway.
+    pub fn sleep_uninterruptible(&self, duration: Duration) -> Duration {
+        // SAFETY: FFI call, as in `sleep_interruptible`.
+        let left =
+            unsafe { bindings::schedule_timeout_uninterruptible(duration_to_jiffies(duration)) };
+        jiffies_to_duration(left)
+    }
+}
+
+/// Converts `duration` to a timeout for `schedule_timeout`, rounded up to the next millisecond
+/// and then to jiffies.
+fn duration_to_jiffies(duration: Duration) -> core::ffi::c_long {
+    let millis = duration.as_nanos().saturating_add(999_999) / 1_000_000;
+    let millis = millis.try_into().unwrap_or(core::ffi::c_uint::MAX);
+    // SAFETY: `__msecs_to_jiffies` has no safety requirements.
+    let jiffies = unsafe { bindings::__msecs_to_jiffies(millis) };
+    // `__msecs_to_jiffies` saturates at `MAX_JIFFY_OFFSET`, which is below `LONG_MAX`.
+    jiffies.try_into().unwrap_or(core::ffi::c_long::MAX)
+}
+
+/// Converts the time left returned by `schedule_timeout` back to a [`Duration`].
+fn jiffies_to_duration(jiffies: core::ffi::c_long) -> Duration {
+    // SAFETY: `jiffies_to_usecs` has no safety requirements.
+    Duration::from_micros(unsafe { bindings::jiffies_to_usecs(jiffies as _) }.into())
+}
+
 impl From<TaskRef<'_>> for ARef<Task> {
     fn from(t: TaskRef<'_>) -> Self {
         t.deref().into()
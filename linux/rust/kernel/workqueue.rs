@@ -10,7 +10,7 @@ use crate::{
     sync::{Arc, LockClassKey, UniqueArc},
     Opaque, Result,
 };
-use core::{fmt, ops::Deref, ptr::NonNull};
+use core::{fmt, ops::Deref, ptr::NonNull, time::Duration};
 
 /// Spawns a new work item to run in the work queue.
 ///
@@ -83,6 +83,67 @@ macro_rules! impl_work_adapter {
     };
 }
 
+/// Implements the [`WorkAdapter`] trait for a type where its [`DelayedWork`] instance is a field.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::workqueue::DelayedWork;
+///
+/// struct Example {
+///     dwork: DelayedWork,
+/// }
+///
+/// kernel::impl_self_delayed_work_adapter!(Example, dwork, |_| {});
+/// ```
+#[macro_export]
+macro_rules! impl_self_delayed_work_adapter {
+    ($work_type:ty, $field:ident, $closure:expr) => {
+        $crate::impl_delayed_work_adapter!($work_type, $work_type, $field, $closure);
+    };
+}
+
+/// Implements the [`WorkAdapter`] trait for an adapter type whose target embeds a
+/// [`DelayedWork`].
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::workqueue::DelayedWork;
+///
+/// struct Example {
+///     dwork: DelayedWork,
+/// }
+///
+/// struct Adapter;
+///
+/// kernel::impl_delayed_work_adapter!(Adapter, Example, dwork, |_| {});
+/// ```
+#[macro_export]
+macro_rules! impl_delayed_work_adapter {
+    ($adapter:ty, $work_type:ty, $field:ident, $closure:expr) => {
+        // SAFETY: We use `offset_of` to ensure that the field is within the given type, and we
+        // also check its type is `DelayedWork`, whose first member is a `struct work_struct`.
+        unsafe impl $crate::workqueue::WorkAdapter for $adapter {
+            type Target = $work_type;
+            const FIELD_OFFSET: isize = $crate::offset_of!(Self::Target, $field);
+            fn run(w: $crate::sync::Arc<Self::Target>) {
+                let closure: fn($crate::sync::Arc<Self::Target>) = $closure;
+                closure(w);
+                return;
+
+                // Checks that the type of the field is actually `DelayedWork`.
+                let tmp = core::mem::MaybeUninit::<$work_type>::uninit();
+                // SAFETY: The pointer is valid and aligned, just not initialised; `addr_of`
+                // ensures that we don't actually read from it (which would be UB) nor create an
+                // intermediate reference.
+                let _x: *const $crate::workqueue::DelayedWork =
+                    unsafe { core::ptr::addr_of!((*tmp.as_ptr()).$field) };
+            }
+        }
+    };
+}
+
 /// Initialises a work item.
 ///
 /// It automatically defines a new lockdep lock class for the work item.
@@ -105,6 +166,28 @@ macro_rules! init_work_item_adapter {
     }};
 }
 
+/// Initialises a delayed work item.
+///
+/// It automatically defines a new lockdep lock class for the work item.
+#[macro_export]
+macro_rules! init_delayed_work_item {
+    ($work_container:expr) => {{
+        static CLASS: $crate::sync::LockClassKey = $crate::sync::LockClassKey::new();
+        $crate::workqueue::DelayedWork::init($work_container, &CLASS)
+    }};
+}
+
+/// Initialises a delayed work item with the given adapter.
+///
+/// It automatically defines a new lockdep lock class for the work item.
+#[macro_export]
+macro_rules! init_delayed_work_item_adapter {
+    ($adapter:ty, $work_container:expr) => {{
+        static CLASS: $crate::sync::LockClassKey = $crate::sync::LockClassKey::new();
+        $crate::workqueue::DelayedWork::init_with_adapter::<$adapter>($work_container, &CLASS)
+    }};
+}
+
 /// A kernel work queue.
 ///
 /// Wraps the kernel's C `struct workqueue_struct`.
@@ -264,6 +347,54 @@ impl Queue {
         ret
     }
 
+    /// Enqueues a delayed work item to run after (at least) `delay` has elapsed.
+    ///
+    /// Returns `true` if the work item was successfully enqueued; returns `false` if it had
+    /// already been (and continued to be) pending.
+    pub fn enqueue_delayed<T: WorkAdapter<Target = T>>(&self, w: Arc<T>, delay: Duration) -> bool {
+        self.enqueue_delayed_adapter::<T>(w, delay)
+    }
+
+    /// Enqueues a delayed work item with an explicit adapter.
+    ///
+    /// The adapter's `FIELD_OFFSET` must point to a [`DelayedWork`] that was initialised with
+    /// [`DelayedWork::init_with_adapter`].
+    ///
+    /// Returns `true` if the work item was successfully enqueued; returns `false` if it had
+    /// already been (and continued to be) pending.
+    pub fn enqueue_delayed_adapter<A: WorkAdapter + ?Sized>(
+        &self,
+        w: Arc<A::Target>,
+        delay: Duration,
+    ) -> bool {
+        let ptr = Arc::into_raw(w);
+        let field_ptr =
+            (ptr as *const u8).wrapping_offset(A::FIELD_OFFSET) as *mut bindings::delayed_work;
+        let millis = delay.as_millis().try_into().unwrap_or(core::ffi::c_uint::MAX);
+
+        // SAFETY: Having a shared reference to work queue guarantees that it remains valid, while
+        // the work item remains valid because we called `into_raw` and only call `from_raw` again
+        // if the object was already queued (so a previous call already guarantees it remains
+        // alive), when the work item runs, or when the work item is canceled.
+        let ret = unsafe {
+            bindings::queue_delayed_work_on(
+                bindings::WORK_CPU_UNBOUND as _,
+                self.0.get(),
+                field_ptr,
+                bindings::__msecs_to_jiffies(millis),
+            )
+        };
+
+        if !ret {
+            // SAFETY: `ptr` comes from a previous call to `into_raw`. Additionally, given that
+            // `queue_delayed_work_on` returned `false`, we know that no-one is going to use the
+            // result of `into_raw`, so we must drop it here to avoid a reference leak.
+            unsafe { Arc::from_raw(ptr) };
+        }
+
+        ret
+    }
+
     /// Tries to spawn the given function or closure as a work item.
     ///
     /// Users are encouraged to use [`spawn_work_item`] as it automatically defines the lock class
@@ -371,17 +502,24 @@ impl Work {
         };
     }
 
-    /// Cancels the work item.
+    /// Cancels the work item embedded in `obj` and waits for it to finish if it is already
+    /// running.
     ///
-    /// It is ok for this to be called when the work is not queued.
-    pub fn cancel(&self) {
-        // SAFETY: The work is valid (we have a reference to it), and the function can be called
-        // whether the work is queued or not.
-        if unsafe { bindings::cancel_work_sync(self.0.get()) } {
-            // SAFETY: When the work was queued, a call to `into_raw` was made. We just canceled
-            // the work without it having the chance to run, so we need to explicitly destroy this
-            // reference (which would have happened in `work_func` if it did run).
-            unsafe { Arc::from_raw(&*self) };
+    /// It is ok for this to be called when the work is not queued. Note that a work item that
+    /// re-enqueues itself must be told to stop doing so before calling this function.
+    pub fn cancel<A: WorkAdapter + ?Sized>(obj: &A::Target) {
+        let ptr = obj as *const A::Target;
+        let field_ptr =
+            (ptr as *const u8).wrapping_offset(A::FIELD_OFFSET) as *mut bindings::work_struct;
+
+        // SAFETY: The work is valid (we have a reference to the object that contains it), and the
+        // function can be called whether the work is queued or not.
+        if unsafe { bindings::cancel_work_sync(field_ptr) } {
+            // SAFETY: When the work was queued, a call to `into_raw` was made on the `Arc` of the
+            // containing object. We just canceled the work without it having the chance to run,
+            // so we need to explicitly destroy this reference (which would have happened in
+            // `work_func` if it did run).
+            unsafe { Arc::from_raw(ptr) };
         }
     }
 
@@ -396,6 +534,126 @@ impl Work {
     }
 }
 
+/// A delayed work item.
+///
+/// Wraps the kernel's C `struct delayed_work`, that is, a [`Work`] paired with a timer that
+/// enqueues it once it expires.
+///
+/// Users must add a field of this type to a structure, then implement [`WorkAdapter`] (normally
+/// with [`impl_self_delayed_work_adapter`] or [`impl_delayed_work_adapter`]) so that it can be
+/// queued with [`Queue::enqueue_delayed`].
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::workqueue::{self, DelayedWork};
+/// use core::sync::atomic::{AtomicU32, Ordering};
+/// use core::time::Duration;
+/// use kernel::sync::UniqueArc;
+///
+/// struct Example {
+///     count: AtomicU32,
+///     dwork: DelayedWork,
+/// }
+///
+/// kernel::impl_self_delayed_work_adapter!(Example, dwork, |w| {
+///     let count = w.count.fetch_add(1, Ordering::Relaxed);
+///     pr_info!("Tick {}\n", count);
+///     workqueue::system().enqueue_delayed(w, Duration::from_secs(1));
+/// });
+///
+/// let e = UniqueArc::try_new(Example {
+///     count: AtomicU32::new(0),
+///     // SAFETY: `dwork` is initialised below.
+///     dwork: unsafe { DelayedWork::new() },
+/// })?;
+///
+/// kernel::init_delayed_work_item!(&e);
+///
+/// let e: kernel::sync::Arc<Example> = e.into();
+/// workqueue::system().enqueue_delayed(e.clone(), Duration::from_secs(1));
+///
+/// // Stop ticking.
+/// DelayedWork::cancel::<Example>(&e);
+///
+/// # Ok::<(), Error>(())
+/// ```
+#[repr(transparent)]
+pub struct DelayedWork(Opaque<bindings::delayed_work>);
+
+impl DelayedWork {
+    /// Creates a new instance of [`DelayedWork`].
+    ///
+    /// # Safety
+    ///
+    /// Callers must call either [`DelayedWork::init`] or [`DelayedWork::init_with_adapter`] before
+    /// the work item can be used.
+    pub unsafe fn new() -> Self {
+        Self(Opaque::uninit())
+    }
+
+    /// Initialises the delayed work item.
+    ///
+    /// Users should prefer the [`init_delayed_work_item`] macro because it automatically defines a
+    /// new lock class key.
+    pub fn init<T: WorkAdapter<Target = T>>(obj: &UniqueArc<T>, key: &'static LockClassKey) {
+        Self::init_with_adapter::<T>(obj, key)
+    }
+
+    /// Initialises the delayed work item with the given adapter.
+    ///
+    /// Users should prefer the [`init_delayed_work_item_adapter`] macro because it automatically
+    /// defines a new lock class key.
+    pub fn init_with_adapter<A: WorkAdapter>(
+        obj: &UniqueArc<A::Target>,
+        key: &'static LockClassKey,
+    ) {
+        let ptr = &**obj as *const _ as *const u8;
+        let field_ptr = ptr.wrapping_offset(A::FIELD_OFFSET) as *mut bindings::delayed_work;
+
+        // SAFETY: `dwork` is valid for writes -- the `UniqueArc` instance guarantees that it has
+        // been allocated and there is only one pointer to it. The embedded `work_struct` is the
+        // first field of `struct delayed_work`, so `Work::work_func` finds the containing object
+        // with the same `FIELD_OFFSET`. `delayed_work_timer_fn` is the timer callback the C
+        // `INIT_DELAYED_WORK` macro installs.
+        unsafe {
+            bindings::__INIT_WORK_WITH_KEY(
+                core::ptr::addr_of_mut!((*field_ptr).work),
+                Some(Work::work_func::<A>),
+                false,
+                key.get(),
+            );
+            bindings::init_timer_key(
+                core::ptr::addr_of_mut!((*field_ptr).timer),
+                Some(bindings::delayed_work_timer_fn),
+                bindings::TIMER_IRQSAFE,
+                core::ptr::null(),
+                core::ptr::null_mut(),
+            );
+        }
+    }
+
+    /// Cancels the delayed work item embedded in `obj` and waits for it to finish if it is
+    /// already running.
+    ///
+    /// It is ok for this to be called when the work is not pending. Note that a work item that
+    /// re-enqueues itself must be told to stop doing so before calling this function.
+    pub fn cancel<A: WorkAdapter + ?Sized>(obj: &A::Target) {
+        let ptr = obj as *const A::Target;
+        let field_ptr =
+            (ptr as *const u8).wrapping_offset(A::FIELD_OFFSET) as *mut bindings::delayed_work;
+
+        // SAFETY: The work is valid (we have a reference to the object that contains it), and the
+        // function can be called whether the work is pending or not.
+        if unsafe { bindings::cancel_delayed_work_sync(field_ptr) } {
+            // SAFETY: When the work was queued, a call to `into_raw` was made. We just canceled
+            // the work without it having the chance to run, so we need to explicitly destroy this
+            // reference (which would have happened in `work_func` if it did run).
+            unsafe { Arc::from_raw(ptr) };
+        }
+    }
+}
+
 /// A boxed owned workqueue.
 ///
 /// # Invariants
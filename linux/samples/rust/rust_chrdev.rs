@@ -3,13 +3,94 @@
 //! Rust character device sample.
 
 use core::result::Result::Err;
+use core::sync::atomic::{AtomicU64, Ordering};
 
+use kernel::file::{FasyncQueue, File, IoctlCommand, IoctlHandler, SeekFrom};
+use kernel::io_buffer::{IoBufferReader, IoBufferWriter};
+use kernel::kfifo::KFifo;
+use kernel::ioctl::{_IO, _IOR, _IOW};
+use kernel::mm::virt::Area;
+use kernel::pages::Pages;
 use kernel::prelude::*;
-use kernel::sync::Mutex;
-use kernel::{chrdev, file};
+use kernel::proc_fs::SeqEntry;
+use kernel::seq_file::{SeqFile, SeqOperations};
+use kernel::sync::{smutex, Arc, CondVar, Mutex};
+use kernel::user_ptr::{UserSlicePtrReader, UserSlicePtrWriter};
+use kernel::device::Class;
+use kernel::sync::LockClassKey;
+use kernel::task::{Comm, Signal, Task};
+use kernel::{bindings, c_str, chrdev, cred, file, init_static_sync, miscdev, seq_print, PAGE_SIZE};
 
-// 定义全局内存缓冲区的大小为4KB
-const GLOBALMEM_SIZE: usize = 0x1000;
+// 全局内存缓冲区的最大大小为1MB。默认大小为4KB，可以通过 buffer_size 参数或 GLOBALMEM_RESIZE 命令修改
+const GLOBALMEM_MAX_SIZE: usize = 0x10_0000;
+
+// ioctl 命令的类型（幻数），与 C 版本 globalmem 的 `GLOBALMEM_MAGIC` 相同
+const GLOBALMEM_MAGIC: u32 = b'g' as u32;
+// 查询缓冲区的大小，向用户空间返回一个 u64
+const GLOBALMEM_GET_SIZE: u32 = _IOR::<u64>(GLOBALMEM_MAGIC, 1);
+// 将缓冲区清零，没有参数，需要 CAP_SYS_ADMIN 权限
+const GLOBALMEM_CLEAR: u32 = _IO(GLOBALMEM_MAGIC, 2);
+// 查询这个次设备的读写计数，向用户空间依次返回成功的 read 和 write 次数
+const GLOBALMEM_GET_STATS: u32 = _IOR::<[u64; 2]>(GLOBALMEM_MAGIC, 3);
+// 调整缓冲区的大小，参数是一个 u64，原有的内容保留，新增的部分为零。文件必须以可写方式打开
+const GLOBALMEM_RESIZE: u32 = _IOW::<u64>(GLOBALMEM_MAGIC, 4);
+
+// 内存设备的次设备数量，每个次设备有自己的缓冲区和读写计数
+const GLOBALMEM_MINORS: usize = 2;
+
+// 一个设备的统计信息，打开同一个设备的所有文件共享，可以在 /proc/rust_chrdev_stats 中查看
+struct Stats {
+    opens: AtomicU64,         // 当前打开的文件数
+    reads: AtomicU64,         // 成功的 read 次数
+    writes: AtomicU64,        // 成功的 write 次数
+    read_bytes: AtomicU64,    // 读取的字节数
+    written_bytes: AtomicU64, // 写入的字节数
+    last_writer: smutex::Mutex<Option<Writer>>, // 最近一次成功 write 的进程
+}
+
+// 执行 write 的进程，在 /proc/rust_chrdev_stats 中显示
+#[derive(Clone, Copy)]
+struct Writer {
+    comm: Comm, // 线程名
+    tgid: i32,  // 进程号
+    uid: u32,   // 真实用户 ID
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Stats {
+            opens: AtomicU64::new(0),
+            reads: AtomicU64::new(0),
+            writes: AtomicU64::new(0),
+            read_bytes: AtomicU64::new(0),
+            written_bytes: AtomicU64::new(0),
+            last_writer: smutex::Mutex::new(None),
+        }
+    }
+
+    // 记录一次读取了 `bytes` 字节的 read
+    fn add_read(&self, bytes: usize) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.read_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    // 记录一次写入了 `bytes` 字节的 write，以及执行它的当前进程
+    fn add_write(&self, bytes: usize) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.written_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        let task = Task::current();
+        *self.last_writer.lock() = Some(Writer {
+            comm: task.comm(),
+            tgid: task.tgid(),
+            uid: task.uid(),
+        });
+    }
+}
+
+// 每个内存次设备的统计信息
+static GLOBALMEM_STATS: [Stats; GLOBALMEM_MINORS] = [Stats::new(), Stats::new()];
+// FIFO 设备的统计信息
+static GLOBALFIFO_STATS: Stats = Stats::new();
 
 module! {
     type: RustChrdev, // 指定模块类型为RustChrdev
@@ -17,18 +98,213 @@ module! {
     author: "Rust for Linux Contributors", // 模块作者信息
     description: "Rust character device sample", // 模块描述信息
     license: "GPL", // 模块许可证类型为GPL
+    params: {
+        private_buffers: bool {
+            default: false,
+            permissions: 0,
+            description: "Give every open() of the memory device its own buffer",
+        },
+        buffer_size: usize {
+            default: 0x1000,
+            permissions: 0,
+            description: "Initial size in bytes of the memory device's buffer (at most 1 MiB)",
+        },
+    },
+}
+
+// 内存设备的缓冲区，由若干个清零的页组成。没有使用 Vec<u8>：kmalloc 分配的内存
+// 不能通过 vm_insert_page 映射给用户空间
+struct GlobalMem {
+    pages: Vec<Pages<0>>, // 保存数据的页，最后一页可能只用了一部分
+    size: usize,          // 缓冲区的大小（字节）
+    // 自上次 fsync 或关闭可写文件以来，是否通过 write 或 ioctl 修改过内容（mmap 的修改不计入）
+    dirty: bool,
+}
+
+impl GlobalMem {
+    // 分配一个 `size` 字节的清零缓冲区
+    fn try_new(size: usize) -> Result<Self> {
+        let mut mem = GlobalMem {
+            pages: Vec::new(),
+            size: 0,
+            dirty: false,
+        };
+        mem.resize(size)?;
+        Ok(mem)
+    }
+
+    // 调整缓冲区的大小，保留原有的内容，新增的部分为零
+    fn resize(&mut self, size: usize) -> Result {
+        if size == 0 || size > GLOBALMEM_MAX_SIZE {
+            return Err(EINVAL);
+        }
+
+        let nr_pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        if nr_pages > self.pages.len() {
+            self.pages.try_reserve(nr_pages - self.pages.len())?;
+            while self.pages.len() < nr_pages {
+                // Pages::new 返回的页已经清零
+                self.pages.try_push(Pages::new()?)?;
+            }
+        } else {
+            // 已经 mmap 的页由映射持有引用，不会在这里真正释放
+            self.pages.truncate(nr_pages);
+        }
+
+        // 缩小时把最后一页中超出新大小的部分清零，这样再次扩大后读到的是零
+        if size < self.size {
+            self.zero(size, nr_pages * PAGE_SIZE - size)?;
+        }
+        self.size = size;
+        Ok(())
+    }
+
+    // 把 [offset, offset + len) 按页拆开，对每一段调用 `f(页, 页内偏移, 长度)`
+    fn for_each_chunk(
+        &self,
+        offset: usize,
+        len: usize,
+        mut f: impl FnMut(&Pages<0>, usize, usize) -> Result,
+    ) -> Result {
+        let end = offset.checked_add(len).ok_or(EINVAL)?;
+        let mut pos = offset;
+        while pos < end {
+            let page = self.pages.get(pos / PAGE_SIZE).ok_or(EINVAL)?;
+            let page_offset = pos % PAGE_SIZE;
+            let count = core::cmp::min(end - pos, PAGE_SIZE - page_offset);
+            f(page, page_offset, count)?;
+            pos += count;
+        }
+        Ok(())
+    }
+
+    // 将 reader 中的 `len` 字节写入缓冲区的 `offset` 处
+    fn write(&self, reader: &mut impl IoBufferReader, offset: usize, len: usize) -> Result {
+        self.for_each_chunk(offset, len, |page, page_offset, count| {
+            page.copy_into_page(reader, page_offset, count)
+        })
+    }
+
+    // 将缓冲区 `offset` 处的 `len` 字节读取到 writer 中
+    fn read(&self, writer: &mut impl IoBufferWriter, offset: usize, len: usize) -> Result {
+        self.for_each_chunk(offset, len, |page, page_offset, count| {
+            page.copy_from_page(writer, page_offset, count)
+        })
+    }
+
+    // 将 [offset, offset + len) 清零
+    fn zero(&self, offset: usize, len: usize) -> Result {
+        let zeroes = [0u8; 256];
+        self.for_each_chunk(offset, len, |page, page_offset, count| {
+            for start in (0..count).step_by(zeroes.len()) {
+                let n = core::cmp::min(zeroes.len(), count - start);
+                // SAFETY: `zeroes` 在整个拷贝过程中有效，范围不超过这一页
+                unsafe { page.write(zeroes.as_ptr(), page_offset + start, n)? };
+            }
+            Ok(())
+        })
+    }
+}
+
+// FIFO 设备缓冲区的大小为4KB
+const GLOBALFIFO_SIZE: usize = 0x1000;
+
+// FIFO 设备 ioctl 命令的类型（幻数）
+const GLOBALFIFO_MAGIC: u32 = b'f' as u32;
+// 设置缓冲区写满时的处理方式，参数是一个 u32，取值见 OverflowPolicy
+const GLOBALFIFO_SET_POLICY: u32 = _IOW::<u32>(GLOBALFIFO_MAGIC, 1);
+// 查询生产者和消费者下标（kfifo 的 in 和 out），向用户空间依次返回两个 u32
+const GLOBALFIFO_GET_INDICES: u32 = _IOR::<[u32; 2]>(GLOBALFIFO_MAGIC, 2);
+
+// FIFO 写满时对新写入数据的处理方式
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OverflowPolicy {
+    Block = 0,   // 等待读者取走数据（默认），O_NONBLOCK 时返回 EAGAIN
+    DropNew = 1, // 写入能放下的部分，丢弃其余的新数据
+    DropOld = 2, // 丢弃最旧的数据，为新数据腾出空间
+}
+
+impl OverflowPolicy {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::DropNew => "drop-new",
+            Self::DropOld => "drop-old",
+        }
+    }
+
+    fn from_u32(v: u32) -> Result<Self> {
+        match v {
+            0 => Ok(Self::Block),
+            1 => Ok(Self::DropNew),
+            2 => Ok(Self::DropOld),
+            _ => Err(EINVAL),
+        }
+    }
 }
 
-// 静态全局内存缓冲区，使用互斥锁进行保护。这里使用了unsafe代码块，因为静态变量初始化的要求。
-// 互斥锁保护的全局缓冲区用于在字符设备操作中存储数据。
-static GLOBALMEM_BUF: Mutex<[u8; GLOBALMEM_SIZE]> = unsafe {
-    Mutex::new([0u8; GLOBALMEM_SIZE])  // 初始化全局缓冲区为全零
-};
+// FIFO 设备的状态，数据保存在 kfifo 中，写入时追加在生产者下标处，读取时从消费者下标处取走
+struct FifoInner {
+    fifo: Option<KFifo>,    // 模块加载时分配，卸载时释放
+    policy: OverflowPolicy, // 写满时的处理方式
+}
+
+impl FifoInner {
+    // 返回 kfifo，模块加载以后总是存在
+    fn fifo(&mut self) -> Result<&mut KFifo> {
+        self.fifo.as_mut().ok_or(ENODEV)
+    }
+
+    // 写入是否需要等待：只有 Block 策略在缓冲区写满时等待
+    fn write_blocks(&mut self) -> Result<bool> {
+        Ok(self.policy == OverflowPolicy::Block && self.fifo()?.is_full())
+    }
+}
+
+init_static_sync! {
+    // 静态全局内存缓冲区，使用互斥锁进行保护，互斥锁在模块加载时由 init_static_sync! 初始化。
+    // 互斥锁保护的全局缓冲区用于在字符设备操作中存储数据。
+    // 缓冲区在模块加载时分配，卸载时释放，用户空间的映射会持有页的引用。
+    // 两个次设备各有一个缓冲区，写入 /dev/rust_chrdev0 的数据不会出现在 /dev/rust_chrdev1 中。
+    static GLOBALMEM_BUF0: Mutex<Option<GlobalMem>> = None;
+    static GLOBALMEM_BUF1: Mutex<Option<GlobalMem>> = None;
+
+    // FIFO 设备的缓冲区，所有打开的文件共享
+    static GLOBALFIFO: Mutex<FifoInner> = FifoInner { fifo: None, policy: OverflowPolicy::Block };
+
+    // FIFO 中的数据量变化时通知等待者，poll 在这里等待
+    static GLOBALFIFO_CHANGED: CondVar;
+}
+
+// 按次设备号索引的全局缓冲区
+static GLOBALMEM_BUFS: [&Mutex<Option<GlobalMem>>; GLOBALMEM_MINORS] =
+    [&GLOBALMEM_BUF0, &GLOBALMEM_BUF1];
+
+// 请求了异步通知（O_ASYNC）的 FIFO 文件，写入新数据时向它们的属主发送 SIGIO
+static GLOBALFIFO_ASYNC: FasyncQueue = FasyncQueue::new();
+
+// 文件是否以只读方式（O_RDONLY）打开
+fn is_read_only(file: &File) -> bool {
+    file.flags() & file::flags::O_ACCMODE == file::flags::O_RDONLY
+}
 
 // 定义表示文件的结构体
 struct RustFile {
-    #[allow(dead_code)]
-    inner: &'static Mutex<[u8; GLOBALMEM_SIZE]>, // 引用全局内存缓冲区
+    minor: usize,                             // 打开的次设备号，用来索引读写计数
+    inner: &'static Mutex<Option<GlobalMem>>, // 引用这个次设备的全局内存缓冲区
+    // 加载模块时指定了 private_buffers=1 时，每次 open 分配的私有缓冲区。
+    // 它随 RustFile 在 release 时释放，已经 mmap 的页由映射持有引用
+    private: Option<smutex::Mutex<GlobalMem>>,
+}
+
+impl RustFile {
+    // 锁定这个文件使用的缓冲区（私有缓冲区或者全局缓冲区）并在其上调用 `f`
+    fn with_buffer<R>(&self, f: impl FnOnce(&mut GlobalMem) -> Result<R>) -> Result<R> {
+        match &self.private {
+            Some(private) => f(&mut private.lock()),
+            None => f(self.inner.lock().as_mut().ok_or(ENODEV)?),
+        }
+    }
 }
 
 // 为RustFile实现文件操作的trait
@@ -37,62 +313,433 @@ impl file::Operations for RustFile {
     type Data = Box<Self>; // 文件操作的数据类型定义为Box包装的RustFile
 
     // 打开文件时的操作，返回一个包含RustFile实例的Box
-    fn open(_shared: &(), _file: &file::File) -> Result<Box<Self>> {
-        Ok(
-            Box::try_new(RustFile {
-                inner: &GLOBALMEM_BUF
-            })?) // 试图创建一个新的RustFile实例并返回
+    fn open(ctx: &chrdev::OpenContext, _file: &file::File) -> Result<Box<Self>> {
+        // 次设备号从 0 开始注册，因此可以直接作为索引
+        let minor = ctx.minor() as usize;
+        let inner = *GLOBALMEM_BUFS.get(minor).ok_or(ENXIO)?;
+
+        // 私有模式下为这次打开分配一个清零的缓冲区
+        let private = if *private_buffers.read() {
+            Some(smutex::Mutex::new(GlobalMem::try_new(*buffer_size.read())?))
+        } else {
+            None
+        };
+
+        let file = Box::try_new(RustFile {
+            minor,
+            inner,
+            private,
+        })?; // 试图创建一个新的RustFile实例并返回
+        GLOBALMEM_STATS[minor].opens.fetch_add(1, Ordering::Relaxed);
+        Ok(file)
     }
 
-    fn write(_this: &Self, _file: &file::File, _reader: &mut impl kernel::io_buffer::IoBufferReader, _offset: u64) -> Result<usize> {
-        let mut guard = _this.inner.lock(); // 锁定全局内存缓冲区
-        let buffer = &mut *guard;
+    // 关闭文件时释放 RustFile，私有缓冲区随之释放
+    fn release(data: Box<Self>, _file: &File) {
+        GLOBALMEM_STATS[data.minor].opens.fetch_sub(1, Ordering::Relaxed);
+    }
 
-        // 检查偏移量是否超出缓冲区大小
-        if _offset as usize >= GLOBALMEM_SIZE {
-            return Err(EINVAL); // 返回无效参数错误
+    fn write(_this: &Self, _file: &file::File, _reader: &mut impl IoBufferReader, _offset: u64) -> Result<usize> {
+        // 以只读方式打开的文件不能写入
+        if is_read_only(_file) {
+            return Err(EBADF);
         }
 
-        // 计算剩余空间
-        let remaining_space = GLOBALMEM_SIZE - _offset as usize;
-        // 计算实际要写入的数据大小，不能超过reader中的数据长度和剩余空间
-        let data_to_write = core::cmp::min(_reader.len(), remaining_space);
+        // 锁定缓冲区，缓冲区的大小可能被其他文件修改，因此在锁内检查偏移量
+        let data_to_write = _this.with_buffer(|buffer| {
+            // 检查偏移量是否超出缓冲区大小
+            let offset = usize::try_from(_offset).map_err(|_| EINVAL)?;
+            if offset >= buffer.size {
+                return Err(EINVAL); // 返回无效参数错误
+            }
 
-        // 将数据从reader读取到全局内存缓冲区中
-        unsafe {
-            _reader.read_raw(buffer.as_mut_ptr().add(_offset as usize), data_to_write)?;
-        }
+            // 计算实际要写入的数据大小，不能超过reader中的数据长度和剩余空间
+            let data_to_write = core::cmp::min(_reader.len(), buffer.size - offset);
+
+            // 将数据从reader读取到缓冲区中
+            buffer.write(_reader, offset, data_to_write)?;
+            buffer.dirty = true;
+            Ok(data_to_write)
+        })?;
 
+        GLOBALMEM_STATS[_this.minor].add_write(data_to_write);
         Ok(data_to_write) // 返回实际写入的数据大小
     }
 
-    fn read(_this: &Self, _file: &file::File, _writer: &mut impl kernel::io_buffer::IoBufferWriter, _offset: u64) -> Result<usize> {
-        let guard = _this.inner.lock(); // 锁定全局内存缓冲区
-        let buffer = &*guard;
+    fn read(_this: &Self, _file: &file::File, _writer: &mut impl IoBufferWriter, _offset: u64) -> Result<usize> {
+        // 锁定缓冲区，同样在锁内检查偏移量
+        let data_to_read = _this.with_buffer(|buffer| {
+            // 检查偏移量是否超出缓冲区大小
+            let offset = match usize::try_from(_offset) {
+                Ok(offset) if offset < buffer.size => offset,
+                _ => return Ok(0), // 超出缓冲区大小，返回EOF
+            };
+
+            // 计算实际要读取的数据大小，不能超过writer中的可写入长度和剩余数据量
+            let data_to_read = core::cmp::min(_writer.len(), buffer.size - offset);
+
+            // 将数据从缓冲区读取到writer中
+            buffer.read(_writer, offset, data_to_read)?;
+            Ok(data_to_read)
+        })?;
 
-        // 检查偏移量是否超出缓冲区大小
-        if _offset as usize >= GLOBALMEM_SIZE {
-            return Ok(0); // 超出缓冲区大小，返回EOF
+        GLOBALMEM_STATS[_this.minor].add_read(data_to_read);
+        Ok(data_to_read) // 返回实际读取的数据大小
+    }
+
+    // 移动文件位置，新的位置不能小于 0，也不能超过缓冲区的大小。
+    // 返回的位置由 file::Operations 保存到文件中，之后的 read/write 从这里开始
+    fn seek(this: &Self, file: &File, offset: SeekFrom) -> Result<u64> {
+        let size = this.with_buffer(|buffer| Ok(buffer.size as i64))?;
+        let (base, delta) = match offset {
+            SeekFrom::Start(off) => (0, i64::try_from(off).map_err(|_| EINVAL)?),
+            SeekFrom::Current(off) => (file.pos() as i64, off),
+            SeekFrom::End(off) => (size, off),
+        };
+        let pos = base.checked_add(delta).ok_or(EINVAL)?;
+        if pos < 0 || pos > size {
+            return Err(EINVAL);
         }
+        Ok(pos as u64)
+    }
+
+    // 把缓冲区映射到用户空间。映射的范围（以页为单位）不能超出缓冲区，
+    // 之后用户空间和 read/write 看到的是同样的页
+    fn mmap(this: &Self, _file: &File, vma: &mut Area) -> Result {
+        let first = vma.pgoff();
+        let count = (vma.end() - vma.start()) / PAGE_SIZE;
+        this.with_buffer(|buffer| {
+            let pages = first
+                .checked_add(count)
+                .and_then(|end| buffer.pages.get(first..end))
+                .ok_or(EINVAL)?;
 
-        // 计算剩余数据量
-        let remaining_data = GLOBALMEM_SIZE - _offset as usize;
-        // 计算实际要读取的数据大小，不能超过writer中的可写入长度和剩余数据量
-        let data_to_read = core::cmp::min(_writer.len(), remaining_data);
+            // vm_insert_page 会增加页的引用计数，因此映射可以比文件和模块活得更久
+            for (i, page) in pages.iter().enumerate() {
+                vma.insert_page(vma.start() + i * PAGE_SIZE, page)?;
+            }
+            Ok(())
+        })
+    }
+
+    // 根据命令中的方向和参数大小，把 ioctl 分发到下面 IoctlHandler 中对应的函数
+    fn ioctl(this: &Self, file: &File, cmd: &mut IoctlCommand) -> Result<i32> {
+        cmd.dispatch::<Self>(this, file)
+    }
+
+    // 内存缓冲区没有后备存储，同步只是提交（清除）脏标志
+    fn fsync(this: &Self, _file: &File, _start: u64, _end: u64, _datasync: bool) -> Result<u32> {
+        this.with_buffer(|buffer| {
+            buffer.dirty = false;
+            Ok(0)
+        })
+    }
 
-        // 将数据从全局内存缓冲区读取到writer中
-        unsafe {
-            _writer.write_raw(buffer.as_ptr().add(_offset as usize), data_to_read)?;
+    // 每次 close 一个可写的文件描述符时调用，和 fsync 一样提交修改
+    fn flush(this: &Self, file: &File) -> Result {
+        if is_read_only(file) {
+            return Ok(());
         }
+        this.with_buffer(|buffer| {
+            buffer.dirty = false;
+            Ok(())
+        })
+    }
+}
 
-        Ok(data_to_read) // 返回实际读取的数据大小
+// 处理 `_IO`、`_IOR` 和 `_IOW` 定义的 ioctl 命令，未知的命令返回 ENOTTY
+impl IoctlHandler for RustFile {
+    type Target<'a> = &'a Self;
+
+    // 没有参数的命令
+    fn pure(this: &Self, _file: &File, cmd: u32, _arg: usize) -> Result<i32> {
+        match cmd {
+            GLOBALMEM_CLEAR => {
+                // 清空缓冲区会丢掉所有用户的数据，只允许管理员执行
+                if !cred::capable(cred::cap::SYS_ADMIN) {
+                    return Err(EPERM);
+                }
+                this.with_buffer(|buffer| {
+                    buffer.zero(0, buffer.size)?;
+                    buffer.dirty = true;
+                    Ok(())
+                })?;
+                Ok(0)
+            }
+            _ => Err(ENOTTY),
+        }
     }
 
+    // 向用户空间返回数据的命令，返回的大小已经编码在命令中
+    fn read(this: &Self, _file: &File, cmd: u32, writer: &mut UserSlicePtrWriter) -> Result<i32> {
+        match cmd {
+            GLOBALMEM_GET_SIZE => {
+                let size = this.with_buffer(|buffer| Ok(buffer.size))?;
+                writer.write(&(size as u64))?;
+                Ok(0)
+            }
+            GLOBALMEM_GET_STATS => {
+                let stats = &GLOBALMEM_STATS[this.minor];
+                writer.write(&stats.reads.load(Ordering::Relaxed))?;
+                writer.write(&stats.writes.load(Ordering::Relaxed))?;
+                Ok(0)
+            }
+            _ => Err(ENOTTY),
+        }
+    }
+
+    // 从用户空间读取参数的命令
+    fn write(this: &Self, file: &File, cmd: u32, reader: &mut UserSlicePtrReader) -> Result<i32> {
+        match cmd {
+            GLOBALMEM_RESIZE => {
+                // 缩小缓冲区会丢掉数据，和 write 一样要求以可写方式打开
+                if is_read_only(file) {
+                    return Err(EBADF);
+                }
+                let size = usize::try_from(reader.read::<u64>()?).map_err(|_| EINVAL)?;
+                this.with_buffer(|buffer| {
+                    buffer.resize(size)?;
+                    buffer.dirty = true;
+                    Ok(())
+                })?;
+                Ok(0)
+            }
+            _ => Err(ENOTTY),
+        }
+    }
 }
 
+// 注册为杂项设备（miscdevice）的 FIFO：像管道一样，写入的数据追加到缓冲区末尾，读取时从头部取走，
+// 与文件偏移量无关。缓冲区为空时读取会睡眠等待，写满时的处理方式由 GLOBALFIFO_SET_POLICY 选择，
+// 默认睡眠等待。以 O_NONBLOCK 打开时不睡眠而是返回 EAGAIN，
+// 可以用 poll/select 等待设备变为可读或可写，也可以通过 O_ASYNC 在有新数据时收到 SIGIO
+struct RustFifo;
+
+#[vtable]
+impl file::Operations for RustFifo {
+    // 所有状态都在静态变量中，不需要每个文件的数据
+
+    fn open(_shared: &(), _file: &File) -> Result {
+        GLOBALFIFO_STATS.opens.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // 异步通知列表保存了文件的指针，关闭文件时必须把它从列表中移除
+    fn release(_data: (), file: &File) {
+        let _ = GLOBALFIFO_ASYNC.helper(-1, file, false);
+        GLOBALFIFO_STATS.opens.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn write(_this: (), file: &File, reader: &mut impl IoBufferReader, _offset: u64) -> Result<usize> {
+        if reader.is_empty() {
+            return Ok(0);
+        }
+
+        let mut inner = GLOBALFIFO.lock();
+
+        // Block 策略下没有剩余空间时等待读者取走数据，wait 在睡眠期间释放锁
+        while inner.write_blocks()? {
+            if file.flags() & file::flags::O_NONBLOCK != 0 {
+                return Err(EAGAIN);
+            }
+            if GLOBALFIFO_CHANGED.wait(&mut inner) {
+                return Err(EINTR); // 被信号打断
+            }
+        }
+
+        // 追加在生产者下标处，最多写入一个缓冲区的数据
+        let policy = inner.policy;
+        let fifo = inner.fifo()?;
+        let len = core::cmp::min(reader.len(), fifo.size());
+        if policy == OverflowPolicy::DropOld && len > fifo.avail() {
+            fifo.skip(len - fifo.avail());
+        }
+        let stored = fifo.push_from(reader, len)?;
+        GLOBALFIFO_STATS.add_write(stored);
+
+        // 唤醒等待数据的读者，并通知请求了异步通知的进程
+        if stored > 0 {
+            GLOBALFIFO_CHANGED.notify_all();
+            GLOBALFIFO_ASYNC.kill(Signal::Io, bindings::POLL_IN as i32);
+        }
+
+        if policy == OverflowPolicy::DropNew {
+            // 放不下的数据被丢弃，但对写者来说全部写入成功
+            return Ok(stored + reader.len());
+        }
+        Ok(stored)
+    }
+
+    fn read(_this: (), file: &File, writer: &mut impl IoBufferWriter, _offset: u64) -> Result<usize> {
+        if writer.is_empty() {
+            return Ok(0);
+        }
+
+        let mut inner = GLOBALFIFO.lock();
+
+        // 还没有数据时等待写者写入，wait 在睡眠期间释放锁
+        while inner.fifo()?.is_empty() {
+            if file.flags() & file::flags::O_NONBLOCK != 0 {
+                return Err(EAGAIN);
+            }
+            if GLOBALFIFO_CHANGED.wait(&mut inner) {
+                return Err(EINTR); // 被信号打断
+            }
+        }
+
+        // 从消费者下标处取走数据
+        let count = inner.fifo()?.pop_into(writer, writer.len())?;
+        GLOBALFIFO_STATS.add_read(count);
+
+        // 唤醒等待空间的写者
+        GLOBALFIFO_CHANGED.notify_all();
+        Ok(count)
+    }
+
+    // 有数据时可读，有剩余空间时可写；否则在 GLOBALFIFO_CHANGED 上等待，
+    // read/write 改变数据量时会唤醒 poll
+    fn poll(_this: (), file: &File, table: &file::PollTable) -> Result<u32> {
+        // SAFETY: GLOBALFIFO_CHANGED 是静态变量，不会在文件之前销毁
+        unsafe { table.register_wait(file, &GLOBALFIFO_CHANGED) };
+
+        let mut inner = GLOBALFIFO.lock();
+        let mut mask = 0;
+        if !inner.fifo()?.is_empty() {
+            mask |= bindings::POLLIN | bindings::POLLRDNORM;
+        }
+        if !inner.write_blocks()? {
+            mask |= bindings::POLLOUT | bindings::POLLWRNORM;
+        }
+        Ok(mask)
+    }
+
+    // 设置或清除 O_ASYNC 时调用，把文件加入或移出异步通知列表
+    fn fasync(_this: (), file: &File, fd: i32, on: bool) -> Result {
+        GLOBALFIFO_ASYNC.helper(fd, file, on)
+    }
+
+    fn ioctl(this: (), file: &File, cmd: &mut IoctlCommand) -> Result<i32> {
+        cmd.dispatch::<Self>(this, file)
+    }
+
+    // 等待读者取走 FIFO 中的全部数据，类似于终端的 tcdrain。
+    // 读者取走数据时会唤醒 GLOBALFIFO_CHANGED，以 O_NONBLOCK 打开时不等待而是返回 EAGAIN
+    fn fsync(_this: (), file: &File, _start: u64, _end: u64, _datasync: bool) -> Result<u32> {
+        let mut inner = GLOBALFIFO.lock();
+        while !inner.fifo()?.is_empty() {
+            if file.flags() & file::flags::O_NONBLOCK != 0 {
+                return Err(EAGAIN);
+            }
+            if GLOBALFIFO_CHANGED.wait(&mut inner) {
+                return Err(EINTR); // 被信号打断
+            }
+        }
+        Ok(0)
+    }
+}
+
+// 处理 FIFO 设备的 ioctl 命令，未知的命令返回 ENOTTY
+impl IoctlHandler for RustFifo {
+    type Target<'a> = ();
+
+    fn read(_this: (), _file: &File, cmd: u32, writer: &mut UserSlicePtrWriter) -> Result<i32> {
+        match cmd {
+            GLOBALFIFO_GET_INDICES => {
+                let mut inner = GLOBALFIFO.lock();
+                let fifo = inner.fifo()?;
+                writer.write(&fifo.producer_index())?;
+                writer.write(&fifo.consumer_index())?;
+                Ok(0)
+            }
+            _ => Err(ENOTTY),
+        }
+    }
+
+    fn write(_this: (), _file: &File, cmd: u32, reader: &mut UserSlicePtrReader) -> Result<i32> {
+        match cmd {
+            GLOBALFIFO_SET_POLICY => {
+                let policy = OverflowPolicy::from_u32(reader.read::<u32>()?)?;
+                GLOBALFIFO.lock().policy = policy;
+                // 不再阻塞的写者需要重新检查策略
+                GLOBALFIFO_CHANGED.notify_all();
+                Ok(0)
+            }
+            _ => Err(ENOTTY),
+        }
+    }
+}
+
+// /proc/rust_chrdev_stats 的内容，每个设备一行：先是各个内存次设备，最后是 FIFO。
+// seq_file 逐行调用 show，一次读不完时会从上次的位置继续
+struct ChrdevStats {
+    name: &'static CStr, // 模块名称，也是设备名称的前缀
+}
+
+impl SeqOperations for ChrdevStats {
+    type Item = usize; // 行号，小于 GLOBALMEM_MINORS 的是内存次设备，等于的是 FIFO
+    type Iter<'a> = core::ops::Range<usize>;
+
+    fn iter(&self) -> Self::Iter<'_> {
+        0..GLOBALMEM_MINORS + 1
+    }
+
+    fn show(&self, m: &mut SeqFile, item: &usize) -> Result {
+        let stats = GLOBALMEM_STATS.get(*item).unwrap_or(&GLOBALFIFO_STATS);
+        if *item < GLOBALMEM_MINORS {
+            seq_print!(m, "{}{}:", self.name, item);
+        } else {
+            seq_print!(m, "{}_fifo:", self.name);
+        }
+        seq_print!(
+            m,
+            " opens={} reads={} writes={} read_bytes={} written_bytes={}",
+            stats.opens.load(Ordering::Relaxed),
+            stats.reads.load(Ordering::Relaxed),
+            stats.writes.load(Ordering::Relaxed),
+            stats.read_bytes.load(Ordering::Relaxed),
+            stats.written_bytes.load(Ordering::Relaxed),
+        );
+        if let Some(w) = *stats.last_writer.lock() {
+            seq_print!(m, " last_writer={}[{}] uid={}", w.comm, w.tgid, w.uid);
+        }
+
+        // 填充情况：内存设备显示共享缓冲区的大小和脏标志，FIFO 显示已用空间和写满时的策略
+        match GLOBALMEM_BUFS.get(*item) {
+            Some(buf) => {
+                let buf = buf.lock();
+                let buf = buf.as_ref().ok_or(ENODEV)?;
+                seq_print!(m, " size={} dirty={}\n", buf.size, buf.dirty as u8);
+            }
+            None => {
+                let mut inner = GLOBALFIFO.lock();
+                let policy = inner.policy;
+                let fifo = inner.fifo()?;
+                seq_print!(
+                    m,
+                    " fill={}/{} policy={}\n",
+                    fifo.len(),
+                    fifo.size(),
+                    policy.name()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// 设备类的锁类（lockdep 使用）
+static CLASS_KEY: LockClassKey = LockClassKey::new();
+
 // 定义表示字符设备的结构体
 struct RustChrdev {
-    _dev: Pin<Box<chrdev::Registration<2>>>, // 包含字符设备注册的引用，这里注册了两个次设备
+    // /proc/rust_chrdev_stats，在 drop 中释放缓冲区之前删除，这样 show 不会看到已经释放的缓冲区
+    stats: Option<SeqEntry<ChrdevStats>>,
+    // 设备类，包含 /dev/rust_chrdev0 和 /dev/rust_chrdev1 两个设备。
+    // 放在注册前面，这样卸载时先删除设备节点，再注销字符设备
+    _class: Class,
+    _dev: Pin<Box<chrdev::Registration<GLOBALMEM_MINORS>>>, // 包含字符设备注册的引用，这里注册了两个次设备
+    // FIFO 的杂项设备注册，杂项设备自己创建 /dev/rust_chrdev_fifo 节点
+    _fifo: Pin<Box<miscdev::Registration<RustFifo>>>,
 }
 
 // 为RustChrdev实现内核模块的trait
@@ -100,15 +747,51 @@ impl kernel::Module for RustChrdev {
     fn init(name: &'static CStr, module: &'static ThisModule) -> Result<Self> {
         pr_info!("Rust character device sample (init)\n"); // 模块初始化时打印信息
 
+        // 按 buffer_size 参数为每个次设备分配清零的全局缓冲区
+        for buf in GLOBALMEM_BUFS {
+            *buf.lock() = Some(GlobalMem::try_new(*buffer_size.read())?);
+        }
+        // 分配 FIFO 设备的 kfifo
+        GLOBALFIFO.lock().fifo = Some(KFifo::try_new(GLOBALFIFO_SIZE)?);
+
         // 创建一个新的字符设备注册，指定设备名称和模块引用
         let mut chrdev_reg = chrdev::Registration::new_pinned(name, 0, module)?;
 
-        // 注册同一种设备两次，以演示可以使用多个次设备。这里次设备类型为chrdev::Registration<2>。
-        chrdev_reg.as_mut().register::<RustFile>()?; // 注册第一个次设备
-        chrdev_reg.as_mut().register::<RustFile>()?; // 注册第二个次设备
+        // 把 RustFile 注册到每个次设备上，open 通过次设备号区分它们。
+        // 这里次设备类型为chrdev::Registration<2>。
+        for _ in 0..GLOBALMEM_MINORS {
+            chrdev_reg.as_mut().register::<RustFile>()?;
+        }
+
+        // 创建设备类，并为每个次设备创建一个设备，udev 或 devtmpfs 会在 /dev 下创建对应的节点，
+        // 不再需要手动 mknod
+        let mut class = Class::try_new(name, &CLASS_KEY, module)?;
+        for i in 0..GLOBALMEM_MINORS {
+            let devt = chrdev_reg.dev(i).ok_or(EINVAL)?;
+            class.create_device(None, devt, fmt!("{}{}", name, i))?;
+        }
+
+        // FIFO 注册为杂项设备：主设备号固定为 MISC_MAJOR，次设备号动态分配，
+        // 不需要自己分配设备号区域，也不需要设备类
+        let fifo = miscdev::Options::new()
+            .mode(0o666)
+            .register_new::<RustFifo>(fmt!("{}_fifo", name), ())?;
+        pr_info!("FIFO registered as misc device, minor {}\n", fifo.minor().unwrap_or(-1));
+
+        // 创建 /proc/rust_chrdev_stats，所有用户可读
+        let stats = SeqEntry::try_new(
+            c_str!("rust_chrdev_stats"),
+            0o444,
+            Arc::try_new(ChrdevStats { name })?,
+        )?;
 
         // 返回包含字符设备注册的RustChrdev实例
-        Ok(RustChrdev { _dev: chrdev_reg })
+        Ok(RustChrdev {
+            stats: Some(stats),
+            _class: class,
+            _dev: chrdev_reg,
+            _fifo: fifo,
+        })
     }
 }
 
@@ -116,5 +799,14 @@ impl kernel::Module for RustChrdev {
 impl Drop for RustChrdev {
     fn drop(&mut self) {
         pr_info!("Rust character device sample (exit)\n"); // 模块卸载时打印信息
+
+        // 先删除 /proc/rust_chrdev_stats，proc_remove 会等待正在进行的读取结束
+        self.stats.take();
+
+        // 模块卸载时已经没有打开的文件，释放全局缓冲区
+        for buf in GLOBALMEM_BUFS {
+            buf.lock().take();
+        }
+        GLOBALFIFO.lock().fifo.take();
     }
 }
@@ -0,0 +1,177 @@
+use kernel::prelude::*;
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use crate::e1000_ops::E1000Hw;
+use crate::regs::{self, Itr, RegIo};
+use crate::stats::{DrvStats, QueueStats};
+
+// 三种流量对应的中断频率（次/秒），与 C 版本相同
+const ITR_LOWEST_LATENCY: u32 = 70000;
+const ITR_LOW_LATENCY: u32 = 20000;
+const ITR_BULK_LATENCY: u32 = 4000;
+
+/// 固定模式下允许的最大中断间隔（微秒），对应于 C 版本的 `E1000_MAX_ITR_USECS`
+pub(crate) const E1000_MAX_ITR_USECS: u32 = 10000;
+
+// 流量的类型，对应于 C 版本的 `enum latency_range`
+const LOWEST_LATENCY: u32 = 0;  // 少量的小包，例如 ping，需要尽快处理
+const LOW_LATENCY: u32 = 1;  // 一般的流量
+const BULK_LATENCY: u32 = 2;  // 大量的大包，例如批量传输，合并中断提高吞吐量
+
+/// 自适应中断节流（AIM），对应于 C 版本的 `e1000_update_itr` 和 `e1000_set_itr`。
+///
+/// 每次接收 NAPI 结束时，根据这段时间内收发的包数和字节数判断流量的类型，并据此调整 ITR：
+/// 少量小包时提高中断频率以降低延迟，大量大包时降低中断频率以减少开销。
+/// 通过 ethtool -C adaptive-rx off rx-usecs N 可以改为固定的中断间隔。
+///
+/// 只有接收 NAPI 调用 `update`，流量类型和上一次的计数只在那里修改，因此使用宽松的原子变量即可。
+pub(crate) struct Aim {
+    adaptive: AtomicBool,  // 是否自适应调整
+    rx_usecs: AtomicU32,  // 固定模式下的中断间隔（微秒），0 表示不节流
+    itr: AtomicU32,  // 当前写入 ITR 的中断频率（次/秒），0 表示不节流
+    rx_range: AtomicU32,  // 接收方向上一次的流量类型
+    tx_range: AtomicU32,  // 发送方向上一次的流量类型
+    last_rx_packets: AtomicU64,  // 上一次调整时的接收包数
+    last_rx_bytes: AtomicU64,  // 上一次调整时的接收字节数
+    last_tx_packets: AtomicU64,  // 上一次调整时的发送包数
+    last_tx_bytes: AtomicU64,  // 上一次调整时的发送字节数
+}
+
+impl Default for Aim {
+    fn default() -> Self {
+        Self {
+            adaptive: AtomicBool::new(true),
+            rx_usecs: AtomicU32::new(0),
+            itr: AtomicU32::new(ITR_LOW_LATENCY),
+            rx_range: AtomicU32::new(LOW_LATENCY),
+            tx_range: AtomicU32::new(LOW_LATENCY),
+            last_rx_packets: AtomicU64::new(0),
+            last_rx_bytes: AtomicU64::new(0),
+            last_tx_packets: AtomicU64::new(0),
+            last_tx_bytes: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Aim {
+    /// 把当前的中断频率写入 ITR。复位会清除 ITR，因此每次配置硬件之后都要调用
+    pub(crate) fn program(&self, hw: &dyn E1000Hw) -> Result {
+        hw.regs().write(regs::ITR, Itr::from_rate(self.itr.load(Ordering::Relaxed)))
+    }
+
+    /// 根据上一次调整以来的流量调整中断频率，在接收 NAPI 结束、重新打开中断之前调用
+    pub(crate) fn update(&self, hw: &dyn E1000Hw, stats: &DrvStats) {
+        if !self.adaptive.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let path = stats.fetch_path();
+        let (rx_packets, rx_bytes) = Self::delta(&path.rx_queues, &self.last_rx_packets, &self.last_rx_bytes);
+        let (tx_packets, tx_bytes) = Self::delta(&path.tx_queues, &self.last_tx_packets, &self.last_tx_bytes);
+        let rx_range = Self::update_range(self.rx_range.load(Ordering::Relaxed), rx_packets, rx_bytes);
+        let tx_range = Self::update_range(self.tx_range.load(Ordering::Relaxed), tx_packets, tx_bytes);
+        self.rx_range.store(rx_range, Ordering::Relaxed);
+        self.tx_range.store(tx_range, Ordering::Relaxed);
+
+        let target = match rx_range.max(tx_range) {
+            LOWEST_LATENCY => ITR_LOWEST_LATENCY,
+            LOW_LATENCY => ITR_LOW_LATENCY,
+            _ => ITR_BULK_LATENCY,
+        };
+        let itr = self.itr.load(Ordering::Relaxed);
+        if target == itr {
+            return;
+        }
+
+        // 提高中断频率时逐步增加，使中断频率偏向于批量传输的设置
+        let new_itr = if target > itr { (itr + (target >> 2)).min(target) } else { target };
+        self.itr.store(new_itr, Ordering::Relaxed);
+        let _ = hw.regs().write(regs::ITR, Itr::from_rate(new_itr));
+    }
+
+    /// 返回 ethtool -c 显示的参数：是否自适应调整，以及当前的中断间隔（微秒）
+    pub(crate) fn coalesce(&self) -> (bool, u32) {
+        let adaptive = self.adaptive.load(Ordering::Relaxed);
+        let usecs = if adaptive {
+            1_000_000 / self.itr.load(Ordering::Relaxed).max(1)
+        } else {
+            self.rx_usecs.load(Ordering::Relaxed)
+        };
+        (adaptive, usecs)
+    }
+
+    /// 处理 ethtool -C：打开自适应调整时忽略 `rx_usecs`，否则使用固定的中断间隔
+    pub(crate) fn set_coalesce(&self, hw: &dyn E1000Hw, adaptive: bool, rx_usecs: u32) -> Result {
+        if !adaptive && rx_usecs > E1000_MAX_ITR_USECS {
+            return Err(EINVAL);
+        }
+
+        if adaptive {
+            self.rx_range.store(LOW_LATENCY, Ordering::Relaxed);
+            self.tx_range.store(LOW_LATENCY, Ordering::Relaxed);
+            self.itr.store(ITR_LOW_LATENCY, Ordering::Relaxed);
+        } else {
+            self.rx_usecs.store(rx_usecs, Ordering::Relaxed);
+            self.itr.store(if rx_usecs == 0 { 0 } else { 1_000_000 / rx_usecs }, Ordering::Relaxed);
+        }
+        self.adaptive.store(adaptive, Ordering::Relaxed);
+        self.program(hw)
+    }
+
+    // 返回各队列的计数器之和与上一次相比增加的包数和字节数，并记录这一次的值
+    fn delta(queues: &[QueueStats], last_packets: &AtomicU64, last_bytes: &AtomicU64) -> (u64, u64) {
+        let packets = queues.iter().map(|q| q.packets).sum::<u64>();
+        let bytes = queues.iter().map(|q| q.bytes).sum::<u64>();
+        (
+            packets.wrapping_sub(last_packets.swap(packets, Ordering::Relaxed)),
+            bytes.wrapping_sub(last_bytes.swap(bytes, Ordering::Relaxed)),
+        )
+    }
+
+    // 根据一段时间内的包数和字节数调整流量类型，对应于 C 版本的 `e1000_update_itr`
+    fn update_range(range: u32, packets: u64, bytes: u64) -> u32 {
+        if packets == 0 {
+            return range;
+        }
+
+        let bytes_per_packet = bytes / packets;
+        match range {
+            LOWEST_LATENCY => {
+                if bytes_per_packet > 8000 {
+                    BULK_LATENCY
+                } else if packets < 5 && bytes > 512 {
+                    LOW_LATENCY
+                } else {
+                    range
+                }
+            }
+            LOW_LATENCY => {
+                if bytes > 10000 {
+                    if bytes_per_packet > 8000 || packets < 10 || bytes_per_packet > 1200 {
+                        BULK_LATENCY
+                    } else if packets > 35 {
+                        LOWEST_LATENCY
+                    } else {
+                        range
+                    }
+                } else if bytes_per_packet > 2000 {
+                    BULK_LATENCY
+                } else if packets <= 2 && bytes < 512 {
+                    LOWEST_LATENCY
+                } else {
+                    range
+                }
+            }
+            _ => {
+                if bytes > 25000 {
+                    if packets > 35 { LOW_LATENCY } else { range }
+                } else if bytes < 6000 {
+                    LOW_LATENCY
+                } else {
+                    range
+                }
+            }
+        }
+    }
+}
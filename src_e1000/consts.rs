@@ -1,7 +1,28 @@
 
+// 收发队列的数量。QEMU 模拟的 82540EM 每个方向只有一个硬件队列，8257x 每个方向有两个
+pub(crate) const E1000_NUM_TX_QUEUES:usize = 1;
+pub(crate) const E1000_NUM_RX_QUEUES:usize = 1;
+
+// 默认的描述符环大小，可通过 ethtool -G 在运行时修改
 pub(crate) const RX_RING_SIZE:usize = 8;
 pub(crate) const TX_RING_SIZE:usize = 8;
+pub(crate) const E1000_MIN_RING_SIZE:usize = 8;
+pub(crate) const E1000_MAX_RING_SIZE:usize = 256;
+// RDLEN/TDLEN 必须是 128 字节对齐的，即描述符数量必须是 8 的倍数
+pub(crate) const E1000_RING_SIZE_MULTIPLE:usize = 8;
 pub(crate) const RXTX_SINGLE_RING_BLOCK_SIZE:usize = 16384;
+// 默认的接收缓冲区大小，足够放下 MTU 为 1500 的帧
+pub(crate) const E1000_RX_BUFFER_SIZE:u32 = 2048;
+// RCTL 的 BSIZE/BSEX 支持的接收缓冲区大小，见 `Rctl::buffer_size`
+pub(crate) const E1000_RX_BUFFER_SIZES: [u32; 4] = [2048, 4096, 8192, 16384];
+// 打开长帧接收（RCTL.LPE）时能接收的最大帧长，包括以太网头和 CRC
+pub(crate) const E1000_MAX_JUMBO_FRAME_SIZE:u32 = 0x3F00;
+// 接收页中数据之前预留的空间，留给协议栈和 XDP 程序使用
+pub(crate) const E1000_RX_HEADROOM:u32 = kernel::bindings::XDP_PACKET_HEADROOM;
+// 发送环中为 XDP_TX 和 ndo_xdp_xmit 保留的描述符数量，协议栈的发送不会占用这部分描述符
+pub(crate) const E1000_XDP_TX_RESERVED:usize = 2;
+// 发送弹跳缓冲区每个槽位的最大大小，更大的包总是直接映射 skb
+pub(crate) const E1000_TX_BOUNCE_MAX_SIZE:usize = 2048;
 
 pub(crate) const MAC_HWADDR: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
 
@@ -9,69 +30,20 @@ pub(crate) const E1000_VENDER_ID:u32 = 0x8086;
 pub(crate) const E1000_DEVICE_ID:u32 = 0x100E;
 
 
-// E1000 Regs
-
-pub(crate) const E1000_CTRL:usize = 0x00000;	/* Device Control - RW */
-pub(crate) const E1000_STATUS:usize = 0x00008;	/* Device Status - RO */
-pub(crate) const E1000_IMC:usize = 0x000D8;	/* Interrupt Mask Clear - WO */
-pub(crate) const E1000_IMS:usize = 0x000D0;	/* Interrupt Mask Set - RW */
-pub(crate) const E1000_RCTL:usize = 0x00100;	/* RX Control - RW */
-pub(crate) const E1000_TCTL:usize = 0x00400;	/* TX Control - RW */
-pub(crate) const E1000_MANC:usize = 0x05820;	/* Management Control - RW */
-pub(crate) const E1000_ICR:usize = 0x000C0;	/* Interrupt Cause Read - R/clr */
-pub(crate) const E1000_RA:usize = 0x05400;	/* Receive Address - RW Array */
-pub(crate) const E1000_MTA:usize = 0x05200 ;	/* Multicast Table Array - RW Array */
-
-pub(crate) const E1000_RDH:usize = 0x02810;	/* RX Descriptor Head - RW */
-pub(crate) const E1000_RDT:usize = 0x02818;	/* RX Descriptor Tail - RW */
-pub(crate) const E1000_RDLEN:usize = 0x02808;	/* RX Descriptor Length - RW */
-pub(crate) const E1000_RDBAL:usize = 0x02800;	/* RX Descriptor Base Address Low - RW */
-pub(crate) const E1000_RDBAH:usize = 0x02804;	/* RX Descriptor Base Address High - RW */
-pub(crate) const E1000_TDH:usize = 0x03810;	/* TX Descriptor Head - RW */
-pub(crate) const E1000_TDT:usize = 0x03818;	/* TX Descripotr Tail - RW */
-pub(crate) const E1000_TDLEN:usize = 0x03808;	/* TX Descriptor Length - RW */
-pub(crate) const E1000_TDBAL:usize = 0x03800;	/* TX Descriptor Base Address Low - RW */
-pub(crate) const E1000_TDBAH:usize = 0x03804;	/* TX Descriptor Base Address High - RW */
-pub(crate) const E1000_TIPG:usize = 0x00410;	/* TX Inter-packet gap -RW */
-
-pub(crate) const E1000_RDTR:usize = 0x02820;	/* RX Delay Timer - RW */
-pub(crate) const E1000_RADV:usize = 0x0282C;	/* RX Interrupt Absolute Delay Timer - RW */
-
-// pub(crate) const E1000_:usize = ;	/*  */
-// pub(crate) const E1000_:usize = ;	/*  */
-// pub(crate) const E1000_:usize = ;	/*  */
-// pub(crate) const E1000_:usize = ;	/*  */
-// pub(crate) const E1000_:usize = ;	/*  */
-// pub(crate) const E1000_:usize = ;	/*  */
-// pub(crate) const E1000_:usize = ;	/*  */
-// pub(crate) const E1000_:usize = ;	/*  */
-// pub(crate) const E1000_:usize = ;	/*  */
-
-
-
+// BAR0 中寄存器空间的最小大小
+pub(crate) const E1000_MMIO_MIN_LEN:usize = 128 * 1024;
 
-// E1000 Regs Fields
-pub(crate) const E1000_TCTL_PSP:u32 = 0x00000008;	/* pad short packets */
-pub(crate) const E1000_TCTL_EN:u32 = 0x00000002;	/* enable tx */
+// I/O BAR 中的寄存器窗口：先把寄存器地址写入 IOADDR，再通过 IODATA 读写寄存器
+pub(crate) const E1000_IOADDR:usize = 0x00;
+pub(crate) const E1000_IODATA:usize = 0x04;
+pub(crate) const E1000_IO_WINDOW_LEN:usize = 8;
 
-pub(crate) const E1000_TCTL_CT:u32 = 0x00000ff0;	/* collision threshold */
-pub(crate) const E1000_CT_SHIFT:u32 = 4;	
+// 寄存器的偏移和各字段定义在 regs.rs 中
 
-pub(crate) const E1000_TCTL_COLD:u32 = 0x003ff000;	/* collision distance */
-pub(crate) const E1000_COLD_SHIFT:u32 = 12;	
-
-/* Receive Control */
-pub(crate) const E1000_RCTL_EN:u32 = 0x00000002;	/* enable */
-pub(crate) const E1000_RCTL_BAM:u32 = 0x00008000;	/* broadcast enable */
-pub(crate) const E1000_RCTL_SZ_2048:u32 = 0x00000000;	/* rx buffer size 2048 */
-pub(crate) const E1000_RCTL_SECRC:u32 = 0x04000000;	/* Strip Ethernet CRC */
-
-// pub(crate) const E1000_:u32 = ;	/*  */
-// pub(crate) const E1000_:u32 = ;	/*  */
-
-
-pub(crate) const E1000_CTRL_RST:u32 = 0x04000000;	/* Global reset */
-pub(crate) const E1000_MANC_ARP_EN:u32 = 0x00002000;	/* Enable ARP Request Filtering */
+// MDIC 操作完成前最多轮询的次数，每次间隔 50 微秒
+pub(crate) const PHY_MDIC_POLL_COUNT:u32 = 64;
+// 82540EM 内部 PHY 的地址固定为 1
+pub(crate) const E1000_PHY_ADDR:u8 = 1;
 
 
 // pub(crate) const E1000_:u32 = ;	/*  */
@@ -86,9 +58,7 @@ pub(crate) const E1000_MANC_ARP_EN:u32 = 0x00002000;	/* Enable ARP Request Filte
 /* Default values for the transmit IPG register */
 pub(crate) const DEFAULT_82543_TIPG_IPGT_COPPER:u32 = 8;
 pub(crate) const DEFAULT_82543_TIPG_IPGR1:u32 = 8;
-pub(crate) const E1000_TIPG_IPGR1_SHIFT:u32 = 10;
 pub(crate) const DEFAULT_82543_TIPG_IPGR2:u32 = 6;
-pub(crate) const E1000_TIPG_IPGR2_SHIFT:u32 = 20;
 
 
 
@@ -100,16 +70,21 @@ pub(crate) const E1000_TXD_CMD_EOP:u32 = 0x01000000;	/* End of Packet */
 
 /* Receive Descriptor bit definitions */
 pub(crate) const E1000_RXD_STAT_DD:u32 = 0x01;	/* Descriptor Done */
+pub(crate) const E1000_RXD_STAT_EOP:u32 = 0x02;	/* End of Packet */
+pub(crate) const E1000_RXD_STAT_IXSM:u32 = 0x04;	/* Ignore checksum */
+pub(crate) const E1000_RXD_STAT_TCPCS:u32 = 0x20;	/* TCP xsum calculated */
+pub(crate) const E1000_RXD_STAT_IPCS:u32 = 0x40;	/* IP xsum calculated */
+pub(crate) const E1000_RXD_ERR_CE:u32 = 0x01;	/* CRC Error */
+pub(crate) const E1000_RXD_ERR_SE:u32 = 0x02;	/* Symbol Error */
+pub(crate) const E1000_RXD_ERR_SEQ:u32 = 0x04;	/* Sequence Error */
+pub(crate) const E1000_RXD_ERR_CXE:u32 = 0x10;	/* Carrier Extension Error */
+pub(crate) const E1000_RXD_ERR_TCPE:u32 = 0x20;	/* TCP/UDP Checksum Error */
+pub(crate) const E1000_RXD_ERR_IPE:u32 = 0x40;	/* IP Checksum Error */
+pub(crate) const E1000_RXD_ERR_RXE:u32 = 0x80;	/* Rx Data Error */
+// 帧本身有错误，需要丢弃。校验和错误不在其中，交给协议栈处理
+pub(crate) const E1000_RXD_ERR_FRAME_ERR_MASK:u32 = E1000_RXD_ERR_CE | E1000_RXD_ERR_SE | E1000_RXD_ERR_SEQ | E1000_RXD_ERR_CXE | E1000_RXD_ERR_RXE;
 // pub(crate) const E1000_:u32 = ;	/*  */
 // pub(crate) const E1000_:u32 = ;	/*  */
 // pub(crate) const E1000_:u32 = ;	/*  */
 // pub(crate) const E1000_:u32 = ;	/*  */
 // pub(crate) const E1000_:u32 = ;	/*  */
-
-/* Interrupt Cause Read Bits*/
-pub(crate) const E1000_ICR_RXT0:u32 = 0x00000080;	/* rx timer intr (ring 0) */
-pub(crate) const E1000_ICR_TXDW:u32 = 0x00000001;	/* Transmit desc written back */
-pub(crate) const E1000_ICR_RXDMT0:u32 = 0x00000010;	/* rx desc min. threshold (0) */
-pub(crate) const E1000_ICR_RXSEQ:u32 = 0x00000008;	/* rx sequence error */
-pub(crate) const E1000_ICR_LSC:u32 = 0x00000004;	/* Link Status Change */
-// pub(crate) const E1000_:u32 = ;	/*  */
\ No newline at end of file
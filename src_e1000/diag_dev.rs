@@ -0,0 +1,210 @@
+use kernel::prelude::*;
+use kernel::{cred, device, dma, miscdev, net};
+use kernel::file::{self, File, IoctlCommand, IoctlHandler};
+use kernel::io_buffer::IoBufferWriter;
+use kernel::ioctl::_IO;
+use kernel::sync::{smutex, Arc};
+
+use core::fmt::{self, Write};
+
+use crate::consts::*;
+use crate::regs::{self, Reg, RegIo, RegValue};
+use crate::selftest;
+use crate::{NetDevice, NetDevicePrvData};
+
+// ioctl 命令的类型（幻数）
+const E1000_DIAG_MAGIC: u32 = b'E' as u32;
+// 复位网卡并重建收发环，与检测到发送挂起之后的复位相同。接口必须处于打开状态，需要 CAP_NET_ADMIN 权限
+const E1000_DIAG_RESET: u32 = _IO(E1000_DIAG_MAGIC, 1);
+// 在回环模式下发送一个测试帧并检查是否原样收到。接口必须处于关闭状态，需要 CAP_NET_ADMIN 权限
+const E1000_DIAG_TEST_TX: u32 = _IO(E1000_DIAG_MAGIC, 2);
+
+// 诊断设备与打开它的文件共享的状态
+struct DiagShared {
+    // 网络设备的私有数据。打开的文件可能比设备的注册活得更久，因此在注销网络设备之前由
+    // `DiagDev` 的 Drop 实现置为 None，每次访问都要持有锁并检查
+    data: smutex::Mutex<Option<*const NetDevicePrvData>>,
+}
+
+// SAFETY: 只通过共享引用访问 `NetDevicePrvData`，它本身是 Send + Sync 的
+unsafe impl Send for DiagShared {}
+unsafe impl Sync for DiagShared {}
+
+impl DiagShared {
+    // 持有锁并在网络设备的私有数据上调用 `f`，网卡已经被移除时返回 ENODEV
+    fn with_data<R>(&self, f: impl FnOnce(&NetDevicePrvData) -> Result<R>) -> Result<R> {
+        let data = self.data.lock();
+        // SAFETY: 见 `data` 字段的说明，持有锁时指针不会失效
+        f(unsafe { &*(*data).ok_or(ENODEV)? })
+    }
+}
+
+/// 诊断用的字符设备 /dev/r4l_e1000_diag-<PCI 地址>，不需要 debugfs 和 ethtool 就能查看和操作网卡。
+///
+/// 每次打开时生成一份 MAC 寄存器和收发环状态的文本，之后的 read 都读取这一份快照。
+/// ioctl 可以复位网卡（`E1000_DIAG_RESET`），或者在接口关闭时发送一个回环测试帧（`E1000_DIAG_TEST_TX`）。
+pub(crate) struct DiagDev {
+    shared: Arc<DiagShared>,
+    _reg: Pin<Box<miscdev::Registration<DiagFile>>>,
+}
+
+impl DiagDev {
+    /// 注册以 `parent` 的设备名命名的杂项设备，只有 root 可以打开
+    pub(crate) fn try_new(parent: &dyn device::RawDevice, data: &NetDevicePrvData) -> Result<Self> {
+        let shared = Arc::try_new(DiagShared {
+            data: smutex::Mutex::new(Some(data as *const _)),
+        })?;
+        let reg = miscdev::Options::new()
+            .mode(0o600)
+            .parent(parent)
+            .register_new::<DiagFile>(fmt!("r4l_e1000_diag-{}", parent.name()), shared.clone())?;
+        Ok(Self { shared, _reg: reg })
+    }
+}
+
+impl Drop for DiagDev {
+    fn drop(&mut self) {
+        // 等待正在进行的 ioctl 结束，之后已经打开的文件只能读取各自的快照
+        *self.shared.data.lock() = None;
+    }
+}
+
+// 把格式化的文本追加到 Vec 中，分配失败时返回 fmt::Error
+struct DumpBuf(Vec<u8>);
+
+impl Write for DumpBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.try_extend_from_slice(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+// 诊断设备上打开的一个文件
+struct DiagFile {
+    shared: Arc<DiagShared>,
+    dump: Vec<u8>,  // 打开时生成的寄存器和收发环状态
+}
+
+impl DiagFile {
+    fn dump_reg<T: RegValue>(w: &mut DumpBuf, data: &NetDevicePrvData, name: &str, reg: Reg<T>) -> fmt::Result {
+        // 读取失败（偏移超出映射的范围）时不中断整个输出
+        match data.e1000_hw_ops.regs().read(reg) {
+            Ok(val) => writeln!(w, "{:<10} {:#07x} {:#010x}", name, reg.offset(), val.bits()),
+            Err(_) => writeln!(w, "{:<10} {:#07x} <error>", name, reg.offset()),
+        }
+    }
+
+    // 依次输出网络设备的状态、MAC 寄存器和每个队列的寄存器与软件索引。
+    // 不读取 ICR 和统计计数器，它们在读取时清零，会影响中断处理和 ethtool -S 的结果
+    fn dump(w: &mut DumpBuf, data: &NetDevicePrvData) -> fmt::Result {
+        let dev = data.napi.dev_get();
+        writeln!(w, "{}: running={} carrier={}", dev.name(), dev.netif_running(), dev.netif_carrier_ok())?;
+
+        writeln!(w, "\nMAC registers:")?;
+        Self::dump_reg(w, data, "CTRL", regs::CTRL)?;
+        Self::dump_reg(w, data, "STATUS", regs::STATUS)?;
+        Self::dump_reg(w, data, "CTRL_EXT", regs::CTRL_EXT)?;
+        Self::dump_reg(w, data, "IMS", regs::IMS)?;
+        Self::dump_reg(w, data, "ITR", regs::ITR)?;
+        Self::dump_reg(w, data, "RCTL", regs::RCTL)?;
+        Self::dump_reg(w, data, "RDTR", regs::RDTR)?;
+        Self::dump_reg(w, data, "RADV", regs::RADV)?;
+        Self::dump_reg(w, data, "RXCSUM", regs::RXCSUM)?;
+        Self::dump_reg(w, data, "TCTL", regs::TCTL)?;
+        Self::dump_reg(w, data, "TIPG", regs::TIPG)?;
+        Self::dump_reg(w, data, "RAL", regs::RAL)?;
+        Self::dump_reg(w, data, "RAH", regs::RAH)?;
+        Self::dump_reg(w, data, "MANC", regs::MANC)?;
+
+        for qid in 0..E1000_NUM_TX_QUEUES {
+            writeln!(w, "\ntx queue {}:", qid)?;
+            Self::dump_reg(w, data, "TDBAL", regs::TDBAL.queue(qid))?;
+            Self::dump_reg(w, data, "TDBAH", regs::TDBAH.queue(qid))?;
+            Self::dump_reg(w, data, "TDLEN", regs::TDLEN.queue(qid))?;
+            Self::dump_reg(w, data, "TDH", regs::TDH.queue(qid))?;
+            Self::dump_reg(w, data, "TDT", regs::TDT.queue(qid))?;
+            // 接口关闭时没有分配描述符环。写入 `w` 可能分配内存（会睡眠），只在锁内复制索引，释放锁之后再格式化
+            let tx_state = data.tx_rings[qid].lock_irqdisable().as_ref()
+                .map(|tx_ring| (tx_ring.len(), tx_ring.desc.next_to_use(), tx_ring.desc.next_to_clean()));
+            match tx_state {
+                Some((len, ntu, ntc)) => writeln!(w, "len={} next_to_use={} next_to_clean={} unused={}",
+                    len, ntu, ntc, dma::ring_unused(ntc, ntu, len))?,
+                None => writeln!(w, "not allocated")?,
+            }
+        }
+
+        for qid in 0..E1000_NUM_RX_QUEUES {
+            writeln!(w, "\nrx queue {}:", qid)?;
+            Self::dump_reg(w, data, "RDBAL", regs::RDBAL.queue(qid))?;
+            Self::dump_reg(w, data, "RDBAH", regs::RDBAH.queue(qid))?;
+            Self::dump_reg(w, data, "RDLEN", regs::RDLEN.queue(qid))?;
+            Self::dump_reg(w, data, "RDH", regs::RDH.queue(qid))?;
+            Self::dump_reg(w, data, "RDT", regs::RDT.queue(qid))?;
+            let rx_state = data.rx_rings[qid].lock_irqdisable().as_ref()
+                .map(|rx_ring| (rx_ring.len(), rx_ring.state.skb_top.is_some(), rx_ring.state.discarding));
+            match rx_state {
+                Some((len, skb_top, discarding)) =>
+                    writeln!(w, "len={} skb_top={} discarding={}", len, skb_top, discarding)?,
+                None => writeln!(w, "not allocated")?,
+            }
+        }
+        Ok(())
+    }
+
+    // 运行回环自检。自检使用临时的收发环并改写收发单元的配置，只能在接口关闭时进行，
+    // 持有 RTNL 锁防止接口在自检过程中被打开
+    fn test_tx(data: &NetDevicePrvData) -> Result {
+        let dev = data.napi.dev_get();
+        let _rtnl = net::RtnlGuard::lock();
+        if dev.netif_running() {
+            return Err(EBUSY);
+        }
+        selftest::e1000_loopback_test(&*data.dev, &*data.e1000_hw_ops, &data.rx_page_pool, &data.mac_addr)
+    }
+}
+
+#[vtable]
+impl file::Operations for DiagFile {
+    type OpenData = Arc<DiagShared>;
+    type Data = Box<Self>;
+
+    fn open(shared: &Arc<DiagShared>, _file: &File) -> Result<Box<Self>> {
+        let mut w = DumpBuf(Vec::new());
+        shared.with_data(|data| Self::dump(&mut w, data).map_err(|_| ENOMEM))?;
+        Ok(Box::try_new(DiagFile {
+            shared: shared.clone(),
+            dump: w.0,
+        })?)
+    }
+
+    fn read(this: &Self, _file: &File, writer: &mut impl IoBufferWriter, offset: u64) -> Result<usize> {
+        let offset = match usize::try_from(offset) {
+            Ok(offset) if offset < this.dump.len() => offset,
+            _ => return Ok(0),
+        };
+        let len = core::cmp::min(writer.len(), this.dump.len() - offset);
+        writer.write_slice(&this.dump[offset..offset + len])?;
+        Ok(len)
+    }
+
+    fn ioctl(this: &Self, file: &File, cmd: &mut IoctlCommand) -> Result<i32> {
+        cmd.dispatch::<Self>(this, file)
+    }
+}
+
+impl IoctlHandler for DiagFile {
+    type Target<'a> = &'a Self;
+
+    fn pure(this: &Self, _file: &File, cmd: u32, _arg: usize) -> Result<i32> {
+        let op = match cmd {
+            E1000_DIAG_RESET => NetDevice::e1000_user_reset,
+            E1000_DIAG_TEST_TX => Self::test_tx,
+            _ => return Err(ENOTTY),
+        };
+        // 两个命令都会中断网络通信
+        if !cred::capable(cred::cap::NET_ADMIN) {
+            return Err(EPERM);
+        }
+        this.shared.with_data(op)?;
+        Ok(0)
+    }
+}
@@ -1,32 +1,261 @@
 use kernel::prelude::*;
 use kernel::pci::{MappedResource, IoPort};
-use kernel::delay::coarse_sleep;
+use kernel::delay::{coarse_sleep, coarse_delay};
+use kernel::net::mii::Mdio;
 use kernel::sync::Arc;
 
 use core::time::Duration;
 
-use crate::ring_buf::{RxRingBuf, TxRingBuf};
+use crate::ring_buf::RingLayout;
+use crate::stats::HwStats;
 
 use crate::consts::*;
+use crate::regs::{self, Reg, RegIo, RegValue, Ctrl, Icr, Manc, Mdic, Rah, Rctl, Rxcsum, Status, Tctl, Tipg};
 
+/// e1000 系列网卡的硬件操作。
+///
+/// 不同代的 MAC（8254x、8257x 以及 em 系列）的寄存器布局基本一致，区别主要在于复位流程和
+/// 收发单元的配置。每种芯片只需实现复位和收发配置，其余的寄存器访问使用默认实现，
+/// 网络设备层只通过这个特征访问硬件。
+pub(crate) trait E1000Hw: Send + Sync {
+    /// 返回映射后的寄存器空间
+    fn regs(&self) -> &MappedResource;
+
+    /// 完全重置硬件，对应于 C 版本的 `e1000_reset_hw`。
+    fn e1000_reset_hw(&self) -> Result;
+
+    /// 配置所有发送队列，`tx_rings` 的第 i 项对应硬件队列 i
+    fn e1000_configure_tx(&self, tx_rings: &mut dyn Iterator<Item = RingLayout>) -> Result;
+
+    /// 配置所有接收队列，`rx_rings` 的第 i 项对应硬件队列 i。描述符的格式由芯片决定
+    fn e1000_configure_rx(&self, rx_rings: &mut dyn Iterator<Item = RingLayout>) -> Result;
+
+    /// 停止接收和发送单元，使网卡不再访问描述符环，之后才能安全地释放环形缓冲区。
+    fn e1000_stop_rxtx(&self) -> Result {
+        self.regs().modify(regs::RCTL, |rctl| rctl & !Rctl::EN)?;
+        self.regs().modify(regs::TCTL, |tctl| tctl & !Tctl::EN)?;
+        self.e1000_write_flush();
+
+        // 等待正在进行的 DMA 事务完成
+        coarse_sleep(Duration::from_millis(10));
+        Ok(())
+    }
+
+    /// 通过 MDIC 寄存器读取 PHY 寄存器，对应于 C 版本的 `e1000_read_phy_reg_ex`。
+    fn e1000_read_phy_reg(&self, phy_addr: u8, reg_addr: u8) -> Result<u16> {
+        self.regs().write(regs::MDIC, Mdic::new(Mdic::OP_READ, phy_addr, reg_addr, 0))?;
+
+        let mdic = self.e1000_wait_mdic_ready()?;
+        Ok(mdic.data())
+    }
+
+    /// 通过 MDIC 寄存器写入 PHY 寄存器，对应于 C 版本的 `e1000_write_phy_reg_ex`。
+    fn e1000_write_phy_reg(&self, phy_addr: u8, reg_addr: u8, data: u16) -> Result {
+        self.regs().write(regs::MDIC, Mdic::new(Mdic::OP_WRITE, phy_addr, reg_addr, data))?;
+
+        self.e1000_wait_mdic_ready()?;
+        Ok(())
+    }
+
+    // 轮询 MDIC 寄存器直到 Ready 位被置位，返回最后读到的值
+    fn e1000_wait_mdic_ready(&self) -> Result<Mdic> {
+        for _ in 0..PHY_MDIC_POLL_COUNT {
+            coarse_delay(Duration::from_micros(50));
+            let mdic = self.regs().read(regs::MDIC)?;
+            if mdic.contains(Mdic::READY) {
+                if mdic.contains(Mdic::ERROR) {
+                    return Err(EIO);
+                }
+                return Ok(mdic);
+            }
+        }
+        Err(ETIMEDOUT)
+    }
+
+    /// 网卡是否工作在 PCI-X 总线上，对应于 C 版本 `e1000_get_bus_info` 中对 `bus_type` 的判断。
+    /// 只有 8254x 的 STATUS 寄存器有这一位
+    fn e1000_bus_pcix(&self) -> bool {
+        self.regs().read(regs::STATUS).map_or(false, |status| status.contains(Status::PCIX_MODE))
+    }
+
+    // 写入并刷新寄存器以确保操作完成
+    fn e1000_write_flush(&self) {
+        // 读取状态寄存器，该操作应该不会失败
+        self.regs().read(regs::STATUS).unwrap();
+    }
+
+    /// 把 MAC 地址写入接收地址寄存器 0，网卡只接收目的地址与之匹配的单播帧
+    fn e1000_write_mac_addr(&self, addr: &[u8; 6]) -> Result {
+        let ral = u32::from_le_bytes([addr[0], addr[1], addr[2], addr[3]]);
+        let rah = Rah(u16::from_le_bytes([addr[4], addr[5]]) as u32) | Rah::AV;
+        self.regs().write(regs::RAL, ral)?;
+        self.regs().write(regs::RAH, rah)?;
+        self.e1000_write_flush();
+        Ok(())
+    }
+
+    /// 配置所有队列的接收和发送缓冲区以及相关中断，`rx_rings`/`tx_rings` 的第 i 项对应硬件队列 i
+    fn e1000_configure(
+        &self,
+        rx_rings: &mut dyn Iterator<Item = RingLayout>,
+        tx_rings: &mut dyn Iterator<Item = RingLayout>,
+    ) -> Result {
+        // 配置接收缓冲区
+        self.e1000_configure_rx(rx_rings)?;
+        // 配置发送缓冲区
+        self.e1000_configure_tx(tx_rings)?;
+
+        // 启用相关中断
+        self.e1000_irq_enable();
+        Ok(())
+    }
+
+    /// 配置接收校验和卸载以及是否保留以太网 CRC，对应于 C 版本 `e1000_set_features` 中的硬件配置。
+    /// 必须在配置接收单元之后调用，`e1000_configure_rx` 会重写 RCTL。
+    fn e1000_set_rx_features(&self, rx_csum: bool, keep_fcs: bool) -> Result {
+        let mut rxcsum = self.regs().read(regs::RXCSUM)?;
+        if rx_csum {
+            rxcsum |= Rxcsum::IPOFL | Rxcsum::TUOFL;
+        } else {
+            rxcsum &= !(Rxcsum::IPOFL | Rxcsum::TUOFL);
+        }
+        self.regs().write(regs::RXCSUM, rxcsum)?;
+
+        let mut rctl = self.regs().read(regs::RCTL)?;
+        if keep_fcs {
+            rctl &= !Rctl::SECRC;
+        } else {
+            rctl |= Rctl::SECRC;
+        }
+        self.regs().write(regs::RCTL, rctl)?;
+        self.e1000_write_flush();
+        Ok(())
+    }
+
+    /// 屏蔽所有中断，对应于 C 版本的 `e1000_irq_disable`
+    fn e1000_irq_disable(&self) {
+        self.regs().write(regs::IMC, Icr::ALL).unwrap();
+        self.e1000_write_flush();
+    }
+
+    /// 重新打开驱动使用的中断，对应于 C 版本的 `e1000_irq_enable`
+    fn e1000_irq_enable(&self) {
+        self.e1000_irq_enable_mask(regs::IMS_ENABLE_MASK);
+    }
+
+    /// 只屏蔽 `mask` 中的中断，收发 NAPI 各自屏蔽自己处理的中断
+    fn e1000_irq_disable_mask(&self, mask: Icr) {
+        self.regs().write(regs::IMC, mask).unwrap();
+        self.e1000_write_flush();
+    }
+
+    /// 重新打开 `mask` 中的中断
+    fn e1000_irq_enable_mask(&self, mask: Icr) {
+        self.regs().write(regs::IMS, mask).unwrap();
+        self.e1000_write_flush();
+    }
+
+    /// 读取硬件统计寄存器并累加到 `stats` 中，对应于 C 版本的 `e1000_update_stats`。
+    /// 这些寄存器是读清零的，因此只能由统计刷新工作项调用。
+    fn e1000_update_stats(&self, stats: &HwStats) {
+        let read = |reg: Reg<u32>| self.regs().read(reg).unwrap() as u64;
+
+        HwStats::add(&stats.rx_crc_errors, read(regs::CRCERRS));
+        HwStats::add(&stats.rx_missed_errors, read(regs::MPC));
+        HwStats::add(&stats.collisions, read(regs::COLC));
+        HwStats::add(&stats.rx_packets, read(regs::GPRC));
+        HwStats::add(&stats.multicast, read(regs::MPRC));
+        HwStats::add(&stats.tx_packets, read(regs::GPTC));
+
+        // 64 位的字节计数器必须先读低 32 位，读高 32 位时整个计数器被清零
+        let gorc = read(regs::GORCL) | (read(regs::GORCH) << 32);
+        HwStats::add(&stats.rx_bytes, gorc);
+        let gotc = read(regs::GOTCL) | (read(regs::GOTCH) << 32);
+        HwStats::add(&stats.tx_bytes, gotc);
+    }
+
+    // 读取中断状态寄存器的值
+    fn e1000_read_interrupt_state(&self) -> Icr {
+        self.regs().read(regs::ICR).unwrap()
+    }
+
+    // 读取发送队列头索引
+    fn e1000_read_tx_queue_head(&self, qid: usize) -> u32 {
+        self.regs().read(regs::TDH.queue(qid)).unwrap()
+    }
+
+    // 读取发送队列尾索引
+    fn e1000_read_tx_queue_tail(&self, qid: usize) -> u32 {
+        self.regs().read(regs::TDT.queue(qid)).unwrap()
+    }
+
+    fn e1000_write_tx_queue_tail(&self, qid: usize, val: u32) {
+        self.regs().write(regs::TDT.queue(qid), val).unwrap()
+    }
+
+
+    fn e1000_read_rx_queue_head(&self, qid: usize) -> u32 {
+        self.regs().read(regs::RDH.queue(qid)).unwrap()
+    }
+
+    fn e1000_read_rx_queue_tail(&self, qid: usize) -> u32 {
+        self.regs().read(regs::RDT.queue(qid)).unwrap()
+    }
+
+    fn e1000_write_rx_queue_tail(&self, qid: usize, val: u32) {
+        self.regs().write(regs::RDT.queue(qid), val).unwrap()
+    }
+}
+
+// 让通用的 MII 辅助函数（kernel::net::mii）可以通过 MDIC 寄存器访问 PHY，所有 e1000 芯片共用
+impl Mdio for dyn E1000Hw {
+    fn mdio_read(&self, phy_id: u8, reg: u8) -> Result<u16> {
+        self.e1000_read_phy_reg(phy_id, reg)
+    }
+
+    fn mdio_write(&self, phy_id: u8, reg: u8, val: u16) -> Result {
+        self.e1000_write_phy_reg(phy_id, reg, val)
+    }
+}
+
+/// 8254x 系列（包括 QEMU 模拟的 82540EM）的硬件操作
 pub(crate) struct E1000Ops {
     pub(crate) mem_addr: Arc<MappedResource>, // 内存映射资源的引用
-    pub(crate) io_addr: Arc<IoPort>, // I/O 端口的引用
+    pub(crate) io_addr: Option<Arc<IoPort>>, // I/O 端口的引用，没有 I/O BAR 时为 None
+    pub(crate) rx_buffer_size: u32, // 接收缓冲区的大小，必须是 E1000_RX_BUFFER_SIZES 之一
 }
 
 impl E1000Ops {
+    // 通过 I/O 端口写入寄存器，没有 I/O BAR 时退回到 MMIO 写入
+    fn e1000_write_reg_io<T: RegValue>(&self, reg: Reg<T>, val: T) -> Result {
+        let io_addr = match &self.io_addr {
+            Some(io_addr) => io_addr,
+            None => return self.mem_addr.write(reg, val),
+        };
+
+        // 写入地址和数据到 I/O 端口
+        io_addr.outl(reg.offset() as u32, E1000_IOADDR)?;
+        io_addr.outl(val.bits(), E1000_IODATA)?;
+        Ok(())
+    }
+}
+
+impl E1000Hw for E1000Ops {
+    fn regs(&self) -> &MappedResource {
+        &self.mem_addr
+    }
 
     /// 完全重置硬件，对应于 C 版本的 `e1000_reset_hw`。
     /// 仅支持 QEMU 的 82540EM 芯片。
-    pub(crate) fn e1000_reset_hw(&self) -> Result {
+    fn e1000_reset_hw(&self) -> Result {
         // 清除中断掩码寄存器，以停止板卡生成任何中断
         // 这确保在重置过程中不会受到中断干扰
-        self.mem_addr.writel(0xffffffff, E1000_IMC)?;
+        self.mem_addr.write(regs::IMC, Icr::ALL)?;
 
         // 禁用接收控制寄存器 (RCTL) 和传输控制寄存器 (TCTL)
         // 允许任何待处理的事务在进行全局重置之前完成
-        self.mem_addr.writel(0, E1000_RCTL)?;
-        self.mem_addr.writel(E1000_TCTL_PSP, E1000_TCTL)?;
+        self.mem_addr.write(regs::RCTL, Rctl::empty())?;
+        self.mem_addr.write(regs::TCTL, Tctl::PSP)?;
 
         // 刷新写缓冲区，以确保写入寄存器的操作完成
         self.e1000_write_flush();
@@ -35,10 +264,11 @@ impl E1000Ops {
         coarse_sleep(Duration::from_millis(10));
 
         // 读取当前控制寄存器的值
-        let ctrl = self.mem_addr.readl(E1000_CTRL)?;
+        let ctrl = self.mem_addr.read(regs::CTRL)?;
 
-        // 使用 I/O 映射发出重置操作，因为这些控制器无法在发出 64 位写操作时进行确认
-        self.e1000_write_reg_io(ctrl | E1000_CTRL_RST, E1000_CTRL)?;
+        // 使用 I/O 映射发出重置操作，因为这些控制器无法在发出 64 位写操作时进行确认。
+        // 虚拟网卡等没有 I/O BAR 的设备只能通过 MMIO 发出重置
+        self.e1000_write_reg_io(regs::CTRL, ctrl | Ctrl::RST)?;
 
         // 在 MAC 重置后，强制重新加载 EEPROM，以恢复设备的上电设置
         // 对于较新的控制器，EEPROM 会自动重新加载
@@ -46,149 +276,89 @@ impl E1000Ops {
 
         // 在启用了 ASF（高级安全功能）的适配器上禁用硬件 ARP
         // 这可能会影响 ARP 请求的处理
-        let manc = self.mem_addr.readl(E1000_MANC)?;
-        self.mem_addr.writel(manc & (!E1000_MANC_ARP_EN), E1000_MANC)?;
+        self.mem_addr.modify(regs::MANC, |manc| manc & !Manc::ARP_EN)?;
 
         // 清除中断掩码寄存器，以停止板卡生成任何中断
-        self.mem_addr.writel(0xffffffff, E1000_IMC)?;
+        self.mem_addr.write(regs::IMC, Icr::ALL)?;
 
         // 读取并清除中断状态寄存器，以确保没有挂起的中断事件
-        self.mem_addr.readl(E1000_ICR)?;
-
-        Ok(())
-    }
-
-    // 写入并刷新寄存器以确保操作完成
-    fn e1000_write_flush(&self) {
-        // 读取状态寄存器，该操作应该不会失败
-        self.mem_addr.readl(E1000_STATUS).unwrap();
-    }
+        self.mem_addr.read(regs::ICR)?;
 
-    // 通过 I/O 端口写入寄存器
-    fn e1000_write_reg_io(&self, value: u32, addr: usize) -> Result {
-        // 写入地址和数据到 I/O 端口
-        self.io_addr.outl(addr as u32, 0)?;
-        self.io_addr.outl(value, 4)?;
-        Ok(())
-    }
-
-    // 配置接收和发送缓冲区以及相关中断
-    pub(crate) fn e1000_configure(&self, rx_ring: &RxRingBuf, tx_ring: &TxRingBuf) -> Result {
-        // 配置接收缓冲区
-        self.e1000_configure_rx(rx_ring)?;
-        // 配置发送缓冲区
-        self.e1000_configure_tx(tx_ring)?;
-
-        // 启用相关中断
-        self.mem_addr.writel(
-            E1000_ICR_TXDW | E1000_ICR_RXT0 | E1000_ICR_RXDMT0 | E1000_ICR_RXSEQ | E1000_ICR_LSC,
-            E1000_IMS
-        )?;
         Ok(())
     }
 
     // 配置发送缓冲区
-    fn e1000_configure_tx(&self, tx_ring: &TxRingBuf) -> Result {
+    fn e1000_configure_tx(&self, tx_rings: &mut dyn Iterator<Item = RingLayout>) -> Result {
         // 根据手册第 14.5 节配置发送缓冲区
 
-        // 设置发送缓冲区的头索引、尾索引和缓冲区大小
-        self.mem_addr.writel(0, E1000_TDH)?; // 设置头索引
-        self.mem_addr.writel(0, E1000_TDT)?; // 设置尾索引
-        self.mem_addr.writel((TX_RING_SIZE * 16) as u32, E1000_TDLEN)?; // 设置缓冲区长度
-        // 设置发送缓冲区的起始地址
-        self.mem_addr.writel(tx_ring.desc.get_dma_addr() as u32, E1000_TDBAL)?;
-        self.mem_addr.writel(0, E1000_TDBAH)?;
+        for (qid, tx_ring) in tx_rings.enumerate() {
+            // 设置发送缓冲区的头索引、尾索引和缓冲区大小
+            self.mem_addr.write(regs::TDH.queue(qid), 0)?; // 设置头索引
+            self.mem_addr.write(regs::TDT.queue(qid), 0)?; // 设置尾索引
+            self.mem_addr.write(regs::TDLEN.queue(qid), (tx_ring.len * 16) as u32)?; // 设置缓冲区长度
+            // 设置发送缓冲区的起始地址
+            self.mem_addr.write(regs::TDBAL.queue(qid), tx_ring.dma_addr as u32)?;
+            self.mem_addr.write(regs::TDBAH.queue(qid), 0)?;
+        }
 
         // 配置发送控制寄存器
         let tctl = (
-            E1000_TCTL_EN | // 启用发送单元
-                E1000_TCTL_PSP | // 填充发送包
-                0x10 << E1000_CT_SHIFT | // 设置计时器
-                0x40 << E1000_COLD_SHIFT // 设置冷却时间
-        );
-        self.mem_addr.writel(tctl, E1000_TCTL)?;
+            Tctl::EN | // 启用发送单元
+                Tctl::PSP // 填充发送包
+        )
+            .with_ct(0x10) // 设置冲突阈值
+            .with_cold(0x40); // 设置冲突距离
+        self.mem_addr.write(regs::TCTL, tctl)?;
 
         // 配置发送间隔寄存器
-        let tipg = (
-            DEFAULT_82543_TIPG_IPGT_COPPER | // 设置 IPGT
-                DEFAULT_82543_TIPG_IPGR1 << E1000_TIPG_IPGR1_SHIFT | // 设置 IPGR1
-                DEFAULT_82543_TIPG_IPGR2 << E1000_TIPG_IPGR2_SHIFT // 设置 IPGR2
+        let tipg = Tipg::new(
+            DEFAULT_82543_TIPG_IPGT_COPPER, // 设置 IPGT
+            DEFAULT_82543_TIPG_IPGR1, // 设置 IPGR1
+            DEFAULT_82543_TIPG_IPGR2, // 设置 IPGR2
         );
-        self.mem_addr.writel(tipg, E1000_TIPG)?;
+        self.mem_addr.write(regs::TIPG, tipg)?;
 
         Ok(())
     }
 
     // 配置接收缓冲区
-    fn e1000_configure_rx(&self, rx_ring: &RxRingBuf) -> Result {
+    fn e1000_configure_rx(&self, rx_rings: &mut dyn Iterator<Item = RingLayout>) -> Result {
         // 根据手册第 14.4 节配置接收缓冲区
 
-        // 根据 MIT6.828 练习 10，硬编码 QEMU 的 MAC 地址
-        // MAC 地址：52:54:00:12:34:56
-        self.mem_addr.writel(0x12005452, E1000_RA)?; // 设置 RAL
-        self.mem_addr.writel(0x5634 | (1 << 31), E1000_RA + 4)?; // 设置 RAH
+        // MAC 地址在 probe 中通过 `e1000_write_mac_addr` 写入，这里不再修改
 
         // 清除多播地址表中的所有条目
-        for i in 0..128 {
-            self.mem_addr.writel(0, E1000_MTA + i * 4)?;
+        for i in 0..regs::MTA.len() {
+            self.mem_addr.write(regs::MTA.at(i).ok_or(EINVAL)?, 0)?;
         }
 
-        // 配置接收缓冲区的头索引、尾索引和缓冲区大小
-        self.mem_addr.writel(0, E1000_RDH)?; // 设置头索引
-        self.mem_addr.writel((RX_RING_SIZE - 1) as u32, E1000_RDT)?; // 设置尾索引
-        self.mem_addr.writel((RX_RING_SIZE * 16) as u32, E1000_RDLEN)?; // 设置缓冲区长度
-        // 设置接收缓冲区的起始地址
-        self.mem_addr.writel(rx_ring.desc.get_dma_addr() as u32, E1000_RDBAL)?;
-        self.mem_addr.writel(0, E1000_RDBAH)?;
+        for (qid, rx_ring) in rx_rings.enumerate() {
+            // 配置接收缓冲区的头索引、尾索引和缓冲区大小
+            self.mem_addr.write(regs::RDH.queue(qid), 0)?; // 设置头索引
+            self.mem_addr.write(regs::RDT.queue(qid), (rx_ring.len - 1) as u32)?; // 设置尾索引
+            self.mem_addr.write(regs::RDLEN.queue(qid), (rx_ring.len * 16) as u32)?; // 设置缓冲区长度
+            // 设置接收缓冲区的起始地址
+            self.mem_addr.write(regs::RDBAL.queue(qid), rx_ring.dma_addr as u32)?;
+            self.mem_addr.write(regs::RDBAH.queue(qid), 0)?;
+        }
 
         // 配置接收控制寄存器
-        let rctl = (
-            E1000_RCTL_EN | // 启用接收单元
-                E1000_RCTL_BAM | // 启用广播接收
-                E1000_RCTL_SZ_2048 | // 设置接收缓冲区大小
-                E1000_RCTL_SECRC // 启用硬件 CRC 校验
+        let mut rctl = (
+            Rctl::EN | // 启用接收单元
+                Rctl::BAM | // 启用广播接收
+                Rctl::buffer_size(self.rx_buffer_size).ok_or(EINVAL)? | // 设置接收缓冲区大小
+                Rctl::SECRC // 启用硬件 CRC 校验
         );
-        self.mem_addr.writel(rctl, E1000_RCTL)?;
+        // 缓冲区比标准帧大时才需要接收超过 1522 字节的长帧
+        if self.rx_buffer_size > E1000_RX_BUFFER_SIZE {
+            rctl |= Rctl::LPE;
+        }
+        self.mem_addr.write(regs::RCTL, rctl)?;
 
         // 禁用 RDTR 和 RADV 计时器，因为我们使用 NAPI，不需要硬件帮助来减少中断
-        self.mem_addr.writel(0, E1000_RDTR)?;
-        self.mem_addr.writel(0, E1000_RADV)?;
+        self.mem_addr.write(regs::RDTR, 0)?;
+        self.mem_addr.write(regs::RADV, 0)?;
 
         Ok(())
     }
-
-    // 读取中断状态寄存器的值
-    pub(crate) fn e1000_read_interrupt_state(&self) -> u32 {
-        self.mem_addr.readl(E1000_ICR).unwrap()
-    }
-
-    // 读取发送队列头索引
-    pub(crate) fn e1000_read_tx_queue_head(&self) -> u32 {
-        self.mem_addr.readl(E1000_TDH).unwrap()
-    }
-
-    // 读取发送队列尾索引
-    pub(crate) fn e1000_read_tx_queue_tail(&self) -> u32 {
-        self.mem_addr.readl(E1000_TDT).unwrap()
-    }
-
-    pub(crate) fn e1000_write_tx_queue_tail(&self, val: u32) {
-        self.mem_addr.writel(val, E1000_TDT).unwrap()
-    }
-
-
-    pub(crate) fn e1000_read_rx_queue_head(&self) -> u32 {
-        self.mem_addr.readl(E1000_RDH).unwrap()
-    }
-
-    pub(crate) fn e1000_read_rx_queue_tail(&self) -> u32 {
-        self.mem_addr.readl(E1000_RDT).unwrap()
-    }
-
-    pub(crate) fn e1000_write_rx_queue_tail(&self, val: u32) {
-        self.mem_addr.writel(val, E1000_RDT).unwrap()
-    }
-
-
 }
-
@@ -0,0 +1,20 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// 错误注入点，让某一类分配每隔若干次失败一次，用来在 QEMU 中触发平时走不到的错误处理路径。
+///
+/// 间隔由模块参数给出，为 0 时不注入错误。只在打开调试断言（CONFIG_RUST_DEBUG_ASSERTIONS）
+/// 时生效，正式构建中 `should_fail` 总是返回 false。
+#[derive(Default)]
+pub(crate) struct FaultInject {
+    calls: AtomicU32,  // 经过这个注入点的次数
+}
+
+impl FaultInject {
+    /// 返回这一次调用是否应该失败：每 `interval` 次调用失败一次
+    pub(crate) fn should_fail(&self, interval: u32) -> bool {
+        if !cfg!(debug_assertions) || interval == 0 {
+            return false;
+        }
+        self.calls.fetch_add(1, Ordering::Relaxed) % interval == interval - 1
+    }
+}
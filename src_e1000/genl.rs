@@ -0,0 +1,160 @@
+use kernel::prelude::*;
+use kernel::{c_str, dma};
+use kernel::net::genetlink::{self, AttrType, Command, Info};
+use kernel::str::{CStr, CString};
+use kernel::sync::smutex;
+
+use crate::consts::*;
+use crate::{NetDevice, NetDevicePrvData};
+
+// 命令。请求都要带上 E1000_ATTR_IFINDEX，指定要操作的网卡
+const E1000_CMD_GET_RINGS: u8 = 1;  // 查询各收发环的状态，每个环回复一个嵌套属性
+const E1000_CMD_GET_STATS: u8 = 2;  // 导出 ethtool -S 中的驱动计数器，每个计数器回复一个嵌套属性
+const E1000_CMD_REINIT: u8 = 3;  // 复位网卡并重建收发环，需要 CAP_NET_ADMIN 权限
+
+// 顶层属性
+const E1000_ATTR_IFINDEX: u16 = 1;  // u32，网络接口的编号
+const E1000_ATTR_TX_RING: u16 = 2;  // 嵌套，E1000_RING_ATTR_*
+const E1000_ATTR_RX_RING: u16 = 3;  // 嵌套，E1000_RING_ATTR_*
+const E1000_ATTR_STAT: u16 = 4;  // 嵌套，E1000_STAT_ATTR_*
+
+// 收发环的属性，都是 u32。接口关闭时没有分配描述符环，只回复队列编号和头尾指针
+const E1000_RING_ATTR_QID: u16 = 1;
+const E1000_RING_ATTR_HEAD: u16 = 2;
+const E1000_RING_ATTR_TAIL: u16 = 3;
+const E1000_RING_ATTR_LEN: u16 = 4;
+const E1000_RING_ATTR_NEXT_TO_USE: u16 = 5;  // 只有发送环有
+const E1000_RING_ATTR_NEXT_TO_CLEAN: u16 = 6;
+
+// 计数器的属性
+const E1000_STAT_ATTR_NAME: u16 = 1;  // 字符串，与 ethtool -S 中的名字相同
+const E1000_STAT_ATTR_VALUE: u16 = 2;  // u64
+const E1000_STAT_ATTR_PAD: u16 = 3;  // 对齐 E1000_STAT_ATTR_VALUE 使用的填充
+
+// 已经注册的网卡，由 `GenlAdapter` 在 probe 中加入，在注销网络设备之前移除
+static ADAPTERS: smutex::Mutex<Vec<AdapterPtr>> = smutex::Mutex::new(Vec::new());
+
+// 网络设备的私有数据，只要还在 `ADAPTERS` 中就一直有效
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct AdapterPtr(*const NetDevicePrvData);
+
+// SAFETY: 只通过共享引用访问 `NetDevicePrvData`，它本身是 Send + Sync 的
+unsafe impl Send for AdapterPtr {}
+
+/// 把网卡加入 generic netlink 接口可以操作的列表，析构时移除。必须在网络设备注销之前析构
+pub(crate) struct GenlAdapter(AdapterPtr);
+
+impl GenlAdapter {
+    /// 加入 `data` 所属的网卡，之后可以按它的 ifindex 找到
+    pub(crate) fn try_new(data: &NetDevicePrvData) -> Result<Self> {
+        let ptr = AdapterPtr(data);
+        ADAPTERS.lock().try_push(ptr)?;
+        Ok(Self(ptr))
+    }
+}
+
+impl Drop for GenlAdapter {
+    fn drop(&mut self) {
+        // 持有锁时没有命令在访问这块网卡，移除之后也不会再有
+        ADAPTERS.lock().retain(|ptr| *ptr != self.0);
+    }
+}
+
+/// 驱动的 generic netlink 族 r4l_e1000，用户空间不需要 ethtool 或 debugfs 就能查看收发环、
+/// 读取计数器以及复位网卡。
+///
+/// 族在加载模块时注册，所有网卡共用；请求中的 E1000_ATTR_IFINDEX 在 `ADAPTERS` 中选择网卡。
+/// generic netlink 在进程上下文中逐个处理命令，因此可以睡眠，也可以获取 RTNL 锁。
+pub(crate) struct E1000Genl;
+
+impl E1000Genl {
+    // 在 ifindex 为 `ifindex` 的网卡上调用 `f`，持有 `ADAPTERS` 的锁，网卡不会在此期间被移除
+    fn with_adapter<R>(ifindex: u32, f: impl FnOnce(&NetDevicePrvData) -> Result<R>) -> Result<R> {
+        let adapters = ADAPTERS.lock();
+        for ptr in adapters.iter() {
+            // SAFETY: 见 `AdapterPtr` 的说明
+            let data = unsafe { &*ptr.0 };
+            if data.napi.dev_get().ifindex() as u32 == ifindex {
+                return f(data);
+            }
+        }
+        Err(ENODEV)
+    }
+
+    fn get_rings(info: &Info, data: &NetDevicePrvData) -> Result {
+        info.reply(E1000_CMD_GET_RINGS, |msg| {
+            for qid in 0..E1000_NUM_TX_QUEUES {
+                let head = data.e1000_hw_ops.e1000_read_tx_queue_head(qid);
+                let tail = data.e1000_hw_ops.e1000_read_tx_queue_tail(qid);
+                // 回复的空间是预先分配好的，添加属性不会睡眠，可以在自旋锁内进行
+                let tx_ring = data.tx_rings[qid].lock_irqdisable();
+                msg.put_nested(E1000_ATTR_TX_RING, |msg| {
+                    msg.put_u32(E1000_RING_ATTR_QID, qid as u32)?;
+                    msg.put_u32(E1000_RING_ATTR_HEAD, head)?;
+                    msg.put_u32(E1000_RING_ATTR_TAIL, tail)?;
+                    if let Some(tx_ring) = tx_ring.as_ref() {
+                        msg.put_u32(E1000_RING_ATTR_LEN, tx_ring.len() as u32)?;
+                        msg.put_u32(E1000_RING_ATTR_NEXT_TO_USE, tx_ring.desc.next_to_use() as u32)?;
+                        msg.put_u32(E1000_RING_ATTR_NEXT_TO_CLEAN, tx_ring.desc.next_to_clean() as u32)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            for qid in 0..E1000_NUM_RX_QUEUES {
+                let head = data.e1000_hw_ops.e1000_read_rx_queue_head(qid);
+                let tail = data.e1000_hw_ops.e1000_read_rx_queue_tail(qid);
+                let rx_ring = data.rx_rings[qid].lock_irqdisable();
+                msg.put_nested(E1000_ATTR_RX_RING, |msg| {
+                    msg.put_u32(E1000_RING_ATTR_QID, qid as u32)?;
+                    msg.put_u32(E1000_RING_ATTR_HEAD, head)?;
+                    msg.put_u32(E1000_RING_ATTR_TAIL, tail)?;
+                    if let Some(rx_ring) = rx_ring.as_ref() {
+                        // 接收环没有单独记录清理位置，NAPI 从 RDT 的下一个描述符开始处理
+                        let len = rx_ring.len();
+                        msg.put_u32(E1000_RING_ATTR_LEN, len as u32)?;
+                        msg.put_u32(E1000_RING_ATTR_NEXT_TO_CLEAN, dma::ring_next(tail as usize % len, len) as u32)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        })
+    }
+
+    fn get_stats(info: &Info, data: &NetDevicePrvData) -> Result {
+        info.reply(E1000_CMD_GET_STATS, |msg| {
+            data.drv_stats.for_each(|name, value| {
+                let name = CString::try_from_fmt(name)?;
+                msg.put_nested(E1000_ATTR_STAT, |msg| {
+                    msg.put_string(E1000_STAT_ATTR_NAME, &name)?;
+                    msg.put_u64(E1000_STAT_ATTR_VALUE, value, E1000_STAT_ATTR_PAD)
+                })
+            })
+        })
+    }
+}
+
+impl genetlink::Family for E1000Genl {
+    type Data = ();
+
+    const NAME: &'static CStr = c_str!("r4l_e1000");
+
+    // 请求中只有 ifindex，其他属性只出现在回复中
+    const ATTRS: &'static [AttrType] = &[AttrType::Unspec, AttrType::U32];
+
+    const COMMANDS: &'static [Command] = &[
+        Command::new(E1000_CMD_GET_RINGS),
+        Command::new(E1000_CMD_GET_STATS),
+        Command::new(E1000_CMD_REINIT).admin(),
+    ];
+
+    fn doit(_data: (), info: &Info) -> Result {
+        let ifindex = info.attr(E1000_ATTR_IFINDEX).ok_or(EINVAL)?.as_u32()?;
+        Self::with_adapter(ifindex, |data| match info.cmd() {
+            E1000_CMD_GET_RINGS => Self::get_rings(info, data),
+            E1000_CMD_GET_STATS => Self::get_stats(info, data),
+            E1000_CMD_REINIT => NetDevice::e1000_user_reset(data),
+            _ => Err(EOPNOTSUPP),
+        })
+    }
+}
@@ -1,3 +1,5 @@
+use kernel::io_buffer::ReadableFromBytes;
+
 
 // Defined in intel chip manual section 3.3.3
 #[repr(C)]
@@ -11,6 +13,9 @@ pub(crate) struct TxDescEntry {
     pub(crate) special: u16,
 }
 
+// SAFETY: The descriptor only contains integers, any bit pattern written by the device is valid.
+unsafe impl ReadableFromBytes for TxDescEntry {}
+
 
 // Defined in intel chip manual section 3.2.3
 #[repr(C)]
@@ -23,3 +28,5 @@ pub(crate) struct RxDescEntry {
     pub(crate) special: u16,
 }
 
+// SAFETY: The descriptor only contains integers, any bit pattern written by the device is valid.
+unsafe impl ReadableFromBytes for RxDescEntry {}
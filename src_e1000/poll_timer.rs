@@ -0,0 +1,63 @@
+use kernel::prelude::*;
+use kernel::net;
+use kernel::sync::{Arc, UniqueArc};
+use kernel::workqueue::{self, DelayedWork};
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+/// 纯轮询模式下调度 NAPI 的周期，实际精度取决于 jiffies
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// 纯轮询模式下代替中断的延迟工作项，周期性地调度收发两个 NAPI 处理收发队列。
+///
+/// 这种模式不注册中断处理程序，网卡中断始终保持屏蔽，
+/// 适合在 QEMU 中排查中断投递的问题，或者作为 MIT6.828 中轮询式驱动的对照。
+pub(crate) struct PollTimer {
+    napi: Arc<net::Napi>,  // 被调度的接收 NAPI
+    tx_napi: Arc<net::Napi>,  // 被调度的发送 NAPI
+    running: AtomicBool,  // 接口是否处于打开状态，为 false 时工作项不再重新排队
+    dwork: DelayedWork,  // 延迟工作项
+}
+
+kernel::impl_self_delayed_work_adapter!(PollTimer, dwork, |w| {
+    if !w.running.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // 相当于一次收发中断
+    w.napi.schedule();
+    w.tx_napi.schedule();
+
+    // 重新排队，等待下一个轮询周期
+    if w.running.load(Ordering::Relaxed) {
+        workqueue::system().enqueue_delayed(w, POLL_INTERVAL);
+    }
+});
+
+impl PollTimer {
+    /// 创建轮询工作项，此时并不会开始运行
+    pub(crate) fn try_new(napi: Arc<net::Napi>, tx_napi: Arc<net::Napi>) -> Result<Arc<Self>> {
+        let w = UniqueArc::try_new(PollTimer {
+            napi,
+            tx_napi,
+            running: AtomicBool::new(false),
+            // SAFETY: `dwork` 在下方被初始化
+            dwork: unsafe { DelayedWork::new() },
+        })?;
+        kernel::init_delayed_work_item!(&w);
+        Ok(w.into())
+    }
+
+    /// 开始周期性轮询，在 `open` 中调用
+    pub(crate) fn start(self: &Arc<Self>) {
+        self.running.store(true, Ordering::Relaxed);
+        workqueue::system().enqueue_delayed(self.clone(), POLL_INTERVAL);
+    }
+
+    /// 停止周期性轮询并等待正在运行的工作项结束，在 `stop` 中调用
+    pub(crate) fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+        DelayedWork::cancel::<Self>(self);
+    }
+}
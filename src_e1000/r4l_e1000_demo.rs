@@ -6,29 +6,61 @@
 
 // 导入核心库中的迭代器模块和原子指针模块
 use core::iter::Iterator;
-use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use core::time::Duration;
 
 // 导入内核模块及其相关依赖
 use kernel::pci::Resource;
 use kernel::prelude::*;
 use kernel::sync::Arc;
 use kernel::{pci, device, driver, bindings, net, dma, c_str};
+use kernel::net::genetlink;
+use kernel::task::context;
 use kernel::device::RawDevice;
+use kernel::str::CString;
 use kernel::sync::SpinLock;
+use kernel::net::mii::MiiIf;
 
 // 导入自定义模块
 mod consts;
+mod regs;
 mod hw_defs;
 mod ring_buf;
 mod e1000_ops;
+mod stats;
+mod poll_timer;
+mod selftest;
+mod fault_inject;
+mod aim;
+mod tx_hang;
+mod diag_dev;
+mod genl;
+#[cfg(CONFIG_DEBUG_FS)]
+mod ring_dump;
 
 // 从 hw_defs 模块导入 TxDescEntry 和 RxDescEntry
 use hw_defs::{TxDescEntry, RxDescEntry};
 // 从 ring_buf 模块导入 RxRingBuf 和 TxRingBuf
-use ring_buf::{RxRingBuf, TxRingBuf};
-
-// 从 e1000_ops 模块导入 E1000Ops
-use e1000_ops::E1000Ops;
+use ring_buf::{RingBuf, RxRingBuf, RxState, TxBounce, TxRingBuf, TxBuf};
+
+// 从 e1000_ops 模块导入硬件操作特征及 8254x 的实现
+use e1000_ops::{E1000Hw, E1000Ops};
+// 从 regs 模块导入中断原因
+use regs::Icr;
+// 从 stats 模块导入统计信息相关类型
+use stats::{DrvStats, HwStats, StatsWatchdog};
+// 从 poll_timer 模块导入纯轮询模式使用的定时工作项
+use poll_timer::PollTimer;
+// 从 tx_hang 模块导入发送挂起检测和复位的工作项
+use tx_hang::{ResetTask, TxHangWatchdog};
+// 从 fault_inject 模块导入调试用的错误注入点
+use fault_inject::FaultInject;
+// 从 aim 模块导入自适应中断节流
+use aim::Aim;
+// 从 diag_dev 模块导入诊断用的字符设备
+use diag_dev::DiagDev;
+// 从 genl 模块导入 generic netlink 族及网卡的登记
+use genl::{E1000Genl, GenlAdapter};
 
 // 从 consts 模块导入常量
 use consts::*;
@@ -40,18 +72,82 @@ module! {
     author: "Myrfy001",
     description: "Rust for linux e1000 driver demo",
     license: "GPL",
+    params: {
+        // 在 NAPI 轮询（软中断上下文）中读取，不能持有参数锁，因此只能在加载模块时设置
+        copybreak: u32 {
+            default: 256,
+            permissions: 0,
+            description: "Maximum size of packet that is copied to a new buffer on receive",
+        },
+        rx_buffer_size: u32 {
+            default: 0,
+            permissions: 0,
+            description: "Receive buffer size: 2048, 4096, 8192 or 16384, 0 picks it from the MTU",
+        },
+        tx_bounce_threshold: u32 {
+            default: 256,
+            permissions: 0,
+            description: "Maximum size of packet that is copied to a pre-mapped buffer on transmit, 0 disables",
+        },
+        irq_affinity_cpu: i32 {
+            default: -1,
+            permissions: 0,
+            description: "CPU to steer the interrupt to, -1 hints the CPUs on the NIC's NUMA node",
+        },
+        polling_mode: bool {
+            default: false,
+            permissions: 0,
+            description: "Don't use interrupts, process the rings from a periodic timer",
+        },
+        mac: str {
+            default: b"",
+            permissions: 0,
+            description: "MAC address to use instead of the built-in one, e.g. 52:54:00:aa:bb:cc",
+        },
+        loopback_test: bool {
+            default: false,
+            permissions: 0,
+            description: "Run a loopback self-test when probing the device",
+        },
+        // 错误注入只在打开调试断言的内核中生效，见 `FaultInject`
+        fail_dma_interval: u32 {
+            default: 0,
+            permissions: 0,
+            description: "Debug only: make every Nth TX DMA mapping fail, 0 disables",
+        },
+        fail_rx_alloc_interval: u32 {
+            default: 0,
+            permissions: 0,
+            description: "Debug only: make every Nth RX page or skb allocation fail, 0 disables",
+        },
+    },
 }
 
 /// 该驱动程序的私有数据结构
 struct NetDevicePrvData {
     dev: Arc<device::Device>,  // 设备的引用计数指针
-    napi: Arc<net::Napi>,  // NAPI 结构的引用计数指针
-    e1000_hw_ops: Arc<E1000Ops>,  // e1000 硬件操作的引用计数指针
-    tx_ring: SpinLock<Option<TxRingBuf>>,  // 发送环形缓冲区的自旋锁
-    rx_ring: SpinLock<Option<RxRingBuf>>,  // 接收环形缓冲区的自旋锁
+    napi: Arc<net::Napi>,  // 处理接收队列的 NAPI
+    tx_napi: Arc<net::Napi>,  // 只回收发送描述符的 NAPI，只有发送流量时也能及时回收
+    e1000_hw_ops: Arc<dyn E1000Hw>,  // e1000 硬件操作的引用计数指针，具体实现取决于芯片型号
+    stats_watchdog: Arc<StatsWatchdog>,  // 周期性刷新硬件统计信息的工作项
+    poll_timer: Arc<PollTimer>,  // 纯轮询模式下代替中断调度 NAPI 的工作项
+    tx_hang_watchdog: Arc<TxHangWatchdog>,  // 周期性检查发送单元是否挂起的工作项
+    mac_addr: [u8; 6],  // 网卡使用的 MAC 地址，复位网卡之后需要重新写入
+    sw_stats: net::PerCpuStats,  // 数据通路中无锁累加的每 CPU 收发包统计
+    drv_stats: Arc<DrvStats>,  // 通过 ethtool -S 导出的驱动计数器
+    rx_page_pool: Arc<net::PagePool>,  // 接收缓冲区的 page pool
+    xdp_rxqs: Vec<net::XdpRxqInfo>,  // 每个接收队列的 XDP 信息，内存模型为 rx_page_pool
+    xdp_prog: SpinLock<Option<ARef<net::BpfProg>>>,  // 当前挂载的 XDP 程序
+    rx_buffer_size: u32,  // 接收缓冲区的大小，在 probe 时确定
+    tx_rings: Vec<SpinLock<Option<TxRingBuf>>>,  // 每个发送队列的环形缓冲区，各自使用独立的自旋锁
+    rx_rings: Vec<SpinLock<Option<RxRingBuf>>>,  // 每个接收队列的环形缓冲区，各自使用独立的自旋锁
+    tx_ring_size: AtomicUsize,  // 发送描述符环的大小，可通过 ethtool -G 修改
+    rx_ring_size: AtomicUsize,  // 接收描述符环的大小，可通过 ethtool -G 修改
     irq: u32,  // 中断请求编号
+    aim: Aim,  // 自适应中断节流，可通过 ethtool -C 改为固定的中断间隔
+    fail_dma: FaultInject,  // 发送路径上 DMA 映射的错误注入点
+    fail_rx_alloc: FaultInject,  // 接收路径上分配页或 SKB 的错误注入点
     _irq_handler: AtomicPtr<kernel::irq::Registration<E1000InterruptHandler>>,  // 中断处理程序的原子指针
-    pci_dev: Arc<*mut bindings::pci_dev>, // pci_dev指针
 }
 
 // 声明 NetDevicePrvData 结构体可以安全地在多线程中传递和共享
@@ -65,108 +161,635 @@ impl NetDevice {
 
     /// 分配发送描述符资源。但不需要分配缓冲区内存，因为网络栈会传递一个 SkBuff。
     fn e1000_setup_all_tx_resources(data: &NetDevicePrvData) -> Result<TxRingBuf> {
+        // 发送环形缓冲区的大小
+        let tx_count = data.tx_ring_size.load(Ordering::Relaxed);
 
         // 为发送描述符分配 DMA 内存空间
-        // dma::Allocation 是一个泛型结构体，这里指定了 TxDescEntry 类型
-        // TX_RING_SIZE 是发送环形缓冲区的大小，bindings::GFP_KERNEL 表示分配内存的标志
-        let dma_desc = dma::Allocation::<hw_defs::TxDescEntry>::try_new(&*data.dev, TX_RING_SIZE, bindings::GFP_KERNEL)?;
-
-        // 安全：从原始指针创建可变切片，大小为 TX_RING_SIZE
-        // 所有切片成员的字段将在下面初始化，因此这是安全的
-        let tx_ring = unsafe { core::slice::from_raw_parts_mut(dma_desc.cpu_addr, TX_RING_SIZE) };
-
-        // 初始化发送描述符环形缓冲区中的每个描述符
-        tx_ring.iter_mut().enumerate().for_each(|(idx, desc)| {
-            desc.buf_addr = 0;     // 缓冲区地址，初始为0
-            desc.cmd = 0;          // 命令字段，初始为0
-            desc.length = 0;       // 数据长度，初始为0
-            desc.cso = 0;          // 校验和偏移，初始为0
-            desc.css = 0;          // 校验和起始，初始为0
-            desc.special = 0;      // 特殊字段，初始为0
-            desc.sta = E1000_TXD_STAT_DD as u8;  // 标记所有描述符为已完成状态，使得第一个数据包可以传输
+        // dma::DescRing 是一个泛型结构体，这里的描述符类型是 TxDescEntry
+        // tx_count 是发送环形缓冲区的大小，bindings::GFP_KERNEL 表示分配内存的标志
+        let mut tx_ring = TxRingBuf::new(dma::DescRing::try_new(&*data.dev, tx_count, bindings::GFP_KERNEL)?)?;
+
+        // 为每个描述符分配一个预先映射好的弹跳缓冲区槽位，不超过阈值的小包拷贝到槽位中发送
+        let bounce_size = (*tx_bounce_threshold.read() as usize).min(E1000_TX_BOUNCE_MAX_SIZE);
+        tx_ring.state = TxBounce::try_new(&*data.dev, tx_count, bounce_size)?;
+
+        // 描述符在分配时已经清零，只需要标记所有描述符为已完成状态，使得第一个数据包可以传输
+        tx_ring.desc.as_mut_slice().iter_mut().for_each(|desc| {
+            desc.sta = E1000_TXD_STAT_DD as u8;
         });
 
-        // 创建并返回一个新的 TxRingBuf 实例
-        Ok(TxRingBuf::new(dma_desc, TX_RING_SIZE))
+        // 返回初始化好的发送环形缓冲区
+        Ok(tx_ring)
     }
 
-    /// 分配接收描述符和相应的内存空间。使用 `alloc_skb_ip_align` 分配缓冲区，然后将其映射到 DMA 地址。
+    /// 分配接收描述符和相应的内存空间。接收缓冲区是从 page pool 分配的整页，已经映射好 DMA 地址。
     fn e1000_setup_all_rx_resources(dev: &net::Device, data: &NetDevicePrvData) -> Result<RxRingBuf> {
+        // 接收环形缓冲区的大小
+        let rx_count = data.rx_ring_size.load(Ordering::Relaxed);
 
         // 为接收描述符分配 DMA 内存空间
-        // dma::Allocation 是一个泛型结构体，这里指定了 RxDescEntry 类型
-        // RX_RING_SIZE 是接收环形缓冲区的大小，bindings::GFP_KERNEL 表示分配内存的标志
-        let dma_desc = dma::Allocation::<hw_defs::RxDescEntry>::try_new(&*data.dev, RX_RING_SIZE, bindings::GFP_KERNEL)?;
+        // dma::DescRing 是一个泛型结构体，这里的描述符类型是 RxDescEntry
+        // rx_count 是接收环形缓冲区的大小，bindings::GFP_KERNEL 表示分配内存的标志
+        let mut rx_ring = RxRingBuf::new(dma::DescRing::try_new(&*data.dev, rx_count, bindings::GFP_KERNEL)?)?;
 
-        // 安全：从原始指针创建可变切片，大小为 RX_RING_SIZE
-        // 所有切片成员的字段将在下面初始化，因此这是安全的
-        let rx_ring_desc = unsafe { core::slice::from_raw_parts_mut(dma_desc.cpu_addr, RX_RING_SIZE) };
-
-        // 为接收缓冲区分配 DMA 内存空间
-        // dma::Allocation 是一个泛型结构体，这里指定了 u8 类型
-        // RX_RING_SIZE * RXTX_SINGLE_RING_BLOCK_SIZE 表示分配的总大小
-        let dma_buf = dma::Allocation::<u8>::try_new(&*data.dev, RX_RING_SIZE * RXTX_SINGLE_RING_BLOCK_SIZE, bindings::GFP_KERNEL)?;
+        // 初始化接收描述符环形缓冲区中的每个描述符
+        for (idx, desc) in rx_ring.desc.as_mut_slice().iter_mut().enumerate() {
+            // 从 page pool 分配一个已经映射好 DMA 的页
+            let page = data.rx_page_pool.alloc()?;
 
-        // 创建一个新的 RxRingBuf 实例
-        let mut rx_ring = RxRingBuf::new(dma_desc, RX_RING_SIZE);
+            // 设置缓冲区地址，跳过预留的头部空间。其余字段在分配时已经清零
+            desc.buf_addr = page.dma_addr() + E1000_RX_HEADROOM as u64;
 
-        // 初始化接收描述符环形缓冲区中的每个描述符
-        rx_ring_desc.iter_mut().enumerate().for_each(|(idx, desc)| {
-            // 分配一个新的 SkBuff，大小为 RXTX_SINGLE_RING_BLOCK_SIZE
-            let skb = dev.alloc_skb_ip_align(RXTX_SINGLE_RING_BLOCK_SIZE as u32).unwrap();
-            // 将 SkBuff 映射到 DMA 地址
-            let dma_map = dma::MapSingle::try_new(&*data.dev, skb.head_data().as_ptr() as *mut u8, RXTX_SINGLE_RING_BLOCK_SIZE, bindings::dma_data_direction_DMA_FROM_DEVICE).unwrap();
-
-            // 初始化描述符字段
-            desc.buf_addr = dma_map.dma_handle as u64;  // 设置缓冲区地址为 DMA 映射的地址
-            desc.length = 0;       // 数据长度，初始为0
-            desc.special = 0;      // 特殊字段，初始为0
-            desc.checksum = 0;     // 校验和，初始为0
-            desc.status = 0;       // 状态，初始为0
-            desc.errors = 0;       // 错误，初始为0
-
-            // 将 DMA 映射和 SkBuff 存储在接收环形缓冲区中
-            rx_ring.buf.borrow_mut()[idx] = Some((dma_map, skb));
-        });
+            // 将页存储在接收环形缓冲区中
+            rx_ring.buf[idx] = Some(page);
+        }
 
         // 返回初始化好的接收环形缓冲区
         Ok(rx_ring)
     }
 
-    // 对应于 C 版本的 e1000_clean_tx_irq()，用于回收发送队列中的描述符
-    fn e1000_recycle_tx_queue(dev: &net::Device, data: &NetDevicePrvData) {
-        // 读取发送队列尾部指针
-        let tdt = data.e1000_hw_ops.e1000_read_tx_queue_tail();
+    /// 为所有队列分配收发环形缓冲区，任何一个分配失败时，已经分配的缓冲区随之释放
+    fn e1000_setup_all_rings(dev: &net::Device, data: &NetDevicePrvData) -> Result<(Vec<RxRingBuf>, Vec<TxRingBuf>)> {
+        // 分配描述符环和缓冲区时可能睡眠
+        debug_assert!(context::may_sleep());
+        let mut rx_rings = Vec::try_with_capacity(E1000_NUM_RX_QUEUES)?;
+        for _ in 0..E1000_NUM_RX_QUEUES {
+            rx_rings.try_push(Self::e1000_setup_all_rx_resources(dev, data)?)?;
+        }
+        let mut tx_rings = Vec::try_with_capacity(E1000_NUM_TX_QUEUES)?;
+        for _ in 0..E1000_NUM_TX_QUEUES {
+            tx_rings.try_push(Self::e1000_setup_all_tx_resources(data)?)?;
+        }
+        Ok((rx_rings, tx_rings))
+    }
+
+    /// 把新的环形缓冲区放入各个队列的锁中，旧的环形缓冲区（如果有）随之释放。
+    /// 旧环在 let 语句结束、锁释放之后才析构，dma_free_coherent 不能在关中断时调用
+    fn e1000_install_rings(dev: &net::Device, data: &NetDevicePrvData, rx_rings: Vec<RxRingBuf>, tx_rings: Vec<TxRingBuf>) {
+        debug_assert!(context::may_sleep());
+        for (lock, ring) in data.rx_rings.iter().zip(rx_rings) {
+            let _old = lock.lock_irqdisable().replace(ring);
+        }
+        for (qid, (lock, ring)) in data.tx_rings.iter().zip(tx_rings).enumerate() {
+            let _old = lock.lock_irqdisable().replace(ring);
+            // 旧环中还没有回收的 skb 不会再报告完成，BQL 的状态需要从头开始
+            dev.tx_reset_queue(qid as u16);
+        }
+    }
+
+    /// 释放所有队列的环形缓冲区，对应于 C 版本的 `e1000_free_all_rx_resources` 和
+    /// `e1000_free_all_tx_resources`。调用前必须停止硬件和 NAPI
+    fn e1000_free_rings(dev: &net::Device, data: &NetDevicePrvData) {
+        // dma_free_coherent 不能在中断上下文或关中断时调用。和 e1000_install_rings 一样，
+        // 先在锁内取出，锁释放之后再析构
+        debug_assert!(context::may_sleep());
+        for lock in data.rx_rings.iter() {
+            let _ring = lock.lock_irqdisable().take();
+        }
+        for (qid, lock) in data.tx_rings.iter().enumerate() {
+            let _ring = lock.lock_irqdisable().take();
+            dev.tx_reset_queue(qid as u16);
+        }
+    }
+
+    /// 注册中断处理程序，对应于 C 版本的 `e1000_request_irq`
+    fn e1000_request_irq(data: &NetDevicePrvData) -> Result {
+        // 创建 IRQ 处理程序的私有数据
+        let irq_prv_data = Box::try_new(IrqPrivateData{
+            dev: Arc::clone(&data.dev),
+            e1000_hw_ops: Arc::clone(&data.e1000_hw_ops),
+            napi: Arc::clone(&data.napi),
+            tx_napi: Arc::clone(&data.tx_napi),
+            drv_stats: Arc::clone(&data.drv_stats),
+        })?;
+
+        // 创建 IRQ 注册对象。irq::Registration 在 Drop 时释放中断，
+        // 因此把它放到堆上，由 `e1000_free_irq` 负责释放
+        let req_reg = kernel::irq::Registration::<E1000InterruptHandler>::try_new(
+            data.irq,
+            irq_prv_data,
+            kernel::irq::flags::SHARED,
+            fmt!("{}", data.dev.name())
+        )?;
+
+        // 按模块参数把中断绑定到指定的 CPU，同时作为提示提供给 irqbalance。
+        // 没有指定时提示使用网卡所在 NUMA 节点上的 CPU，NAPI 在这些 CPU 上运行时
+        // 访问的描述符环和接收页都是本地内存
+        if let Ok(cpu) = u32::try_from(*irq_affinity_cpu.read()) {
+            let mask = kernel::irq::CpuMask::of(cpu).ok_or(EINVAL)?;
+            req_reg.set_affinity_hint(Some(mask))?;
+        } else if let Some(mask) = kernel::irq::CpuMask::of_node(data.dev.numa_node()) {
+            req_reg.set_affinity_hint(Some(mask))?;
+        }
+
+        let old = data._irq_handler.swap(Box::into_raw(Box::try_new(req_reg)?), Ordering::AcqRel);
+        debug_assert!(old.is_null());
+        Ok(())
+    }
+
+    /// 注销中断处理程序，对应于 C 版本的 `e1000_free_irq`。返回时中断处理程序已经执行完毕，
+    /// 没有注册时什么也不做
+    fn e1000_free_irq(data: &NetDevicePrvData) {
+        let irq_handler_ptr = data._irq_handler.swap(core::ptr::null_mut(), Ordering::AcqRel);
+        if !irq_handler_ptr.is_null() {
+            // SAFETY: 指针由 `e1000_request_irq` 中的 `Box::into_raw` 得到，并且已经从
+            // `_irq_handler` 中取出，不会被释放两次
+            drop(unsafe { Box::from_raw(irq_handler_ptr) });
+        }
+    }
+
+    /// 发送单元挂起之后复位网卡，由 `ResetTask` 在进程上下文中调用，对应于 C 版本的 `e1000_reset_task`
+    fn e1000_reset_task(dev: &net::Device) {
+        // 与 open、stop 以及 ethtool 的配置互斥
+        let _rtnl = net::RtnlGuard::lock();
+
+        // 接口已经关闭时，stop 已经停止了硬件并释放了环形缓冲区，不需要再复位
+        if !dev.netif_running() {
+            return;
+        }
+
+        // SAFETY: 复位工作项在网络设备注销之后、释放之前取消，见 `E1000DrvPrvData` 的 Drop 实现
+        let data = unsafe { dev.drvdata::<NetDevice>() };
+
+        Self::e1000_dump_tx_hang(dev, data);
+        match Self::e1000_reinit(dev, data) {
+            Ok(()) => netdev_info!(dev, "adapter reset after Tx hang\n"),
+            Err(e) => netdev_err!(dev, "failed to reset the adapter: {:?}\n", e),
+        }
+    }
+
+    /// 用户空间通过诊断设备或 generic netlink 请求复位网卡。步骤与 `e1000_reset_task` 相同，
+    /// 但接口关闭时返回 ENETDOWN，并把复位的错误返回给调用者
+    fn e1000_user_reset(data: &NetDevicePrvData) -> Result {
+        let dev = data.napi.dev_get();
+        let _rtnl = net::RtnlGuard::lock();
+        // 接口关闭时硬件已经停止，环形缓冲区也已经释放
+        if !dev.netif_running() {
+            return Err(ENETDOWN);
+        }
+        Self::e1000_reinit(&dev, data)?;
+        netdev_info!(dev, "adapter reset requested from userspace\n");
+        Ok(())
+    }
+
+    // 打印各发送队列的状态以及下一个要清理的描述符，对应于 C 版本 Tx Unit Hang 报告中的内容
+    fn e1000_dump_tx_hang(dev: &net::Device, data: &NetDevicePrvData) {
+        for qid in 0..E1000_NUM_TX_QUEUES {
+            let tdh = data.e1000_hw_ops.e1000_read_tx_queue_head(qid);
+            let tdt = data.e1000_hw_ops.e1000_read_tx_queue_tail(qid);
+            let tx_ring = data.tx_rings[qid].lock_irqdisable();
+            if let Some(tx_ring) = tx_ring.as_ref() {
+                let ntc = tx_ring.desc.next_to_clean();
+                let desc = &tx_ring.desc.as_slice()[ntc];
+                netdev_err!(dev, "Tx queue {}: TDH <{}> TDT <{}> next_to_use <{}> next_to_clean <{}>\n",
+                    qid, tdh, tdt, tx_ring.desc.next_to_use(), ntc);
+                netdev_err!(dev, "  desc[{}]: buf_addr <{:#x}> length <{}> cmd <{:#x}> sta <{:#x}> buffer <{}>\n",
+                    ntc, desc.buf_addr, desc.length, desc.cmd, desc.sta, tx_ring.buf[ntc].is_some());
+            }
+        }
+    }
+
+    /// 复位硬件并换上新的环形缓冲区，对应于 C 版本的 `e1000_reinit_locked`，调用者持有 RTNL 锁。
+    /// 中断处理程序保持注册，旧环中没有发送完成的包随旧环一起释放
+    fn e1000_reinit(dev: &net::Device, data: &NetDevicePrvData) -> Result {
+        // 先分配新的环形缓冲区，失败时网卡保持原来的状态
+        let (rx_rings, tx_rings) = Self::e1000_setup_all_rings(dev, data)?;
+
+        // 停止数据通路，对应于 C 版本的 `e1000_down`
+        dev.netif_carrier_off();
+        dev.netif_tx_stop_all_queues();
+        data.napi.disable();
+        data.tx_napi.disable();
+        data.e1000_hw_ops.e1000_irq_disable();
+
+        // 复位 MAC。复位会从 EEPROM 重新加载接收地址，因此需要重新写入 MAC 地址
+        let ret = data.e1000_hw_ops.e1000_reset_hw()
+            .and_then(|_| data.e1000_hw_ops.e1000_write_mac_addr(&data.mac_addr))
+            .and_then(|_| data.e1000_hw_ops.e1000_configure(
+                &mut rx_rings.iter().map(RingBuf::layout),
+                &mut tx_rings.iter().map(RingBuf::layout),
+            ))
+            .and_then(|_| Self::e1000_apply_features(data, dev.features()))
+            .and_then(|_| data.aim.program(&*data.e1000_hw_ops));
+
+        // 硬件已经复位，不会再访问旧的环形缓冲区，无论配置是否成功都可以替换
+        Self::e1000_install_rings(dev, data, rx_rings, tx_rings);
+        if *polling_mode.read() {
+            data.e1000_hw_ops.e1000_irq_disable();
+        }
+
+        // NAPI 必须重新启用，否则之后的 stop 会一直等待。配置失败时发送队列保持停止
+        data.napi.enable();
+        data.tx_napi.enable();
+        ret?;
+
+        dev.netif_tx_wake_all_queues();
+        HwStats::add(&data.drv_stats.restart_queue, 1);
+        MiiIf::new(&*data.e1000_hw_ops, E1000_PHY_ADDR, true).check_link(dev)?;
+        Ok(())
+    }
+
+    // 对应于 C 版本的 e1000_clean_rx_irq()，处理接收队列 `qid` 中所有已完成的描述符
+    fn e1000_clean_rx_queue(dev: &net::Device, data: &NetDevicePrvData, qid: usize, xdp_prog: Option<&net::BpfProg>) {
+        // 读取接收队列的尾部索引，并更新为下一个索引
+        let mut rdt = data.e1000_hw_ops.e1000_read_rx_queue_tail(qid) as usize;
+
+        // 锁定接收环形缓冲区
+        let mut rx_ring_guard = data.rx_rings[qid].lock();
+        let rx_ring = rx_ring_guard.as_mut().unwrap();
+        let rx_count = rx_ring.len();
+        rdt = dma::ring_next(rdt, rx_count);
+
+        // 获取接收描述符数组
+        let mut descs = rx_ring.desc.as_mut_slice();
+
+        // 小于这个长度的包拷贝到新的 SKB 中
+        let copybreak = *copybreak.read();
+
+        // 遍历所有待处理的接收描述符
+        while descs[rdt].status & E1000_RXD_STAT_DD as u8 != 0 {
+            // 获取数据包长度
+            let packet_len = descs[rdt].length as u32;
+            let eop = descs[rdt].status as u32 & E1000_RXD_STAT_EOP != 0;
+
+            // 错误位只在帧的最后一个描述符中有效。出错的帧连同已经收到的部分一起丢弃，
+            // 页留在接收环中继续使用
+            if eop && descs[rdt].errors as u32 & E1000_RXD_ERR_FRAME_ERR_MASK != 0 {
+                Self::e1000_rx_error(data, descs[rdt].errors as u32);
+                rx_ring.state.skb_top = None;
+                rx_ring.state.discarding = false;
+                descs[rdt].status = 0;
+                data.e1000_hw_ops.e1000_write_rx_queue_tail(qid, rdt as u32);
+                rdt = dma::ring_next(rdt, rx_count);
+                continue;
+            }
+
+            // 统计硬件的校验和检查结果，检查通过的包不需要协议栈再次计算校验和。
+            // 校验和只在帧的最后一个描述符中有效
+            let csum_ok = eop && Self::e1000_rx_checksum(data, descs[rdt].status as u32, descs[rdt].errors as u32);
+
+            // 帧跨多个描述符时，把每个缓冲区挂到帧的 SKB 上，收到 EOP 后再交给协议栈
+            if !eop || rx_ring.state.skb_top.is_some() || rx_ring.state.discarding {
+                Self::e1000_rx_chain(
+                    dev,
+                    data,
+                    qid,
+                    xdp_prog.is_some(),
+                    &mut rx_ring.state,
+                    &mut rx_ring.buf[rdt],
+                    &mut descs[rdt],
+                    packet_len,
+                    eop,
+                    csum_ok,
+                );
+                descs[rdt].status = 0;
+                data.e1000_hw_ops.e1000_write_rx_queue_tail(qid, rdt as u32);
+                rdt = dma::ring_next(rdt, rx_count);
+                continue;
+            }
+
+            // 小包直接拷贝出来，页留在接收环中继续使用，不需要分配新的页。XDP 程序需要在页上运行，
+            // 挂载了 XDP 程序时不做拷贝
+            if xdp_prog.is_none() && packet_len < copybreak {
+                Self::e1000_rx_copybreak(dev, data, qid, rx_ring.buf[rdt].as_ref().unwrap(), packet_len, csum_ok);
+                descs[rdt].status = 0;
+                data.e1000_hw_ops.e1000_write_rx_queue_tail(qid, rdt as u32);
+                rdt = dma::ring_next(rdt, rx_count);
+                continue;
+            }
+
+            // 先为这个描述符分配新的页，分配失败时保留旧的页并丢弃这个包，避免接收环出现空洞
+            let new_page = match Self::e1000_alloc_rx_page(data) {
+                Ok(page) => page,
+                Err(_) => {
+                    HwStats::add(&data.drv_stats.alloc_failures, 1);
+                    descs[rdt].status = 0;
+                    data.e1000_hw_ops.e1000_write_rx_queue_tail(qid, rdt as u32);
+                    rdt = dma::ring_next(rdt, rx_count);
+                    continue;
+                }
+            };
+
+            // 取出接收到数据的页，并换上新的页
+            descs[rdt].buf_addr = new_page.dma_addr() + E1000_RX_HEADROOM as u64;
+            let page = rx_ring.buf[rdt].replace(new_page).unwrap();
+
+            Self::e1000_rx_page(dev, data, qid, xdp_prog, page, packet_len, csum_ok);
+
+            // 清除当前描述符的状态，并更新接收队列的尾部索引
+            descs[rdt].status = 0;
+            data.e1000_hw_ops.e1000_write_rx_queue_tail(qid, rdt as u32);
+            rdt = dma::ring_next(rdt, rx_count);
+        }
+    }
+
+    // 对应于 C 版本的 e1000_clean_tx_irq()，用于回收发送队列 `qid` 中的描述符
+    fn e1000_recycle_tx_queue(dev: &net::Device, data: &NetDevicePrvData, qid: usize) {
         // 读取发送队列头部指针
-        let tdh = data.e1000_hw_ops.e1000_read_tx_queue_head();
+        let tdh = data.e1000_hw_ops.e1000_read_tx_queue_head(qid);
 
         // 获取发送环形缓冲区的锁并禁用中断
-        let mut tx_ring = data.tx_ring.lock_irqdisable();
+        let mut tx_guard = data.tx_rings[qid].lock_irqdisable();
         // 确保发送环形缓冲区存在
-        let mut tx_ring = tx_ring.as_mut().unwrap();
+        let mut tx_ring = tx_guard.as_mut().unwrap();
 
         // 获取发送描述符的切片
-        let descs = tx_ring.desc.as_desc_slice();
+        let tx_count = tx_ring.len();
+        let mut idx = tx_ring.desc.next_to_clean();
+        let descs = tx_ring.desc.as_mut_slice();
+
+        // 回收的包数和字节数先在本地累加，只有协议栈发送的包计入 BQL
+        let (mut packets, mut bytes) = (0, 0);
+        let (mut bql_packets, mut bql_bytes) = (0, 0);
 
-        // 获取下一个要清理的描述符索引
-        let mut idx = tx_ring.next_to_clean;
         // 循环遍历发送描述符，回收已完成的描述符
         while descs[idx].sta & E1000_TXD_STAT_DD as u8 != 0 && idx != tdh as usize {
-            // 取出并丢弃 DMA 映射和 SkBuff
-            let (dm, skb) = tx_ring.buf.borrow_mut()[idx].take().unwrap();
-            // 更新已完成队列的统计信息
-            dev.completed_queue(1, skb.len());
-            // 消耗 napi
-            skb.napi_consume(64);
-            drop(dm);  // 释放 DMA 映射
-            drop(skb);  // 释放 SkBuff
+            let len = descs[idx].length as u32;
+            match tx_ring.buf[idx].take().unwrap() {
+                TxBuf::Skb((dm, skb)) => {
+                    bql_packets += 1;
+                    bql_bytes += skb.len();
+                    // 消耗 napi
+                    skb.napi_consume(64);
+                    drop(dm);  // 释放 DMA 映射
+                    drop(skb);  // 释放 SkBuff
+                }
+                // 弹跳缓冲区中的包在发送时已经释放了 skb，长度从描述符中读取
+                TxBuf::Bounce => {
+                    bql_packets += 1;
+                    bql_bytes += len;
+                }
+                // XDP 发送的页回收到 page pool，重定向过来的帧归还给它的内存分配器
+                TxBuf::XdpPage(_) | TxBuf::XdpFrame(..) => {}
+            }
+            packets += 1;
+            bytes += len as u64;
 
             // 更新索引
-            idx = (idx + 1) % TX_RING_SIZE;
+            idx = dma::ring_next(idx, tx_count);
         }
 
         // 更新环形缓冲区的下一个清理索引
-        tx_ring.next_to_clean = idx;
+        tx_ring.desc.set_next_to_clean(idx);
+        // 在持有锁时检查空闲描述符，start_xmit 停止队列也在锁内进行，不会错过唤醒
+        let wake = tx_ring.desc.unused() > E1000_XDP_TX_RESERVED;
+        drop(tx_guard);
+
+        // 每 CPU 的计数器不能在关闭中断时更新，在释放发送环的锁之后再统计
+        if packets != 0 {
+            dev.tx_completed_queue(qid as u16, bql_packets, bql_bytes);
+            data.sw_stats.tx_add(packets, bytes);
+            data.drv_stats.tx_add(qid, packets, bytes);
+        }
+
+        // 发送环有了空闲的描述符，唤醒因为环满而被 start_xmit 停止的发送队列
+        if wake && dev.netif_subqueue_stopped(qid as u16) {
+            dev.netif_wake_subqueue(qid as u16);
+        }
+    }
+
+    /// 把一个 XDP 缓冲区放入发送队列 `qid`。XDP 可以使用为它保留的描述符，发送环满时返回 EBUSY，
+    /// `buf` 随之被释放。
+    fn e1000_xdp_tx(data: &NetDevicePrvData, qid: usize, dma_addr: u64, len: u32, buf: TxBuf) -> Result {
+        let mut tx_ring = data.tx_rings[qid].lock_irqdisable();
+        let tx_ring = tx_ring.as_mut().ok_or(ENETDOWN)?;
+        // 发送环中保留一个描述符用于区分环满和环空
+        if tx_ring.desc.unused() == 0 {
+            return Err(EBUSY);
+        }
+        Self::e1000_put_xdp_desc(tx_ring, dma_addr, len, buf);
+        data.e1000_hw_ops.e1000_write_tx_queue_tail(qid, tx_ring.desc.next_to_use() as u32);
+        Ok(())
+    }
+
+    /// 把一个 XDP 帧填入发送环的下一个描述符，调用者持有发送环的锁并确认还有空闲的描述符，之后负责更新 TDT
+    fn e1000_put_xdp_desc(tx_ring: &mut TxRingBuf, dma_addr: u64, len: u32, buf: TxBuf) {
+        let tdt = tx_ring.desc.advance_use();
+
+        let tx_desc = &mut tx_ring.desc.as_mut_slice()[tdt];
+        tx_desc.buf_addr = dma_addr;
+        tx_desc.length = len as u16;
+        tx_desc.cmd = ((E1000_TXD_CMD_RS | E1000_TXD_CMD_EOP) >> 24) as u8;
+        tx_desc.sta = 0;
+        tx_ring.buf[tdt].replace(buf);
+    }
+
+    /// 处理跨多个描述符的帧中的一个缓冲区，对应于 C 版本 `e1000_clean_jumbo_rx_irq` 中的分片处理。
+    ///
+    /// 第一个缓冲区构建 SKB，之后的缓冲区作为分片挂到这个 SKB 上，收到 EOP 后整个帧交给协议栈。
+    /// XDP 不支持多缓冲区的帧，分配失败或者分片过多时也无法继续组装，这些情况下丢弃帧剩下的描述符，
+    /// 对应的页留在接收环中继续使用。
+    #[allow(clippy::too_many_arguments)]
+    fn e1000_rx_chain(
+        dev: &net::Device,
+        data: &NetDevicePrvData,
+        qid: usize,
+        xdp: bool,
+        state: &mut RxState,
+        slot: &mut Option<net::PoolPage>,
+        desc: &mut RxDescEntry,
+        packet_len: u32,
+        eop: bool,
+        csum_ok: bool,
+    ) {
+        if xdp && !state.discarding {
+            HwStats::add(&data.drv_stats.rx_multi_buf_dropped, 1);
+            state.skb_top = None;
+            state.discarding = true;
+        }
+        if state.discarding {
+            state.discarding = !eop;
+            return;
+        }
+
+        // 先为这个描述符分配新的页，分配失败时丢弃整个帧
+        let new_page = match Self::e1000_alloc_rx_page(data) {
+            Ok(page) => page,
+            Err(_) => {
+                HwStats::add(&data.drv_stats.alloc_failures, 1);
+                state.skb_top = None;
+                state.discarding = !eop;
+                return;
+            }
+        };
+        desc.buf_addr = new_page.dma_addr() + E1000_RX_HEADROOM as u64;
+        let page = slot.replace(new_page).unwrap();
+        page.sync_for_cpu(E1000_RX_HEADROOM, packet_len);
+
+        let res = if let Some(skb) = &state.skb_top {
+            page.add_to_skb(skb, E1000_RX_HEADROOM, packet_len)
+        } else {
+            page.build_skb(E1000_RX_HEADROOM, packet_len).map(|skb| state.skb_top = Some(skb))
+        };
+        if res.is_err() {
+            HwStats::add(&data.drv_stats.rx_multi_buf_dropped, 1);
+            state.skb_top = None;
+            state.discarding = !eop;
+            return;
+        }
+
+        if !eop {
+            return;
+        }
+        if let Some(skb) = state.skb_top.take() {
+            let protocol = skb.eth_type_trans(dev);
+            skb.protocol_set(protocol);
+            if csum_ok {
+                skb.checksum_unnecessary();
+            }
+
+            // 将 SKB 交给 NAPI 进行处理，napi_gro_receive 接管了 SKB 的所有权
+            data.sw_stats.rx_add(1, skb.len() as u64);
+            data.drv_stats.rx_add(qid, 1, skb.len() as u64);
+            data.napi.gro_receive(&skb);
+            core::mem::forget(skb);
+        }
+    }
+
+    /// 为要发送的缓冲区建立 DMA 映射，可以通过 `fail_dma_interval` 参数注入映射失败
+    fn e1000_map_tx(data: &NetDevicePrvData, ptr: *mut u8, len: usize) -> Result<dma::MapSingle<u8>> {
+        if data.fail_dma.should_fail(*fail_dma_interval.read()) {
+            return Err(ENOMEM);
+        }
+        dma::MapSingle::try_new(&*data.dev, ptr, len, bindings::dma_data_direction_DMA_TO_DEVICE)
+    }
+
+    /// 为接收环分配一个新的页，可以通过 `fail_rx_alloc_interval` 参数注入分配失败
+    fn e1000_alloc_rx_page(data: &NetDevicePrvData) -> Result<net::PoolPage> {
+        if data.fail_rx_alloc.should_fail(*fail_rx_alloc_interval.read()) {
+            return Err(ENOMEM);
+        }
+        data.rx_page_pool.alloc()
+    }
+
+    /// 丢弃一个无法发送的 skb。返回 Ok 之后 skb 归驱动所有，因此需要在这里释放
+    fn e1000_xmit_drop(skb: &net::SkBuff, data: &NetDevicePrvData) -> net::NetdevTx {
+        HwStats::add(&data.drv_stats.tx_dropped, 1);
+        skb.free_any();
+        net::NetdevTx::Ok
+    }
+
+    /// 把一个小包拷贝到新分配的 SKB 中交给协议栈，对应于 C 版本的 `e1000_copybreak`。
+    /// 页仍然属于接收环，分配失败时丢弃这个包。
+    fn e1000_rx_copybreak(
+        dev: &net::Device,
+        data: &NetDevicePrvData,
+        qid: usize,
+        page: &net::PoolPage,
+        packet_len: u32,
+        csum_ok: bool,
+    ) {
+        page.sync_for_cpu(E1000_RX_HEADROOM, packet_len);
+
+        let skb = if data.fail_rx_alloc.should_fail(*fail_rx_alloc_interval.read()) {
+            Err(ENOMEM)
+        } else {
+            data.napi.alloc_skb(packet_len)
+        };
+        if let Ok(skb) = skb {
+            let start = E1000_RX_HEADROOM as usize;
+            skb.put(packet_len).copy_from_slice(&page.data()[start..start + packet_len as usize]);
+
+            // 识别协议类型并设置到 SKB 中
+            let protocol = skb.eth_type_trans(dev);
+            skb.protocol_set(protocol);
+            if csum_ok {
+                skb.checksum_unnecessary();
+            }
+
+            // 将 SKB 交给 NAPI 进行处理，napi_gro_receive 接管了 SKB 的所有权
+            data.sw_stats.rx_add(1, packet_len as u64);
+            data.drv_stats.rx_add(qid, 1, packet_len as u64);
+            data.napi.gro_receive(&skb);
+            core::mem::forget(skb);
+        } else {
+            HwStats::add(&data.drv_stats.alloc_failures, 1);
+        }
+
+        // 页会被网卡再次写入，把它交还给设备
+        page.sync_for_device(E1000_RX_HEADROOM, packet_len);
+    }
+
+    /// 统计一个因为错误而丢弃的帧
+    fn e1000_rx_error(data: &NetDevicePrvData, errors: u32) {
+        HwStats::add(&data.drv_stats.rx_errors, 1);
+        if errors & E1000_RXD_ERR_CE != 0 {
+            HwStats::add(&data.drv_stats.rx_crc_errors, 1);
+        }
+    }
+
+    /// 根据接收描述符的状态和错误位统计硬件校验和的检查结果，对应于 C 版本的 `e1000_rx_checksum`。
+    /// 只有硬件检查过 TCP/UDP 校验和并且没有错误时返回 true。关闭 NETIF_F_RXCSUM 后硬件不再检查。
+    fn e1000_rx_checksum(data: &NetDevicePrvData, status: u32, errors: u32) -> bool {
+        if status & E1000_RXD_STAT_IXSM != 0 || status & (E1000_RXD_STAT_IPCS | E1000_RXD_STAT_TCPCS) == 0 {
+            return false;
+        }
+        if errors & (E1000_RXD_ERR_IPE | E1000_RXD_ERR_TCPE) != 0 {
+            data.drv_stats.path.update(|s| s.rx_csum_bad += 1);
+            return false;
+        }
+        data.drv_stats.path.update(|s| s.rx_csum_good += 1);
+        status & E1000_RXD_STAT_TCPCS != 0
+    }
+
+    /// 按网络设备的功能配置接收单元，在配置收发队列之后以及功能改变时调用
+    fn e1000_apply_features(data: &NetDevicePrvData, features: net::Features) -> Result {
+        data.e1000_hw_ops.e1000_set_rx_features(
+            features.contains(net::Features::RXCSUM),
+            features.contains(net::Features::RXFCS),
+        )
+    }
+
+    /// 处理一个接收到的包：如果挂载了 XDP 程序就先运行它，再根据结果构建 SKB 交给协议栈、
+    /// 原路发回或者丢弃。
+    fn e1000_rx_page(
+        dev: &net::Device,
+        data: &NetDevicePrvData,
+        qid: usize,
+        xdp_prog: Option<&net::BpfProg>,
+        page: net::PoolPage,
+        packet_len: u32,
+        csum_ok: bool,
+    ) {
+        // 页在整个生命周期内保持映射，这里只需要把收到的数据同步给 CPU
+        page.sync_for_cpu(E1000_RX_HEADROOM, packet_len);
+
+        let (headroom, len) = match xdp_prog {
+            None => (E1000_RX_HEADROOM, packet_len),
+            Some(prog) => {
+                let mut xdp = net::XdpBuff::new(&data.xdp_rxqs[qid], &page, E1000_RX_HEADROOM, packet_len);
+                match prog.run_xdp(&mut xdp) {
+                    // XDP 程序可能调整了包的头部和长度
+                    net::XdpAction::Pass => (xdp.headroom(), xdp.len()),
+                    net::XdpAction::Tx => {
+                        page.sync_for_device(xdp.headroom(), xdp.len());
+                        let dma_addr = page.dma_addr() + xdp.headroom() as u64;
+                        // 从与接收队列对应的发送队列发回，发送环满时页被释放，回收到 page pool
+                        let txq = qid % E1000_NUM_TX_QUEUES;
+                        let _ = Self::e1000_xdp_tx(data, txq, dma_addr, xdp.len(), TxBuf::XdpPage(page));
+                        return;
+                    }
+                    // 不支持 REDIRECT，和 DROP、ABORTED 一样丢弃，页回收到 page pool
+                    _ => return,
+                }
+            }
+        };
+
+        // 直接在页上构建 SKB，协议栈释放 SKB 时页会回收到 page pool 中
+        if let Ok(skb) = page.build_skb(headroom, len) {
+            // 识别协议类型并设置到 SKB 中
+            let protocol = skb.eth_type_trans(dev);
+            skb.protocol_set(protocol);
+            if csum_ok {
+                skb.checksum_unnecessary();
+            }
+
+            // 将 SKB 交给 NAPI 进行处理，napi_gro_receive 接管了 SKB 的所有权
+            data.sw_stats.rx_add(1, len as u64);
+            data.drv_stats.rx_add(qid, 1, len as u64);
+            data.napi.gro_receive(&skb);
+            core::mem::forget(skb);
+        }
     }
 }
 
@@ -177,145 +800,438 @@ impl net::DeviceOperations for NetDevice {
 
     /// 当你在 shell 中输入 ip link set eth0 up 时，这个方法会被调用。
     fn open(dev: &net::Device, data: &NetDevicePrvData) -> Result {
-        pr_info!("Rust for linux e1000 driver demo (net device open)\n");
+        netdev_info!(dev, "Rust for linux e1000 driver demo (net device open)\n");
 
         // 关闭网络接口的 carrier
         dev.netif_carrier_off();
 
-        // 初始化用于传输（TX）和接收（RX）的 DMA 内存
-        let tx_ringbuf = Self::e1000_setup_all_tx_resources(data)?;
-        let rx_ringbuf = Self::e1000_setup_all_rx_resources(dev, data)?;
+        // 初始化所有队列用于传输（TX）和接收（RX）的 DMA 内存
+        let (rx_rings, tx_rings) = Self::e1000_setup_all_rings(dev, data)?;
 
         // TODO: e1000_power_up_phy() 方法尚未实现。此方法用于在 PHY 可能处于关闭状态时进行电源恢复，
         // 但在这个最小可行产品（MVP）驱动程序中不支持该功能。
 
         // 修改 e1000 硬件寄存器，向网卡提供 RX/TX 队列信息
-        data.e1000_hw_ops.e1000_configure(&rx_ringbuf, &tx_ringbuf)?;
-
-        // 将接收（RX）和传输（TX）队列的锁定状态存储到数据结构中
-        *data.rx_ring.lock_irqdisable() = Some(rx_ringbuf);
-        *data.tx_ring.lock_irqdisable() = Some(tx_ringbuf);
-
-        // 创建 IRQ 处理程序的私有数据
-        let irq_prv_data = Box::try_new(IrqPrivateData{
-            e1000_hw_ops: Arc::clone(&data.e1000_hw_ops),
-            napi: Arc::clone(&data.napi),
-        })?;
+        let ret = data.e1000_hw_ops.e1000_configure(
+            &mut rx_rings.iter().map(RingBuf::layout),
+            &mut tx_rings.iter().map(RingBuf::layout),
+        )
+            .and_then(|_| Self::e1000_apply_features(data, dev.features()))
+            .and_then(|_| data.aim.program(&*data.e1000_hw_ops));
+
+        // 将接收（RX）和传输（TX）队列存储到各自的锁中。配置失败时网卡可能已经开始使用它们，
+        // 也要先放入锁中，由下面的出错处理在停止硬件之后释放
+        Self::e1000_install_rings(dev, data, rx_rings, tx_rings);
+
+        // 纯轮询模式不注册中断处理程序，网卡中断保持屏蔽，由定时工作项调度 NAPI
+        let ret = ret.and_then(|_| if *polling_mode.read() {
+            data.e1000_hw_ops.e1000_irq_disable();
+            Ok(())
+        } else {
+            Self::e1000_request_irq(data)
+        });
 
-        // 创建 IRQ 注册对象。注意 irq::Registration 包含一个实现了 Drop trait 的 irq::InternalRegistration，
-        // 因此我们必须确保它不会被释放。
-        // TODO: 目前存在内存泄漏问题。
-        let req_reg = kernel::irq::Registration::<E1000InterruptHandler>::try_new(
-            data.irq,
-            irq_prv_data,
-            kernel::irq::flags::SHARED,
-            fmt!("{}", data.dev.name())
-        )?;
+        // 根据 PHY 的链路状态设置 carrier，而不是无条件地认为链路已连接
+        let ret = ret.and_then(|_| {
+            MiiIf::new(&*data.e1000_hw_ops, E1000_PHY_ADDR, true).check_link(dev)?;
+            Ok(())
+        });
 
-        data._irq_handler.store(Box::into_raw(Box::try_new(req_reg)?), core::sync::atomic::Ordering::Relaxed);
+        // open 失败时协议栈不会调用 stop，这里撤销上面的操作。硬件停止访问环形缓冲区之后才能释放它们
+        if let Err(e) = ret {
+            dev.netif_carrier_off();
+            data.e1000_hw_ops.e1000_irq_disable();
+            Self::e1000_free_irq(data);
+            if data.e1000_hw_ops.e1000_stop_rxtx().is_ok() {
+                Self::e1000_free_rings(dev, data);
+            }
+            return Err(e);
+        }
 
         // 启用 NAPI（New API）以处理网络中断
         data.napi.enable();
+        data.tx_napi.enable();
 
         // 启动网络接口队列
-        dev.netif_start_queue();
+        dev.netif_tx_start_all_queues();
 
-        // 启用网络接口的 carrier
-        dev.netif_carrier_on();
+        // 开始周期性刷新硬件统计信息，并检查发送单元是否挂起
+        data.stats_watchdog.start();
+        data.tx_hang_watchdog.start();
+
+        if *polling_mode.read() {
+            data.poll_timer.start();
+        }
 
         Ok(())
     }
 
     // 停止网络设备的操作
-    fn stop(_dev: &net::Device, _data: &NetDevicePrvData) -> Result {
-        pr_info!("Rust for linux e1000 driver demo (net device stop)\n");
+    fn stop(dev: &net::Device, data: &NetDevicePrvData) -> Result {
+        netdev_info!(dev, "Rust for linux e1000 driver demo (net device stop)\n");
+
+        // 停止统计信息刷新，确保工作项不会在接口关闭后继续访问硬件
+        data.stats_watchdog.stop();
+        data.tx_hang_watchdog.stop();
+        // 纯轮询模式下停止调度 NAPI，没有启动时什么也不做
+        data.poll_timer.stop();
+
+        // 停止数据通路，之后硬件和 NAPI 都不再访问环形缓冲区，对应于 C 版本的 `e1000_down`
+        dev.netif_carrier_off();
+        dev.netif_tx_stop_all_queues();
+        data.napi.disable();
+        data.tx_napi.disable();
+        data.e1000_hw_ops.e1000_irq_disable();
+
+        // 网卡中断已经屏蔽，注销中断处理程序，下次 open 时重新注册，对应于 C 版本 `e1000_close`
+        // 中的 `e1000_free_irq`
+        Self::e1000_free_irq(data);
+
+        data.e1000_hw_ops.e1000_stop_rxtx()?;
+
+        // 释放环形缓冲区，其中未发送完成的 skb 随之释放，BQL 的状态随之重置
+        Self::e1000_free_rings(dev, data);
+
         Ok(())
     }
 
     // 处理网络数据包的发送
     fn start_xmit(skb: &net::SkBuff, dev: &net::Device, data: &NetDevicePrvData) -> net::NetdevTx {
 
-        // 如果数据包大小超过单个 RX/TX 环形缓冲区的大小，打印错误信息并返回忙碌状态
-        if skb.head_data().len() > RXTX_SINGLE_RING_BLOCK_SIZE {
-            pr_err!("xmit msg too long");
-            return net::NetdevTx::Busy;
+        // 没有启用 NETIF_F_SG，每个包只使用一个描述符，因此分片的 skb 需要先线性化。
+        // 返回 Busy 会让协议栈不停地重试同一个包，无法发送的包直接丢弃
+        if skb.is_nonlinear() && skb.linearize().is_err() {
+            netdev_err_ratelimited!(dev, "failed to linearize skb\n");
+            return Self::e1000_xmit_drop(skb, data);
         }
 
-        // 获取传输（TX）环形缓冲区
-        let mut tx_ring = data.tx_ring.lock_irqdisable();
-        // 读取 TX 队列的尾部和头部索引，以及 RX 队列的尾部和头部索引
-        let mut tdt = data.e1000_hw_ops.e1000_read_tx_queue_tail();
-        let tdh = data.e1000_hw_ops.e1000_read_tx_queue_head();
-        let rdt = data.e1000_hw_ops.e1000_read_rx_queue_tail();
-        let rdh = data.e1000_hw_ops.e1000_read_rx_queue_head();
-
-        pr_info!("Rust for linux e1000 driver demo (net device start_xmit) tdt={}, tdh={}, rdt={}, rdh={}\n", tdt, tdh, rdt, rdh);
+        // 如果数据包大小超过单个 RX/TX 环形缓冲区的大小，丢弃这个包
+        if skb.head_data().len() > RXTX_SINGLE_RING_BLOCK_SIZE {
+            netdev_err_ratelimited!(dev, "xmit msg too long\n");
+            return Self::e1000_xmit_drop(skb, data);
+        }
 
-        // 在 PCI/PCI-X 硬件上，如果数据包大小小于 ETH_ZLEN，数据包在硬件填充过程中可能会被破坏。
-        // 为了避免这个问题，手动填充所有小数据包。
-        skb.put_padto(bindings::ETH_ZLEN);
+        // 获取 skb 所在发送队列的环形缓冲区，不同的发送队列互不竞争
+        let qid = skb.queue_mapping() as usize;
+        let mut tx_guard = data.tx_rings[qid].lock_irqdisable();
+        let mut tx_ring = tx_guard.as_mut().unwrap();
+        // 尾部索引只由驱动写入，使用软件记录的副本，发送路径上不再读取任何寄存器
+        let tdt = tx_ring.desc.next_to_use();
 
-        // 告诉内核我们已经将数据提交到硬件
-        dev.sent_queue(skb.len());
+        netdev_dbg_ratelimited!(dev, "Rust for linux e1000 driver demo (net device start_xmit) tdt={}, ntc={}\n", tdt, tx_ring.desc.next_to_clean());
 
-        let mut tx_ring = tx_ring.as_mut().unwrap();
+        // 协议栈的发送不能占用为 XDP 保留的描述符。返回 Busy 之前停止这个发送队列，
+        // 否则协议栈会立即重试同一个包；回收描述符之后在 e1000_recycle_tx_queue 中唤醒
+        if tx_ring.desc.unused() <= E1000_XDP_TX_RESERVED {
+            netdev_err_ratelimited!(dev, "xmit busy\n");
+            dev.netif_stop_subqueue(qid as u16);
+            return net::NetdevTx::Busy;
+        }
         // 获取 TX 描述符数组中的描述符
-        let tx_descs: &mut [TxDescEntry] = tx_ring.desc.as_desc_slice();
+        let tx_descs: &mut [TxDescEntry] = tx_ring.desc.as_mut_slice();
         // 获取当前的 TX 描述符
-        let tx_desc = &mut tx_descs[tdt as usize];
+        let tx_desc = &mut tx_descs[tdt];
         // 检查 TX 描述符的状态位，如果描述符不可用，则打印错误信息并返回忙碌状态
         if tx_desc.sta & E1000_TXD_STAT_DD as u8 == 0 {
-            pr_err!("xmit busy");
+            netdev_err_ratelimited!(dev, "xmit busy\n");
             return net::NetdevTx::Busy;
         }
 
-        // 为 skb 分配 DMA 映射
-        let ms: dma::MapSingle<u8> = if let Ok(ms) = dma::MapSingle::try_new(
-            &*data.dev,
-            skb.head_data().as_ptr() as *mut u8,
-            skb.len() as usize,
-            bindings::dma_data_direction_DMA_TO_DEVICE
-        ) {
-            ms
+        // 在 PCI/PCI-X 硬件上，如果数据包大小小于 ETH_ZLEN，数据包在硬件填充过程中可能会被破坏。
+        // 为了避免这个问题，手动填充所有小数据包。失败时 skb 已经被释放
+        if skb.put_padto(bindings::ETH_ZLEN) != 0 {
+            HwStats::add(&data.drv_stats.tx_dropped, 1);
+            return net::NetdevTx::Ok;
+        }
+
+        // 小包拷贝到描述符对应的弹跳缓冲区中，不需要映射 skb
+        let len = skb.len();
+        let (dma_addr, tx_buf) = if let Some(dma_addr) = tx_ring.state.copy_in(tdt, skb.head_data()) {
+            (dma_addr, TxBuf::Bounce)
         } else {
-            return net::NetdevTx::Busy;
+            // 为 skb 分配 DMA 映射。映射失败（例如 IOMMU 空间不足）时丢弃这个包，描述符保持空闲
+            let ms = match Self::e1000_map_tx(data, skb.head_data().as_ptr() as *mut u8, len as usize) {
+                Ok(ms) => ms,
+                Err(_) => {
+                    HwStats::add(&data.drv_stats.tx_dma_failed, 1);
+                    return Self::e1000_xmit_drop(skb, data);
+                }
+            };
+            (ms.dma_handle as u64, TxBuf::Skb((ms, skb.into())))
         };
 
+        // 包一定会交给硬件，告诉内核我们已经将数据提交到硬件
+        dev.tx_sent_queue(qid as u16, len);
+
         // 更新 TX 描述符的缓冲区地址、长度和命令
-        tx_desc.buf_addr = ms.dma_handle as u64;
-        tx_desc.length = skb.len() as u16;
+        let tx_desc = &mut tx_ring.desc.as_mut_slice()[tdt];
+        tx_desc.buf_addr = dma_addr;
+        tx_desc.length = len as u16;
         tx_desc.cmd = ((E1000_TXD_CMD_RS | E1000_TXD_CMD_EOP) >> 24) as u8;
         tx_desc.sta = 0;
-        // 将 DMA 映射和 skb 存储到 TX 环形缓冲区中
-        tx_ring.buf.borrow_mut()[tdt as usize].replace((ms, skb.into()));
+        // 将 DMA 映射和 skb 存储到 TX 环形缓冲区中。数据已经拷贝到弹跳缓冲区时 skb 可以立即释放
+        let bounced = matches!(tx_buf, TxBuf::Bounce);
+        if bounced {
+            skb.consume_any();
+        }
+        tx_ring.buf[tdt].replace(tx_buf);
 
         // TODO: 在这里可能需要内存屏障。我们在 x86 上进行测试，因此可以忽略这一步。
 
         // 更新 TX 队列尾部索引
-        tdt = (tdt + 1) % TX_RING_SIZE as u32;
-        data.e1000_hw_ops.e1000_write_tx_queue_tail(tdt);
+        tx_ring.desc.advance_use();
+        data.e1000_hw_ops.e1000_write_tx_queue_tail(qid, tx_ring.desc.next_to_use() as u32);
+        drop(tx_guard);
+
+        // 每 CPU 的计数器不能在关闭中断时更新，释放锁之后再统计
+        if bounced {
+            data.drv_stats.path.update(|s| s.tx_bounce += 1);
+        }
 
         net::NetdevTx::Ok
     }
 
+    // MII ioctl（mii-tool 等工具使用），对应于 C 版本的 `e1000_mii_ioctl`
+    fn eth_ioctl(_dev: &net::Device, data: &NetDevicePrvData, mii_data: &mut net::mii::MiiIoctlData, cmd: u32) -> Result {
+        MiiIf::new(&*data.e1000_hw_ops, E1000_PHY_ADDR, true).generic_ioctl(mii_data, cmd)
+    }
+
+    // 对应 ethtool -K，dev.features() 仍然是旧的功能，只在相关的功能改变时重新配置接收单元
+    fn set_features(dev: &net::Device, data: &NetDevicePrvData, features: net::Features) -> Result {
+        let changed = features ^ dev.features();
+        if !changed.intersects(net::Features::RXCSUM | net::Features::RXFCS) {
+            return Ok(());
+        }
+
+        // 接口未启动时，open 会按新的功能配置硬件
+        if dev.netif_running() {
+            Self::e1000_apply_features(data, features)?;
+        }
+        Ok(())
+    }
+
+    // 挂载或卸载 XDP 程序（ip link set dev eth0 xdp obj ...）
+    fn bpf(_dev: &net::Device, data: &NetDevicePrvData, bpf: &mut net::NetdevBpf) -> Result {
+        if bpf.command() != bindings::bpf_netdev_command_XDP_SETUP_PROG {
+            return Err(EINVAL);
+        }
+
+        // XDP 的帧必须放在一个普通的页中，大于 2048 字节的接收缓冲区使用多页，不支持 XDP
+        let prog = bpf.prog()?.map(ARef::from);
+        if prog.is_some() && data.rx_buffer_size > E1000_RX_BUFFER_SIZE {
+            return Err(EOPNOTSUPP);
+        }
+
+        // 接收缓冲区本来就是预留了 XDP_PACKET_HEADROOM 的整页，不需要重新配置环形缓冲区，
+        // 直接替换程序即可。旧程序在锁外释放。
+        let old = core::mem::replace(&mut *data.xdp_prog.lock_irqdisable(), prog);
+        drop(old);
+        Ok(())
+    }
+
+    // 发送其他设备通过 XDP_REDIRECT 重定向过来的帧
+    fn xdp_xmit(dev: &net::Device, data: &NetDevicePrvData, frames: &mut net::XdpFrames<'_>, _flags: u32) {
+        if !dev.netif_running() {
+            return;
+        }
+
+        // 重定向过来的帧都从队列 0 发送。取出的帧都算作已经发送，因此先确认发送环有空位并完成映射，
+        // 再从 `frames` 中取出。发送环满或映射失败时停止，剩下的帧由调用者释放并计入丢弃
+        let mut tx_ring = data.tx_rings[0].lock_irqdisable();
+        let tx_ring = match tx_ring.as_mut() {
+            Some(tx_ring) => tx_ring,
+            None => return,
+        };
+        let mut queued = false;
+        while tx_ring.desc.unused() > 0 {
+            let (ptr, len) = match frames.peek_data() {
+                Some(buf) => (buf.as_ptr() as *mut u8, buf.len()),
+                None => break,
+            };
+            let ms = match Self::e1000_map_tx(data, ptr, len) {
+                Ok(ms) => ms,
+                Err(_) => {
+                    HwStats::add(&data.drv_stats.tx_dma_failed, 1);
+                    break;
+                }
+            };
+            let frame = match frames.next() {
+                Some(frame) => frame,
+                None => break,
+            };
+            Self::e1000_put_xdp_desc(tx_ring, ms.dma_handle as u64, len as u32, TxBuf::XdpFrame(ms, frame));
+            queued = true;
+        }
+        // 整批帧只更新一次 TDT
+        if queued {
+            data.e1000_hw_ops.e1000_write_tx_queue_tail(0, tx_ring.desc.next_to_use() as u32);
+        }
+    }
+
     // 获取网络设备的统计信息
-    fn get_stats64(_netdev: &net::Device, _data: &NetDevicePrvData, stats: &mut net::RtnlLinkStats64) {
-        pr_info!("Rust for linux e1000 driver demo (net device get_stats64)\n");
-        // TODO: 尚未实现统计信息的获取
-        stats.set_rx_bytes(0);
-        stats.set_rx_packets(0);
-        stats.set_tx_bytes(0);
-        stats.set_tx_packets(0);
+    fn get_stats64(netdev: &net::Device, data: &NetDevicePrvData, stats: &mut net::RtnlLinkStats64) {
+        netdev_dbg!(netdev, "Rust for linux e1000 driver demo (net device get_stats64)\n");
+        // 收发包数和字节数由数据通路在每个 CPU 上无锁累加，这里把所有 CPU 的值加起来
+        let sw_stats = data.sw_stats.fetch();
+        stats.set_rx_bytes(sw_stats.rx_bytes);
+        stats.set_rx_packets(sw_stats.rx_packets);
+        stats.set_tx_bytes(sw_stats.tx_bytes);
+        stats.set_tx_packets(sw_stats.tx_packets);
+        stats.set_rx_errors(HwStats::get(&data.drv_stats.rx_errors));
+        stats.set_rx_crc_errors(HwStats::get(&data.drv_stats.rx_crc_errors));
+        stats.set_rx_dropped(HwStats::get(&data.drv_stats.alloc_failures));
+        stats.set_tx_dropped(HwStats::get(&data.drv_stats.tx_dropped));
+        // 下面几项来自硬件统计寄存器，由 StatsWatchdog 周期性累加
+        let hw_stats = &data.stats_watchdog.stats;
+        stats.set_multicast(HwStats::get(&hw_stats.multicast));
+        stats.set_collisions(HwStats::get(&hw_stats.collisions));
+        stats.set_rx_missed_errors(HwStats::get(&hw_stats.rx_missed_errors));
+    }
+}
+
+// ethtool 的回调单独实现，在注册网络设备之前通过 set_ethtool_ops 安装
+#[vtable]
+impl net::EthtoolOps for NetDevice {
+    type Data = Box<NetDevicePrvData>;
+
+    // ethtool -C 只支持自适应开关和接收中断间隔，ITR 对收发中断同时生效
+    const SUPPORTED_COALESCE_PARAMS: u32 = net::coalesce::RX_USECS | net::coalesce::USE_ADAPTIVE_RX;
+
+    // 对应 ethtool -g，报告描述符环的当前大小和最大大小
+    fn get_ringparam(_dev: &net::Device, data: &NetDevicePrvData, ring: &mut net::EthtoolRingParam) {
+        ring.set_rx_max_pending(E1000_MAX_RING_SIZE as u32);
+        ring.set_tx_max_pending(E1000_MAX_RING_SIZE as u32);
+        ring.set_rx_pending(data.rx_ring_size.load(Ordering::Relaxed) as u32);
+        ring.set_tx_pending(data.tx_ring_size.load(Ordering::Relaxed) as u32);
+    }
+
+    // 对应 ethtool -G，修改描述符环的大小。如果接口处于运行状态，需要重新分配并重新配置环形缓冲区
+    fn set_ringparam(dev: &net::Device, data: &NetDevicePrvData, ring: &net::EthtoolRingParam) -> Result {
+        // e1000 不支持 mini/jumbo 环
+        if ring.rx_mini_pending() != 0 || ring.rx_jumbo_pending() != 0 {
+            return Err(EINVAL);
+        }
+
+        // 限制在硬件支持的范围内，并向上取整为 E1000_RING_SIZE_MULTIPLE 的倍数（硬件要求描述符环长度为 128 字节对齐）
+        let clamp = |n: u32| {
+            let n = (n as usize).clamp(E1000_MIN_RING_SIZE, E1000_MAX_RING_SIZE);
+            (n + E1000_RING_SIZE_MULTIPLE - 1) / E1000_RING_SIZE_MULTIPLE * E1000_RING_SIZE_MULTIPLE
+        };
+        let new_rx = clamp(ring.rx_pending());
+        let new_tx = clamp(ring.tx_pending());
+
+        if new_rx == data.rx_ring_size.load(Ordering::Relaxed) &&
+           new_tx == data.tx_ring_size.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let old_rx = data.rx_ring_size.swap(new_rx, Ordering::Relaxed);
+        let old_tx = data.tx_ring_size.swap(new_tx, Ordering::Relaxed);
+
+        // 接口未启动，下次 open 时会按新的大小分配
+        if !dev.netif_running() {
+            return Ok(());
+        }
+
+        // 先按新的大小分配环形缓冲区，失败时恢复原来的大小，硬件状态保持不变
+        let (rx_rings, tx_rings) = match Self::e1000_setup_all_rings(dev, data) {
+            Ok(rings) => rings,
+            Err(e) => {
+                data.rx_ring_size.store(old_rx, Ordering::Relaxed);
+                data.tx_ring_size.store(old_tx, Ordering::Relaxed);
+                return Err(e);
+            }
+        };
+
+        // 停止数据通路，确保硬件和 NAPI 都不再访问旧的环形缓冲区
+        dev.netif_carrier_off();
+        dev.netif_tx_stop_all_queues();
+        data.napi.disable();
+        data.tx_napi.disable();
+
+        let ret = match data.e1000_hw_ops.e1000_stop_rxtx() {
+            // 收发单元没有停下来，硬件可能仍在访问旧的环形缓冲区，保留旧环，新环随之释放
+            Err(e) => Err(e),
+            Ok(()) => {
+                // 修改 e1000 硬件寄存器，使用新的 RX/TX 队列
+                let ret = data.e1000_hw_ops.e1000_configure(
+                    &mut rx_rings.iter().map(RingBuf::layout),
+                    &mut tx_rings.iter().map(RingBuf::layout),
+                )
+                    .and_then(|_| Self::e1000_apply_features(data, dev.features()))
+                    .and_then(|_| data.aim.program(&*data.e1000_hw_ops));
+
+                // 配置失败时硬件可能已经指向新的环形缓冲区，因此与 e1000_reinit 一样总是换上新环。
+                // 旧的环形缓冲区在这里被释放，其中的 DMA 映射和 SkBuff 随之释放
+                Self::e1000_install_rings(dev, data, rx_rings, tx_rings);
+                ret
+            }
+        };
+
+        // NAPI 必须重新启用，否则之后的 stop 会一直等待。失败时恢复原来的大小，发送队列保持停止
+        data.napi.enable();
+        data.tx_napi.enable();
+        if let Err(e) = ret {
+            data.rx_ring_size.store(old_rx, Ordering::Relaxed);
+            data.tx_ring_size.store(old_tx, Ordering::Relaxed);
+            return Err(e);
+        }
+
+        // 重新启动数据通路
+        dev.netif_tx_start_all_queues();
+        HwStats::add(&data.drv_stats.restart_queue, 1);
+        MiiIf::new(&*data.e1000_hw_ops, E1000_PHY_ADDR, true).check_link(dev)?;
+
+        Ok(())
+    }
+
+    // 对应 ethtool -c，自适应模式下 rx-usecs 显示当前的中断间隔
+    fn get_coalesce(_dev: &net::Device, data: &NetDevicePrvData, coal: &mut net::EthtoolCoalesce) -> Result {
+        let (adaptive, usecs) = data.aim.coalesce();
+        coal.set_use_adaptive_rx_coalesce(adaptive);
+        coal.set_rx_coalesce_usecs(usecs);
+        Ok(())
+    }
+
+    // 对应 ethtool -C，在自适应调整和固定的中断间隔之间切换，立即写入 ITR
+    fn set_coalesce(_dev: &net::Device, data: &NetDevicePrvData, coal: &net::EthtoolCoalesce) -> Result {
+        data.aim.set_coalesce(&*data.e1000_hw_ops, coal.use_adaptive_rx_coalesce(), coal.rx_coalesce_usecs())
+    }
+
+    // 对应 ethtool -S，返回驱动计数器的数量
+    fn get_sset_count(_dev: &net::Device, _data: &NetDevicePrvData, sset: u32) -> Result<u32> {
+        match sset {
+            net::ETH_SS_STATS => Ok(DrvStats::count()),
+            _ => Err(EOPNOTSUPP),
+        }
+    }
+
+    // 对应 ethtool -S，填写驱动计数器的名字
+    fn get_strings(_dev: &net::Device, _data: &NetDevicePrvData, sset: u32, strings: &mut net::EthtoolStrings<'_>) {
+        if sset == net::ETH_SS_STATS {
+            DrvStats::fill_strings(strings);
+        }
+    }
+
+    // 对应 ethtool -S，按名字的顺序填写驱动计数器的值
+    fn get_ethtool_stats(_dev: &net::Device, data: &NetDevicePrvData, stats: &mut net::EthtoolStats<'_>) {
+        data.drv_stats.fill_stats(stats);
     }
 }
 
 
 // 由于所有权限制，我们不能直接使用 C 代码中的 NetDevicePrvData 类型，因此需要在此定义一个新的类型。
 struct IrqPrivateData {
+    // 设备的引用计数指针，用于在日志中标明设备
+    dev: Arc<device::Device>,
     // E1000 硬件操作结构体的引用，使用 Arc 进行线程安全的共享
-    e1000_hw_ops: Arc<E1000Ops>,
+    e1000_hw_ops: Arc<dyn E1000Hw>,
     // NAPI（网络设备轮询接口）的引用，使用 Arc 进行线程安全的共享
     napi: Arc<net::Napi>,
+    // 回收发送描述符的 NAPI
+    tx_napi: Arc<net::Napi>,
+    // 驱动计数器，统计中断次数
+    drv_stats: Arc<DrvStats>,
 }
 
 // 中断处理器结构体
@@ -327,22 +1243,31 @@ impl kernel::irq::Handler for E1000InterruptHandler {
 
     // 处理中断的逻辑
     fn handle_irq(data: &IrqPrivateData) -> kernel::irq::Return {
-        // 打印日志，表明中断处理程序被调用
-        pr_info!("Rust for linux e1000 driver demo (handle_irq)\n");
-
         // 读取当前中断状态
         let pending_irqs = data.e1000_hw_ops.e1000_read_interrupt_state();
 
-        // 打印待处理的中断标志
-        pr_info!("pending_irqs: {}\n", pending_irqs);
+        // 每个中断都会执行到这里，日志必须限速，否则会严重影响吞吐量
+        dev_dbg_ratelimited!(data.dev, "Rust for linux e1000 driver demo (handle_irq) pending_irqs: {:#x}\n", pending_irqs.bits());
 
         // 如果没有待处理的中断，则返回 None
-        if pending_irqs == 0 {
+        if pending_irqs.is_empty() {
             return kernel::irq::Return::None;
         }
 
-        // 如果有待处理的中断，则调度 NAPI 进行处理
-        data.napi.schedule();
+        // 屏蔽网卡中断后再调度 NAPI 进行处理，轮询期间网卡不会继续产生中断，
+        // 直到 poll 处理完所有工作后重新打开。发送完成和接收由两个 NAPI 分别处理，各自只屏蔽
+        // 自己的中断。即使中断已经屏蔽，读取 ICR 也会清除其中的原因，因此只要有对应的原因就
+        // 调度 NAPI，正在运行的 NAPI 会在结束前再轮询一次
+        HwStats::add(&data.drv_stats.irqs, 1);
+        data.drv_stats.count_irq_causes(pending_irqs);
+        if pending_irqs.intersects(regs::IMS_TX_MASK) {
+            data.e1000_hw_ops.e1000_irq_disable_mask(regs::IMS_TX_MASK);
+            data.tx_napi.schedule();
+        }
+        if pending_irqs.intersects(regs::IMS_RX_MASK | Icr::RXO) {
+            data.e1000_hw_ops.e1000_irq_disable_mask(regs::IMS_RX_MASK);
+            data.napi.schedule();
+        }
 
         // 返回中断处理完成的标志
         kernel::irq::Return::Handled
@@ -355,13 +1280,41 @@ impl kernel::irq::Handler for E1000InterruptHandler {
 struct E1000DrvPrvData {
     // 网络设备的注册信息
     _netdev_reg: net::Registration<NetDevice>,
+    // NAPI 必须在网络设备注销之后、释放之前删除
+    napi: Arc<net::Napi>,
+    tx_napi: Arc<net::Napi>,
+    // 复位工作项通过网络设备访问私有数据，也必须在网络设备注销之后、释放之前取消
+    reset_task: Arc<ResetTask>,
+    // debugfs 中的 rings 文件，读取网络设备的私有数据，必须在网络设备注销之前删除。
+    // debugfs 只是调试手段，创建失败时为 None
+    #[cfg(CONFIG_DEBUG_FS)]
+    ring_dump: Option<kernel::debugfs::File<ring_dump::RingDump>>,
+    // 诊断用的字符设备，同样读取网络设备的私有数据，必须在网络设备注销之前注销。注册失败时为 None
+    diag_dev: Option<DiagDev>,
+    // 在 generic netlink 族中的登记，同样必须在网络设备注销之前移除
+    genl_adapter: Option<GenlAdapter>,
+}
+
+impl Drop for E1000DrvPrvData {
+    fn drop(&mut self) {
+        #[cfg(CONFIG_DEBUG_FS)]
+        self.ring_dump.take();
+        self.diag_dev.take();
+        self.genl_adapter.take();
+        // 注销网络设备，接口处于打开状态时协议栈会先调用 stop，停止硬件、注销中断并禁用 NAPI。
+        // 之后 NAPI 不会再被调度，可以安全地删除，网络设备在字段析构时释放
+        self._netdev_reg.unregister();
+        self.reset_task.cancel();
+        self.napi.del();
+        self.tx_napi.del();
+    }
 }
 
 // 实现 `driver::DeviceRemoval` 特征，用于处理设备移除事件
 impl driver::DeviceRemoval for E1000DrvPrvData {
     fn device_remove(&self) {
         // 打印日志，表明设备正在被移除
-        pr_info!("Rust for linux e1000 driver demo (device_remove)\n");
+        netdev_info!(self._netdev_reg.dev_get(), "Rust for linux e1000 driver demo (device_remove)\n");
     }
 }
 
@@ -381,60 +1334,122 @@ impl net::NapiPoller for NapiHandler {
         data: &NetDevicePrvData,
     ) -> i32 {
         // 打印日志，表明 NAPI 正在进行轮询
-        pr_info!("Rust for linux e1000 driver demo (napi poll)\n");
-
-        // 读取接收队列的尾部索引，并更新为下一个索引
-        let mut rdt = data.e1000_hw_ops.e1000_read_rx_queue_tail() as usize;
-        rdt = (rdt + 1) % RX_RING_SIZE;
+        netdev_dbg_ratelimited!(dev, "Rust for linux e1000 driver demo (napi poll)\n");
 
-        // 锁定接收环形缓冲区
-        let mut rx_ring_guard = data.rx_ring.lock();
-        let rx_ring = rx_ring_guard.as_mut().unwrap();
+        // 发送描述符由 `TxNapiHandler` 回收，这里只处理接收队列
 
-        // 获取接收描述符数组
-        let mut descs = rx_ring.desc.as_desc_slice();
+        // 本次轮询使用的 XDP 程序，持有引用以免在轮询过程中被卸载
+        let xdp_prog = data.xdp_prog.lock_irqdisable().clone();
 
-        // 遍历所有待处理的接收描述符
-        while descs[rdt].status & E1000_RXD_STAT_DD as u8 != 0 {
-            // 获取数据包长度
-            let packet_len = descs[rdt].length as usize;
-            // 获取缓冲区中的 SKB（socket buffer）
-            let buf = &mut rx_ring.buf.borrow_mut();
-            let skb = &buf[rdt].as_mut().unwrap().1;
+        // 依次处理每个接收队列
+        for qid in 0..E1000_NUM_RX_QUEUES {
+            NetDevice::e1000_clean_rx_queue(dev, data, qid, xdp_prog.as_deref());
+        }
 
-            // 将接收到的数据填入 SKB
-            skb.put(packet_len as u32);
-            // 识别协议类型并设置到 SKB 中
-            let protocol = skb.eth_type_trans(dev);
-            skb.protocol_set(protocol);
+        // 完成 NAPI 的处理，只有 NAPI 真正结束时才重新打开接收中断，
+        // 否则 NAPI 会被再次调度，中断保持屏蔽。纯轮询模式下中断始终保持屏蔽
+        if data.napi.complete_done(1) && !*polling_mode.read() {
+            data.aim.update(&*data.e1000_hw_ops, &data.drv_stats);
+            data.e1000_hw_ops.e1000_irq_enable_mask(regs::IMS_RX_MASK);
+        }
+        // 返回处理的包数
+        1
+    }
+}
 
-            // 将 SKB 交给 NAPI 进行处理
-            data.napi.gro_receive(skb);
+// 回收发送描述符的 NAPI 轮询处理程序，对应于 C 版本 `e1000_clean` 中调用 `e1000_clean_tx_irq` 的部分。
+// 与接收分开调度，只有发送流量时 TXDW 中断也能及时回收描述符，不需要等待接收中断
+struct TxNapiHandler {}
 
-            // 为下一个接收描述符分配新的 SKB
-            let skb_new = dev.alloc_skb_ip_align(RXTX_SINGLE_RING_BLOCK_SIZE as u32).unwrap();
-            let dma_map = dma::MapSingle::try_new(&*data.dev, skb_new.head_data().as_ptr() as *mut u8, RXTX_SINGLE_RING_BLOCK_SIZE, bindings::dma_data_direction_DMA_FROM_DEVICE).unwrap();
-            descs[rdt].buf_addr = dma_map.dma_handle as u64;
-            buf[rdt] = Some((dma_map, skb_new));
+impl net::NapiPoller for TxNapiHandler {
+    type Data = Box<NetDevicePrvData>;
 
-            // 清除当前描述符的状态，并更新接收队列的尾部索引
-            descs[rdt].status = 0;
-            data.e1000_hw_ops.e1000_write_rx_queue_tail(rdt as u32);
-            rdt = (rdt + 1) % RX_RING_SIZE;
+    fn poll(
+        napi: &net::Napi,
+        _budget: i32,
+        dev: &net::Device,
+        data: &NetDevicePrvData,
+    ) -> i32 {
+        // 回收每个发送队列中的资源
+        for qid in 0..E1000_NUM_TX_QUEUES {
+            NetDevice::e1000_recycle_tx_queue(dev, data, qid);
         }
 
-        // 回收传输队列中的资源
-        NetDevice::e1000_recycle_tx_queue(dev, data);
-        // 完成 NAPI 的处理
-        data.napi.complete_done(1);
-        // 返回处理的包数
-        1
+        // 与 C 版本一样，回收发送描述符不计入 budget
+        if napi.complete_done(0) && !*polling_mode.read() {
+            data.e1000_hw_ops.e1000_irq_enable_mask(regs::IMS_TX_MASK);
+        }
+        0
     }
 }
 
 // 定义 E1000Drv 结构体用于 PCI 驱动的实现
 struct E1000Drv {}
 
+impl E1000Drv {
+    /// 找到并检查寄存器所在的 BAR，返回 (MMIO BAR, I/O BAR)，对应于 C 版本 `e1000_probe` 中查找 BAR 的部分。
+    ///
+    /// BAR0 必须是至少 128KB 的内存 BAR；I/O BAR 不一定紧跟在 BAR0 之后（BAR0 为 64 位时占用两个 BAR），
+    /// 因此与 C 版本一样在其余的标准 BAR 中查找第一个非空的 I/O BAR，它必须能容纳 IOADDR/IODATA 窗口。
+    /// 找不到 I/O BAR 时返回 None，此时只通过 MMIO 访问寄存器。
+    fn e1000_find_bars(dev: &pci::Device) -> Result<(Resource, Option<Resource>)> {
+        let mem_res = match dev.resource(0) {
+            Some(res) if res.is_mem() => res,
+            _ => {
+                dev_err!(dev, "BAR0 is not a memory BAR, cannot map registers\n");
+                return Err(ENODEV);
+            }
+        };
+        if mem_res.len() < E1000_MMIO_MIN_LEN {
+            dev_err!(dev, "BAR0 is too small: {} bytes, expected at least {}\n", mem_res.len(), E1000_MMIO_MIN_LEN);
+            return Err(ENODEV);
+        }
+
+        let (io_bar, io_res) = match (1..bindings::PCI_STD_NUM_BARS as u8)
+            .find_map(|bar| dev.resource(bar).filter(|res| res.is_io()).map(|res| (bar, res)))
+        {
+            Some(bar) => bar,
+            None => {
+                dev_info!(dev, "no I/O BAR found, using MMIO for all register accesses\n");
+                return Ok((mem_res, None));
+            }
+        };
+        if io_res.len() < E1000_IO_WINDOW_LEN {
+            dev_err!(dev, "I/O BAR{} is too small: {} bytes, expected at least {}\n", io_bar, io_res.len(), E1000_IO_WINDOW_LEN);
+            return Err(ENODEV);
+        }
+        dev_dbg!(dev, "registers: BAR0 ({} bytes), I/O window: BAR{} ({} bytes)\n", mem_res.len(), io_bar, io_res.len());
+
+        Ok((mem_res, Some(io_res)))
+    }
+
+    /// 选择接收缓冲区的大小，对应于 C 版本 `e1000_change_mtu` 中 `rx_buffer_len` 的计算。
+    /// 模块参数为 0 时使用能放下一个完整帧的最小缓冲区，否则必须是 RCTL 支持的大小之一
+    fn e1000_rx_buffer_size(dev: &pci::Device, mtu: u32) -> Result<u32> {
+        match *rx_buffer_size.read() {
+            0 => {
+                let max_frame = mtu + bindings::ETH_HLEN + bindings::ETH_FCS_LEN;
+                Ok(E1000_RX_BUFFER_SIZES.into_iter().find(|&size| size >= max_frame).unwrap_or(E1000_RX_BUFFER_SIZES[3]))
+            }
+            size if E1000_RX_BUFFER_SIZES.contains(&size) => Ok(size),
+            size => {
+                dev_err!(dev, "invalid rx_buffer_size {}, expected 2048, 4096, 8192 or 16384\n", size);
+                Err(EINVAL)
+            }
+        }
+    }
+
+    /// 接收页的阶数，页中要放下预留空间、接收缓冲区和 build_skb 需要的 skb_shared_info
+    fn e1000_rx_page_order(buf_size: u32) -> u32 {
+        let need = (E1000_RX_HEADROOM + buf_size) as usize + core::mem::size_of::<bindings::skb_shared_info>();
+        let mut order = 0;
+        while (bindings::PAGE_SIZE as usize) << order < need {
+            order += 1;
+        }
+        order
+    }
+}
+
 impl pci::Driver for E1000Drv {
     // `Data` 类型表示驱动程序私有数据的包装，使用 `Box<E1000DrvPrvData>` 类型
     type Data = Box<E1000DrvPrvData>;
@@ -446,58 +1461,145 @@ impl pci::Driver for E1000Drv {
 
     // 设备探测函数，用于初始化和配置 PCI 设备
     fn probe(dev: &mut pci::Device, id: core::option::Option<&Self::IdInfo>) -> Result<Self::Data> {
-        pr_info!("Rust for linux e1000 driver demo (probe): {:?}\n", id);
+        dev_info!(dev, "Rust for linux e1000 driver demo (probe): {:?}\n", id);
 
         // 注意：目前只支持 QEMU 的 82540EM 芯片。
 
         // 选择 PCI 设备的 BAR（基址寄存器），根据指定的条件筛选出需要的资源
         let bars = dev.select_bars((bindings::IORESOURCE_MEM | bindings::IORESOURCE_IO) as u64);
 
-        // 启用 PCI 设备
-        dev.enable_device()?;
+        // 启用 PCI 设备，驱动解绑或 probe 失败时由内核自动关闭
+        dev.pcim_enable_device()?;
 
-        // 请求所选 BAR 的物理内存区域
-        dev.request_selected_regions(bars, c_str!("e1000 reserved memory"))?;
+        // 请求所选 BAR 的物理内存区域，驱动解绑时在 remove 之后由内核自动释放，
+        // 此时中断已经在 remove 中注销
+        dev.devm_request_selected_regions(bars, c_str!("e1000 reserved memory"))?;
 
         // 设置设备为主模式
         dev.set_master();
 
-        // 获取由 BAR0 提供的资源（内存区域）
-        let mem_res = dev.iter_resource().next().ok_or(kernel::error::code::EIO)?;
-        // 获取 I/O 端口地址
-        let io_res = dev.iter_resource().skip(1).find(|r:&Resource|r.check_flags(bindings::IORESOURCE_IO)).ok_or(kernel::error::code::EIO)?;
+        // 获取由 BAR0 提供的资源（内存区域）和 I/O 端口所在的 BAR，检查它们的类型和大小
+        let (mem_res, io_res) = Self::e1000_find_bars(dev)?;
 
         // TODO: `pci_save_state` 函数暂时不支持，只能使用原始的 C 绑定
 
         // 分配新的以太网设备，相当于 C 版本中的 `alloc_etherdev()` 和 `SET_NETDEV_DEV()`
-        let mut netdev_reg = net::Registration::<NetDevice>::try_new(dev)?;
+        let mut netdev_reg = net::Registration::<NetDevice>::try_new_mq(
+            dev,
+            E1000_NUM_TX_QUEUES as u32,
+            E1000_NUM_RX_QUEUES as u32,
+        )?;
         let netdev = netdev_reg.dev_get();
-
-        // 将设备寄存器的硬件地址映射到逻辑地址，以便内核驱动可以访问
-        let mem_addr = Arc::try_new(dev.map_resource(&mem_res, mem_res.len())?)?;
-        let io_addr = Arc::try_new(pci::IoPort::try_new(&io_res)?)?;
+        netdev.netif_set_real_num_tx_queues(E1000_NUM_TX_QUEUES as u32)?;
+        netdev.netif_set_real_num_rx_queues(E1000_NUM_RX_QUEUES as u32)?;
+
+        // 接收校验和卸载默认打开，可以通过 ethtool -K 关闭；保留 CRC 默认关闭
+        netdev.set_feature_set(
+            net::FeatureSet::new()
+                .toggleable(net::Features::RXCSUM | net::Features::RXFCS)
+                .enabled(net::Features::RXCSUM),
+        );
+
+        // 将设备寄存器的硬件地址映射到逻辑地址，以便内核驱动可以访问，大小已经在上面检查过。
+        // 映射在 remove 之后才由内核解除，此时 `mem_addr` 的所有引用都已经随网络设备释放
+        let mem_addr = Arc::try_new(dev.pcim_iomap(0, mem_res.len())?)?;
+        // 只使用 I/O BAR 开头的 IOADDR/IODATA 窗口，越界的访问会返回错误
+        let io_addr = match io_res {
+            Some(io_res) => Some(Arc::try_new(pci::IoPort::try_new_len(&io_res, E1000_IO_WINDOW_LEN)?)?),
+            None => None,
+        };
 
         // TODO: 实现 C 版本中的 `e1000_init_hw_struct()`
 
-        // 只针对 PCI-X 需要 64 位，为简化代码，这里硬编码为 32 位
-        dma::set_coherent_mask(dev, 0xFFFFFFFF)?;
-
-        // TODO: 这里实现 ethtool 支持
+        // ethtool 支持：目前只实现了 ring 参数（ethtool -g/-G）
 
         // 启用 NAPI，R4L 将调用 `netif_napi_add_weight()`，而原始 C 版本调用 `netif_napi_add`
-        let napi = net::NapiAdapter::<NapiHandler>::add_weight(&netdev, 64)?;
+        let napi: Arc<net::Napi> = net::NapiAdapter::<NapiHandler>::add_weight(&netdev, 64)?.into();
+        let tx_napi: Arc<net::Napi> = net::NapiAdapter::<TxNapiHandler>::add_weight(&netdev, 64)?.into();
 
         // TODO: 实现 C 版本中的 `e1000_sw_init()`
 
         // TODO: 许多功能标志在 C 代码中进行分配，这里暂时跳过
+        // 接收缓冲区的大小在 probe 时确定，page pool 按它选择页的大小
+        let rx_buffer_size = Self::e1000_rx_buffer_size(dev, netdev.mtu_get())?;
+
+        // 声明支持的 MTU 范围，由内核检查 ip link set mtu 的参数。只有接收缓冲区大于 2048 字节时
+        // 才打开长帧接收，更大的帧分散到多个接收描述符中
+        let max_frame = if rx_buffer_size > E1000_RX_BUFFER_SIZE {
+            E1000_MAX_JUMBO_FRAME_SIZE
+        } else {
+            bindings::ETH_DATA_LEN + bindings::ETH_HLEN + bindings::ETH_FCS_LEN
+        };
+        netdev.min_mtu_set(bindings::ETH_MIN_MTU);
+        netdev.max_mtu_set(max_frame - bindings::ETH_HLEN - bindings::ETH_FCS_LEN);
+        // 与 C 版本相同的发送超时时间
+        netdev.watchdog_timeo_set(Duration::from_secs(5));
+
         let e1000_hw_ops = E1000Ops {
             mem_addr: Arc::clone(&mem_addr),
-            io_addr: Arc::clone(&io_addr),
+            io_addr,
+            rx_buffer_size,
         };
+
+        // 与 C 版本一样，只在 PCI-X 总线上尝试 64 位 DMA（PCI 总线上的 8254x 有双地址周期的勘误），
+        // 其余情况（包括 QEMU）使用 32 位。流式映射和一致性内存使用相同的掩码
+        let using_dac = e1000_hw_ops.e1000_bus_pcix() && dma::set_mask_and_coherent(dev, dma::bit_mask(64)).is_ok();
+        if !using_dac {
+            dma::set_mask_and_coherent(dev, dma::bit_mask(32)).map_err(|e| {
+                dev_err!(dev, "no usable DMA configuration, aborting\n");
+                e
+            })?;
+        }
+        if using_dac {
+            netdev.features_set(netdev.features() | net::Features::HIGHDMA);
+        }
+
         e1000_hw_ops.e1000_reset_hw()?;
 
-        // TODO: 目前硬编码 MAC 地址，应该从 EEPROM 中读取
-        netdev.eth_hw_addr_set(&MAC_HWADDR);
+        // TODO: 目前默认使用硬编码的 MAC 地址，应该从 EEPROM 中读取。可以通过模块参数指定其他地址
+        let mac_addr = match mac.read() {
+            b"" => MAC_HWADDR,
+            s => match net::mac_pton(s) {
+                Some(addr) if net::is_valid_ether_addr(&addr) => addr,
+                _ => {
+                    dev_err!(dev, "invalid mac parameter: {}\n", core::str::from_utf8(s).unwrap_or("?"));
+                    return Err(EINVAL);
+                }
+            },
+        };
+        e1000_hw_ops.e1000_write_mac_addr(&mac_addr)?;
+        let e1000_hw_ops: Arc<dyn E1000Hw> = Arc::try_new(e1000_hw_ops)?;
+
+        // 创建接收缓冲区的 page pool，容量按最大的接收环大小计算，以支持 ethtool -G。
+        // 接收页可能通过 XDP_TX 原路发回，因此使用双向映射。大于 2048 字节的缓冲区使用多页
+        let rx_page_pool = net::PagePool::try_new(
+            dev,
+            E1000_MAX_RING_SIZE as u32,
+            Self::e1000_rx_page_order(rx_buffer_size),
+            bindings::dma_data_direction_DMA_BIDIRECTIONAL,
+            E1000_RX_HEADROOM,
+            rx_buffer_size,
+        )?;
+
+        // 按模块参数运行回环自检，检查收发环的配置是否正确。自检失败只打印错误，网卡仍然可以使用
+        if *loopback_test.read() {
+            match selftest::e1000_loopback_test(&*dev, &*e1000_hw_ops, &rx_page_pool, &mac_addr) {
+                Ok(()) => dev_info!(dev, "Rust for linux e1000 driver demo (loopback self-test passed)\n"),
+                Err(e) => dev_err!(dev, "Rust for linux e1000 driver demo (loopback self-test failed: {:?})\n", e),
+            }
+        }
+
+        // 创建周期性刷新硬件统计信息的工作项，在 open 时启动
+        let stats_watchdog = StatsWatchdog::try_new(Arc::clone(&e1000_hw_ops))?;
+        // 发送单元挂起时由复位工作项复位网卡，它在驱动私有数据析构时取消
+        let reset_task = ResetTask::try_new(Arc::clone(&napi))?;
+        let tx_hang_watchdog = TxHangWatchdog::try_new(
+            Arc::try_new(device::Device::from_dev(dev))?,
+            Arc::clone(&e1000_hw_ops),
+            Arc::clone(&reset_task),
+        )?;
+
+        netdev.eth_hw_addr_set(&mac_addr);
 
         // TODO: 背景任务和 Wake on LAN 目前不支持
 
@@ -510,60 +1612,127 @@ impl pci::Driver for E1000Drv {
         // 关闭网络设备的 carrier 状态
         netdev.netif_carrier_off();
 
+        // 每个队列一把自旋锁。锁位于 Vec 的堆内存中，之后不会再向 Vec 中添加元素，因此不会被移动
+        let mut tx_rings = Vec::try_with_capacity(E1000_NUM_TX_QUEUES)?;
+        for _ in 0..E1000_NUM_TX_QUEUES {
+            // SAFETY: `spinlock_init` 在下方被调用
+            tx_rings.try_push(unsafe { SpinLock::new(None) })?;
+        }
+        for lock in tx_rings.iter_mut() {
+            // SAFETY: 我们不会移动 `tx_rings` 中的锁
+            kernel::spinlock_init!(unsafe { Pin::new_unchecked(lock) }, "tx_ring");
+        }
+        let mut rx_rings = Vec::try_with_capacity(E1000_NUM_RX_QUEUES)?;
+        for _ in 0..E1000_NUM_RX_QUEUES {
+            // SAFETY: `spinlock_init` 在下方被调用
+            rx_rings.try_push(unsafe { SpinLock::new(None) })?;
+        }
+        for lock in rx_rings.iter_mut() {
+            // SAFETY: 我们不会移动 `rx_rings` 中的锁
+            kernel::spinlock_init!(unsafe { Pin::new_unchecked(lock) }, "rx_ring");
+        }
         // SAFETY: `spinlock_init` 在下方被调用
-        let mut tx_ring = unsafe { SpinLock::new(None) };
-        let mut rx_ring = unsafe { SpinLock::new(None) };
-        // SAFETY: 我们不会移动 `tx_ring` 和 `rx_ring`
-        kernel::spinlock_init!(unsafe { Pin::new_unchecked(&mut tx_ring) }, "tx_ring");
-        kernel::spinlock_init!(unsafe { Pin::new_unchecked(&mut rx_ring) }, "rx_ring");
-
-        unsafe {
-            let pci_dev = dev.get_pci_device_ptr();
-
-            // 注册网络设备及其私有数据
-            netdev_reg.register(Box::try_new(
-                NetDevicePrvData {
-                    dev: Arc::try_new(common_dev)?,
-                    e1000_hw_ops: Arc::try_new(e1000_hw_ops)?,
-                    napi: napi.into(),
-                    tx_ring,
-                    rx_ring,
-                    irq,
-                    _irq_handler: AtomicPtr::new(core::ptr::null_mut()),
-                    pci_dev: Arc::try_new(pci_dev)?,
-                }
-            )?)?;
-
-            // 返回驱动程序私有数据
-            Ok(Box::try_new(
-                E1000DrvPrvData {
-                    // 必须持有这个注册，否则设备将被移除
-                    _netdev_reg: netdev_reg,
-                }
-            )?)
+        let mut xdp_prog = unsafe { SpinLock::new(None) };
+        // SAFETY: 我们不会移动 `xdp_prog`
+        kernel::spinlock_init!(unsafe { Pin::new_unchecked(&mut xdp_prog) }, "xdp_prog");
+
+        // 注册每个接收队列的 XDP 信息，接收页的内存模型为 page pool
+        let mut xdp_rxqs = Vec::try_with_capacity(E1000_NUM_RX_QUEUES)?;
+        for qid in 0..E1000_NUM_RX_QUEUES {
+            xdp_rxqs.try_push(net::XdpRxqInfo::try_new(&netdev, qid as u32, &rx_page_pool)?)?;
         }
+
+        // 注册网络设备及其私有数据
+        netdev_reg.set_ethtool_ops::<NetDevice>()?;
+        netdev_reg.register(Box::try_new(
+            NetDevicePrvData {
+                dev: Arc::try_new(common_dev)?,
+                e1000_hw_ops,
+                stats_watchdog,
+                tx_hang_watchdog,
+                mac_addr,
+                sw_stats: net::PerCpuStats::try_new()?,
+                drv_stats: Arc::try_new(DrvStats::try_new()?)?,
+                rx_page_pool,
+                xdp_rxqs,
+                xdp_prog,
+                rx_buffer_size,
+                poll_timer: PollTimer::try_new(Arc::clone(&napi), Arc::clone(&tx_napi))?,
+                napi: Arc::clone(&napi),
+                tx_napi: Arc::clone(&tx_napi),
+                tx_rings,
+                rx_rings,
+                tx_ring_size: AtomicUsize::new(TX_RING_SIZE),
+                rx_ring_size: AtomicUsize::new(RX_RING_SIZE),
+                irq,
+                fail_dma: FaultInject::default(),
+                fail_rx_alloc: FaultInject::default(),
+                aim: Aim::default(),
+                _irq_handler: AtomicPtr::new(core::ptr::null_mut()),
+            }
+        )?)?;
+
+        // 在 debugfs 中创建以设备名命名的目录，其中的 rings 文件显示收发环的内容
+        #[cfg(CONFIG_DEBUG_FS)]
+        let ring_dump = CString::try_from_fmt(fmt!("r4l_e1000_demo-{}", dev.name()))
+            .and_then(|name| kernel::debugfs::Dir::try_new(&name, None))
+            .and_then(|dir| ring_dump::RingDump::try_new(&dir, netdev_reg.drvdata().ok_or(EINVAL)?))
+            .ok();
+
+        // 注册诊断用的字符设备，读取时输出寄存器和收发环的状态，ioctl 可以复位网卡或者运行回环自检
+        let diag_dev = match DiagDev::try_new(&*dev, netdev_reg.drvdata().ok_or(EINVAL)?) {
+            Ok(diag_dev) => Some(diag_dev),
+            Err(e) => {
+                dev_warn!(dev, "failed to register the diagnostic device: {:?}\n", e);
+                None
+            }
+        };
+
+        // 登记到 generic netlink 族中，之后可以通过 ifindex 操作这块网卡
+        let genl_adapter = Some(GenlAdapter::try_new(netdev_reg.drvdata().ok_or(EINVAL)?)?);
+
+        // 返回驱动程序私有数据
+        Ok(Box::try_new(
+            E1000DrvPrvData {
+                // 必须持有这个注册，否则设备将被移除
+                _netdev_reg: netdev_reg,
+                napi,
+                tx_napi,
+                reset_task,
+                #[cfg(CONFIG_DEBUG_FS)]
+                ring_dump,
+                diag_dev,
+                genl_adapter,
+            }
+        )?)
     }
 
     // 设备移除函数
-    fn remove(data: &Self::Data) {
-        pr_info!("Rust for linux e1000 driver demo (remove)\n");
-
-        // 获取私有数据
-        let edpd = data.as_ref(); // 驱动程序私有数据
-        let dev = &*(edpd._netdev_reg.dev_get());  // 转换为 &Device
-        let dev_ptr = unsafe{ dev.get_net_device_ptr()};  // 获取 net_device 指针
-        let drvdata = unsafe { &*(bindings::dev_get_drvdata(&mut (*dev_ptr).dev) as *const NetDevicePrvData) }; // 获取 Box<NetDevicePrvData>
-        let pci_dev = unsafe { drvdata.pci_dev.as_ref() };  // 获取 pci_dev: *mut bindings::pci_dev
-
-        // 注销中断处理程序
-        let irq_handler_ptr = drvdata._irq_handler.load(core::sync::atomic::Ordering::Relaxed);
-        if !irq_handler_ptr.is_null() {
-            unsafe { Box::from_raw(irq_handler_ptr) };
-        }
+    fn remove(dev: &mut pci::Device, _data: &Self::Data) {
+        dev_info!(dev, "Rust for linux e1000 driver demo (remove)\n");
 
-        // 释放 PCI 设备资源
-        let bars = unsafe { bindings::pci_select_bars(*pci_dev, (bindings::IORESOURCE_MEM | bindings::IORESOURCE_IO) as u64) } as i32;
-        unsafe { bindings::pci_release_selected_regions(*pci_dev, bars) };
+        // 网络设备在驱动私有数据析构时注销，见 `E1000DrvPrvData` 的 Drop 实现。
+        // 中断处理程序在 stop 中注销，接口关闭之后不会再持有中断。
+        // probe 时申请的 PCI 设备资源由 devres 在 remove 返回后释放
+    }
+
+    // 关机、重启或 kexec 之前调用，之后不会再调用 remove，对应于 C 版本的 `e1000_shutdown`。
+    // 必须让网卡停止 DMA，否则新内核可能被仍在写入接收缓冲区的网卡破坏内存
+    fn shutdown(dev: &mut pci::Device, data: &E1000DrvPrvData) {
+        dev_info!(dev, "Rust for linux e1000 driver demo (shutdown)\n");
+
+        // 协议栈不再调用 start_xmit
+        data._netdev_reg.dev_get().netif_device_detach();
+
+        // 屏蔽中断并关闭收发单元。这里没有禁用 NAPI，正在运行的轮询可能重新打开中断，
+        // 但收发单元已经关闭，总线主控也在下面关闭，网卡不会再发起 DMA
+        if let Some(prv) = data._netdev_reg.drvdata() {
+            prv.e1000_hw_ops.e1000_irq_disable();
+            if prv.e1000_hw_ops.e1000_stop_rxtx().is_err() {
+                dev_warn!(dev, "failed to stop the rx/tx units on shutdown\n");
+            }
+        }
+        dev.clear_master();
     }
 
 }
@@ -572,6 +1741,8 @@ impl pci::Driver for E1000Drv {
 struct E1000KernelMod {
     // `Pin<Box<driver::Registration::<pci::Adapter<E1000Drv>>>>` 表示驱动注册对象，必须固定在内存中以避免驱动被意外移除
     _dev: Pin<Box<driver::Registration::<pci::Adapter<E1000Drv>>>>,
+    // generic netlink 族，所有网卡共用。在驱动之后注销，此时所有网卡都已经移除
+    _genl: Pin<Box<genetlink::Registration<E1000Genl>>>,
 }
 
 // 实现 kernel::Module 特征以支持内核模块操作
@@ -584,8 +1755,11 @@ impl kernel::Module for E1000KernelMod {
         // 使用驱动注册对象 `driver::Registration` 来注册 PCI 驱动
         let d = driver::Registration::<pci::Adapter<E1000Drv>>::new_pinned(name, module)?;
 
+        // 注册 generic netlink 族，用户空间可以通过它查询收发环、读取计数器和复位网卡
+        let genl = genetlink::Registration::<E1000Genl>::try_new(module, ())?;
+
         // 将驱动注册对象存储在模块结构体中，否则它会被丢弃，从而导致驱动被移除
-        Ok(E1000KernelMod {_dev: d})
+        Ok(E1000KernelMod {_dev: d, _genl: genl})
     }
 }
 
@@ -0,0 +1,484 @@
+//! e1000 系列网卡的寄存器表。
+//!
+//! 每个寄存器是一个 `Reg<T>` 常量，`T` 是寄存器的值类型：带有标志位的寄存器使用各自的新类型
+//! （例如 `Rctl`、`Tctl`），只保存数值的寄存器（描述符环地址、头尾索引、统计计数器）使用 `u32`。
+//! 把 `Tctl` 的值写进 RCTL，或者把 16 位的值写进 32 位的寄存器，都无法通过编译。
+
+use core::marker::PhantomData;
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
+
+use kernel::pci::MappedResource;
+use kernel::prelude::*;
+
+/// 寄存器的值类型，与寄存器中的 32 位数值相互转换
+pub(crate) trait RegValue: Copy {
+    /// 由寄存器中读到的数值构造
+    fn from_bits(bits: u32) -> Self;
+    /// 要写入寄存器的数值
+    fn bits(self) -> u32;
+}
+
+impl RegValue for u32 {
+    fn from_bits(bits: u32) -> Self {
+        bits
+    }
+
+    fn bits(self) -> u32 {
+        self
+    }
+}
+
+/// 一个 32 位寄存器，`T` 是它的值类型
+pub(crate) struct Reg<T> {
+    offset: usize,
+    _value: PhantomData<T>,
+}
+
+// 手动实现，`T` 不需要实现 Clone 和 Copy
+impl<T> Clone for Reg<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Reg<T> {}
+
+impl<T> Reg<T> {
+    /// 位于寄存器空间 `offset` 处的寄存器
+    pub(crate) const fn new(offset: usize) -> Self {
+        Self { offset, _value: PhantomData }
+    }
+
+    /// 寄存器在寄存器空间中的偏移
+    pub(crate) const fn offset(self) -> usize {
+        self.offset
+    }
+}
+
+/// 每个队列各有一个的寄存器。队列 n 的寄存器位于队列 0 的寄存器加上 n * `QUEUE_REG_STRIDE` 处
+/// （8257x 及以后的芯片）
+#[derive(Clone, Copy)]
+pub(crate) struct QueueReg<T>(Reg<T>);
+
+/// 相邻两个队列的描述符寄存器之间的距离
+pub(crate) const QUEUE_REG_STRIDE: usize = 0x100;
+
+impl<T> QueueReg<T> {
+    const fn new(offset: usize) -> Self {
+        Self(Reg::new(offset))
+    }
+
+    /// 队列 `qid` 的寄存器
+    pub(crate) const fn queue(self, qid: usize) -> Reg<T> {
+        Reg::new(self.0.offset + qid * QUEUE_REG_STRIDE)
+    }
+}
+
+/// 连续排列的一组寄存器，例如多播地址表
+#[derive(Clone, Copy)]
+pub(crate) struct RegArray<T> {
+    base: Reg<T>,
+    len: usize,
+}
+
+impl<T> RegArray<T> {
+    const fn new(offset: usize, len: usize) -> Self {
+        Self { base: Reg::new(offset), len }
+    }
+
+    /// 数组中寄存器的数量
+    pub(crate) const fn len(self) -> usize {
+        self.len
+    }
+
+    /// 数组中的第 `i` 个寄存器，越界时返回 None
+    pub(crate) fn at(self, i: usize) -> Option<Reg<T>> {
+        (i < self.len).then(|| Reg::new(self.base.offset + i * 4))
+    }
+}
+
+/// 通过寄存器表访问映射后的寄存器空间
+pub(crate) trait RegIo {
+    /// 读取寄存器
+    fn read<T: RegValue>(&self, reg: Reg<T>) -> Result<T>;
+    /// 写入寄存器
+    fn write<T: RegValue>(&self, reg: Reg<T>, val: T) -> Result;
+
+    /// 读取寄存器，用 `f` 修改之后写回
+    fn modify<T: RegValue>(&self, reg: Reg<T>, f: impl FnOnce(T) -> T) -> Result {
+        let val = self.read(reg)?;
+        self.write(reg, f(val))
+    }
+}
+
+impl RegIo for MappedResource {
+    fn read<T: RegValue>(&self, reg: Reg<T>) -> Result<T> {
+        self.readl(reg.offset).map(T::from_bits)
+    }
+
+    fn write<T: RegValue>(&self, reg: Reg<T>, val: T) -> Result {
+        self.writel(val.bits(), reg.offset)
+    }
+}
+
+// 定义一个由标志位组成的寄存器值类型
+macro_rules! reg_bits {
+    ($(#[$attr:meta])* $name:ident { $($(#[$flag_attr:meta])* $flag:ident = $val:expr;)* }) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, PartialEq, Eq, Default)]
+        pub(crate) struct $name(pub(crate) u32);
+
+        impl $name {
+            $($(#[$flag_attr])* pub(crate) const $flag: Self = Self($val);)*
+
+            /// 没有任何位被置位的值
+            pub(crate) const fn empty() -> Self {
+                Self(0)
+            }
+
+            /// 是否没有任何位被置位
+            pub(crate) const fn is_empty(self) -> bool {
+                self.0 == 0
+            }
+
+            /// 是否包含 `other` 中的任意一位
+            pub(crate) const fn intersects(self, other: Self) -> bool {
+                self.0 & other.0 != 0
+            }
+
+            /// 是否包含 `other` 中的所有位
+            pub(crate) const fn contains(self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+
+            /// 两个值的并集，可以在常量中使用
+            pub(crate) const fn union(self, other: Self) -> Self {
+                Self(self.0 | other.0)
+            }
+        }
+
+        impl RegValue for $name {
+            fn from_bits(bits: u32) -> Self {
+                Self(bits)
+            }
+
+            fn bits(self) -> u32 {
+                self.0
+            }
+        }
+
+        impl BitOr for $name {
+            type Output = Self;
+            fn bitor(self, rhs: Self) -> Self {
+                Self(self.0 | rhs.0)
+            }
+        }
+
+        impl BitOrAssign for $name {
+            fn bitor_assign(&mut self, rhs: Self) {
+                self.0 |= rhs.0;
+            }
+        }
+
+        impl BitAnd for $name {
+            type Output = Self;
+            fn bitand(self, rhs: Self) -> Self {
+                Self(self.0 & rhs.0)
+            }
+        }
+
+        impl BitAndAssign for $name {
+            fn bitand_assign(&mut self, rhs: Self) {
+                self.0 &= rhs.0;
+            }
+        }
+
+        impl Not for $name {
+            type Output = Self;
+            fn not(self) -> Self {
+                Self(!self.0)
+            }
+        }
+    };
+}
+
+reg_bits! {
+    /// Device Control
+    Ctrl {
+        /// Global reset
+        RST = 0x04000000;
+    }
+}
+
+reg_bits! {
+    /// Device Status
+    Status {
+        /// Link up
+        LU = 0x00000002;
+        /// 网卡工作在 PCI-X 总线上（8254x）
+        PCIX_MODE = 0x00002000;
+    }
+}
+
+reg_bits! {
+    /// Extended Device Control（8257x）
+    CtrlExt {
+        /// PBA Clear, required in MSI-X mode
+        PBA_CLR = 0x80000000;
+    }
+}
+
+reg_bits! {
+    /// MDI Control，用于读写 PHY 寄存器
+    Mdic {
+        OP_WRITE = 0x04000000;
+        OP_READ = 0x08000000;
+        READY = 0x10000000;
+        ERROR = 0x40000000;
+    }
+}
+
+impl Mdic {
+    const DATA_MASK: u32 = 0x0000ffff;
+    const REG_SHIFT: u32 = 16;
+    const PHY_SHIFT: u32 = 21;
+
+    /// 对 PHY `phy_addr` 的寄存器 `reg_addr` 发起一次操作，`op` 是 `OP_READ` 或 `OP_WRITE`
+    pub(crate) const fn new(op: Self, phy_addr: u8, reg_addr: u8, data: u16) -> Self {
+        Self(op.0 | (phy_addr as u32) << Self::PHY_SHIFT | (reg_addr as u32) << Self::REG_SHIFT | data as u32)
+    }
+
+    /// 读操作完成后得到的数据
+    pub(crate) const fn data(self) -> u16 {
+        (self.0 & Self::DATA_MASK) as u16
+    }
+}
+
+reg_bits! {
+    /// 中断原因，ICR、IMS 和 IMC 使用相同的位
+    Icr {
+        /// Transmit desc written back
+        TXDW = 0x00000001;
+        /// Link Status Change
+        LSC = 0x00000004;
+        /// rx sequence error
+        RXSEQ = 0x00000008;
+        /// rx desc min. threshold (0)
+        RXDMT0 = 0x00000010;
+        /// rx overrun
+        RXO = 0x00000040;
+        /// rx timer intr (ring 0)
+        RXT0 = 0x00000080;
+        /// Rx Queue 0 Interrupt（82574 MSI-X 模式）
+        RXQ0 = 0x00100000;
+        /// Tx Queue 0 Interrupt（82574 MSI-X 模式）
+        TXQ0 = 0x00400000;
+        /// Other Interrupts（82574 MSI-X 模式）
+        OTHER = 0x01000000;
+    }
+}
+
+impl Icr {
+    /// 所有中断原因
+    pub(crate) const ALL: Self = Self(!0);
+}
+
+/// 驱动使用的中断，对应于 C 版本的 IMS_ENABLE_MASK
+pub(crate) const IMS_ENABLE_MASK: Icr = IMS_TX_MASK.union(IMS_RX_MASK);
+/// 由发送 NAPI 处理的中断
+pub(crate) const IMS_TX_MASK: Icr = Icr::TXDW;
+/// 由接收 NAPI 处理的中断
+pub(crate) const IMS_RX_MASK: Icr = Icr::RXT0.union(Icr::RXDMT0).union(Icr::RXSEQ).union(Icr::LSC);
+
+/// Interrupt Throttling Rate，两次中断之间的最小间隔，单位为 256 纳秒，0 表示不节流
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Itr(pub(crate) u32);
+
+impl Itr {
+    /// 每秒最多产生 `ints_per_sec` 次中断，0 表示不节流
+    pub(crate) const fn from_rate(ints_per_sec: u32) -> Self {
+        if ints_per_sec == 0 {
+            Self(0)
+        } else {
+            Self(1_000_000_000 / (ints_per_sec * 256))
+        }
+    }
+}
+
+impl RegValue for Itr {
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+reg_bits! {
+    /// Receive Control
+    Rctl {
+        /// enable
+        EN = 0x00000002;
+        /// long packet enable
+        LPE = 0x00000020;
+        /// MAC loopback mode
+        LBM_MAC = 0x00000040;
+        /// broadcast enable
+        BAM = 0x00008000;
+        /// rx buffer size 2048
+        SZ_2048 = 0x00000000;
+        /// rx buffer size 4096, needs BSEX
+        SZ_4096 = 0x02030000;
+        /// rx buffer size 8192, needs BSEX
+        SZ_8192 = 0x02020000;
+        /// rx buffer size 16384, needs BSEX
+        SZ_16384 = 0x02010000;
+        /// Strip Ethernet CRC
+        SECRC = 0x04000000;
+    }
+}
+
+reg_bits! {
+    /// Transmit Control
+    Tctl {
+        /// enable tx
+        EN = 0x00000002;
+        /// pad short packets
+        PSP = 0x00000008;
+    }
+}
+
+impl Rctl {
+    /// 接收缓冲区大小对应的 BSIZE 和 BSEX 编码，只支持 2048 到 16384 字节的四种大小
+    pub(crate) const fn buffer_size(size: u32) -> Option<Self> {
+        match size {
+            2048 => Some(Self::SZ_2048),
+            4096 => Some(Self::SZ_4096),
+            8192 => Some(Self::SZ_8192),
+            16384 => Some(Self::SZ_16384),
+            _ => None,
+        }
+    }
+}
+
+impl Tctl {
+    const CT_MASK: u32 = 0x00000ff0;
+    const CT_SHIFT: u32 = 4;
+    const COLD_MASK: u32 = 0x003ff000;
+    const COLD_SHIFT: u32 = 12;
+
+    /// 设置冲突阈值（collision threshold）
+    pub(crate) const fn with_ct(self, ct: u32) -> Self {
+        Self(self.0 & !Self::CT_MASK | (ct << Self::CT_SHIFT) & Self::CT_MASK)
+    }
+
+    /// 设置冲突距离（collision distance）
+    pub(crate) const fn with_cold(self, cold: u32) -> Self {
+        Self(self.0 & !Self::COLD_MASK | (cold << Self::COLD_SHIFT) & Self::COLD_MASK)
+    }
+}
+
+/// Transmit Inter-packet gap
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Tipg(pub(crate) u32);
+
+impl Tipg {
+    const IPGR1_SHIFT: u32 = 10;
+    const IPGR2_SHIFT: u32 = 20;
+
+    /// 由三个间隔字段构造
+    pub(crate) const fn new(ipgt: u32, ipgr1: u32, ipgr2: u32) -> Self {
+        Self(ipgt | ipgr1 << Self::IPGR1_SHIFT | ipgr2 << Self::IPGR2_SHIFT)
+    }
+}
+
+impl RegValue for Tipg {
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+reg_bits! {
+    /// Receive Address High，低 16 位是 MAC 地址的最后两个字节
+    Rah {
+        /// Receive Address Valid
+        AV = 0x80000000;
+    }
+}
+
+reg_bits! {
+    /// Receive Checksum Control
+    Rxcsum {
+        /// IPv4 checksum offload
+        IPOFL = 0x00000100;
+        /// TCP / UDP checksum offload
+        TUOFL = 0x00000200;
+    }
+}
+
+reg_bits! {
+    /// Receive Filter Control（8257x）
+    Rfctl {
+        /// Use extended rx descriptors
+        EXTEN = 0x00008000;
+    }
+}
+
+reg_bits! {
+    /// Management Control
+    Manc {
+        /// Enable ARP Request Filtering
+        ARP_EN = 0x00002000;
+    }
+}
+
+// E1000 Regs
+pub(crate) const CTRL: Reg<Ctrl> = Reg::new(0x00000);	/* Device Control - RW */
+pub(crate) const STATUS: Reg<Status> = Reg::new(0x00008);	/* Device Status - RO */
+pub(crate) const CTRL_EXT: Reg<CtrlExt> = Reg::new(0x00018);	/* Extended Device Control - RW */
+pub(crate) const MDIC: Reg<Mdic> = Reg::new(0x00020);	/* MDI Control - RW */
+pub(crate) const ICR: Reg<Icr> = Reg::new(0x000C0);	/* Interrupt Cause Read - R/clr */
+pub(crate) const ITR: Reg<Itr> = Reg::new(0x000C4);	/* Interrupt Throttling Rate - RW */
+pub(crate) const IMS: Reg<Icr> = Reg::new(0x000D0);	/* Interrupt Mask Set - RW */
+pub(crate) const IMC: Reg<Icr> = Reg::new(0x000D8);	/* Interrupt Mask Clear - WO */
+pub(crate) const IVAR: Reg<u32> = Reg::new(0x000E4);	/* Interrupt Vector Allocation Register - RW */
+pub(crate) const RCTL: Reg<Rctl> = Reg::new(0x00100);	/* RX Control - RW */
+pub(crate) const TCTL: Reg<Tctl> = Reg::new(0x00400);	/* TX Control - RW */
+pub(crate) const TIPG: Reg<Tipg> = Reg::new(0x00410);	/* TX Inter-packet gap -RW */
+pub(crate) const RXCSUM: Reg<Rxcsum> = Reg::new(0x05000);	/* RX Checksum Control - RW */
+pub(crate) const RFCTL: Reg<Rfctl> = Reg::new(0x05008);	/* Receive Filter Control - RW */
+pub(crate) const MTA: RegArray<u32> = RegArray::new(0x05200, 128);	/* Multicast Table Array - RW Array */
+pub(crate) const RAL: Reg<u32> = Reg::new(0x05400);	/* Receive Address Low - RW */
+pub(crate) const RAH: Reg<Rah> = Reg::new(0x05404);	/* Receive Address High - RW */
+pub(crate) const MANC: Reg<Manc> = Reg::new(0x05820);	/* Management Control - RW */
+
+// 描述符环寄存器
+pub(crate) const RDBAL: QueueReg<u32> = QueueReg::new(0x02800);	/* RX Descriptor Base Address Low - RW */
+pub(crate) const RDBAH: QueueReg<u32> = QueueReg::new(0x02804);	/* RX Descriptor Base Address High - RW */
+pub(crate) const RDLEN: QueueReg<u32> = QueueReg::new(0x02808);	/* RX Descriptor Length - RW */
+pub(crate) const RDH: QueueReg<u32> = QueueReg::new(0x02810);	/* RX Descriptor Head - RW */
+pub(crate) const RDT: QueueReg<u32> = QueueReg::new(0x02818);	/* RX Descriptor Tail - RW */
+pub(crate) const RDTR: Reg<u32> = Reg::new(0x02820);	/* RX Delay Timer - RW */
+pub(crate) const RADV: Reg<u32> = Reg::new(0x0282C);	/* RX Interrupt Absolute Delay Timer - RW */
+pub(crate) const TDBAL: QueueReg<u32> = QueueReg::new(0x03800);	/* TX Descriptor Base Address Low - RW */
+pub(crate) const TDBAH: QueueReg<u32> = QueueReg::new(0x03804);	/* TX Descriptor Base Address High - RW */
+pub(crate) const TDLEN: QueueReg<u32> = QueueReg::new(0x03808);	/* TX Descriptor Length - RW */
+pub(crate) const TDH: QueueReg<u32> = QueueReg::new(0x03810);	/* TX Descriptor Head - RW */
+pub(crate) const TDT: QueueReg<u32> = QueueReg::new(0x03818);	/* TX Descripotr Tail - RW */
+
+// E1000 Statistics Regs (read to clear)
+pub(crate) const CRCERRS: Reg<u32> = Reg::new(0x04000);	/* CRC Error Count - R/clr */
+pub(crate) const MPC: Reg<u32> = Reg::new(0x04010);	/* Missed Packet Count - R/clr */
+pub(crate) const COLC: Reg<u32> = Reg::new(0x04028);	/* Collision Count - R/clr */
+pub(crate) const GPRC: Reg<u32> = Reg::new(0x04074);	/* Good Packets RX Count - R/clr */
+pub(crate) const MPRC: Reg<u32> = Reg::new(0x0407C);	/* Multicast Packets RX Count - R/clr */
+pub(crate) const GPTC: Reg<u32> = Reg::new(0x04080);	/* Good Packets TX Count - R/clr */
+pub(crate) const GORCL: Reg<u32> = Reg::new(0x04088);	/* Good Octets RX Count Low - R/clr */
+pub(crate) const GORCH: Reg<u32> = Reg::new(0x0408C);	/* Good Octets RX Count High - R/clr */
+pub(crate) const GOTCL: Reg<u32> = Reg::new(0x04090);	/* Good Octets TX Count Low - R/clr */
+pub(crate) const GOTCH: Reg<u32> = Reg::new(0x04094);	/* Good Octets TX Count High - R/clr */
@@ -1,69 +1,113 @@
-use kernel::net::SkBuff;
+use kernel::net::{SkBuff, PoolPage, XdpFrame};
 use kernel::prelude::*;
-use kernel::dma;
-use core::cell::RefCell;
+use kernel::{bindings, device, dma};
+use kernel::io_buffer::ReadableFromBytes;
 use crate::hw_defs::{RxDescEntry, TxDescEntry};
 
 /// 一个由 SkBuff 和其 DMA 映射组成的元组
 pub(crate) type SkbDma = (dma::MapSingle::<u8>, ARef<SkBuff>);
 
-/// 对 `dma::Allocation` 的切片视图
-pub(crate) struct DmaAllocSlice<T> {
-    desc: dma::Allocation::<T>,  // DMA 分配的描述符
-    count: usize,  // 描述符的数量
+/// 发送描述符对应的缓冲区
+pub(crate) enum TxBuf {
+    Skb(SkbDma),  // 协议栈通过 start_xmit 发送的 skb
+    XdpPage(PoolPage),  // XDP_TX 原路发回的接收页，发送完成后回收到 page pool
+    XdpFrame(dma::MapSingle::<u8>, XdpFrame),  // 通过 ndo_xdp_xmit 重定向过来的帧
+    Bounce,  // 已经拷贝到弹跳缓冲区的小包，skb 在发送时就释放了，长度记录在描述符中
 }
 
-impl<T> DmaAllocSlice<T> {
-    /// 返回描述符的可变切片视图
-    pub(crate) fn as_desc_slice(&mut self) -> &mut [T] {
-        // 使用不安全代码从原始指针创建可变切片
-        unsafe { core::slice::from_raw_parts_mut(self.desc.cpu_addr, self.count) }
-    }
+/// 描述符环在内存中的位置和大小，配置网卡的队列寄存器时只需要这些信息，
+/// 与描述符的格式无关
+#[derive(Clone, Copy)]
+pub(crate) struct RingLayout {
+    pub(crate) dma_addr: u64,  // 第一个描述符的 DMA 地址
+    pub(crate) len: usize,  // 描述符的数量
+}
 
-    /// 获取 DMA 地址
-    pub(crate) fn get_dma_addr(&self) -> usize {
-        self.desc.dma_handle as usize
+/// 接收环在两次 NAPI 轮询之间需要保存的软件状态
+#[derive(Default)]
+pub(crate) struct RxState {
+    pub(crate) skb_top: Option<ARef<SkBuff>>,  // 跨多个描述符的帧中已经收到的部分，对应于 C 版本的 rx_skb_top
+    pub(crate) discarding: bool,  // 正在丢弃一个帧剩下的描述符，直到遇到 EOP
+}
+
+/// 预先映射好的发送弹跳缓冲区，每个发送描述符对应一个固定大小的槽位。
+///
+/// 小包直接拷贝到描述符对应的槽位中发送，省去每个包的 DMA 映射和解除映射。
+/// 描述符空闲时它的槽位一定没有被网卡使用，因此不需要单独管理槽位的分配。
+#[derive(Default)]
+pub(crate) struct TxBounce {
+    bufs: Option<dma::Allocation<u8>>,  // 所有槽位所在的一致性 DMA 内存，关闭弹跳缓冲区时为 None
+    count: usize,  // 槽位的数量，与描述符数量相同
+    slot_size: usize,  // 每个槽位的大小，即可以拷贝的最大包长
+}
+
+// SAFETY: 一致性 DMA 内存只属于这个发送环，只能通过环的 `&mut` 引用写入
+unsafe impl Send for TxBounce {}
+
+impl TxBounce {
+    /// 为 `count` 个描述符分配弹跳缓冲区，`slot_size` 为 0 时不分配
+    pub(crate) fn try_new(dev: &dyn device::RawDevice, count: usize, slot_size: usize) -> Result<Self> {
+        if slot_size == 0 {
+            return Ok(Self::default());
+        }
+        let bufs = dma::Allocation::try_new(dev, count * slot_size, bindings::GFP_KERNEL)?;
+        Ok(Self { bufs: Some(bufs), count, slot_size })
     }
 
-    /// 获取 CPU 地址
-    pub(crate) fn get_cpu_addr(&self) -> usize {
-        self.desc.cpu_addr as usize
+    /// 把 `data` 拷贝到第 `idx` 个描述符的槽位中，返回槽位的 DMA 地址。
+    /// 包比槽位大或者没有分配弹跳缓冲区时返回 None，由调用者映射 skb
+    pub(crate) fn copy_in(&mut self, idx: usize, data: &[u8]) -> Option<u64> {
+        let bufs = self.bufs.as_mut()?;
+        if idx >= self.count || data.len() > self.slot_size {
+            return None;
+        }
+
+        // 分配时为每个描述符预留了 `slot_size` 字节，描述符空闲时网卡不会读取这个槽位
+        let offset = idx * self.slot_size;
+        bufs.as_mut_slice()[offset..offset + data.len()].copy_from_slice(data);
+        Some(bufs.dma_handle as u64 + offset as u64)
     }
 }
 
-/// 环形缓冲区结构体
-pub(crate) struct RingBuf<T> {
-    pub(crate) desc: DmaAllocSlice<T>,  // DMA 描述符的切片视图
-    pub(crate) buf: RefCell<Vec<Option<SkbDma>>>,  // 包含 SkbDma 的可变缓冲区
-    pub(crate) next_to_clean: usize,  // 下一个要清理的描述符索引
+/// 环形缓冲区结构体，`B` 是每个描述符对应的缓冲区类型，`S` 是整个环的软件状态。
+///
+/// 缓冲区和描述符一起放在队列的自旋锁中，只能通过 `&mut RingBuf` 访问，
+/// 因此 NAPI、发送和回收路径之间不需要内部可变性。
+pub(crate) struct RingBuf<T: ReadableFromBytes, B, S = ()> {
+    pub(crate) desc: dma::DescRing<T>,  // DMA 描述符环，同时记录下一个要清理的描述符索引
+    pub(crate) buf: Vec<Option<B>>,  // 每个描述符对应的缓冲区
+    pub(crate) state: S,  // 环的软件状态，随环一起释放
 }
 
-impl<T> RingBuf<T> {
-    /// 创建一个新的环形缓冲区
-    pub(crate) fn new(desc: dma::Allocation::<T>, len: usize) -> Self {
-        // 创建一个新的可变缓冲区
-        let buf = RefCell::new(Vec::new());
-
+impl<T: ReadableFromBytes, B, S: Default> RingBuf<T, B, S> {
+    /// 创建一个新的环形缓冲区，每个描述符对应一个空的缓冲区槽位
+    pub(crate) fn new(desc: dma::DescRing<T>) -> Result<Self> {
         // 初始化缓冲区，填充 None
-        {
-            let mut buf_ref = buf.borrow_mut();
-            for _ in 0..len {
-                buf_ref.try_push(None).unwrap();
-            }
+        let len = desc.len();
+        let mut buf = Vec::try_with_capacity(len)?;
+        for _ in 0..len {
+            buf.try_push(None)?;
         }
 
-        // 创建 DMA 描述符的切片视图
-        let desc = DmaAllocSlice {
-            desc,
-            count: len,
-        };
-
         // 返回新的环形缓冲区实例
-        Self { desc, buf, next_to_clean: 0 }
+        Ok(Self { desc, buf, state: S::default() })
+    }
+
+    /// 返回环中描述符的数量
+    pub(crate) fn len(&self) -> usize {
+        self.desc.len()
+    }
+
+    /// 返回用于配置队列寄存器的环信息
+    pub(crate) fn layout(&self) -> RingLayout {
+        RingLayout {
+            dma_addr: self.desc.dma_handle() as u64,
+            len: self.len(),
+        }
     }
 }
 
-// 为接收描述符定义类型别名
-pub(crate) type RxRingBuf = RingBuf<RxDescEntry>;
-// 为发送描述符定义类型别名
-pub(crate) type TxRingBuf = RingBuf<TxDescEntry>;
+// 为接收描述符定义类型别名，接收缓冲区是从 page pool 分配的页，整个生命周期内保持 DMA 映射
+pub(crate) type RxRingBuf = RingBuf<RxDescEntry, PoolPage, RxState>;
+// 为发送描述符定义类型别名，小包使用环中的弹跳缓冲区发送
+pub(crate) type TxRingBuf = RingBuf<TxDescEntry, TxBuf, TxBounce>;
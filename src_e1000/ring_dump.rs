@@ -0,0 +1,125 @@
+use kernel::prelude::*;
+use kernel::{c_str, debugfs, dma, seq_print};
+use kernel::sync::Arc;
+
+use crate::consts::*;
+use crate::ring_buf::TxBuf;
+use crate::NetDevicePrvData;
+
+/// debugfs 中的 rings 文件，每次打开时把所有收发环画成一张表，排查发送队列卡住（xmit busy）时使用。
+///
+/// 每一行是一个描述符：索引、缓冲区 DMA 地址、长度、状态位、归属（hw 表示位于头尾指针之间，
+/// 仍由网卡处理）、对应的缓冲区，最后是落在这个描述符上的头尾指针和软件索引。
+pub(crate) struct RingDump {
+    // 网络设备的私有数据，在 `E1000DrvPrvData` 中先删除文件再注销网络设备，因此一直有效
+    data: *const NetDevicePrvData,
+}
+
+// SAFETY: 只通过共享引用访问 `NetDevicePrvData`，它本身是 Send + Sync 的
+unsafe impl Send for RingDump {}
+unsafe impl Sync for RingDump {}
+
+impl RingDump {
+    /// 在 `parent` 目录中创建 rings 文件
+    pub(crate) fn try_new(parent: &Arc<debugfs::Dir>, data: &NetDevicePrvData) -> Result<debugfs::File<Self>> {
+        debugfs::File::try_new(c_str!("rings"), parent, Arc::try_new(Self { data })?)
+    }
+
+    // 描述符是否位于 [head, tail) 之间，即已经交给网卡但还没有处理完
+    fn owned_by_hw(idx: usize, head: usize, tail: usize, len: usize) -> bool {
+        dma::ring_used(head, idx, len) < dma::ring_used(head, tail, len)
+    }
+
+    // 打印落在描述符 `idx` 上的指针，并结束这一行
+    fn show_markers(m: &mut debugfs::SeqFile, idx: usize, markers: &[(&str, usize)]) {
+        for (name, pos) in markers {
+            if *pos == idx {
+                seq_print!(m, " <{}", name);
+            }
+        }
+        seq_print!(m, "\n");
+    }
+
+    fn show_tx(m: &mut debugfs::SeqFile, data: &NetDevicePrvData, qid: usize) {
+        let tdh = data.e1000_hw_ops.e1000_read_tx_queue_head(qid) as usize;
+        let tdt = data.e1000_hw_ops.e1000_read_tx_queue_tail(qid) as usize;
+
+        let tx_ring = data.tx_rings[qid].lock_irqdisable();
+        // 接口关闭时没有分配描述符环
+        let tx_ring = match tx_ring.as_ref() {
+            Some(tx_ring) => tx_ring,
+            None => {
+                seq_print!(m, "tx queue {}: not allocated\n\n", qid);
+                return;
+            }
+        };
+        let len = tx_ring.len();
+        let (tdh, tdt) = (tdh % len, tdt % len);
+        let ntu = tx_ring.desc.next_to_use();
+        let ntc = tx_ring.desc.next_to_clean();
+        let markers = [("TDH", tdh), ("TDT", tdt), ("next_to_use", ntu), ("next_to_clean", ntc)];
+
+        seq_print!(m, "tx queue {}: len={} TDH={} TDT={} next_to_use={} next_to_clean={} unused={}\n",
+            qid, len, tdh, tdt, ntu, ntc, dma::ring_unused(ntc, ntu, len));
+        seq_print!(m, "{:>5} {:>18} {:>5} {:>4} {:>4} {:>5} {:>5}\n", "idx", "dma_addr", "len", "cmd", "sta", "owner", "buf");
+        for (idx, desc) in tx_ring.desc.as_slice().iter().enumerate() {
+            let buf = match &tx_ring.buf[idx] {
+                Some(TxBuf::Skb(_)) => "skb",
+                Some(TxBuf::XdpPage(_)) => "page",
+                Some(TxBuf::XdpFrame(..)) => "frame",
+                Some(TxBuf::Bounce) => "bounce",
+                None => "-",
+            };
+            let owner = if Self::owned_by_hw(idx, tdh, tdt, len) { "hw" } else { "sw" };
+            seq_print!(m, "{:>5} {:#018x} {:>5} {:#04x} {:#04x} {:>5} {:>5}",
+                idx, desc.buf_addr, desc.length, desc.cmd, desc.sta, owner, buf);
+            Self::show_markers(m, idx, &markers);
+        }
+        seq_print!(m, "\n");
+    }
+
+    fn show_rx(m: &mut debugfs::SeqFile, data: &NetDevicePrvData, qid: usize) {
+        let rdh = data.e1000_hw_ops.e1000_read_rx_queue_head(qid) as usize;
+        let rdt = data.e1000_hw_ops.e1000_read_rx_queue_tail(qid) as usize;
+
+        let rx_ring = data.rx_rings[qid].lock_irqdisable();
+        let rx_ring = match rx_ring.as_ref() {
+            Some(rx_ring) => rx_ring,
+            None => {
+                seq_print!(m, "rx queue {}: not allocated\n\n", qid);
+                return;
+            }
+        };
+        let len = rx_ring.len();
+        let (rdh, rdt) = (rdh % len, rdt % len);
+        // 接收环没有单独记录清理位置，NAPI 从 RDT 的下一个描述符开始处理
+        let ntc = dma::ring_next(rdt, len);
+        let markers = [("RDH", rdh), ("RDT", rdt), ("next_to_clean", ntc)];
+
+        seq_print!(m, "rx queue {}: len={} RDH={} RDT={} next_to_clean={} skb_top={} discarding={}\n",
+            qid, len, rdh, rdt, ntc, rx_ring.state.skb_top.is_some(), rx_ring.state.discarding);
+        seq_print!(m, "{:>5} {:>18} {:>5} {:>4} {:>4} {:>5} {:>5}\n", "idx", "dma_addr", "len", "sta", "err", "owner", "buf");
+        for (idx, desc) in rx_ring.desc.as_slice().iter().enumerate() {
+            let buf = if rx_ring.buf[idx].is_some() { "page" } else { "-" };
+            let owner = if Self::owned_by_hw(idx, rdh, rdt, len) { "hw" } else { "sw" };
+            seq_print!(m, "{:>5} {:#018x} {:>5} {:#04x} {:#04x} {:>5} {:>5}",
+                idx, desc.buf_addr, desc.length, desc.status, desc.errors, owner, buf);
+            Self::show_markers(m, idx, &markers);
+        }
+        seq_print!(m, "\n");
+    }
+}
+
+impl debugfs::Show for RingDump {
+    fn show(&self, m: &mut debugfs::SeqFile) -> Result {
+        // SAFETY: 见 `data` 字段的说明
+        let data = unsafe { &*self.data };
+        for qid in 0..E1000_NUM_TX_QUEUES {
+            Self::show_tx(m, data, qid);
+        }
+        for qid in 0..E1000_NUM_RX_QUEUES {
+            Self::show_rx(m, data, qid);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use kernel::prelude::*;
+use kernel::{bindings, device, dma, net};
+use kernel::delay::coarse_sleep;
+use kernel::sync::Arc;
+
+use core::iter;
+use core::time::Duration;
+
+use crate::e1000_ops::E1000Hw;
+use crate::hw_defs::TxDescEntry;
+use crate::ring_buf::{RingLayout, RxRingBuf};
+use crate::regs::{self, RegIo, Rctl};
+
+use crate::consts::*;
+
+// 自检帧的长度（不含 CRC），等于以太网的最小帧长，不需要填充
+const LOOPBACK_FRAME_LEN: usize = 60;
+// 自检帧的以太网类型，0x88B5 保留给本地实验使用
+const LOOPBACK_ETH_TYPE: [u8; 2] = [0x88, 0xb5];
+// 自检时每个环的描述符数量，描述符环的长度必须是 128 字节的倍数
+const LOOPBACK_RING_SIZE: usize = 8;
+// 等待回环帧的最长时间，以毫秒为单位
+const LOOPBACK_TIMEOUT_MS: u64 = 100;
+
+/// 回环自检，对应于 C 版本 ethtool 自检中的 `e1000_loopback_test`，但只发送一个帧。
+///
+/// 使用临时的收发环配置网卡并打开回环模式，发送一个广播帧，检查它是否原样出现在接收环的
+/// 第一个描述符中，最后停止收发单元并关闭回环。网卡的中断保持屏蔽，不需要中断处理程序。
+/// 描述符环或寄存器的配置有误时，这里会直接失败，而不需要在 QEMU 中配置网络再去排查。
+pub(crate) fn e1000_loopback_test(
+    dev: &dyn device::RawDevice,
+    hw: &dyn E1000Hw,
+    page_pool: &Arc<net::PagePool>,
+    mac_addr: &[u8; 6],
+) -> Result {
+    // 接收环的每个描述符都放一个页，与 open 中相同
+    let mut rx_ring = RxRingBuf::new(dma::DescRing::try_new(dev, LOOPBACK_RING_SIZE, bindings::GFP_KERNEL)?)?;
+    for (idx, desc) in rx_ring.desc.as_mut_slice().iter_mut().enumerate() {
+        let page = page_pool.alloc()?;
+        desc.buf_addr = page.dma_addr() + E1000_RX_HEADROOM as u64;
+        rx_ring.buf[idx] = Some(page);
+    }
+    let mut tx_ring = dma::DescRing::<TxDescEntry>::try_new(dev, LOOPBACK_RING_SIZE, bindings::GFP_KERNEL)?;
+
+    // 构造自检帧：广播目的地址、本机源地址，负载为递增的字节
+    let mut tx_page = page_pool.alloc()?;
+    let frame = &mut tx_page.data_mut()[..LOOPBACK_FRAME_LEN];
+    frame[..6].fill(0xff);
+    frame[6..12].copy_from_slice(mac_addr);
+    frame[12..14].copy_from_slice(&LOOPBACK_ETH_TYPE);
+    for (i, b) in frame[14..].iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    tx_page.sync_for_device(0, LOOPBACK_FRAME_LEN as u32);
+
+    // 不调用 `e1000_configure`，它会打开中断，而 probe 时还没有注册中断处理程序
+    hw.e1000_irq_disable();
+    let ret = e1000_loopback_run(dev, hw, &rx_ring, &mut tx_ring, &tx_page);
+
+    // 无论自检是否成功，都要先停止收发单元再释放环。接收控制寄存器在 open 中重新配置
+    hw.e1000_stop_rxtx()?;
+
+    ret
+}
+
+// 配置收发环并打开回环，发送自检帧并检查收到的帧，最后关闭 PHY 回环
+fn e1000_loopback_run(
+    dev: &dyn device::RawDevice,
+    hw: &dyn E1000Hw,
+    rx_ring: &RxRingBuf,
+    tx_ring: &mut dma::DescRing<TxDescEntry>,
+    tx_page: &net::PoolPage,
+) -> Result {
+    hw.e1000_configure_rx(&mut iter::once(rx_ring.layout()))?;
+    hw.e1000_configure_tx(&mut iter::once(RingLayout {
+        dma_addr: tx_ring.dma_handle() as u64,
+        len: tx_ring.len(),
+    }))?;
+
+    // 打开 MAC 回环。QEMU 只模拟了 PHY 回环，因此同时打开 PHY 的回环模式
+    hw.regs().modify(regs::RCTL, |rctl| rctl | Rctl::LBM_MAC)?;
+    let bmcr = hw.e1000_read_phy_reg(E1000_PHY_ADDR, bindings::MII_BMCR as u8)?;
+    hw.e1000_write_phy_reg(E1000_PHY_ADDR, bindings::MII_BMCR as u8, bmcr | bindings::BMCR_LOOPBACK as u16)?;
+
+    // 发送自检帧
+    let tdt = tx_ring.advance_use();
+    let tx_desc = &mut tx_ring.as_mut_slice()[tdt];
+    tx_desc.buf_addr = tx_page.dma_addr();
+    tx_desc.length = LOOPBACK_FRAME_LEN as u16;
+    tx_desc.cmd = ((E1000_TXD_CMD_RS | E1000_TXD_CMD_EOP) >> 24) as u8;
+    tx_desc.sta = 0;
+    hw.e1000_write_tx_queue_tail(0, tx_ring.next_to_use() as u32);
+
+    let ret = e1000_loopback_check(dev, rx_ring, &tx_page.data()[..LOOPBACK_FRAME_LEN]);
+
+    hw.e1000_write_phy_reg(E1000_PHY_ADDR, bindings::MII_BMCR as u8, bmcr)?;
+    ret
+}
+
+// 等待接收环的第一个描述符完成，并检查收到的帧与发送的帧是否相同
+fn e1000_loopback_check(dev: &dyn device::RawDevice, rx_ring: &RxRingBuf, expected: &[u8]) -> Result {
+    let desc = &rx_ring.desc.as_slice()[0];
+    let mut waited = 0;
+    // 描述符由网卡写回，循环中必须每次重新读取
+    // SAFETY: `desc` 是一个有效的引用
+    while unsafe { core::ptr::read_volatile(&desc.status) } & E1000_RXD_STAT_DD as u8 == 0 {
+        if waited == LOOPBACK_TIMEOUT_MS {
+            dev_err!(dev, "loopback self-test: no frame received\n");
+            return Err(ETIMEDOUT);
+        }
+        coarse_sleep(Duration::from_millis(1));
+        waited += 1;
+    }
+
+    let len = desc.length as usize;
+    if desc.status as u32 & E1000_RXD_STAT_EOP == 0 || desc.errors as u32 & E1000_RXD_ERR_FRAME_ERR_MASK != 0 {
+        dev_err!(dev, "loopback self-test: bad descriptor, status {:#x} errors {:#x}\n", desc.status, desc.errors);
+        return Err(EIO);
+    }
+    if len != expected.len() {
+        dev_err!(dev, "loopback self-test: received {} bytes, expected {}\n", len, expected.len());
+        return Err(EIO);
+    }
+
+    let page = rx_ring.buf[0].as_ref().unwrap();
+    page.sync_for_cpu(E1000_RX_HEADROOM, len as u32);
+    let offset = E1000_RX_HEADROOM as usize;
+    if page.data()[offset..offset + len] != *expected {
+        dev_err!(dev, "loopback self-test: received frame differs from the one sent\n");
+        return Err(EIO);
+    }
+
+    Ok(())
+}
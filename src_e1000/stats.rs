@@ -0,0 +1,284 @@
+use kernel::prelude::*;
+use kernel::net;
+use kernel::percpu::PerCpu;
+use kernel::sync::{Arc, UniqueArc};
+use kernel::workqueue::{self, DelayedWork};
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::e1000_ops::E1000Hw;
+use crate::consts::{E1000_NUM_RX_QUEUES, E1000_NUM_TX_QUEUES};
+use crate::regs::Icr;
+
+/// 硬件统计寄存器的刷新周期
+pub(crate) const STATS_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 从硬件统计寄存器累加得到的计数器。
+///
+/// e1000 的统计寄存器是读清零的，因此每次刷新都把读到的值累加到这里，
+/// 读取这些原子变量不会访问 MMIO。收发包数和字节数由数据通路的 `PerCpuStats` 统计。
+#[derive(Default)]
+pub(crate) struct HwStats {
+    pub(crate) rx_packets: AtomicU64,
+    pub(crate) tx_packets: AtomicU64,
+    pub(crate) rx_bytes: AtomicU64,
+    pub(crate) tx_bytes: AtomicU64,
+    pub(crate) multicast: AtomicU64,
+    pub(crate) collisions: AtomicU64,
+    pub(crate) rx_crc_errors: AtomicU64,
+    pub(crate) rx_missed_errors: AtomicU64,
+}
+
+impl HwStats {
+    /// 读取一个计数器的当前值
+    pub(crate) fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+
+    /// 把一次寄存器读数累加到计数器上
+    pub(crate) fn add(counter: &AtomicU64, val: u64) {
+        counter.fetch_add(val, Ordering::Relaxed);
+    }
+}
+
+/// 一个队列的收发包数和字节数
+#[derive(Clone, Copy, Default)]
+pub(crate) struct QueueStats {
+    pub(crate) packets: u64,
+    pub(crate) bytes: u64,
+}
+
+impl QueueStats {
+    /// 累加一次收发的包数和字节数
+    pub(crate) fn add(&mut self, packets: u64, bytes: u64) {
+        self.packets += packets;
+        self.bytes += bytes;
+    }
+}
+
+/// 数据通路上每个包都会更新的计数器。每个 CPU 一份，更新时不需要锁和原子操作，
+/// 读取时把所有 CPU 的值加起来
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PathStats {
+    pub(crate) rx_queues: [QueueStats; E1000_NUM_RX_QUEUES],
+    pub(crate) tx_queues: [QueueStats; E1000_NUM_TX_QUEUES],
+    pub(crate) tx_bounce: u64,  // 拷贝到弹跳缓冲区发送、没有映射 skb 的包数
+    pub(crate) rx_csum_good: u64,  // 硬件校验和检查通过的包数
+    pub(crate) rx_csum_bad: u64,  // 硬件报告校验和错误的包数
+}
+
+impl PathStats {
+    // 把另一个 CPU 的计数器加到这里
+    fn add(mut self, other: &PathStats) -> Self {
+        for (q, o) in self.rx_queues.iter_mut().zip(other.rx_queues.iter()) {
+            q.add(o.packets, o.bytes);
+        }
+        for (q, o) in self.tx_queues.iter_mut().zip(other.tx_queues.iter()) {
+            q.add(o.packets, o.bytes);
+        }
+        self.tx_bounce += other.tx_bounce;
+        self.rx_csum_good += other.rx_csum_good;
+        self.rx_csum_bad += other.rx_csum_bad;
+        self
+    }
+}
+
+/// 驱动的软件计数器，通过 ethtool -S 导出。
+///
+/// 每个包都要更新的计数器放在每 CPU 的 `PathStats` 中，其余只在出错或者中断时更新的计数器使用原子变量
+pub(crate) struct DrvStats {
+    pub(crate) path: PerCpu<PathStats>,  // 数据通路的计数器，不能在关闭中断时更新
+    pub(crate) restart_queue: AtomicU64,  // 发送队列被重新启动的次数
+    pub(crate) alloc_failures: AtomicU64,  // 接收路径上分配页或 SKB 失败的次数，这些包被丢弃
+    pub(crate) rx_errors: AtomicU64,  // 因为描述符报告错误而丢弃的帧
+    pub(crate) rx_crc_errors: AtomicU64,  // 其中 CRC 错误的帧
+    pub(crate) rx_multi_buf_dropped: AtomicU64,  // 因为 XDP 或者分片过多而丢弃的跨多个描述符的帧
+    pub(crate) tx_dma_failed: AtomicU64,  // 发送路径上 DMA 映射失败的次数
+    pub(crate) tx_dropped: AtomicU64,  // 协议栈交给驱动但没有发送的包数
+    pub(crate) irqs: AtomicU64,  // 属于本设备的中断次数
+    pub(crate) irq_txdw: AtomicU64,  // 发送描述符写回
+    pub(crate) irq_rxt0: AtomicU64,  // 接收定时器到期，即收到了包
+    pub(crate) irq_rxdmt0: AtomicU64,  // 空闲的接收描述符低于阈值，接收环快要耗尽
+    pub(crate) irq_rxseq: AtomicU64,  // 接收序列错误
+    pub(crate) irq_lsc: AtomicU64,  // 链路状态变化
+    pub(crate) irq_rxo: AtomicU64,  // 接收 FIFO 溢出，包在进入接收环之前就被丢弃了
+}
+
+// ethtool -S 中与队列无关的计数器，顺序与 `DrvStats::for_each` 一致
+const DRV_STAT_NAMES: [&str; 17] = [
+    "restart_queue", "alloc_failures", "rx_errors", "rx_crc_errors", "rx_multi_buf_dropped", "tx_dma_failed", "tx_dropped", "tx_bounce", "irqs",
+    "rx_csum_good", "rx_csum_bad",
+    "irq_txdw", "irq_rxt0", "irq_rxdmt0", "irq_rxseq", "irq_lsc", "irq_rxo",
+];
+
+impl DrvStats {
+    /// 创建全部为 0 的计数器
+    pub(crate) fn try_new() -> Result<Self> {
+        Ok(Self {
+            path: PerCpu::try_new()?,
+            restart_queue: AtomicU64::new(0),
+            alloc_failures: AtomicU64::new(0),
+            rx_errors: AtomicU64::new(0),
+            rx_crc_errors: AtomicU64::new(0),
+            rx_multi_buf_dropped: AtomicU64::new(0),
+            tx_dma_failed: AtomicU64::new(0),
+            tx_dropped: AtomicU64::new(0),
+            irqs: AtomicU64::new(0),
+            irq_txdw: AtomicU64::new(0),
+            irq_rxt0: AtomicU64::new(0),
+            irq_rxdmt0: AtomicU64::new(0),
+            irq_rxseq: AtomicU64::new(0),
+            irq_lsc: AtomicU64::new(0),
+            irq_rxo: AtomicU64::new(0),
+        })
+    }
+
+    /// 在当前 CPU 上累加队列 `qid` 收到的包
+    pub(crate) fn rx_add(&self, qid: usize, packets: u64, bytes: u64) {
+        self.path.update(|s| s.rx_queues[qid].add(packets, bytes));
+    }
+
+    /// 在当前 CPU 上累加队列 `qid` 发送完成的包
+    pub(crate) fn tx_add(&self, qid: usize, packets: u64, bytes: u64) {
+        self.path.update(|s| s.tx_queues[qid].add(packets, bytes));
+    }
+
+    /// 返回所有 CPU 上数据通路计数器的和
+    pub(crate) fn fetch_path(&self) -> PathStats {
+        self.path.fold(PathStats::default(), PathStats::add)
+    }
+
+    /// ethtool -S 中的计数器数量
+    pub(crate) const fn count() -> u32 {
+        (DRV_STAT_NAMES.len() + 2 * (E1000_NUM_RX_QUEUES + E1000_NUM_TX_QUEUES)) as u32
+    }
+
+    /// 填写计数器的名字，对应于 C 版本的 `e1000_get_strings`
+    pub(crate) fn fill_strings(strings: &mut net::EthtoolStrings<'_>) {
+        for qid in 0..E1000_NUM_RX_QUEUES {
+            strings.add(fmt!("rx_queue_{}_packets", qid));
+            strings.add(fmt!("rx_queue_{}_bytes", qid));
+        }
+        for qid in 0..E1000_NUM_TX_QUEUES {
+            strings.add(fmt!("tx_queue_{}_packets", qid));
+            strings.add(fmt!("tx_queue_{}_bytes", qid));
+        }
+        for name in DRV_STAT_NAMES {
+            strings.add(fmt!("{}", name));
+        }
+    }
+
+    /// 按 `fill_strings` 的顺序填写计数器的值，对应于 C 版本的 `e1000_get_ethtool_stats`
+    pub(crate) fn fill_stats(&self, stats: &mut net::EthtoolStats<'_>) {
+        // 闭包不会失败
+        let _ = self.for_each(|_, value| {
+            stats.add(value);
+            Ok(())
+        });
+    }
+
+    /// 按 ethtool -S 的顺序，用每个计数器的名字和值调用 `f`，`f` 失败时停止并返回错误
+    pub(crate) fn for_each(&self, mut f: impl FnMut(fmt::Arguments<'_>, u64) -> Result) -> Result {
+        let path = self.fetch_path();
+        for (qid, q) in path.rx_queues.iter().enumerate() {
+            f(fmt!("rx_queue_{}_packets", qid), q.packets)?;
+            f(fmt!("rx_queue_{}_bytes", qid), q.bytes)?;
+        }
+        for (qid, q) in path.tx_queues.iter().enumerate() {
+            f(fmt!("tx_queue_{}_packets", qid), q.packets)?;
+            f(fmt!("tx_queue_{}_bytes", qid), q.bytes)?;
+        }
+        let values: [u64; DRV_STAT_NAMES.len()] = [
+            HwStats::get(&self.restart_queue),
+            HwStats::get(&self.alloc_failures),
+            HwStats::get(&self.rx_errors),
+            HwStats::get(&self.rx_crc_errors),
+            HwStats::get(&self.rx_multi_buf_dropped),
+            HwStats::get(&self.tx_dma_failed),
+            HwStats::get(&self.tx_dropped),
+            path.tx_bounce,
+            HwStats::get(&self.irqs),
+            path.rx_csum_good,
+            path.rx_csum_bad,
+            HwStats::get(&self.irq_txdw),
+            HwStats::get(&self.irq_rxt0),
+            HwStats::get(&self.irq_rxdmt0),
+            HwStats::get(&self.irq_rxseq),
+            HwStats::get(&self.irq_lsc),
+            HwStats::get(&self.irq_rxo),
+        ];
+        for (name, value) in DRV_STAT_NAMES.iter().zip(values) {
+            f(fmt!("{}", name), value)?;
+        }
+        Ok(())
+    }
+
+    /// 按 ICR 中的每个原因分别计数。中断屏蔽寄存器只决定是否产生中断，ICR 中仍然会报告
+    /// 没有打开的原因，例如接收溢出，因此可以区分接收溢出和接收描述符耗尽
+    pub(crate) fn count_irq_causes(&self, icr: Icr) {
+        let causes = [
+            (Icr::TXDW, &self.irq_txdw),
+            (Icr::RXT0, &self.irq_rxt0),
+            (Icr::RXDMT0, &self.irq_rxdmt0),
+            (Icr::RXSEQ, &self.irq_rxseq),
+            (Icr::LSC, &self.irq_lsc),
+            (Icr::RXO, &self.irq_rxo),
+        ];
+        for (bit, counter) in causes {
+            if icr.contains(bit) {
+                HwStats::add(counter, 1);
+            }
+        }
+    }
+}
+
+/// 周期性刷新硬件统计信息的延迟工作项
+pub(crate) struct StatsWatchdog {
+    e1000_hw_ops: Arc<dyn E1000Hw>,  // e1000 硬件操作的引用计数指针
+    pub(crate) stats: HwStats,  // 累加后的统计信息
+    running: AtomicBool,  // 接口是否处于打开状态，为 false 时工作项不再重新排队
+    dwork: DelayedWork,  // 延迟工作项
+}
+
+kernel::impl_self_delayed_work_adapter!(StatsWatchdog, dwork, |w| {
+    if !w.running.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // 在进程上下文中读取统计寄存器，避免在 get_stats64 中访问硬件
+    w.e1000_hw_ops.e1000_update_stats(&w.stats);
+
+    // 重新排队，等待下一个刷新周期
+    if w.running.load(Ordering::Relaxed) {
+        workqueue::system().enqueue_delayed(w, STATS_REFRESH_INTERVAL);
+    }
+});
+
+impl StatsWatchdog {
+    /// 创建统计刷新工作项，此时并不会开始运行
+    pub(crate) fn try_new(e1000_hw_ops: Arc<dyn E1000Hw>) -> Result<Arc<Self>> {
+        let w = UniqueArc::try_new(StatsWatchdog {
+            e1000_hw_ops,
+            stats: HwStats::default(),
+            running: AtomicBool::new(false),
+            // SAFETY: `dwork` 在下方被初始化
+            dwork: unsafe { DelayedWork::new() },
+        })?;
+        kernel::init_delayed_work_item!(&w);
+        Ok(w.into())
+    }
+
+    /// 开始周期性刷新，在 `open` 中调用
+    pub(crate) fn start(self: &Arc<Self>) {
+        self.running.store(true, Ordering::Relaxed);
+        workqueue::system().enqueue_delayed(self.clone(), STATS_REFRESH_INTERVAL);
+    }
+
+    /// 停止周期性刷新并等待正在运行的工作项结束，在 `stop` 中调用
+    pub(crate) fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+        DelayedWork::cancel::<Self>(self);
+    }
+}
@@ -0,0 +1,143 @@
+use kernel::prelude::*;
+use kernel::{device, net};
+use kernel::sync::{Arc, UniqueArc};
+use kernel::workqueue::{self, DelayedWork, Work};
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::time::Duration;
+
+use crate::e1000_ops::E1000Hw;
+use crate::consts::*;
+use crate::regs::{self, RegIo, Status};
+
+/// 检查发送单元是否挂起的周期
+pub(crate) const TX_HANG_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+// 一个发送队列在上一次检查时的状态
+#[derive(Default)]
+struct TxQueueProgress {
+    tdh: AtomicU32,  // 上一次检查时的 TDH
+    pending: AtomicBool,  // 上一次检查时是否有交给网卡但还没有发送完的描述符
+}
+
+/// 检测发送单元挂起的延迟工作项，对应于 C 版本 `e1000_clean_tx_irq` 中的 Tx Unit Hang 检测。
+///
+/// 每个周期读取各发送队列的 TDH 和 TDT。如果连续两次检查时队列中都有待发送的描述符，而 TDH
+/// 没有前进，就认为发送单元挂起了，调度 `ResetTask` 复位网卡。链路断开时发送停滞是正常的，不做检查。
+pub(crate) struct TxHangWatchdog {
+    dev: Arc<device::Device>,  // 打印日志使用的设备
+    e1000_hw_ops: Arc<dyn E1000Hw>,  // e1000 硬件操作的引用计数指针
+    reset_task: Arc<ResetTask>,  // 检测到挂起时调度的复位工作项
+    queues: [TxQueueProgress; E1000_NUM_TX_QUEUES],  // 每个发送队列上一次检查时的状态
+    running: AtomicBool,  // 接口是否处于打开状态，为 false 时工作项不再重新排队
+    dwork: DelayedWork,  // 延迟工作项
+}
+
+kernel::impl_self_delayed_work_adapter!(TxHangWatchdog, dwork, |w| {
+    if !w.running.load(Ordering::Relaxed) {
+        return;
+    }
+
+    w.check();
+
+    // 重新排队，等待下一个检查周期
+    if w.running.load(Ordering::Relaxed) {
+        workqueue::system().enqueue_delayed(w, TX_HANG_CHECK_INTERVAL);
+    }
+});
+
+impl TxHangWatchdog {
+    /// 创建挂起检测工作项，此时并不会开始运行
+    pub(crate) fn try_new(
+        dev: Arc<device::Device>,
+        e1000_hw_ops: Arc<dyn E1000Hw>,
+        reset_task: Arc<ResetTask>,
+    ) -> Result<Arc<Self>> {
+        let w = UniqueArc::try_new(TxHangWatchdog {
+            dev,
+            e1000_hw_ops,
+            reset_task,
+            queues: Default::default(),
+            running: AtomicBool::new(false),
+            // SAFETY: `dwork` 在下方被初始化
+            dwork: unsafe { DelayedWork::new() },
+        })?;
+        kernel::init_delayed_work_item!(&w);
+        Ok(w.into())
+    }
+
+    /// 开始周期性检查，在 `open` 中调用。发送环是新分配的，之前记录的状态都作废
+    pub(crate) fn start(self: &Arc<Self>) {
+        for q in self.queues.iter() {
+            q.pending.store(false, Ordering::Relaxed);
+        }
+        self.running.store(true, Ordering::Relaxed);
+        workqueue::system().enqueue_delayed(self.clone(), TX_HANG_CHECK_INTERVAL);
+    }
+
+    /// 停止周期性检查并等待正在运行的工作项结束，在 `stop` 中调用
+    pub(crate) fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+        DelayedWork::cancel::<Self>(self);
+    }
+
+    // 比较各发送队列与上一次检查时的状态，发现挂起时调度复位
+    fn check(&self) {
+        let link_up = match self.e1000_hw_ops.regs().read(regs::STATUS) {
+            Ok(status) => status.contains(Status::LU),
+            Err(_) => false,
+        };
+
+        for (qid, q) in self.queues.iter().enumerate() {
+            let tdh = self.e1000_hw_ops.e1000_read_tx_queue_head(qid);
+            let tdt = self.e1000_hw_ops.e1000_read_tx_queue_tail(qid);
+            let pending = link_up && tdh != tdt;
+            let last_tdh = q.tdh.swap(tdh, Ordering::Relaxed);
+            let was_pending = q.pending.swap(pending, Ordering::Relaxed);
+
+            if pending && was_pending && tdh == last_tdh {
+                dev_err!(self.dev, "Detected Tx Unit Hang on queue {}: TDH <{}> TDT <{}>\n", qid, tdh, tdt);
+                // 复位之后重新开始判断，避免在复位完成之前重复报告
+                q.pending.store(false, Ordering::Relaxed);
+                self.reset_task.schedule();
+            }
+        }
+    }
+}
+
+/// 复位网卡的工作项，对应于 C 版本的 `e1000_reset_task`。
+///
+/// 复位需要睡眠并持有 RTNL 锁，不能在检测到挂起的地方直接进行，具体的步骤见 `NetDevice::e1000_reset_task`。
+///
+/// 不能持有网络设备的引用，否则注销网络设备时会一直等待引用计数归零，因此通过 NAPI 找到网络设备。
+pub(crate) struct ResetTask {
+    napi: Arc<net::Napi>,  // 要复位的网络设备的 NAPI
+    work: Work,  // 工作项
+}
+
+kernel::impl_self_work_adapter!(ResetTask, work, |w| {
+    crate::NetDevice::e1000_reset_task(&w.napi.dev_get());
+});
+
+impl ResetTask {
+    /// 创建复位工作项
+    pub(crate) fn try_new(napi: Arc<net::Napi>) -> Result<Arc<Self>> {
+        let w = UniqueArc::try_new(ResetTask {
+            napi,
+            // SAFETY: `work` 在下方被初始化
+            work: unsafe { Work::new() },
+        })?;
+        kernel::init_work_item!(&w);
+        Ok(w.into())
+    }
+
+    /// 调度复位，已经在排队时什么也不做
+    pub(crate) fn schedule(self: &Arc<Self>) {
+        workqueue::system().enqueue(self.clone());
+    }
+
+    /// 取消还没有运行的复位，并等待正在进行的复位结束
+    pub(crate) fn cancel(&self) {
+        Work::cancel::<Self>(self);
+    }
+}
@@ -0,0 +1,60 @@
+use kernel::io_buffer::ReadableFromBytes;
+use kernel::net::PoolPage;
+
+use crate::ring_buf::RingBuf;
+
+
+// 82574 的扩展接收描述符，定义在 82574 手册第 7.1.5.2 节。
+// 软件填写的读格式中 lower 为缓冲区地址、upper 为 0；硬件写回后 upper 的
+// 低 32 位是状态和错误位，32..48 位是包长度。
+#[repr(C)]
+pub(crate) struct RxDescExt {
+    #[allow(dead_code)] // 只由网卡读取
+    pub(crate) lower: u64,
+    pub(crate) upper: u64,
+}
+
+// SAFETY: The descriptor only contains integers, any bit pattern written by the device is valid.
+unsafe impl ReadableFromBytes for RxDescExt {}
+
+impl RxDescExt {
+    /// 设置接收缓冲区的地址并清除写回的状态，交还给网卡
+    pub(crate) fn set_buffer(&mut self, dma_addr: u64) {
+        self.lower = dma_addr;
+        self.upper = 0;
+    }
+
+    /// 写回的状态和错误位
+    pub(crate) fn status_error(&self) -> u32 {
+        self.upper as u32
+    }
+
+    /// 写回的包长度
+    pub(crate) fn length(&self) -> u32 {
+        ((self.upper >> 32) & 0xffff) as u32
+    }
+}
+
+// 82574 使用扩展接收描述符的接收环
+pub(crate) type ExtRxRingBuf = RingBuf<RxDescExt, PoolPage>;
+
+
+// QEMU 的 e1000e 模拟的是 82574L
+pub(crate) const E1000E_DEVICE_ID:u32 = 0x10D3;
+
+// 不超过这个长度的包拷贝到新的 SKB 中，接收页留在环中继续使用，对应于 e1000 的 copybreak 参数
+pub(crate) const E1000E_RX_COPYBREAK:u32 = 256;
+
+// 82574 特有的寄存器（CTRL_EXT、RFCTL、IVAR）和 MSI-X 模式下的中断原因定义在 regs.rs 中
+
+// IVAR 把每个中断原因映射到一个 MSI-X 向量，每个原因占 4 位，最高位表示映射有效
+pub(crate) const E1000_IVAR_VALID:u32 = 0x8;
+pub(crate) const E1000_IVAR_RXQ0_SHIFT:u32 = 0;
+pub(crate) const E1000_IVAR_TXQ0_SHIFT:u32 = 8;
+pub(crate) const E1000_IVAR_OTHER_SHIFT:u32 = 16;
+pub(crate) const E1000_IVAR_INT_ON_ALL_WB:u32 = 0x80000000;	/* Interrupt on every tx write back */
+
+/* Extended Receive Descriptor bit definitions */
+pub(crate) const E1000_RXDEXT_STAT_DD:u32 = 0x00000001;	/* Descriptor Done */
+pub(crate) const E1000_RXDEXT_STAT_EOP:u32 = 0x00000002;	/* End of Packet */
+pub(crate) const E1000_RXDEXT_ERR_RXE:u32 = 0x80000000;	/* Rx Data Error */
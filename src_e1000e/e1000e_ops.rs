@@ -0,0 +1,146 @@
+use kernel::prelude::*;
+use kernel::pci::MappedResource;
+use kernel::delay::coarse_sleep;
+use kernel::sync::Arc;
+
+use core::time::Duration;
+
+use crate::e1000_ops::E1000Hw;
+use crate::ring_buf::RingLayout;
+use crate::e1000e_defs::*;
+use crate::regs::{self, RegIo, Ctrl, CtrlExt, Icr, Rah, Rctl, Rfctl, Tctl, Tipg};
+
+use crate::consts::*;
+
+/// 82574（QEMU 的 e1000e）的硬件操作。
+///
+/// 描述符寄存器、统计寄存器和 MDIC 与 8254x 相同，使用 `E1000Hw` 的默认实现。
+/// 不同之处在于接收使用扩展描述符，并且在 MSI-X 模式下需要通过 IVAR 把中断原因映射到向量。
+pub(crate) struct E1000eOps {
+    pub(crate) mem_addr: Arc<MappedResource>, // 内存映射资源的引用
+    pub(crate) msix: bool, // 是否使用 MSI-X 中断
+}
+
+impl E1000eOps {
+    /// 读取 EEPROM 加载到接收地址寄存器 0 中的 MAC 地址
+    pub(crate) fn e1000e_read_mac_addr(&self) -> Result<[u8; 6]> {
+        let ral = self.mem_addr.read(regs::RAL)?;
+        let rah = self.mem_addr.read(regs::RAH)?;
+        if !rah.contains(Rah::AV) {
+            return Err(EIO);
+        }
+
+        let mut addr = [0u8; 6];
+        addr[..4].copy_from_slice(&ral.to_le_bytes());
+        addr[4..].copy_from_slice(&rah.0.to_le_bytes()[..2]);
+        Ok(addr)
+    }
+
+    /// 返回发送 NAPI 和接收 NAPI 各自处理的中断原因。MSI-X 模式下收发队列的中断原因与 8254x 不同
+    pub(crate) fn e1000e_irq_masks(&self) -> (Icr, Icr) {
+        if self.msix {
+            (Icr::TXQ0, Icr::RXQ0 | Icr::OTHER | Icr::LSC)
+        } else {
+            (regs::IMS_TX_MASK, regs::IMS_RX_MASK)
+        }
+    }
+
+    // 把所有中断原因映射到 MSI-X 向量 0
+    fn e1000e_configure_msix(&self) -> Result {
+        let ivar = E1000_IVAR_INT_ON_ALL_WB |
+            E1000_IVAR_VALID << E1000_IVAR_RXQ0_SHIFT |
+            E1000_IVAR_VALID << E1000_IVAR_TXQ0_SHIFT |
+            E1000_IVAR_VALID << E1000_IVAR_OTHER_SHIFT;
+        self.mem_addr.write(regs::IVAR, ivar)?;
+
+        self.mem_addr.modify(regs::CTRL_EXT, |ctrl_ext| ctrl_ext | CtrlExt::PBA_CLR)?;
+        self.e1000_write_flush();
+        Ok(())
+    }
+}
+
+impl E1000Hw for E1000eOps {
+    fn regs(&self) -> &MappedResource {
+        &self.mem_addr
+    }
+
+    /// 完全重置硬件，对应于 C 版本 e1000e 的 `e1000_reset_hw_82571`。
+    fn e1000_reset_hw(&self) -> Result {
+        // 屏蔽所有中断，停止收发单元，等待正在进行的 PCI 事务完成
+        self.mem_addr.write(regs::IMC, Icr::ALL)?;
+        self.mem_addr.write(regs::RCTL, Rctl::empty())?;
+        self.mem_addr.write(regs::TCTL, Tctl::PSP)?;
+        self.e1000_write_flush();
+        coarse_sleep(Duration::from_millis(10));
+
+        // 82574 可以直接通过 MMIO 发出复位，不需要 8254x 的 I/O 端口
+        self.mem_addr.modify(regs::CTRL, |ctrl| ctrl | Ctrl::RST)?;
+
+        // 等待 EEPROM 重新加载完成，之后 RAL/RAH 中是 EEPROM 中的 MAC 地址
+        coarse_sleep(Duration::from_millis(20));
+
+        // 再次屏蔽中断并清除挂起的中断原因
+        self.mem_addr.write(regs::IMC, Icr::ALL)?;
+        self.mem_addr.read(regs::ICR)?;
+
+        Ok(())
+    }
+
+    // 配置发送缓冲区，82574 仍然支持传统发送描述符
+    fn e1000_configure_tx(&self, tx_rings: &mut dyn Iterator<Item = RingLayout>) -> Result {
+        // 82574 每个方向有两个队列，寄存器布局与 8257x 相同
+        for (qid, tx_ring) in tx_rings.enumerate() {
+            self.mem_addr.write(regs::TDH.queue(qid), 0)?;
+            self.mem_addr.write(regs::TDT.queue(qid), 0)?;
+            self.mem_addr.write(regs::TDLEN.queue(qid), (tx_ring.len * 16) as u32)?;
+            self.mem_addr.write(regs::TDBAL.queue(qid), tx_ring.dma_addr as u32)?;
+            self.mem_addr.write(regs::TDBAH.queue(qid), (tx_ring.dma_addr >> 32) as u32)?;
+        }
+
+        let tctl = (Tctl::EN | Tctl::PSP).with_ct(0x10).with_cold(0x40);
+        self.mem_addr.write(regs::TCTL, tctl)?;
+
+        let tipg = Tipg::new(DEFAULT_82543_TIPG_IPGT_COPPER, DEFAULT_82543_TIPG_IPGR1, DEFAULT_82543_TIPG_IPGR2);
+        self.mem_addr.write(regs::TIPG, tipg)?;
+
+        Ok(())
+    }
+
+    // 配置接收缓冲区，使用扩展接收描述符
+    fn e1000_configure_rx(&self, rx_rings: &mut dyn Iterator<Item = RingLayout>) -> Result {
+        // 清除多播地址表中的所有条目，MAC 地址保留 EEPROM 加载的值
+        for i in 0..regs::MTA.len() {
+            self.mem_addr.write(regs::MTA.at(i).ok_or(EINVAL)?, 0)?;
+        }
+
+        // 描述符的格式必须在打开接收单元之前设置
+        self.mem_addr.modify(regs::RFCTL, |rfctl| rfctl | Rfctl::EXTEN)?;
+
+        for (qid, rx_ring) in rx_rings.enumerate() {
+            self.mem_addr.write(regs::RDH.queue(qid), 0)?;
+            self.mem_addr.write(regs::RDT.queue(qid), (rx_ring.len - 1) as u32)?;
+            self.mem_addr.write(regs::RDLEN.queue(qid), (rx_ring.len * 16) as u32)?;
+            self.mem_addr.write(regs::RDBAL.queue(qid), rx_ring.dma_addr as u32)?;
+            self.mem_addr.write(regs::RDBAH.queue(qid), (rx_ring.dma_addr >> 32) as u32)?;
+        }
+
+        let rctl = Rctl::EN | Rctl::BAM | Rctl::SZ_2048 | Rctl::SECRC;
+        self.mem_addr.write(regs::RCTL, rctl)?;
+
+        // 使用 NAPI，不需要接收中断延迟
+        self.mem_addr.write(regs::RDTR, 0)?;
+        self.mem_addr.write(regs::RADV, 0)?;
+
+        if self.msix {
+            self.e1000e_configure_msix()?;
+        }
+
+        Ok(())
+    }
+
+    /// 打开收发两个 NAPI 处理的中断，MSI-X 模式下的中断原因见 `e1000e_irq_masks`
+    fn e1000_irq_enable(&self) {
+        let (tx_mask, rx_mask) = self.e1000e_irq_masks();
+        self.e1000_irq_enable_mask(tx_mask | rx_mask);
+    }
+}
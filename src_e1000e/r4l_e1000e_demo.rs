@@ -0,0 +1,666 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust for linux e1000e (82574) driver demo
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use kernel::prelude::*;
+use kernel::sync::Arc;
+use kernel::{pci, device, driver, bindings, net, dma, c_str};
+use kernel::sync::SpinLock;
+use kernel::net::mii::MiiIf;
+
+// 与 e1000 驱动共用的模块：寄存器表、描述符环、硬件操作特征和统计信息。
+// 其中只有一部分定义被 82574 用到，其余的（多队列、传统接收描述符、驱动统计等）只属于 e1000
+#[path = "../src_e1000/consts.rs"]
+#[allow(dead_code)]
+mod consts;
+#[path = "../src_e1000/regs.rs"]
+#[allow(dead_code)]
+mod regs;
+#[path = "../src_e1000/hw_defs.rs"]
+#[allow(dead_code)]
+mod hw_defs;
+#[path = "../src_e1000/ring_buf.rs"]
+#[allow(dead_code)]
+mod ring_buf;
+#[path = "../src_e1000/e1000_ops.rs"]
+#[allow(dead_code)]
+mod e1000_ops;
+#[path = "../src_e1000/stats.rs"]
+#[allow(dead_code)]
+mod stats;
+
+// 82574 特有的模块
+mod e1000e_defs;
+mod e1000e_ops;
+
+use hw_defs::TxDescEntry;
+use ring_buf::{TxRingBuf, TxBuf};
+use e1000_ops::E1000Hw;
+use e1000e_defs::*;
+use e1000e_ops::E1000eOps;
+use stats::{HwStats, StatsWatchdog};
+use regs::{Icr, RegIo};
+
+use consts::*;
+
+module! {
+    type: E1000eKernelMod,
+    name: "r4l_e1000e_demo",
+    author: "Myrfy001",
+    description: "Rust for linux e1000e (82574) driver demo",
+    license: "GPL",
+}
+
+/// 该驱动程序的私有数据结构
+struct NetDevicePrvData {
+    dev: Arc<device::Device>,  // 设备的引用计数指针
+    napi: Arc<net::Napi>,  // 接收 NAPI 结构的引用计数指针
+    tx_napi: Arc<net::Napi>,  // 只回收发送描述符的 NAPI，与 e1000 相同
+    e1000_hw_ops: Arc<dyn E1000Hw>,  // 82574 的硬件操作
+    tx_irq_mask: Icr,  // 由发送 NAPI 处理的中断原因
+    rx_irq_mask: Icr,  // 由接收 NAPI 处理的中断原因
+    stats_watchdog: Arc<StatsWatchdog>,  // 周期性刷新硬件统计信息的工作项
+    sw_stats: net::PerCpuStats,  // 数据通路中无锁累加的每 CPU 收发包统计
+    rx_page_pool: Arc<net::PagePool>,  // 接收缓冲区的 page pool
+    tx_ring: SpinLock<Option<TxRingBuf>>,  // 发送环形缓冲区
+    rx_ring: SpinLock<Option<ExtRxRingBuf>>,  // 使用扩展描述符的接收环形缓冲区
+    irq: u32,  // 向量 0 的中断号，可能是 MSI-X、MSI 或者传统中断
+    _irq_handler: AtomicPtr<kernel::irq::Registration<E1000eInterruptHandler>>,  // 中断处理程序的原子指针
+}
+
+// 声明 NetDevicePrvData 结构体可以安全地在多线程中传递和共享
+unsafe impl Send for NetDevicePrvData {}
+unsafe impl Sync for NetDevicePrvData {}
+
+/// 表示网络设备的结构体
+struct NetDevice {}
+
+impl NetDevice {
+    /// 分配发送描述符，与 e1000 相同
+    fn e1000e_setup_tx_resources(data: &NetDevicePrvData) -> Result<TxRingBuf> {
+        let mut tx_ring = TxRingBuf::new(dma::DescRing::try_new(&*data.dev, TX_RING_SIZE, bindings::GFP_KERNEL)?)?;
+        // 描述符在分配时已经清零，标记所有描述符为已完成状态
+        tx_ring.desc.as_mut_slice().iter_mut().for_each(|desc| {
+            desc.sta = E1000_TXD_STAT_DD as u8;
+        });
+        Ok(tx_ring)
+    }
+
+    /// 分配扩展接收描述符，每个描述符对应 page pool 中的一页
+    fn e1000e_setup_rx_resources(data: &NetDevicePrvData) -> Result<ExtRxRingBuf> {
+        let mut rx_ring = ExtRxRingBuf::new(dma::DescRing::try_new(&*data.dev, RX_RING_SIZE, bindings::GFP_KERNEL)?)?;
+        for (idx, desc) in rx_ring.desc.as_mut_slice().iter_mut().enumerate() {
+            let page = data.rx_page_pool.alloc()?;
+            desc.set_buffer(page.dma_addr() + E1000_RX_HEADROOM as u64);
+            rx_ring.buf[idx] = Some(page);
+        }
+        Ok(rx_ring)
+    }
+
+    // 对应于 C 版本的 e1000_clean_rx_irq()，扩展描述符的状态位于写回格式的高 64 位中
+    fn e1000e_clean_rx_irq(dev: &net::Device, data: &NetDevicePrvData) {
+        let mut rx_ring_guard = data.rx_ring.lock();
+        // 接收环只在 NAPI 禁用之后释放，这里取不到时什么也不做
+        let rx_ring = match rx_ring_guard.as_mut() {
+            Some(rx_ring) => rx_ring,
+            None => return,
+        };
+
+        loop {
+            let idx = rx_ring.desc.next_to_clean();
+            let status_error = rx_ring.desc.as_slice()[idx].status_error();
+            if status_error & E1000_RXDEXT_STAT_DD == 0 {
+                break;
+            }
+            let packet_len = rx_ring.desc.as_slice()[idx].length();
+
+            // 不支持跨多个缓冲区的包，出错或者不完整的包直接丢弃，页留在接收环中
+            let good = status_error & E1000_RXDEXT_ERR_RXE == 0 && status_error & E1000_RXDEXT_STAT_EOP != 0;
+            let copybreak = good && packet_len <= E1000E_RX_COPYBREAK;
+            let new_page = if good && !copybreak { data.rx_page_pool.alloc().ok() } else { None };
+
+            let desc = &mut rx_ring.desc.as_mut_slice()[idx];
+            if copybreak {
+                // 小包拷贝出来之后页留在接收环中，只需要同步，不需要换页
+                let page = rx_ring.buf[idx].as_ref().unwrap();
+                Self::e1000e_rx_copybreak(dev, data, page, packet_len);
+                desc.set_buffer(page.dma_addr() + E1000_RX_HEADROOM as u64);
+            } else if let Some(new_page) = new_page {
+                // 换上新的页，接收到数据的页交给协议栈
+                desc.set_buffer(new_page.dma_addr() + E1000_RX_HEADROOM as u64);
+                let page = rx_ring.buf[idx].replace(new_page).unwrap();
+                Self::e1000e_rx_page(dev, data, page, packet_len);
+            } else {
+                // 保留旧的页，写回格式覆盖了缓冲区地址，需要重新填写
+                let page = rx_ring.buf[idx].as_ref().unwrap();
+                desc.set_buffer(page.dma_addr() + E1000_RX_HEADROOM as u64);
+            }
+
+            rx_ring.desc.advance_clean();
+            data.e1000_hw_ops.e1000_write_rx_queue_tail(0, idx as u32);
+        }
+    }
+
+    /// 把一个小包拷贝到新分配的 SKB 中交给协议栈，对应于 C 版本的 `e1000_copybreak`。
+    /// 页一直保持 DMA 映射，拷贝前后只做同步，分配失败时丢弃这个包
+    fn e1000e_rx_copybreak(dev: &net::Device, data: &NetDevicePrvData, page: &net::PoolPage, packet_len: u32) {
+        page.sync_for_cpu(E1000_RX_HEADROOM, packet_len);
+
+        if let Ok(skb) = data.napi.alloc_skb(packet_len) {
+            let start = E1000_RX_HEADROOM as usize;
+            skb.put(packet_len).copy_from_slice(&page.data()[start..start + packet_len as usize]);
+
+            let protocol = skb.eth_type_trans(dev);
+            skb.protocol_set(protocol);
+
+            // napi_gro_receive 接管了 SKB 的所有权
+            data.sw_stats.rx_add(1, packet_len as u64);
+            data.napi.gro_receive(&skb);
+            core::mem::forget(skb);
+        }
+
+        // 页会被网卡再次写入，把它交还给设备
+        page.sync_for_device(E1000_RX_HEADROOM, packet_len);
+    }
+
+    /// 在接收页上构建 SKB 交给协议栈，协议栈释放 SKB 时页会回收到 page pool 中
+    fn e1000e_rx_page(dev: &net::Device, data: &NetDevicePrvData, page: net::PoolPage, packet_len: u32) {
+        page.sync_for_cpu(E1000_RX_HEADROOM, packet_len);
+
+        if let Ok(skb) = page.build_skb(E1000_RX_HEADROOM, packet_len) {
+            let protocol = skb.eth_type_trans(dev);
+            skb.protocol_set(protocol);
+
+            // napi_gro_receive 接管了 SKB 的所有权
+            data.sw_stats.rx_add(1, packet_len as u64);
+            data.napi.gro_receive(&skb);
+            core::mem::forget(skb);
+        }
+    }
+
+    // 对应于 C 版本的 e1000_clean_tx_irq()，回收已经发送完成的描述符
+    fn e1000e_clean_tx_irq(dev: &net::Device, data: &NetDevicePrvData) {
+        let tdh = data.e1000_hw_ops.e1000_read_tx_queue_head(0) as usize;
+
+        let mut tx_guard = data.tx_ring.lock_irqdisable();
+        let tx_ring = match tx_guard.as_mut() {
+            Some(tx_ring) => tx_ring,
+            None => return,
+        };
+
+        let tx_count = tx_ring.len();
+        let mut idx = tx_ring.desc.next_to_clean();
+        let descs = tx_ring.desc.as_mut_slice();
+        let (mut packets, mut bytes) = (0, 0);
+
+        while descs[idx].sta & E1000_TXD_STAT_DD as u8 != 0 && idx != tdh {
+            // 这个驱动只发送协议栈的 skb，全部计入 BQL
+            if let Some(TxBuf::Skb((dm, skb))) = tx_ring.buf[idx].take() {
+                packets += 1;
+                bytes += skb.len();
+                skb.napi_consume(64);
+                drop(dm);
+            }
+            idx = dma::ring_next(idx, tx_count);
+        }
+
+        tx_ring.desc.set_next_to_clean(idx);
+        // 在持有锁时检查空闲描述符，start_xmit 停止队列也在锁内进行，不会错过唤醒
+        let wake = tx_ring.desc.unused() > 0;
+        drop(tx_guard);
+
+        // 每 CPU 的计数器不能在关闭中断时更新，释放锁之后再统计
+        if packets != 0 {
+            dev.tx_completed_queue(0, packets, bytes);
+            data.sw_stats.tx_add(packets as u64, bytes as u64);
+        }
+
+        // 发送环有了空闲的描述符，唤醒因为环满而被 start_xmit 停止的发送队列
+        if wake && dev.netif_queue_stopped() {
+            dev.netif_wake_queue();
+        }
+    }
+}
+
+impl NetDevice {
+    /// 把环形缓冲区放入锁中。旧的环在锁释放之后才析构，dma_free_coherent 不能在关中断时调用
+    fn e1000e_install_rings(dev: &net::Device, data: &NetDevicePrvData, rx_ring: ExtRxRingBuf, tx_ring: TxRingBuf) {
+        let _old = data.rx_ring.lock_irqdisable().replace(rx_ring);
+        let _old = data.tx_ring.lock_irqdisable().replace(tx_ring);
+        // 旧环中还没有回收的 skb 不会再报告完成，BQL 的状态需要从头开始
+        dev.tx_reset_queue(0);
+    }
+
+    /// 释放环形缓冲区，调用前必须停止硬件和 NAPI
+    fn e1000e_free_rings(dev: &net::Device, data: &NetDevicePrvData) {
+        let _ring = data.rx_ring.lock_irqdisable().take();
+        let _ring = data.tx_ring.lock_irqdisable().take();
+        dev.tx_reset_queue(0);
+    }
+
+    fn e1000e_request_irq(data: &NetDevicePrvData) -> Result {
+        let irq_prv_data = Box::try_new(IrqPrivateData {
+            e1000_hw_ops: Arc::clone(&data.e1000_hw_ops),
+            napi: Arc::clone(&data.napi),
+            tx_napi: Arc::clone(&data.tx_napi),
+            tx_irq_mask: data.tx_irq_mask,
+            rx_irq_mask: data.rx_irq_mask,
+        })?;
+        let req_reg = kernel::irq::Registration::<E1000eInterruptHandler>::try_new(
+            data.irq,
+            irq_prv_data,
+            kernel::irq::flags::SHARED,
+            fmt!("{}", data.dev.name())
+        )?;
+        let old = data._irq_handler.swap(Box::into_raw(Box::try_new(req_reg)?), Ordering::AcqRel);
+        debug_assert!(old.is_null());
+        Ok(())
+    }
+
+    // 注销中断处理程序，没有注册时什么也不做
+    fn e1000e_free_irq(data: &NetDevicePrvData) {
+        let irq_handler_ptr = data._irq_handler.swap(core::ptr::null_mut(), Ordering::AcqRel);
+        if !irq_handler_ptr.is_null() {
+            // SAFETY: 指针由 `e1000e_request_irq` 中的 `Box::into_raw` 得到，并且已经从
+            // `_irq_handler` 中取出，不会被释放两次
+            drop(unsafe { Box::from_raw(irq_handler_ptr) });
+        }
+    }
+}
+
+#[vtable]
+impl net::DeviceOperations for NetDevice {
+
+    type Data = Box<NetDevicePrvData>;
+
+    fn open(dev: &net::Device, data: &NetDevicePrvData) -> Result {
+        netdev_info!(dev, "Rust for linux e1000e driver demo (net device open)\n");
+
+        dev.netif_carrier_off();
+
+        let rx_ring = Self::e1000e_setup_rx_resources(data)?;
+        let tx_ring = Self::e1000e_setup_tx_resources(data)?;
+
+        // 网络设备层只通过 E1000Hw 配置硬件，扩展描述符和 IVAR 由 E1000eOps 处理
+        let ret = data.e1000_hw_ops.e1000_configure(
+            &mut core::iter::once(rx_ring.layout()),
+            &mut core::iter::once(tx_ring.layout()),
+        );
+
+        // 配置失败时网卡可能已经开始使用环形缓冲区，也要先放入锁中，由下面的出错处理在停止硬件之后释放
+        Self::e1000e_install_rings(dev, data, rx_ring, tx_ring);
+
+        // 82574 的 PHY 地址与 82540EM 相同
+        let ret = ret
+            .and_then(|_| Self::e1000e_request_irq(data))
+            .and_then(|_| {
+                MiiIf::new(&*data.e1000_hw_ops, E1000_PHY_ADDR, true).check_link(dev)?;
+                Ok(())
+            });
+
+        // open 失败时协议栈不会调用 stop，在这里撤销上面的操作。硬件停止访问环形缓冲区之后才能释放它们
+        if let Err(e) = ret {
+            dev.netif_carrier_off();
+            data.e1000_hw_ops.e1000_irq_disable();
+            Self::e1000e_free_irq(data);
+            if data.e1000_hw_ops.e1000_stop_rxtx().is_ok() {
+                Self::e1000e_free_rings(dev, data);
+            }
+            return Err(e);
+        }
+
+        data.napi.enable();
+        data.tx_napi.enable();
+        dev.netif_start_queue();
+
+        data.stats_watchdog.start();
+
+        Ok(())
+    }
+
+    fn stop(dev: &net::Device, data: &NetDevicePrvData) -> Result {
+        netdev_info!(dev, "Rust for linux e1000e driver demo (net device stop)\n");
+
+        data.stats_watchdog.stop();
+
+        // 与 e1000 相同的顺序：先停止队列和 NAPI，再屏蔽并注销中断，最后在硬件停止后释放环形缓冲区
+        dev.netif_carrier_off();
+        dev.netif_stop_queue();
+        data.napi.disable();
+        data.tx_napi.disable();
+        data.e1000_hw_ops.e1000_irq_disable();
+        Self::e1000e_free_irq(data);
+        data.e1000_hw_ops.e1000_stop_rxtx()?;
+
+        Self::e1000e_free_rings(dev, data);
+
+        Ok(())
+    }
+
+    fn start_xmit(skb: &net::SkBuff, dev: &net::Device, data: &NetDevicePrvData) -> net::NetdevTx {
+        // 每个包只使用一个描述符，无法发送的包直接丢弃，返回 Busy 会让协议栈不停地重试
+        if skb.is_nonlinear() && skb.linearize().is_err() {
+            skb.free_any();
+            return net::NetdevTx::Ok;
+        }
+        if skb.head_data().len() > RXTX_SINGLE_RING_BLOCK_SIZE {
+            netdev_err_ratelimited!(dev, "xmit msg too long\n");
+            skb.free_any();
+            return net::NetdevTx::Ok;
+        }
+
+        let mut tx_guard = data.tx_ring.lock_irqdisable();
+        // 发送队列停止之后才会释放发送环，取不到时丢弃这个包
+        let tx_ring = match tx_guard.as_mut() {
+            Some(tx_ring) => tx_ring,
+            None => {
+                skb.free_any();
+                return net::NetdevTx::Ok;
+            }
+        };
+        // 返回 Busy 之前停止发送队列，否则协议栈会立即重试同一个包；回收描述符之后在
+        // e1000e_clean_tx_irq 中唤醒
+        if tx_ring.desc.unused() == 0 {
+            dev.netif_stop_queue();
+            return net::NetdevTx::Busy;
+        }
+
+        // 失败时 skb 已经被释放
+        if skb.put_padto(bindings::ETH_ZLEN) != 0 {
+            return net::NetdevTx::Ok;
+        }
+
+        // DMA 映射失败时丢弃这个包，描述符保持空闲
+        let ms = match dma::MapSingle::try_new(
+            &*data.dev,
+            skb.head_data().as_ptr() as *mut u8,
+            skb.len() as usize,
+            bindings::dma_data_direction_DMA_TO_DEVICE
+        ) {
+            Ok(ms) => ms,
+            Err(_) => {
+                skb.free_any();
+                return net::NetdevTx::Ok;
+            }
+        };
+
+        // 包一定会交给硬件，告诉内核我们已经将数据提交到硬件
+        let len = skb.len();
+        dev.tx_sent_queue(0, len);
+
+        let tdt = tx_ring.desc.advance_use();
+        let tx_desc: &mut TxDescEntry = &mut tx_ring.desc.as_mut_slice()[tdt];
+        tx_desc.buf_addr = ms.dma_handle as u64;
+        tx_desc.length = len as u16;
+        tx_desc.cmd = ((E1000_TXD_CMD_RS | E1000_TXD_CMD_EOP) >> 24) as u8;
+        tx_desc.sta = 0;
+        tx_ring.buf[tdt].replace(TxBuf::Skb((ms, skb.into())));
+
+        data.e1000_hw_ops.e1000_write_tx_queue_tail(0, tx_ring.desc.next_to_use() as u32);
+
+        net::NetdevTx::Ok
+    }
+
+    fn get_stats64(_netdev: &net::Device, data: &NetDevicePrvData, stats: &mut net::RtnlLinkStats64) {
+        let sw_stats = data.sw_stats.fetch();
+        stats.set_rx_bytes(sw_stats.rx_bytes);
+        stats.set_rx_packets(sw_stats.rx_packets);
+        stats.set_tx_bytes(sw_stats.tx_bytes);
+        stats.set_tx_packets(sw_stats.tx_packets);
+        let hw_stats = &data.stats_watchdog.stats;
+        stats.set_multicast(HwStats::get(&hw_stats.multicast));
+        stats.set_collisions(HwStats::get(&hw_stats.collisions));
+        stats.set_rx_crc_errors(HwStats::get(&hw_stats.rx_crc_errors));
+        stats.set_rx_missed_errors(HwStats::get(&hw_stats.rx_missed_errors));
+    }
+}
+
+// 中断处理程序的私有数据
+struct IrqPrivateData {
+    e1000_hw_ops: Arc<dyn E1000Hw>,
+    napi: Arc<net::Napi>,
+    tx_napi: Arc<net::Napi>,
+    tx_irq_mask: Icr,
+    rx_irq_mask: Icr,
+}
+
+// 向量 0 的中断处理器，所有中断原因都映射到这个向量
+struct E1000eInterruptHandler {}
+
+impl kernel::irq::Handler for E1000eInterruptHandler {
+    type Data = Box<IrqPrivateData>;
+
+    fn handle_irq(data: &IrqPrivateData) -> kernel::irq::Return {
+        let pending_irqs = data.e1000_hw_ops.e1000_read_interrupt_state();
+        if pending_irqs.is_empty() {
+            return kernel::irq::Return::None;
+        }
+
+        // MSI-X 模式下读取 ICR 不会清除中断原因，需要写 1 清除。中断上下文中不能 panic，
+        // 写入失败时原因保持置位，NAPI 结束重新打开中断后会再次进入这里
+        let _ = data.e1000_hw_ops.regs().write(regs::ICR, pending_irqs);
+
+        // 与 e1000 相同，发送完成和接收由两个 NAPI 分别处理，各自只屏蔽自己的中断
+        if pending_irqs.intersects(data.tx_irq_mask) {
+            data.e1000_hw_ops.e1000_irq_disable_mask(data.tx_irq_mask);
+            data.tx_napi.schedule();
+        }
+        if pending_irqs.intersects(data.rx_irq_mask | Icr::RXO) {
+            data.e1000_hw_ops.e1000_irq_disable_mask(data.rx_irq_mask);
+            data.napi.schedule();
+        }
+
+        kernel::irq::Return::Handled
+    }
+}
+
+// 接收 NAPI 轮询处理程序
+struct NapiHandler {}
+
+impl net::NapiPoller for NapiHandler {
+    type Data = Box<NetDevicePrvData>;
+
+    fn poll(
+        _napi: &net::Napi,
+        _budget: i32,
+        dev: &net::Device,
+        data: &NetDevicePrvData,
+    ) -> i32 {
+        NetDevice::e1000e_clean_rx_irq(dev, data);
+
+        // 只有 NAPI 真正结束时才重新打开中断
+        if data.napi.complete_done(1) {
+            data.e1000_hw_ops.e1000_irq_enable_mask(data.rx_irq_mask);
+        }
+        1
+    }
+}
+
+// 回收发送描述符的 NAPI 轮询处理程序，与 e1000 的 TxNapiHandler 相同
+struct TxNapiHandler {}
+
+impl net::NapiPoller for TxNapiHandler {
+    type Data = Box<NetDevicePrvData>;
+
+    fn poll(
+        napi: &net::Napi,
+        _budget: i32,
+        dev: &net::Device,
+        data: &NetDevicePrvData,
+    ) -> i32 {
+        NetDevice::e1000e_clean_tx_irq(dev, data);
+
+        // 回收发送描述符不计入 budget
+        if napi.complete_done(0) {
+            data.e1000_hw_ops.e1000_irq_enable_mask(data.tx_irq_mask);
+        }
+        0
+    }
+}
+
+// 定义用于管理网络设备注册信息的结构体
+struct E1000eDrvPrvData {
+    _netdev_reg: net::Registration<NetDevice>,
+    napi: Arc<net::Napi>,
+    tx_napi: Arc<net::Napi>,
+}
+
+impl Drop for E1000eDrvPrvData {
+    fn drop(&mut self) {
+        // 注销时协议栈会先调用 stop，之后 NAPI 不会再被调度，必须在网络设备释放之前删除
+        self._netdev_reg.unregister();
+        self.napi.del();
+        self.tx_napi.del();
+    }
+}
+
+impl driver::DeviceRemoval for E1000eDrvPrvData {
+    fn device_remove(&self) {
+        netdev_info!(self._netdev_reg.dev_get(), "Rust for linux e1000e driver demo (device_remove)\n");
+    }
+}
+
+struct E1000eDrv {}
+
+impl pci::Driver for E1000eDrv {
+    type Data = Box<E1000eDrvPrvData>;
+
+    kernel::define_pci_id_table! {(), [
+        (pci::DeviceId::new(E1000_VENDER_ID, E1000E_DEVICE_ID), None),
+    ]}
+
+    fn probe(dev: &mut pci::Device, id: core::option::Option<&Self::IdInfo>) -> Result<Self::Data> {
+        dev_info!(dev, "Rust for linux e1000e driver demo (probe): {:?}\n", id);
+
+        // 82574 只需要 BAR0 的寄存器空间
+        let bars = dev.select_bars(bindings::IORESOURCE_MEM as u64);
+        dev.pcim_enable_device()?;
+        dev.devm_request_selected_regions(bars, c_str!("e1000e reserved memory"))?;
+        dev.set_master();
+
+        // 82574 的 BAR0 同样是至少 128KB 的寄存器空间
+        let mem_res = match dev.resource(0) {
+            Some(res) if res.is_mem() && res.len() >= E1000_MMIO_MIN_LEN => res,
+            _ => {
+                dev_err!(dev, "BAR0 is not a memory BAR of at least {} bytes\n", E1000_MMIO_MIN_LEN);
+                return Err(ENODEV);
+            }
+        };
+        // 与 e1000 一样，映射和设备的启用都在驱动解绑时由内核撤销
+        let mem_addr = Arc::try_new(dev.pcim_iomap(0, mem_res.len())?)?;
+
+        // 82574 支持 64 位地址，平台不支持时退回到 32 位，对应于 C 版本 `e1000_probe` 中的处理
+        let using_dac = dma::set_mask_and_coherent(dev, dma::bit_mask(64)).is_ok();
+        if !using_dac {
+            dma::set_mask_and_coherent(dev, dma::bit_mask(32))?;
+        }
+
+        // 只使用一个中断向量，优先使用 MSI-X，不支持时退回到 MSI 或传统中断
+        dev.alloc_irq_vectors(1, 1, pci::irq_types::ALL_TYPES)?;
+        let irq = dev.irq_vector(0)?;
+
+        let e1000_hw_ops = E1000eOps {
+            mem_addr,
+            msix: dev.msix_enabled(),
+        };
+        e1000_hw_ops.e1000_reset_hw()?;
+        let mac_addr = e1000_hw_ops.e1000e_read_mac_addr()?;
+        let (tx_irq_mask, rx_irq_mask) = e1000_hw_ops.e1000e_irq_masks();
+        let e1000_hw_ops: Arc<dyn E1000Hw> = Arc::try_new(e1000_hw_ops)?;
+
+        let mut netdev_reg = net::Registration::<NetDevice>::try_new(dev)?;
+        let netdev = netdev_reg.dev_get();
+        netdev.eth_hw_addr_set(&mac_addr);
+        netdev.netif_carrier_off();
+        if using_dac {
+            netdev.features_set(netdev.features() | net::Features::HIGHDMA);
+        }
+
+        let napi: Arc<net::Napi> = net::NapiAdapter::<NapiHandler>::add_weight(&netdev, 64)?.into();
+        let tx_napi: Arc<net::Napi> = net::NapiAdapter::<TxNapiHandler>::add_weight(&netdev, 64)?.into();
+
+        let rx_page_pool = net::PagePool::try_new(
+            dev,
+            RX_RING_SIZE as u32,
+            0,
+            bindings::dma_data_direction_DMA_FROM_DEVICE,
+            E1000_RX_HEADROOM,
+            E1000_RX_BUFFER_SIZE,
+        )?;
+
+        let stats_watchdog = StatsWatchdog::try_new(Arc::clone(&e1000_hw_ops))?;
+
+        // SAFETY: `spinlock_init` 在下方被调用
+        let mut tx_ring = unsafe { SpinLock::new(None) };
+        // SAFETY: 我们不会移动 `tx_ring`
+        kernel::spinlock_init!(unsafe { Pin::new_unchecked(&mut tx_ring) }, "tx_ring");
+        // SAFETY: `spinlock_init` 在下方被调用
+        let mut rx_ring = unsafe { SpinLock::new(None) };
+        // SAFETY: 我们不会移动 `rx_ring`
+        kernel::spinlock_init!(unsafe { Pin::new_unchecked(&mut rx_ring) }, "rx_ring");
+
+        netdev_reg.register(Box::try_new(
+            NetDevicePrvData {
+                dev: Arc::try_new(device::Device::from_dev(dev))?,
+                napi: Arc::clone(&napi),
+                tx_napi: Arc::clone(&tx_napi),
+                e1000_hw_ops,
+                tx_irq_mask,
+                rx_irq_mask,
+                stats_watchdog,
+                sw_stats: net::PerCpuStats::try_new()?,
+                rx_page_pool,
+                tx_ring,
+                rx_ring,
+                irq,
+                _irq_handler: AtomicPtr::new(core::ptr::null_mut()),
+            }
+        )?)?;
+
+        Ok(Box::try_new(
+            E1000eDrvPrvData {
+                _netdev_reg: netdev_reg,
+                napi,
+                tx_napi,
+            }
+        )?)
+    }
+
+    fn remove(dev: &mut pci::Device, _data: &Self::Data) {
+        dev_info!(dev, "Rust for linux e1000e driver demo (remove)\n");
+
+        // 中断处理程序在 stop 中注销，网络设备在 `E1000eDrvPrvData` 析构时注销，都发生在
+        // devres 释放中断向量和 PCI 资源之前
+    }
+
+    // 关机或 kexec 之前停止网卡的 DMA，之后不会再调用 remove
+    fn shutdown(dev: &mut pci::Device, data: &E1000eDrvPrvData) {
+        data._netdev_reg.dev_get().netif_device_detach();
+        if let Some(prv) = data._netdev_reg.drvdata() {
+            prv.e1000_hw_ops.e1000_irq_disable();
+            let _ = prv.e1000_hw_ops.e1000_stop_rxtx();
+        }
+        dev.clear_master();
+    }
+}
+
+struct E1000eKernelMod {
+    _dev: Pin<Box<driver::Registration::<pci::Adapter<E1000eDrv>>>>,
+}
+
+impl kernel::Module for E1000eKernelMod {
+    fn init(name: &'static CStr, module: &'static ThisModule) -> Result<Self> {
+        pr_info!("Rust for linux e1000e driver demo (init)\n");
+
+        let d = driver::Registration::<pci::Adapter<E1000eDrv>>::new_pinned(name, module)?;
+        Ok(E1000eKernelMod {_dev: d})
+    }
+}
+
+impl Drop for E1000eKernelMod {
+    fn drop(&mut self) {
+        pr_info!("Rust for linux e1000e driver demo (exit)\n");
+    }
+}